@@ -0,0 +1,464 @@
+// Structural and content anomalies of the keys tree
+//
+// Windows tolerates a number of inconsistencies which a regular writer never produces: they are the
+// mark of a hive edited by hand or of a subtle corruption, so they are reported as findings.
+//
+use std::collections::HashSet;
+
+use crate::{
+    findings::{Finding, Severity},
+    hive::{Key, MAX_DEPTH, RegistryHive, join_path},
+    list::{SubkeysList, name_hash, name_hint},
+    payloads::is_pe_image,
+    utils::{escape_name, filetime_to_string, is_hidden_char, now_filetime, shannon_entropy},
+    value::{KeyValue, RegType},
+};
+
+// FILETIME ticks (100 ns) in a day
+const FILETIME_DAY: u64 = 864_000_000_000;
+
+// a key older than its parent and all its siblings by more than this is out of its creation context
+const CONTEXT_GAP: u64 = 365 * FILETIME_DAY;
+
+// Windows timestamps have a resolution of a few milliseconds so a handful of keys created together
+// can share a timestamp, a whole subtree can't
+const MIN_UNIFORM_KEYS: usize = 10;
+
+pub fn anomaly_findings(hive: &mut RegistryHive) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |hive, path, key| {
+        if !key.node.is_root() {
+            check_name(path, "key", key.name(), &mut findings);
+        }
+        for value in hive.values(key)? {
+            check_name(path, "value", &value.name, &mut findings);
+            if let Ok(data) = hive.value_bytes(&value) {
+                check_data(path, &value, &data, &mut findings);
+            }
+        }
+        if key.node.header.number_of_subkeys > 0 {
+            check_subkeys_list(
+                hive,
+                path,
+                key.node.header.subkeys_list_offset,
+                &mut findings,
+            )?;
+        }
+        Ok(())
+    })?;
+
+    let reference = hive.base_block.last_written_timestamp;
+    let mut visited = HashSet::new();
+    let (uniform, count) =
+        check_timestamps(hive, &root, "\\", reference, &mut visited, 0, &mut findings)?;
+    if uniform.is_some() && count >= MIN_UNIFORM_KEYS {
+        findings.push(uniform_finding("\\", &root, count));
+    }
+
+    Ok(findings)
+}
+
+// documented maximum lengths of names, in characters
+const MAX_KEY_NAME_LEN: usize = 255;
+const MAX_VALUE_NAME_LEN: usize = 16383;
+
+// names hiding from regedit and most tools: everything after an embedded NUL is not displayed, other
+// control or invisible characters make keys look like others. Trailing spaces, backslashes in key
+// names or names too long can't go through the Win32 API, so such keys can't be opened or deleted
+// by standard tools.
+fn check_name(path: &str, kind: &str, name: &str, findings: &mut Vec<Finding>) {
+    let mut report = |severity, category, issue: &str| {
+        findings.push(Finding::new(
+            severity,
+            category,
+            path,
+            format!("{kind} name '{}' {issue}", escape_name(name)),
+        ));
+    };
+
+    if name.contains('\0') {
+        report(Severity::High, "name-nul", "contains NUL characters");
+    } else if name.chars().any(|c| c.is_control()) {
+        report(
+            Severity::Medium,
+            "name-control",
+            "contains control characters",
+        );
+    } else if name.chars().any(is_hidden_char) {
+        report(
+            Severity::Medium,
+            "name-hidden",
+            "contains characters regedit doesn't display",
+        );
+    }
+
+    if name.ends_with(' ') {
+        report(Severity::Medium, "name-space", "ends with spaces");
+    }
+
+    let max_len = if kind == "key" {
+        MAX_KEY_NAME_LEN
+    } else {
+        MAX_VALUE_NAME_LEN
+    };
+    let len = name.encode_utf16().count();
+    if len > max_len {
+        report(
+            Severity::Medium,
+            "name-length",
+            &format!("is {len} characters long, more than {max_len}"),
+        );
+    }
+
+    if kind == "key" && (name.is_empty() || name.contains('\\')) {
+        report(
+            Severity::High,
+            "name-illegal",
+            "is empty or contains backslashes",
+        );
+    }
+}
+
+// strings this large are unusual, most likely a payload stored as text
+const LARGE_STRING_SIZE: usize = 64 * 1024;
+
+// executables and base64 or hex encoded PE images, the usual fileless malware storage
+const EXECUTABLE_MAGICS: &[(&[u8], &str)] = &[
+    (b"MZ", "a PE image"),
+    (b"\x7FELF", "an ELF image"),
+    (b"TVqQ", "a base64 encoded PE image"),
+    (b"TVpQ", "a base64 encoded PE image"),
+    (b"4D5A90", "a hex encoded PE image"),
+    (b"4d5a90", "a hex encoded PE image"),
+];
+
+// binary data at least this large and random looking is compressed or encrypted: below, the entropy
+// of a few bytes isn't significant
+const ENTROPY_MIN_SIZE: usize = 1024;
+const HIGH_ENTROPY: f64 = 7.2;
+
+// keys where Windows itself stores encrypted or hashed data
+const ENCRYPTED_LOCATIONS: &[&str] = &[
+    "\\SYSTEMCERTIFICATES\\",
+    "\\CRYPTOGRAPHY\\",
+    "\\PROTECT\\",
+    "\\SAM\\DOMAINS\\",
+    "\\POLICY\\SECRETS\\",
+];
+
+// keys where executable content is loaded or started from, favoured by fileless malware
+const PERSISTENCE_LOCATIONS: &[&str] = &[
+    "\\CURRENTVERSION\\RUN",
+    "\\SERVICES\\",
+    "\\CLASSES\\CLSID\\",
+    "\\WINLOGON",
+    "\\ENVIRONMENT",
+];
+
+// data contradicting the declared type, or executable content
+fn check_data(path: &str, value: &KeyValue, data: &[u8], findings: &mut Vec<Finding>) {
+    let reg_type = value.reg_type();
+    let mut report = |severity, category, issue: String| {
+        findings.push(Finding::new(
+            severity,
+            category,
+            path,
+            format!(
+                "{reg_type} value '{}' {issue}",
+                escape_name(value.display_name())
+            ),
+        ));
+    };
+
+    let expected_size = match reg_type {
+        RegType::Dword | RegType::DwordBigEndian => Some(4),
+        RegType::Qword => Some(8),
+        _ => None,
+    };
+    if let Some(size) = expected_size
+        && data.len() != size
+    {
+        report(
+            Severity::Medium,
+            "value-size",
+            format!("is {} bytes long instead of {size}", data.len()),
+        );
+    }
+
+    if matches!(reg_type, RegType::Sz | RegType::ExpandSz | RegType::MultiSz) {
+        if !data.len().is_multiple_of(2) || !data.is_empty() && !data.ends_with(&[0, 0]) {
+            report(
+                Severity::Low,
+                "value-type",
+                "is not a null terminated UTF-16 string".to_string(),
+            );
+        }
+        if data.len() > LARGE_STRING_SIZE {
+            report(
+                Severity::Medium,
+                "value-size",
+                format!("is a {} bytes string", data.len()),
+            );
+        }
+    }
+
+    // strings are also checked once decoded, encoded payloads being text
+    let text: Vec<u8> = match reg_type {
+        RegType::Sz | RegType::ExpandSz | RegType::MultiSz => data
+            .chunks_exact(2)
+            .filter_map(|c| u8::try_from(u16::from_le_bytes([c[0], c[1]])).ok())
+            .collect(),
+        _ => Vec::new(),
+    };
+    if !matches!(reg_type, RegType::Sz | RegType::ExpandSz | RegType::MultiSz)
+        && data.len() >= ENTROPY_MIN_SIZE
+    {
+        let entropy = shannon_entropy(data);
+        let location = format!("{}\\", path.to_uppercase());
+        if entropy >= HIGH_ENTROPY && !ENCRYPTED_LOCATIONS.iter().any(|l| location.contains(l)) {
+            let severity = if PERSISTENCE_LOCATIONS.iter().any(|l| location.contains(l)) {
+                Severity::High
+            } else {
+                Severity::Medium
+            };
+            report(
+                severity,
+                "value-entropy",
+                format!(
+                    "has an entropy of {entropy:.2} bits per byte ({} bytes), packed or encrypted data",
+                    data.len()
+                ),
+            );
+        }
+    }
+
+    for content in [data, text.as_slice()] {
+        if let Some((_, description)) = EXECUTABLE_MAGICS
+            .iter()
+            .find(|(magic, _)| content.starts_with(magic) && is_executable(content, magic))
+        {
+            report(
+                Severity::High,
+                "value-executable",
+                format!("contains {description} ({} bytes)", data.len()),
+            );
+            break;
+        }
+    }
+}
+
+fn is_executable(content: &[u8], magic: &[u8]) -> bool {
+    if magic != b"MZ" {
+        return content.len() > 64;
+    }
+    is_pe_image(content)
+}
+
+// timestamp manipulation: keys in the future, zeroed, out of their parent's context or a whole
+// subtree sharing the same timestamp. Returns the timestamp of the subtree if uniform and the number
+// of keys in it.
+fn check_timestamps(
+    hive: &mut RegistryHive,
+    key: &Key,
+    path: &str,
+    reference: u64,
+    visited: &mut HashSet<u32>,
+    depth: usize,
+    findings: &mut Vec<Finding>,
+) -> anyhow::Result<(Option<u64>, usize)> {
+    let ts = key.node.header.last_written_timestamp;
+    if depth > MAX_DEPTH || !visited.insert(key.offset) {
+        return Ok((Some(ts), 0));
+    }
+
+    if ts == 0 {
+        findings.push(Finding::new(
+            Severity::Medium,
+            "timestamp-zero",
+            path,
+            "last written timestamp is zero".to_string(),
+        ));
+    } else if ts > now_filetime() {
+        findings.push(Finding::new(
+            Severity::Medium,
+            "timestamp-future",
+            path,
+            format!("last written in the future: {}", key.node.timestamp()),
+        ));
+    } else if ts > reference.saturating_add(FILETIME_DAY) && reference != 0 {
+        findings.push(Finding::new(
+            Severity::Low,
+            "timestamp-future",
+            path,
+            format!(
+                "last written {} after the hive itself: {}",
+                key.node.timestamp(),
+                filetime_to_string(reference)
+            ),
+        ));
+    }
+
+    let subkeys = hive.subkeys(key)?;
+
+    // the two oldest non-zero sibling timestamps with their index, the oldest other than a subkey
+    // being one of them
+    let mut oldest: [Option<(u64, usize)>; 2] = [None, None];
+    for (i, subkey) in subkeys.iter().enumerate() {
+        let sub_ts = subkey.node.header.last_written_timestamp;
+        if sub_ts == 0 {
+            continue;
+        }
+        if oldest[0].is_none_or(|(t, _)| sub_ts < t) {
+            oldest = [Some((sub_ts, i)), oldest[0]];
+        } else if oldest[1].is_none_or(|(t, _)| sub_ts < t) {
+            oldest[1] = Some((sub_ts, i));
+        }
+    }
+
+    // siblings and their parent form the creation context of each subkey
+    for (i, subkey) in subkeys.iter().enumerate() {
+        let sub_ts = subkey.node.header.last_written_timestamp;
+        let oldest_sibling = oldest
+            .iter()
+            .flatten()
+            .find(|(_, j)| *j != i)
+            .map(|(t, _)| *t);
+        let oldest_other = oldest_sibling
+            .into_iter()
+            .chain([ts])
+            .filter(|t| *t != 0)
+            .min()
+            .unwrap_or(0);
+        if sub_ts != 0 && sub_ts.saturating_add(CONTEXT_GAP) < oldest_other {
+            findings.push(Finding::new(
+                Severity::Low,
+                "timestamp-context",
+                &join_path(path, subkey.name()),
+                format!(
+                    "last written {} long before its parent and siblings, oldest being {}",
+                    subkey.node.timestamp(),
+                    filetime_to_string(oldest_other)
+                ),
+            ));
+        }
+    }
+
+    let mut uniform = Some(ts);
+    let mut count = 1;
+    let mut uniform_subtrees = Vec::new();
+    for subkey in &subkeys {
+        let subpath = join_path(path, subkey.name());
+        let (sub_uniform, sub_count) = check_timestamps(
+            hive,
+            subkey,
+            &subpath,
+            reference,
+            visited,
+            depth + 1,
+            findings,
+        )?;
+        if sub_uniform != Some(ts) {
+            uniform = None;
+        }
+        if sub_uniform.is_some() && sub_count >= MIN_UNIFORM_KEYS {
+            uniform_subtrees.push((subpath, subkey, sub_count));
+        }
+        count += sub_count;
+    }
+
+    // only the topmost key of a uniform subtree is reported
+    if uniform.is_none() {
+        for (subpath, subkey, sub_count) in uniform_subtrees {
+            findings.push(uniform_finding(&subpath, subkey, sub_count));
+        }
+    }
+
+    Ok((uniform, count))
+}
+
+fn uniform_finding(path: &str, key: &Key, count: usize) -> Finding {
+    Finding::new(
+        Severity::Low,
+        "timestamp-uniform",
+        path,
+        format!(
+            "{count} keys of the subtree last written at the same time: {}",
+            key.node.timestamp()
+        ),
+    )
+}
+
+// lh hashes and lf hints are recomputed from the names of the subkeys they point to
+fn check_subkeys_list(
+    hive: &mut RegistryHive,
+    path: &str,
+    list_offset: u32,
+    findings: &mut Vec<Finding>,
+) -> anyhow::Result<()> {
+    let (_, data) = hive.cell(list_offset)?;
+    let entries: Vec<(u32, Option<[u8; 4]>, Option<u32>)> = match SubkeysList::try_from(data)? {
+        SubkeysList::FastLeaf(v) => v.into_iter().map(|(o, h)| (o, Some(h), None)).collect(),
+        SubkeysList::HashLeaf(v) => v.into_iter().map(|(o, h)| (o, None, Some(h))).collect(),
+        // an Index root points to leaves, which can't be Index roots themselves
+        SubkeysList::IndexRoot(lists) => {
+            for list in lists {
+                let (_, data) = hive.cell(list)?;
+                if !data.starts_with(b"ri") {
+                    check_subkeys_list(hive, path, list, findings)?;
+                }
+            }
+            return Ok(());
+        }
+        SubkeysList::IndexLeaf(_) => return Ok(()),
+    };
+
+    for (offset, hint, hash) in entries {
+        let Ok(subkey) = hive.key(offset) else {
+            continue;
+        };
+        let name = subkey.name();
+
+        if let Some(hint) = hint {
+            // characters beyond Latin-1 have no single byte hint
+            let expected = name_hint(name);
+            let comparable = name.chars().take(4).all(|c| (c as u32) < 0x100);
+            if comparable && hint != expected {
+                findings.push(Finding::new(
+                    Severity::Medium,
+                    "lf-hint",
+                    path,
+                    format!(
+                        "hint '{}' of subkey '{name}' in lf list at 0x{list_offset:X} doesn't match its name, expected '{}'",
+                        hint_string(&hint),
+                        hint_string(&expected)
+                    ),
+                ));
+            }
+        }
+
+        if let Some(hash) = hash {
+            let expected = name_hash(name);
+            if hash != expected {
+                findings.push(Finding::new(
+                    Severity::Medium,
+                    "lh-hash",
+                    path,
+                    format!(
+                        "hash 0x{hash:08X} of subkey '{name}' in lh list at 0x{list_offset:X} doesn't match its name, expected 0x{expected:08X}"
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn hint_string(hint: &[u8; 4]) -> String {
+    hint.iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect()
+}
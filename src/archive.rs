@@ -0,0 +1,173 @@
+// Hives read out of the archives of triage collections (zip, 7z) or compressed files (gzip)
+//
+// collections made by KAPE or Velociraptor are shipped as archives holding the hives with their path
+// on the collected system (e.g. C/Windows/System32/config/SYSTEM, or uploads/auto/C%3A/Windows/...).
+// A single file is decompressed out of the archive, nothing else is extracted. Paths are compared
+// case-insensitively with either separator, and the end of a path is enough when it matches a single
+// file (e.g. Windows/System32/config/SYSTEM). A gzip file holds a single file, whatever the path.
+//
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use flate2::read::MultiGzDecoder;
+use sevenz_rust::{Password, SevenZReader};
+use zip::ZipArchive;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveFormat {
+    Zip,
+    SevenZip,
+    Gzip,
+}
+
+impl ArchiveFormat {
+    // from the signature of the file, None if not an archive
+    pub fn detect<R: Read + Seek>(reader: &mut R) -> anyhow::Result<Option<Self>> {
+        let mut signature = Vec::new();
+        reader.seek(SeekFrom::Start(0))?;
+        reader.by_ref().take(6).read_to_end(&mut signature)?;
+
+        let format = if signature.starts_with(b"PK\x03\x04") || signature.starts_with(b"PK\x05\x06")
+        {
+            Some(ArchiveFormat::Zip)
+        } else if signature.starts_with(b"7z\xBC\xAF\x27\x1C") {
+            Some(ArchiveFormat::SevenZip)
+        } else if signature.starts_with(b"\x1F\x8B") {
+            Some(ArchiveFormat::Gzip)
+        } else {
+            None
+        };
+        Ok(format)
+    }
+}
+
+impl fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveFormat::Zip => write!(f, "zip"),
+            ArchiveFormat::SevenZip => write!(f, "7z"),
+            ArchiveFormat::Gzip => write!(f, "gzip"),
+        }
+    }
+}
+
+// paths of the files of an archive, directories left out
+pub fn archive_files(archive: &Path) -> anyhow::Result<Vec<String>> {
+    let mut reader = BufReader::new(File::open(archive)?);
+    archive_files_from(&mut reader, &archive.display().to_string())
+}
+
+// same as archive_files for an archive read from anything, e.g. over HTTP
+pub fn archive_files_from<R: Read + Seek>(
+    reader: &mut R,
+    archive: &str,
+) -> anyhow::Result<Vec<String>> {
+    let files = match format(reader, archive)? {
+        ArchiveFormat::Zip => {
+            reader.seek(SeekFrom::Start(0))?;
+            let zip = ZipArchive::new(reader)?;
+            zip.file_names()
+                .filter(|name| !name.ends_with('/'))
+                .map(String::from)
+                .collect()
+        }
+        ArchiveFormat::SevenZip => seven_zip(reader)?
+            .archive()
+            .files
+            .iter()
+            .filter(|entry| !entry.is_directory())
+            .map(|entry| entry.name().to_string())
+            .collect(),
+        ArchiveFormat::Gzip => vec![String::new()],
+    };
+    Ok(files)
+}
+
+// decompress the file of the archive at path into out
+pub fn copy_archive_file<W: Write>(archive: &Path, path: &str, out: &mut W) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(File::open(archive)?);
+    copy_archive_file_from(&mut reader, &archive.display().to_string(), path, out)
+}
+
+// same as copy_archive_file for an archive read from anything, e.g. over HTTP
+pub fn copy_archive_file_from<R: Read + Seek, W: Write>(
+    reader: &mut R,
+    archive: &str,
+    path: &str,
+    out: &mut W,
+) -> anyhow::Result<()> {
+    let format = format(reader, archive)?;
+    if format == ArchiveFormat::Gzip {
+        reader.seek(SeekFrom::Start(0))?;
+        io::copy(&mut MultiGzDecoder::new(reader), out)?;
+        return Ok(());
+    }
+
+    let name = find_file(&archive_files_from(reader, archive)?, path)
+        .map_err(|e| anyhow::anyhow!("{e} in {archive}"))?;
+
+    if format == ArchiveFormat::Zip {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut zip = ZipArchive::new(reader)?;
+        io::copy(&mut zip.by_name(&name)?, out)?;
+        return Ok(());
+    }
+
+    // files of a solid 7z archive are decompressed in a row: the ones before are skipped over
+    let mut result = Ok(());
+    seven_zip(reader)?.for_each_entries(|entry, reader| {
+        if entry.name() != name {
+            io::copy(reader, &mut io::sink())?;
+            return Ok(true);
+        }
+        result = io::copy(reader, out).map(|_| ());
+        Ok(false)
+    })?;
+    result?;
+    Ok(())
+}
+
+fn format<R: Read + Seek>(reader: &mut R, archive: &str) -> anyhow::Result<ArchiveFormat> {
+    ArchiveFormat::detect(reader)?
+        .ok_or_else(|| anyhow::anyhow!("{archive} isn't a zip, 7z or gzip file"))
+}
+
+fn seven_zip<R: Read + Seek>(reader: &mut R) -> anyhow::Result<SevenZReader<&mut R>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(SevenZReader::new(reader, len, Password::empty())?)
+}
+
+// the file with this path, or the only one whose path ends with it
+fn find_file(files: &[String], path: &str) -> anyhow::Result<String> {
+    let normalize = |p: &str| p.replace('\\', "/").trim_matches('/').to_lowercase();
+    let wanted = normalize(path);
+    anyhow::ensure!(!wanted.is_empty(), "no file path given");
+
+    if let Some(file) = files.iter().find(|f| normalize(f) == wanted) {
+        return Ok(file.clone());
+    }
+
+    let suffix = format!("/{wanted}");
+    let mut matches: Vec<_> = files
+        .iter()
+        .filter(|f| normalize(f).ends_with(&suffix))
+        .collect();
+    matches.sort();
+    match matches.as_slice() {
+        [] => anyhow::bail!("'{path}' not found"),
+        [file] => Ok(file.to_string()),
+        _ => anyhow::bail!(
+            "'{path}' matches several files ({})",
+            matches
+                .iter()
+                .map(|f| f.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
@@ -0,0 +1,140 @@
+// Parsers of well known forensic artifacts stored in values and keys
+
+use crate::{
+    hive::{Key, RegistryHive, names_equal},
+    utils::{days_from_civil, unix_to_filetime},
+    value::RegData,
+};
+
+pub mod autoruns;
+pub mod bam;
+pub mod layers;
+pub mod mru;
+pub mod muicache;
+pub mod network;
+pub mod sam;
+pub mod services;
+pub mod shellbags;
+pub mod shellitem;
+pub mod shimcache;
+pub mod usb;
+pub mod userassist;
+
+// bounds checked little endian readers, artifacts data being often truncated
+pub(crate) fn u16_at(data: &[u8], offset: usize) -> anyhow::Result<u16> {
+    Ok(u16::from_le_bytes(bytes_at(data, offset, 2)?.try_into()?))
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> anyhow::Result<u32> {
+    Ok(u32::from_le_bytes(bytes_at(data, offset, 4)?.try_into()?))
+}
+
+pub(crate) fn u64_at(data: &[u8], offset: usize) -> anyhow::Result<u64> {
+    Ok(u64::from_le_bytes(bytes_at(data, offset, 8)?.try_into()?))
+}
+
+pub(crate) fn bytes_at(data: &[u8], offset: usize, len: usize) -> anyhow::Result<&[u8]> {
+    data.get(offset..offset.saturating_add(len))
+        .ok_or_else(|| anyhow::anyhow!("{len} bytes at 0x{offset:X} beyond data end"))
+}
+
+// UTF-16LE string up to the first null character
+pub(crate) fn utf16_at(data: &[u8], offset: usize, len: usize) -> anyhow::Result<String> {
+    let units: Vec<u16> = bytes_at(data, offset, len)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|u| *u != 0)
+        .collect();
+    Ok(String::from_utf16_lossy(&units))
+}
+
+// MS-DOS date (low word) and time (high word), local time with a 2 seconds resolution, to a FILETIME.
+// Returns 0 when not set or invalid.
+pub(crate) fn dos_datetime_to_filetime(datetime: u32) -> u64 {
+    let date = datetime & 0xFFFF;
+    let time = datetime >> 16;
+
+    let (year, month, day) = (1980 + (date >> 9) as i64, (date >> 5) & 0x0F, date & 0x1F);
+    let (hours, minutes, seconds) = (time >> 11, (time >> 5) & 0x3F, (time & 0x1F) * 2);
+    if date == 0 || !(1..=12).contains(&month) || day == 0 || hours > 23 || minutes > 59 {
+        return 0;
+    }
+
+    let days = days_from_civil(year, month, day);
+    unix_to_filetime(days * 86400 + (hours * 3600 + minutes * 60 + seconds) as i64)
+}
+
+// MRUListEx: indexes of the MRU values, most recently used first, ending with 0xFFFFFFFF
+pub(crate) fn mru_list_ex(data: &[u8]) -> Vec<u32> {
+    data.chunks_exact(4)
+        .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+        .take_while(|i| *i != u32::MAX)
+        .collect()
+}
+
+// data of the value of a key with this name, None if there's no such value
+pub(crate) fn value_data(
+    hive: &mut RegistryHive,
+    key: &Key,
+    name: &str,
+) -> anyhow::Result<Option<RegData>> {
+    match hive
+        .values(key)?
+        .into_iter()
+        .find(|v| names_equal(&v.name, name))
+    {
+        Some(value) => Ok(Some(hive.value_data(&value)?)),
+        None => Ok(None),
+    }
+}
+
+// string of a REG_SZ or REG_EXPAND_SZ value
+pub(crate) fn string_value(
+    hive: &mut RegistryHive,
+    key: &Key,
+    name: &str,
+) -> anyhow::Result<Option<String>> {
+    Ok(match value_data(hive, key, name)? {
+        Some(RegData::Sz(s) | RegData::ExpandSz(s)) => Some(s),
+        _ => None,
+    })
+}
+
+pub(crate) fn dword_value(
+    hive: &mut RegistryHive,
+    key: &Key,
+    name: &str,
+) -> anyhow::Result<Option<u32>> {
+    Ok(match value_data(hive, key, name)? {
+        Some(RegData::Dword(d)) => Some(d),
+        _ => None,
+    })
+}
+
+// control set used at the last boot (Select\Current), the first one if there's no Select key
+pub(crate) fn current_control_set(
+    hive: &mut RegistryHive,
+) -> anyhow::Result<Option<(String, Key)>> {
+    if let Some(select) = hive.open_key("Select")?
+        && let Some(current) = dword_value(hive, &select, "Current")?
+        && let Some(found) = hive.open_key_path(&format!("ControlSet{current:03}"))?
+    {
+        return Ok(Some(found));
+    }
+    Ok(hive.find_keys("ControlSet*")?.into_iter().next())
+}
+
+// SYSTEMTIME structure (year, month, day of week, day, hours, minutes, seconds, milliseconds) to a
+// FILETIME, 0 when not set or invalid
+pub(crate) fn systemtime_to_filetime(data: &[u8]) -> u64 {
+    let field = |i: usize| u16_at(data, i * 2).unwrap_or(0) as u32;
+    let (year, month, day) = (field(0) as i64, field(1), field(3));
+    let (hours, minutes, seconds) = (field(4), field(5), field(6));
+    if year < 1601 || !(1..=12).contains(&month) || day == 0 || hours > 23 || minutes > 59 {
+        return 0;
+    }
+
+    let days = days_from_civil(year, month, day);
+    unix_to_filetime(days * 86400 + (hours * 3600 + minutes * 60 + seconds) as i64)
+        + field(7) as u64 * 10_000
+}
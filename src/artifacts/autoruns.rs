@@ -0,0 +1,206 @@
+// Persistence locations: programs started at boot, at logon or when another program is launched
+//
+// SOFTWARE and NTUSER.DAT hives share most locations, the latter under Software. Services started
+// automatically are taken from SYSTEM hives. Locations not found in a hive are skipped, so any hive
+// can be swept.
+//
+use std::fmt;
+
+use crate::{
+    artifacts::services::{services, suspicious_path},
+    findings::{Finding, Severity},
+    hive::{RegistryHive, join_path},
+    utils::filetime_to_string,
+    value::RegData,
+};
+
+// location name, key pattern ('*' and '?' wildcards) and the values to report, all when empty
+const LOCATIONS: &[(&str, &str, &[&str])] = &[
+    ("Run", "Microsoft\\Windows\\CurrentVersion\\Run*", &[]),
+    (
+        "Run",
+        "Microsoft\\Windows\\CurrentVersion\\Policies\\Explorer\\Run",
+        &[],
+    ),
+    (
+        "Run",
+        "Wow6432Node\\Microsoft\\Windows\\CurrentVersion\\Run*",
+        &[],
+    ),
+    (
+        "Run",
+        "Software\\Microsoft\\Windows\\CurrentVersion\\Run*",
+        &[],
+    ),
+    (
+        "Run",
+        "Software\\Microsoft\\Windows\\CurrentVersion\\Policies\\Explorer\\Run",
+        &[],
+    ),
+    (
+        "Run",
+        "Software\\Microsoft\\Windows NT\\CurrentVersion\\Windows",
+        &["Load", "Run"],
+    ),
+    (
+        "Winlogon",
+        "Microsoft\\Windows NT\\CurrentVersion\\Winlogon",
+        &["Shell", "Userinit", "Taskman", "AppSetup"],
+    ),
+    (
+        "Winlogon",
+        "Software\\Microsoft\\Windows NT\\CurrentVersion\\Winlogon",
+        &["Shell"],
+    ),
+    (
+        "IFEO",
+        "Microsoft\\Windows NT\\CurrentVersion\\Image File Execution Options\\*",
+        &["Debugger"],
+    ),
+    (
+        "IFEO",
+        "Wow6432Node\\Microsoft\\Windows NT\\CurrentVersion\\Image File Execution Options\\*",
+        &["Debugger"],
+    ),
+    (
+        "AppInit",
+        "Microsoft\\Windows NT\\CurrentVersion\\Windows",
+        &["AppInit_DLLs"],
+    ),
+    (
+        "AppInit",
+        "Wow6432Node\\Microsoft\\Windows NT\\CurrentVersion\\Windows",
+        &["AppInit_DLLs"],
+    ),
+    (
+        "Active Setup",
+        "Microsoft\\Active Setup\\Installed Components\\*",
+        &["StubPath"],
+    ),
+];
+
+// Winlogon values set by Windows itself
+const WINLOGON_DEFAULTS: &[&str] = &[
+    "explorer.exe",
+    "c:\\windows\\system32\\userinit.exe",
+    "userinit.exe",
+];
+
+#[derive(Debug, Clone)]
+pub struct Autorun {
+    pub location: &'static str,
+    pub key_path: String,
+    pub value_name: String,
+    pub command: String,
+
+    // last written time of the key
+    pub timestamp: u64,
+
+    // why the command looks suspicious
+    pub suspicious: Option<&'static str>,
+}
+
+// suspicious entries are worth a look, the others are listed for the record
+impl From<&Autorun> for Finding {
+    fn from(autorun: &Autorun) -> Self {
+        let (severity, reason) = match autorun.suspicious {
+            Some(reason) => (Severity::Medium, format!(" ({reason})")),
+            None => (Severity::Info, String::new()),
+        };
+        Finding::new(
+            severity,
+            "autorun",
+            &join_path(&autorun.key_path, &autorun.value_name),
+            format!("{}: {}{reason}", autorun.location, autorun.command),
+        )
+    }
+}
+
+impl fmt::Display for Autorun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.location,
+            filetime_to_string(self.timestamp),
+            self.key_path,
+            self.value_name,
+            self.command,
+            self.suspicious.unwrap_or("")
+        )
+    }
+}
+
+pub fn autoruns(hive: &mut RegistryHive) -> anyhow::Result<Vec<Autorun>> {
+    let mut autoruns = Vec::new();
+
+    for (location, pattern, names) in LOCATIONS {
+        for (key_path, key) in hive.find_keys(pattern)? {
+            for value in hive.values(&key)? {
+                if !names.is_empty() && !names.iter().any(|n| n.eq_ignore_ascii_case(&value.name)) {
+                    continue;
+                }
+                let command = match hive.value_data(&value)? {
+                    RegData::Sz(s) | RegData::ExpandSz(s) => s,
+                    RegData::MultiSz(v) => v.join(" "),
+                    _ => continue,
+                };
+                if command.trim().is_empty() {
+                    continue;
+                }
+
+                autoruns.push(Autorun {
+                    location,
+                    key_path: key_path.clone(),
+                    value_name: value.name.clone(),
+                    suspicious: command_suspicious(location, &command),
+                    command,
+                    timestamp: key.node.header.last_written_timestamp,
+                });
+            }
+        }
+    }
+
+    // services loaded at boot or started automatically
+    for service in services(hive, 0)? {
+        if !matches!(service.start, Some(0..=2)) {
+            continue;
+        }
+        let Some(command) = service.service_dll.clone().or(service.image_path.clone()) else {
+            continue;
+        };
+        autoruns.push(Autorun {
+            location: "Services",
+            key_path: service.path.clone(),
+            value_name: if service.service_dll.is_some() {
+                "Parameters\\ServiceDll".to_string()
+            } else {
+                "ImagePath".to_string()
+            },
+            command,
+            timestamp: service.timestamp,
+            suspicious: service.suspicious,
+        });
+    }
+
+    Ok(autoruns)
+}
+
+// Winlogon and AppInit_DLLs values are lists separated by commas or spaces
+fn command_suspicious(location: &str, command: &str) -> Option<&'static str> {
+    match location {
+        "Winlogon" => command
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .filter(|c| !WINLOGON_DEFAULTS.iter().any(|d| d.eq_ignore_ascii_case(c)))
+            .find_map(|c| suspicious_path(c).or(Some("not the Windows default"))),
+        "AppInit" => command
+            .split([',', ' '])
+            .filter(|c| !c.is_empty())
+            .find_map(suspicious_path)
+            .or(Some("DLL loaded by every process linked to user32.dll")),
+        "IFEO" => suspicious_path(command).or(Some("debugger launched instead of the program")),
+        _ => suspicious_path(command),
+    }
+}
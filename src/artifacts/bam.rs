@@ -0,0 +1,85 @@
+// Background Activity Moderator (BAM) and Desktop Activity Moderator (DAM): last execution of the
+// programs of each user, as of Windows 10 1709
+//
+// locations in SYSTEM hives, one subkey per user SID:
+// - ControlSet*\Services\bam\State\UserSettings (as of 1809), ControlSet*\Services\bam\UserSettings
+// - same under Services\dam
+//
+// value names are program paths with a volume device (\Device\HarddiskVolume3\Windows\...) or
+// application ids of packaged applications, the data starts with the FILETIME of the last execution.
+// Version and SequenceNumber values aren't programs.
+//
+use std::{cmp::Reverse, fmt};
+
+use crate::{artifacts::u64_at, hive::RegistryHive, utils::filetime_to_string, value::RegType};
+
+const USER_SETTINGS_KEYS: &[(&str, &str)] = &[
+    ("bam", "ControlSet*\\Services\\bam\\State\\UserSettings\\*"),
+    ("bam", "ControlSet*\\Services\\bam\\UserSettings\\*"),
+    ("dam", "ControlSet*\\Services\\dam\\State\\UserSettings\\*"),
+    ("dam", "ControlSet*\\Services\\dam\\UserSettings\\*"),
+];
+
+const NOT_PROGRAMS: &[&str] = &["Version", "SequenceNumber"];
+
+#[derive(Debug, Clone)]
+pub struct BamEntry {
+    // "bam" or "dam"
+    pub source: &'static str,
+
+    // key of the user
+    pub key_path: String,
+    pub sid: String,
+    pub program: String,
+
+    // FILETIME
+    pub last_executed: u64,
+}
+
+impl fmt::Display for BamEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}",
+            filetime_to_string(self.last_executed),
+            self.source,
+            self.sid,
+            self.program
+        )
+    }
+}
+
+// entries of all control sets, most recently executed first
+pub fn bam_entries(hive: &mut RegistryHive) -> anyhow::Result<Vec<BamEntry>> {
+    let mut entries = Vec::new();
+
+    for (source, pattern) in USER_SETTINGS_KEYS {
+        for (path, key) in hive.find_keys(pattern)? {
+            let sid = key.name().to_string();
+
+            for value in hive.values(&key)? {
+                if value.reg_type() != RegType::Binary
+                    || NOT_PROGRAMS
+                        .iter()
+                        .any(|n| n.eq_ignore_ascii_case(&value.name))
+                {
+                    continue;
+                }
+                let data = hive.value_bytes(&value)?;
+                let Ok(last_executed) = u64_at(&data, 0) else {
+                    continue;
+                };
+                entries.push(BamEntry {
+                    source,
+                    key_path: path.clone(),
+                    sid: sid.clone(),
+                    program: value.name.clone(),
+                    last_executed,
+                });
+            }
+        }
+    }
+
+    entries.sort_by_key(|e| Reverse(e.last_executed));
+    Ok(entries)
+}
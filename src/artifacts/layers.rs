@@ -0,0 +1,81 @@
+// AppCompatFlags\Layers: compatibility modes set on programs, from their properties dialog, by the
+// Program Compatibility Assistant or by installers
+//
+// locations:
+// - NTUSER.DAT: Software\Microsoft\Windows NT\CurrentVersion\AppCompatFlags\Layers, for the user
+// - SOFTWARE: Microsoft\Windows NT\CurrentVersion\AppCompatFlags\Layers, for all users
+//
+// value names are program paths, the data the layers applied (e.g. "~ RUNASADMIN WIN7RTM"), "~" only
+// telling the layers were set as of Windows 8. RUNASADMIN is also set by malware wanting its program
+// to be elevated each time it's started.
+//
+use std::fmt;
+
+use crate::{hive::RegistryHive, utils::filetime_to_string, value::RegData};
+
+const LAYERS_KEYS: &[&str] = &[
+    "Software\\Microsoft\\Windows NT\\CurrentVersion\\AppCompatFlags\\Layers",
+    "Microsoft\\Windows NT\\CurrentVersion\\AppCompatFlags\\Layers",
+];
+
+const RUN_AS_ADMIN: &str = "RUNASADMIN";
+
+#[derive(Debug, Clone)]
+pub struct LayersEntry {
+    pub key_path: String,
+    pub program: String,
+    pub layers: Vec<String>,
+
+    // last written time of the Layers key
+    pub key_timestamp: u64,
+}
+
+impl LayersEntry {
+    pub fn runs_as_admin(&self) -> bool {
+        self.layers
+            .iter()
+            .any(|l| l.eq_ignore_ascii_case(RUN_AS_ADMIN))
+    }
+}
+
+impl fmt::Display for LayersEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}",
+            filetime_to_string(self.key_timestamp),
+            self.program,
+            self.layers.join(" ")
+        )
+    }
+}
+
+// entries in the order of the values
+pub fn layers(hive: &mut RegistryHive) -> anyhow::Result<Vec<LayersEntry>> {
+    let mut entries = Vec::new();
+
+    for location in LAYERS_KEYS {
+        let Some((key_path, key)) = hive.open_key_path(location)? else {
+            continue;
+        };
+
+        for value in hive.values(&key)? {
+            let data = match hive.value_data(&value)? {
+                RegData::Sz(s) | RegData::ExpandSz(s) => s,
+                _ => continue,
+            };
+            entries.push(LayersEntry {
+                key_path: key_path.clone(),
+                program: value.name.clone(),
+                layers: data
+                    .split_whitespace()
+                    .filter(|l| *l != "~")
+                    .map(String::from)
+                    .collect(),
+                key_timestamp: key.node.header.last_written_timestamp,
+            });
+        }
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,213 @@
+// Most recently used (MRU) lists of NTUSER.DAT hives
+//
+// - RecentDocs (and one subkey per extension): files and folders opened, the value data is the
+//   UTF-16 name followed by a shell item
+// - ComDlg32\OpenSavePidlMRU\<extension>: files opened or saved with the common dialogs, the value data
+//   is an item ID list
+// - RunMRU: commands typed in the Run dialog, strings ending with "\1"
+// - Internet Explorer\TypedURLs: URLs typed in the address bar, url1 being the most recent. As of
+//   Windows 8, TypedURLsTime has the FILETIME of each of them.
+//
+// values of the other lists are ordered by MRUListEx (binary list of value indexes) or MRUList (string
+// of value names). Only the time of the most recent entry is known: it's the last written time of the
+// key.
+//
+use std::fmt;
+
+use crate::{
+    artifacts::{
+        mru_list_ex,
+        shellitem::{ShellItem, item_id_list},
+        u64_at, utf16_at,
+    },
+    hive::{Key, RegistryHive, names_equal},
+    utils::filetime_to_string,
+    value::RegData,
+};
+
+const EXPLORER_KEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Explorer";
+const TYPED_URLS_KEY: &str = "Software\\Microsoft\\Internet Explorer\\TypedURLs";
+const TYPED_URLS_TIME_KEY: &str = "Software\\Microsoft\\Internet Explorer\\TypedURLsTime";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MruKind {
+    RecentDocs,
+    OpenSave,
+    Run,
+    TypedUrls,
+}
+
+impl fmt::Display for MruKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MruKind::RecentDocs => "RecentDocs",
+            MruKind::OpenSave => "OpenSavePidlMRU",
+            MruKind::Run => "RunMRU",
+            MruKind::TypedUrls => "TypedURLs",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MruEntry {
+    pub kind: MruKind,
+    pub key_path: String,
+    pub value_name: String,
+
+    // 0 for the most recently used entry of the list
+    pub position: usize,
+
+    // file name, path, command or URL
+    pub item: String,
+
+    // FILETIME of the last use, 0 if unknown
+    pub timestamp: u64,
+}
+
+impl fmt::Display for MruEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let timestamp = if self.timestamp == 0 {
+            "-".to_string()
+        } else {
+            filetime_to_string(self.timestamp)
+        };
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{timestamp}\t{}",
+            self.kind, self.key_path, self.position, self.value_name, self.item
+        )
+    }
+}
+
+// entries of all lists, each list in MRU order
+pub fn mru_lists(hive: &mut RegistryHive) -> anyhow::Result<Vec<MruEntry>> {
+    let mut entries = Vec::new();
+
+    let lists = [
+        (MruKind::RecentDocs, format!("{EXPLORER_KEY}\\RecentDocs")),
+        (
+            MruKind::RecentDocs,
+            format!("{EXPLORER_KEY}\\RecentDocs\\*"),
+        ),
+        (
+            MruKind::OpenSave,
+            format!("{EXPLORER_KEY}\\ComDlg32\\OpenSavePidlMRU\\*"),
+        ),
+        (MruKind::Run, format!("{EXPLORER_KEY}\\RunMRU")),
+        (MruKind::TypedUrls, TYPED_URLS_KEY.to_string()),
+    ];
+    for (kind, pattern) in lists {
+        for (key_path, key) in hive.find_keys(&pattern)? {
+            mru_list(hive, kind, &key_path, &key, &mut entries)?;
+        }
+    }
+
+    Ok(entries)
+}
+
+fn mru_list(
+    hive: &mut RegistryHive,
+    kind: MruKind,
+    key_path: &str,
+    key: &Key,
+    entries: &mut Vec<MruEntry>,
+) -> anyhow::Result<()> {
+    let values = hive.values(key)?;
+
+    // names of the values in MRU order
+    let mut order: Vec<String> = Vec::new();
+    for value in &values {
+        if names_equal(&value.name, "MRUListEx") {
+            order = mru_list_ex(&hive.value_bytes(value)?)
+                .iter()
+                .map(|i| i.to_string())
+                .collect();
+        } else if names_equal(&value.name, "MRUList")
+            && let RegData::Sz(list) = hive.value_data(value)?
+        {
+            order = list.chars().map(String::from).collect();
+        }
+    }
+    if kind == MruKind::TypedUrls {
+        let mut urls: Vec<(u32, String)> = values
+            .iter()
+            .filter_map(|v| {
+                let index = v.name.to_lowercase().strip_prefix("url")?.parse().ok()?;
+                Some((index, v.name.clone()))
+            })
+            .collect();
+        urls.sort();
+        order = urls.into_iter().map(|(_, name)| name).collect();
+    }
+
+    let typed_urls_time = if kind == MruKind::TypedUrls {
+        hive.open_key(TYPED_URLS_TIME_KEY)?
+    } else {
+        None
+    };
+
+    for (position, name) in order.iter().enumerate() {
+        let Some(value) = values.iter().find(|v| names_equal(&v.name, name)) else {
+            continue;
+        };
+        let data = hive.value_bytes(value)?;
+        let item = match kind {
+            MruKind::RecentDocs => {
+                let name = utf16_at(&data, 0, data.len())?;
+                // the shell item following the name is used when there's no name
+                if name.is_empty() {
+                    item_id_list(data.get(2..).unwrap_or_default())
+                        .map(|items| pidl_path(&items))
+                        .unwrap_or_default()
+                } else {
+                    name
+                }
+            }
+            MruKind::OpenSave => item_id_list(&data)
+                .map(|items| pidl_path(&items))
+                .unwrap_or_default(),
+            MruKind::Run | MruKind::TypedUrls => match hive.value_data(value)? {
+                RegData::Sz(s) | RegData::ExpandSz(s) => s.trim_end_matches("\\1").to_string(),
+                _ => continue,
+            },
+        };
+
+        let mut timestamp = if position == 0 {
+            key.node.header.last_written_timestamp
+        } else {
+            0
+        };
+        if let Some(times) = &typed_urls_time
+            && let Some(time) = hive
+                .values(times)?
+                .into_iter()
+                .find(|v| names_equal(&v.name, name))
+        {
+            timestamp = u64_at(&hive.value_bytes(&time)?, 0).unwrap_or(0);
+        }
+
+        entries.push(MruEntry {
+            kind,
+            key_path: key_path.to_string(),
+            value_name: value.name.clone(),
+            position,
+            item,
+            timestamp,
+        });
+    }
+
+    Ok(())
+}
+
+// path made of the names of the items of an item ID list
+fn pidl_path(items: &[ShellItem]) -> String {
+    let mut path = String::new();
+    for item in items {
+        if !path.is_empty() && !path.ends_with('\\') {
+            path.push('\\');
+        }
+        path.push_str(&item.name());
+    }
+    path
+}
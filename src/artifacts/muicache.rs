@@ -0,0 +1,104 @@
+// MUICache: display names of the programs run by the user, cached by the shell when they were first
+// executed
+//
+// locations:
+// - NTUSER.DAT: Software\Microsoft\Windows\ShellNoRoam\MUICache (XP), value names are program paths
+//   and the data their friendly names
+// - UsrClass.dat: Local Settings\Software\Microsoft\Windows\Shell\MuiCache (as of Vista), value names
+//   are program paths followed by .FriendlyAppName or .ApplicationCompany
+//
+// values whose names start with "@" are resource strings of DLLs, not programs, and LangID is the
+// language of the cache. Entries have no time of their own: the last written time of the key is the
+// time of the most recent one.
+//
+use std::fmt;
+
+use crate::{hive::RegistryHive, utils::filetime_to_string, value::RegData};
+
+const MUICACHE_KEYS: &[&str] = &[
+    "Software\\Microsoft\\Windows\\ShellNoRoam\\MUICache",
+    "Local Settings\\Software\\Microsoft\\Windows\\Shell\\MuiCache",
+];
+
+const FRIENDLY_APP_NAME: &str = ".FriendlyAppName";
+const APPLICATION_COMPANY: &str = ".ApplicationCompany";
+
+#[derive(Debug, Clone)]
+pub struct MuiCacheEntry {
+    pub key_path: String,
+    pub program: String,
+    pub friendly_name: Option<String>,
+
+    // only recorded as of Vista
+    pub company: Option<String>,
+
+    // last written time of the MUICache key
+    pub key_timestamp: u64,
+}
+
+impl fmt::Display for MuiCacheEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}",
+            filetime_to_string(self.key_timestamp),
+            self.program,
+            self.friendly_name.as_deref().unwrap_or("-"),
+            self.company.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+// entries in the order of the values
+pub fn muicache(hive: &mut RegistryHive) -> anyhow::Result<Vec<MuiCacheEntry>> {
+    let mut entries: Vec<MuiCacheEntry> = Vec::new();
+
+    for location in MUICACHE_KEYS {
+        let Some((key_path, key)) = hive.open_key_path(location)? else {
+            continue;
+        };
+
+        for value in hive.values(&key)? {
+            let name = value.name.as_str();
+            if name.starts_with('@') || name.eq_ignore_ascii_case("LangID") {
+                continue;
+            }
+            let data = match hive.value_data(&value)? {
+                RegData::Sz(s) | RegData::ExpandSz(s) => s,
+                _ => continue,
+            };
+
+            let (program, company) = if let Some(program) = name.strip_suffix(FRIENDLY_APP_NAME) {
+                (program, false)
+            } else if let Some(program) = name.strip_suffix(APPLICATION_COMPANY) {
+                (program, true)
+            } else {
+                (name, false)
+            };
+
+            let index = match entries
+                .iter()
+                .position(|e| e.key_path == key_path && e.program == program)
+            {
+                Some(index) => index,
+                None => {
+                    entries.push(MuiCacheEntry {
+                        key_path: key_path.clone(),
+                        program: program.to_string(),
+                        friendly_name: None,
+                        company: None,
+                        key_timestamp: key.node.header.last_written_timestamp,
+                    });
+                    entries.len() - 1
+                }
+            };
+            if company {
+                entries[index].company = Some(data);
+            } else {
+                entries[index].friendly_name = Some(data);
+            }
+        }
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,276 @@
+// Network configuration and the networks the system connected to
+//
+// in SYSTEM hives, each subkey of Services\Tcpip\Parameters\Interfaces of the current control set is
+// a network interface named after its GUID, with either a static configuration (IPAddress,
+// SubnetMask, DefaultGateway, NameServer) or the one given by a DHCP server (DhcpIPAddress...).
+//
+// in SOFTWARE hives, Microsoft\Windows NT\CurrentVersion\NetworkList has:
+// - Profiles\{GUID}: name of the network (SSID for wireless ones), category, creation and last
+//   connection times as local time SYSTEMTIME structures
+// - Signatures\Managed and Signatures\Unmanaged: the gateway MAC address and DNS suffix of each
+//   profile
+//
+use std::fmt;
+
+use crate::{
+    artifacts::{
+        current_control_set, dword_value, string_value, systemtime_to_filetime, value_data,
+    },
+    hive::{Key, RegistryHive, join_path},
+    utils::{filetime_to_string, unix_to_filetime},
+    value::RegData,
+};
+
+const INTERFACES: &str = "Services\\Tcpip\\Parameters\\Interfaces";
+const PROFILES: &str = "Microsoft\\Windows NT\\CurrentVersion\\NetworkList\\Profiles";
+const SIGNATURES: &str = "Microsoft\\Windows NT\\CurrentVersion\\NetworkList\\Signatures\\*\\*";
+
+#[derive(Debug, Clone, Default)]
+pub struct NetworkInterface {
+    pub key_path: String,
+    pub guid: String,
+    pub timestamp: u64,
+    pub dhcp: bool,
+
+    pub ip_addresses: Vec<String>,
+    pub subnet_masks: Vec<String>,
+    pub gateways: Vec<String>,
+    pub dns_servers: Vec<String>,
+    pub domain: Option<String>,
+
+    pub dhcp_server: Option<String>,
+    // FILETIME, 0 if unknown
+    pub lease_obtained: u64,
+    pub lease_expires: u64,
+}
+
+impl fmt::Display for NetworkInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |l: &[String]| {
+            if l.is_empty() {
+                "-".to_string()
+            } else {
+                l.join(", ")
+            }
+        };
+
+        writeln!(f, "{}", self.guid)?;
+        writeln!(
+            f,
+            "    key last written: {}",
+            filetime_to_string(self.timestamp)
+        )?;
+        writeln!(
+            f,
+            "    configuration: {}",
+            if self.dhcp { "DHCP" } else { "static" }
+        )?;
+        writeln!(f, "    IP addresses: {}", list(&self.ip_addresses))?;
+        writeln!(f, "    subnet masks: {}", list(&self.subnet_masks))?;
+        writeln!(f, "    gateways: {}", list(&self.gateways))?;
+        writeln!(f, "    DNS servers: {}", list(&self.dns_servers))?;
+        writeln!(f, "    domain: {}", self.domain.as_deref().unwrap_or("-"))?;
+        write!(
+            f,
+            "    DHCP server: {}",
+            self.dhcp_server.as_deref().unwrap_or("-")
+        )?;
+        if self.lease_obtained != 0 {
+            write!(
+                f,
+                "\n    lease: {} to {}",
+                filetime_to_string(self.lease_obtained),
+                filetime_to_string(self.lease_expires)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NetworkProfile {
+    pub guid: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub category: Option<u32>,
+    pub name_type: Option<u32>,
+
+    // local time FILETIME, 0 if unknown
+    pub created: u64,
+    pub last_connected: u64,
+
+    // from the signature of the profile
+    pub gateway_mac: Option<String>,
+    pub dns_suffix: Option<String>,
+}
+
+impl NetworkProfile {
+    pub fn category_name(&self) -> &'static str {
+        match self.category {
+            Some(0) => "public",
+            Some(1) => "private",
+            Some(2) => "domain",
+            Some(_) => "unknown",
+            None => "-",
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self.name_type {
+            Some(0x06) => "wired",
+            Some(0x17) => "broadband",
+            Some(0x47) => "wireless",
+            Some(0xF3) => "mobile",
+            Some(_) => "unknown",
+            None => "-",
+        }
+    }
+}
+
+impl fmt::Display for NetworkProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |t: u64| {
+            if t == 0 {
+                "-".to_string()
+            } else {
+                filetime_to_string(t)
+            }
+        };
+
+        // times are local ones
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.guid,
+            self.name,
+            self.kind(),
+            self.category_name(),
+            time(self.created),
+            time(self.last_connected),
+            self.gateway_mac.as_deref().unwrap_or("-"),
+            self.dns_suffix.as_deref().unwrap_or("-"),
+            self.description.as_deref().unwrap_or("-")
+        )
+    }
+}
+
+// interfaces of the current control set of a SYSTEM hive, none if it's not one
+pub fn interfaces(hive: &mut RegistryHive) -> anyhow::Result<Vec<NetworkInterface>> {
+    let mut interfaces = Vec::new();
+    let Some((control_set, _)) = current_control_set(hive)? else {
+        return Ok(interfaces);
+    };
+    let Some((interfaces_path, interfaces_key)) =
+        hive.open_key_path(&join_path(&control_set, INTERFACES))?
+    else {
+        return Ok(interfaces);
+    };
+
+    for key in hive.subkeys(&interfaces_key)? {
+        let dhcp = dword_value(hive, &key, "EnableDHCP")? == Some(1);
+        let mut interface = NetworkInterface {
+            key_path: join_path(&interfaces_path, key.name()),
+            guid: key.name().to_string(),
+            timestamp: key.node.header.last_written_timestamp,
+            dhcp,
+            ..Default::default()
+        };
+
+        // DHCP values are kept when switching to a static configuration
+        let prefix = if dhcp { "Dhcp" } else { "" };
+        interface.ip_addresses = addresses(hive, &key, &format!("{prefix}IPAddress"))?;
+        interface.subnet_masks = addresses(hive, &key, &format!("{prefix}SubnetMask"))?;
+        interface.gateways = addresses(hive, &key, &format!("{prefix}DefaultGateway"))?;
+
+        // static DNS servers take precedence
+        interface.dns_servers = addresses(hive, &key, "NameServer")?;
+        if interface.dns_servers.is_empty() {
+            interface.dns_servers = addresses(hive, &key, "DhcpNameServer")?;
+        }
+        interface.domain = match string_value(hive, &key, "Domain")? {
+            Some(domain) if !domain.is_empty() => Some(domain),
+            _ => string_value(hive, &key, "DhcpDomain")?.filter(|d| !d.is_empty()),
+        };
+
+        interface.dhcp_server = string_value(hive, &key, "DhcpServer")?;
+        if let Some(obtained) = dword_value(hive, &key, "LeaseObtainedTime")? {
+            interface.lease_obtained = unix_to_filetime(obtained as i64);
+        }
+        if let Some(expires) = dword_value(hive, &key, "LeaseTerminatesTime")? {
+            interface.lease_expires = unix_to_filetime(expires as i64);
+        }
+
+        // interfaces never configured
+        if interface.ip_addresses.is_empty() && interface.dhcp_server.is_none() {
+            continue;
+        }
+        interfaces.push(interface);
+    }
+
+    Ok(interfaces)
+}
+
+// network profiles of a SOFTWARE hive, none if it's not one
+pub fn profiles(hive: &mut RegistryHive) -> anyhow::Result<Vec<NetworkProfile>> {
+    let mut profiles = Vec::new();
+    let Some(profiles_key) = hive.open_key(PROFILES)? else {
+        return Ok(profiles);
+    };
+
+    for key in hive.subkeys(&profiles_key)? {
+        let time = |data: Option<RegData>| match data {
+            Some(RegData::Binary(data)) => systemtime_to_filetime(&data),
+            _ => 0,
+        };
+
+        profiles.push(NetworkProfile {
+            guid: key.name().to_string(),
+            name: string_value(hive, &key, "ProfileName")?.unwrap_or_default(),
+            description: string_value(hive, &key, "Description")?,
+            category: dword_value(hive, &key, "Category")?,
+            name_type: dword_value(hive, &key, "NameType")?,
+            created: time(value_data(hive, &key, "DateCreated")?),
+            last_connected: time(value_data(hive, &key, "DateLastConnected")?),
+            ..Default::default()
+        });
+    }
+
+    // signatures refer to their profile by its GUID
+    for (_, key) in hive.find_keys(SIGNATURES)? {
+        let Some(guid) = string_value(hive, &key, "ProfileGuid")? else {
+            continue;
+        };
+        let Some(profile) = profiles
+            .iter_mut()
+            .find(|p| p.guid.eq_ignore_ascii_case(&guid))
+        else {
+            continue;
+        };
+
+        if let Some(RegData::Binary(mac)) = value_data(hive, &key, "DefaultGatewayMac")?
+            && !mac.is_empty()
+        {
+            let mac: Vec<_> = mac.iter().map(|b| format!("{b:02X}")).collect();
+            profile.gateway_mac = Some(mac.join(":"));
+        }
+        profile.dns_suffix = string_value(hive, &key, "DnsSuffix")?.filter(|s| s != "<none>");
+    }
+
+    Ok(profiles)
+}
+
+// addresses of a REG_MULTI_SZ value or of a REG_SZ one separated by commas or spaces, the 0.0.0.0
+// placeholders are skipped
+fn addresses(hive: &mut RegistryHive, key: &Key, name: &str) -> anyhow::Result<Vec<String>> {
+    let list = match value_data(hive, key, name)? {
+        Some(RegData::MultiSz(list)) => list,
+        Some(RegData::Sz(s)) => s.split([',', ' ']).map(str::to_string).collect::<Vec<_>>(),
+        _ => Vec::new(),
+    };
+
+    Ok(list
+        .into_iter()
+        .map(|a| a.trim().to_string())
+        .filter(|a| !a.is_empty() && a != "0.0.0.0")
+        .collect())
+}
@@ -0,0 +1,152 @@
+// Local user accounts of a SAM hive
+//
+// each user has a subkey of SAM\Domains\Account\Users named after its RID in hexadecimal, with two
+// binary values:
+//
+// - F: fixed size structure with the timestamps, the RID, the account flags and the counters
+//
+//   0x08 last logon   0x18 password last set   0x20 account expires   0x28 last failed logon
+//   0x30 RID   0x38 flags   0x40 bad password count   0x42 logon count
+//
+// - V: table of (offset, length, unknown) entries of 12 bytes, then the data they point to starting
+//   at 0xCC: user name (entry 1), full name (entry 2), comment (entry 3)...
+//
+use std::fmt;
+
+use crate::{
+    artifacts::{u16_at, u32_at, u64_at, utf16_at},
+    hive::{RegistryHive, names_equal},
+    utils::filetime_to_string,
+};
+
+const USERS_KEY: &str = "SAM\\Domains\\Account\\Users";
+
+// V data starts after the entries table
+const V_DATA_OFFSET: usize = 0xCC;
+
+// account control flags
+const ACCOUNT_FLAGS: &[(u16, &str)] = &[
+    (0x0001, "disabled"),
+    (0x0002, "home directory required"),
+    (0x0004, "password not required"),
+    (0x0008, "temporary duplicate account"),
+    (0x0010, "normal account"),
+    (0x0020, "MNS logon account"),
+    (0x0040, "interdomain trust account"),
+    (0x0080, "workstation trust account"),
+    (0x0100, "server trust account"),
+    (0x0200, "password doesn't expire"),
+    (0x0400, "locked out"),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct SamUser {
+    pub rid: u32,
+    pub name: String,
+    pub full_name: String,
+    pub comment: String,
+    pub flags: u16,
+
+    // FILETIME, 0 if never
+    pub last_logon: u64,
+    pub password_last_set: u64,
+    pub account_expires: u64,
+    pub last_failed_logon: u64,
+
+    pub bad_password_count: u16,
+    pub logon_count: u16,
+}
+
+impl SamUser {
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        ACCOUNT_FLAGS
+            .iter()
+            .filter(|(flag, _)| self.flags & flag != 0)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl fmt::Display for SamUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never set or never expiring
+        let time = |t: u64| {
+            if t == 0 || t == i64::MAX as u64 {
+                "never".to_string()
+            } else {
+                filetime_to_string(t)
+            }
+        };
+
+        writeln!(f, "{} {}", self.rid, self.name)?;
+        writeln!(f, "    full name: {}", self.full_name)?;
+        writeln!(f, "    comment: {}", self.comment)?;
+        writeln!(
+            f,
+            "    flags: 0x{:04X} {}",
+            self.flags,
+            self.flag_names().join(", ")
+        )?;
+        writeln!(f, "    last logon: {}", time(self.last_logon))?;
+        writeln!(f, "    password last set: {}", time(self.password_last_set))?;
+        writeln!(f, "    account expires: {}", time(self.account_expires))?;
+        writeln!(f, "    last failed logon: {}", time(self.last_failed_logon))?;
+        writeln!(f, "    bad password count: {}", self.bad_password_count)?;
+        write!(f, "    logon count: {}", self.logon_count)
+    }
+}
+
+pub fn sam_users(hive: &mut RegistryHive) -> anyhow::Result<Vec<SamUser>> {
+    let mut users = Vec::new();
+
+    let Some(users_key) = hive.open_key(USERS_KEY)? else {
+        anyhow::bail!("key '{USERS_KEY}' not found, not a SAM hive");
+    };
+
+    for key in hive.subkeys(&users_key)? {
+        // the Names subkey maps names to RIDs
+        let Ok(rid) = u32::from_str_radix(key.name(), 16) else {
+            continue;
+        };
+
+        let mut user = SamUser {
+            rid,
+            ..Default::default()
+        };
+        for value in hive.values(&key)? {
+            let data = hive.value_bytes(&value)?;
+            if names_equal(&value.name, "F") {
+                parse_f(&data, &mut user)?;
+            } else if names_equal(&value.name, "V") {
+                parse_v(&data, &mut user)?;
+            }
+        }
+        users.push(user);
+    }
+
+    Ok(users)
+}
+
+fn parse_f(data: &[u8], user: &mut SamUser) -> anyhow::Result<()> {
+    user.last_logon = u64_at(data, 0x08)?;
+    user.password_last_set = u64_at(data, 0x18)?;
+    user.account_expires = u64_at(data, 0x20)?;
+    user.last_failed_logon = u64_at(data, 0x28)?;
+    user.flags = u16_at(data, 0x38)?;
+    user.bad_password_count = u16_at(data, 0x40)?;
+    user.logon_count = u16_at(data, 0x42)?;
+    Ok(())
+}
+
+fn parse_v(data: &[u8], user: &mut SamUser) -> anyhow::Result<()> {
+    let string = |index: usize| -> anyhow::Result<String> {
+        let offset = u32_at(data, index * 12)? as usize;
+        let len = u32_at(data, index * 12 + 4)? as usize;
+        utf16_at(data, V_DATA_OFFSET + offset, len)
+    };
+
+    user.name = string(1)?;
+    user.full_name = string(2)?;
+    user.comment = string(3)?;
+    Ok(())
+}
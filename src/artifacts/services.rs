@@ -0,0 +1,187 @@
+// Services and drivers of the control sets of a SYSTEM hive
+//
+// each subkey of ControlSet*\Services is a service or a driver: Start and Type tell how and when it's
+// loaded, ImagePath the executable or driver and ObjectName the account it runs as. Services hosted by
+// svchost.exe load the DLL given by Parameters\ServiceDll.
+//
+use std::fmt;
+
+use crate::{
+    artifacts::{dword_value, string_value},
+    hive::{RegistryHive, join_path},
+    utils::filetime_to_string,
+};
+
+const SERVICES_KEYS: &str = "ControlSet*\\Services";
+
+// FILETIME ticks (100 ns) in a day
+const FILETIME_DAY: u64 = 864_000_000_000;
+
+// locations of the binaries installed with Windows or by an installer
+const TRUSTED_PREFIXES: &[&str] = &[
+    "\\systemroot\\",
+    "system32\\",
+    "syswow64\\",
+    "%systemroot%\\",
+    "%windir%\\",
+    "c:\\windows\\",
+    "\\??\\c:\\windows\\",
+    "%programfiles%\\",
+    "%programfiles(x86)%\\",
+    "c:\\program files\\",
+    "c:\\program files (x86)\\",
+];
+
+// locations writable by any user, where installers don't put binaries
+const SUSPICIOUS_DIRECTORIES: &[&str] = &[
+    "\\temp\\",
+    "\\tmp\\",
+    "\\appdata\\",
+    "\\users\\public\\",
+    "\\downloads\\",
+    "\\recycle",
+    "\\perflogs\\",
+];
+
+#[derive(Debug, Clone)]
+pub struct Service {
+    // path of the service key
+    pub path: String,
+    pub name: String,
+    pub timestamp: u64,
+
+    pub start: Option<u32>,
+    pub service_type: Option<u32>,
+    pub image_path: Option<String>,
+    pub service_dll: Option<String>,
+    pub account: Option<String>,
+
+    // last written within the recent period
+    pub recent: bool,
+
+    // why the image path or service DLL look suspicious
+    pub suspicious: Option<&'static str>,
+}
+
+impl Service {
+    pub fn start_name(&self) -> &'static str {
+        match self.start {
+            Some(0) => "boot",
+            Some(1) => "system",
+            Some(2) => "auto",
+            Some(3) => "manual",
+            Some(4) => "disabled",
+            Some(_) => "unknown",
+            None => "-",
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.service_type {
+            Some(0x1) => "kernel driver",
+            Some(0x2) => "file system driver",
+            Some(0x8) => "recognizer driver",
+            Some(0x10) => "own process",
+            Some(0x20) => "share process",
+            Some(0x50) | Some(0x60) => "user service",
+            Some(0x110) => "own process, interactive",
+            Some(0x120) => "share process, interactive",
+            Some(_) => "unknown",
+            None => "-",
+        }
+    }
+}
+
+impl fmt::Display for Service {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut flags = Vec::new();
+        if self.recent {
+            flags.push("recently modified");
+        }
+        if let Some(reason) = self.suspicious {
+            flags.push(reason);
+        }
+
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.path,
+            filetime_to_string(self.timestamp),
+            self.start_name(),
+            self.type_name(),
+            self.account.as_deref().unwrap_or("-"),
+            self.image_path.as_deref().unwrap_or("-"),
+            self.service_dll.as_deref().unwrap_or("-"),
+            flags.join(", ")
+        )
+    }
+}
+
+// services of all control sets, recent ones are those last written less than recent_days before the
+// hive itself
+pub fn services(hive: &mut RegistryHive, recent_days: u64) -> anyhow::Result<Vec<Service>> {
+    let mut services = Vec::new();
+    let recent_limit = hive
+        .base_block
+        .last_written_timestamp
+        .saturating_sub(recent_days * FILETIME_DAY);
+
+    for (services_path, services_key) in hive.find_keys(SERVICES_KEYS)? {
+        for key in hive.subkeys(&services_key)? {
+            let service_dll = match hive
+                .subkeys(&key)?
+                .into_iter()
+                .find(|k| k.name().eq_ignore_ascii_case("Parameters"))
+            {
+                Some(parameters) => string_value(hive, &parameters, "ServiceDll")?,
+                None => None,
+            };
+            let image_path = string_value(hive, &key, "ImagePath")?;
+
+            let suspicious = [&image_path, &service_dll]
+                .into_iter()
+                .flatten()
+                .find_map(|p| suspicious_path(p));
+
+            services.push(Service {
+                path: join_path(&services_path, key.name()),
+                name: key.name().to_string(),
+                timestamp: key.node.header.last_written_timestamp,
+                start: dword_value(hive, &key, "Start")?,
+                service_type: dword_value(hive, &key, "Type")?,
+                image_path,
+                service_dll,
+                account: string_value(hive, &key, "ObjectName")?,
+                recent: key.node.header.last_written_timestamp > recent_limit,
+                suspicious,
+            });
+        }
+    }
+
+    Ok(services)
+}
+
+// reason why a binary path doesn't look like the one of an installed binary, None if it does
+pub fn suspicious_path(path: &str) -> Option<&'static str> {
+    let lower = path.trim().trim_start_matches('"').to_lowercase();
+    if lower.is_empty() {
+        return None;
+    }
+
+    if SUSPICIOUS_DIRECTORIES.iter().any(|d| lower.contains(d)) {
+        return Some("user writable location");
+    }
+    if !TRUSTED_PREFIXES.iter().any(|p| lower.starts_with(p)) {
+        return Some("outside of system and program directories");
+    }
+
+    // arguments may follow the binary name
+    if ![".exe", ".sys", ".dll"]
+        .iter()
+        .any(|ext| lower.contains(ext))
+    {
+        return Some("no executable extension");
+    }
+
+    None
+}
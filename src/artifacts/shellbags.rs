@@ -0,0 +1,174 @@
+// ShellBags: folders browsed with Explorer, kept after the folders are deleted
+//
+// BagMRU is a tree mirroring the shell namespace. The numbered values of each of its keys are shell
+// items for the child folders, the subkey with the same number holds the children of that folder,
+// MRUListEx gives the order in which they were browsed and NodeSlot points to the view settings in
+// Bags\<slot>. The last written time of a BagMRU key is the last time one of its children was added
+// or browsed.
+//
+// locations:
+// - NTUSER.DAT: Software\Microsoft\Windows\Shell\BagMRU (ShellNoRoam on XP)
+// - UsrClass.dat: Local Settings\Software\Microsoft\Windows\Shell\BagMRU (as of Vista)
+//
+use std::fmt;
+
+use crate::{
+    artifacts::{
+        mru_list_ex,
+        shellitem::{ShellItem, item_id_list},
+    },
+    hive::{Key, MAX_DEPTH, RegistryHive, join_path, names_equal},
+    utils::filetime_to_string,
+};
+
+const SHELL_KEYS: &[&str] = &[
+    "Software\\Microsoft\\Windows\\Shell",
+    "Software\\Microsoft\\Windows\\ShellNoRoam",
+    "Local Settings\\Software\\Microsoft\\Windows\\Shell",
+];
+
+#[derive(Debug, Clone)]
+pub struct ShellBag {
+    // BagMRU key and value holding the shell item
+    pub key_path: String,
+    pub value_name: String,
+
+    // last written time of the BagMRU key holding the value
+    pub key_timestamp: u64,
+
+    // 0 for the most recently browsed child of the parent folder, None if not in MRUListEx
+    pub mru_position: Option<usize>,
+
+    // Bags subkey with the view settings
+    pub node_slot: Option<u32>,
+
+    // last written time of the Bags\<slot> key, 0 if not found
+    pub slot_timestamp: u64,
+
+    // path rebuilt from the shell items of the ancestors
+    pub path: String,
+    pub item: ShellItem,
+}
+
+impl fmt::Display for ShellBag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |t: u64| {
+            if t == 0 {
+                "-".to_string()
+            } else {
+                filetime_to_string(t)
+            }
+        };
+        let (modified, created, accessed) = self.item.times();
+        write!(
+            f,
+            "{}\\{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.key_path,
+            self.value_name,
+            self.mru_position.map_or("-".to_string(), |p| p.to_string()),
+            time(self.key_timestamp),
+            time(self.slot_timestamp),
+            time(modified),
+            time(created),
+            time(accessed),
+            self.path
+        )
+    }
+}
+
+pub fn shellbags(hive: &mut RegistryHive) -> anyhow::Result<Vec<ShellBag>> {
+    let mut bags = Vec::new();
+
+    for shell_key in SHELL_KEYS {
+        let Some((shell_path, _)) = hive.open_key_path(shell_key)? else {
+            continue;
+        };
+        let Some((bagmru_path, bagmru)) = hive.open_key_path(&join_path(&shell_path, "BagMRU"))?
+        else {
+            continue;
+        };
+        let bags_path = join_path(&shell_path, "Bags");
+
+        walk_bagmru(hive, &bagmru, &bagmru_path, "", &bags_path, 0, &mut bags)?;
+    }
+
+    Ok(bags)
+}
+
+fn walk_bagmru(
+    hive: &mut RegistryHive,
+    key: &Key,
+    key_path: &str,
+    parent_path: &str,
+    bags_path: &str,
+    depth: usize,
+    bags: &mut Vec<ShellBag>,
+) -> anyhow::Result<()> {
+    if depth > MAX_DEPTH {
+        return Ok(());
+    }
+
+    let values = hive.values(key)?;
+    let subkeys = hive.subkeys(key)?;
+
+    let mut mru = Vec::new();
+    for value in &values {
+        if names_equal(&value.name, "MRUListEx") {
+            mru = mru_list_ex(&hive.value_bytes(value)?);
+        }
+    }
+
+    for value in &values {
+        let Ok(index) = value.name.parse::<u32>() else {
+            continue;
+        };
+        let data = hive.value_bytes(value)?;
+        let Some(item) = item_id_list(&data).ok().and_then(|l| l.into_iter().next()) else {
+            continue;
+        };
+
+        let path = if parent_path.is_empty() {
+            item.name()
+        } else {
+            format!("{}\\{}", parent_path.trim_end_matches('\\'), item.name())
+        };
+
+        // the subkey of the folder holds its node slot and its own children
+        let subkey = subkeys.iter().find(|k| k.name() == value.name);
+        let mut node_slot = None;
+        if let Some(subkey) = subkey {
+            for v in hive.values(subkey)? {
+                if names_equal(&v.name, "NodeSlot")
+                    && let Ok(slot) = hive.value_bytes(&v)
+                    && let Some(slot) = slot.get(..4)
+                {
+                    node_slot = Some(u32::from_le_bytes(slot.try_into().unwrap()));
+                }
+            }
+        }
+        let slot_timestamp = match node_slot {
+            Some(slot) => hive
+                .open_key(&join_path(bags_path, &slot.to_string()))?
+                .map_or(0, |k| k.node.header.last_written_timestamp),
+            None => 0,
+        };
+
+        bags.push(ShellBag {
+            key_path: key_path.to_string(),
+            value_name: value.name.clone(),
+            key_timestamp: key.node.header.last_written_timestamp,
+            mru_position: mru.iter().position(|i| *i == index),
+            node_slot,
+            slot_timestamp,
+            path: path.clone(),
+            item,
+        });
+
+        if let Some(subkey) = subkey {
+            let subpath = join_path(key_path, subkey.name());
+            walk_bagmru(hive, subkey, &subpath, &path, bags_path, depth + 1, bags)?;
+        }
+    }
+
+    Ok(())
+}
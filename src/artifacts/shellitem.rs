@@ -0,0 +1,224 @@
+// Shell items: the elements of the item ID lists (PIDL) Explorer uses to identify folders and files
+//
+// a shell item starts with its size and a class type, the layout of the rest depends on the class:
+//
+// +------+------------+----------------------------------------+
+// | size | class type |            class specific              |
+// +------+------------+----------------------------------------+
+// 0      2            3
+//
+// - 0x1F root folder: sort index and the GUID of a shell folder (My Computer, Control Panel...)
+// - 0x20-0x2F volume: drive letter as an ASCII string ("C:\")
+// - 0x30-0x3F file entry: size, modification time, attributes, 8.3 name and an extension block
+//   (0xBEEF0004) holding the creation and access times and the long name
+// - 0x40-0x4F network location: UNC path
+// - 0x61 URI
+//
+// see: https://github.com/libyal/libfwsi/blob/main/documentation/Windows%20Shell%20Item%20format.asciidoc
+//
+use std::fmt;
+
+use crate::{
+    artifacts::{bytes_at, dos_datetime_to_filetime, u16_at, u32_at, utf16_at},
+    utils::guid_to_string,
+};
+
+// signature of the extension block of file entries
+const BEEF0004: u32 = 0xBEEF_0004;
+
+// file entry flag: the primary name is UTF-16
+const FILE_ENTRY_UNICODE: u8 = 0x04;
+
+// shell folders found in root folder items
+const SHELL_FOLDERS: &[(&str, &str)] = &[
+    ("{20D04FE0-3AEA-1069-A2D8-08002B30309D}", "My Computer"),
+    ("{450D8FBA-AD25-11D0-98A8-0800361B1103}", "My Documents"),
+    (
+        "{208D2C60-3AEA-1069-A2D7-08002B30309D}",
+        "My Network Places",
+    ),
+    ("{F02C1A0D-BE21-4350-88B0-7367FC96EF3C}", "Network"),
+    ("{21EC2020-3AEA-1069-A2DD-08002B30309D}", "Control Panel"),
+    ("{26EE0668-A00A-44D7-9371-BEB064C98683}", "Control Panel"),
+    ("{645FF040-5081-101B-9F08-00AA002F954E}", "Recycle Bin"),
+    ("{031E4825-7B94-4DC3-B131-E946B44C8DD5}", "Libraries"),
+    ("{59031A47-3F72-44A7-89C5-5595FE6B30EE}", "Users Files"),
+    ("{F3364BA0-65B9-11CE-A9BA-00AA004AE837}", "Home"),
+    ("{679F85CB-0220-4080-B29B-5540CC05AAB6}", "Quick Access"),
+    ("{B4BFCC3A-DB2C-424C-B029-7FE99A87C641}", "Desktop"),
+    ("{374DE290-123F-4565-9164-39C4925E467B}", "Downloads"),
+    ("{1CF1260C-4DD0-4EBB-811F-33C572699FDE}", "Music"),
+    ("{3ADD1653-EB32-4CB0-BBD7-DFA0ABB5ACCA}", "Pictures"),
+    ("{A0953C92-50DC-43BF-BE83-3742FED03C9C}", "Videos"),
+    ("{D3162B92-9365-467A-956B-92703ACA08AF}", "Documents"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShellItem {
+    RootFolder(String),
+    Volume(String),
+    FileEntry {
+        name: String,
+        is_directory: bool,
+        // FILETIME, 0 if not set
+        modified: u64,
+        created: u64,
+        accessed: u64,
+    },
+    Network(String),
+    Uri(String),
+    Unknown(u8),
+}
+
+impl ShellItem {
+    // name of the item as a path component
+    pub fn name(&self) -> String {
+        match self {
+            ShellItem::RootFolder(name)
+            | ShellItem::Volume(name)
+            | ShellItem::Network(name)
+            | ShellItem::Uri(name) => name.clone(),
+            ShellItem::FileEntry { name, .. } => name.clone(),
+            ShellItem::Unknown(class) => format!("<item 0x{class:02X}>"),
+        }
+    }
+
+    // modification, creation and access times of file entries
+    pub fn times(&self) -> (u64, u64, u64) {
+        match self {
+            ShellItem::FileEntry {
+                modified,
+                created,
+                accessed,
+                ..
+            } => (*modified, *created, *accessed),
+            _ => (0, 0, 0),
+        }
+    }
+}
+
+impl fmt::Display for ShellItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// data starts at the size field of the item
+impl TryFrom<&[u8]> for ShellItem {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let size = u16_at(data, 0)? as usize;
+        let data = bytes_at(data, 0, size)?;
+        let class = *bytes_at(data, 2, 1)?.first().unwrap_or(&0);
+
+        let item = match class {
+            0x1F => {
+                let guid = guid_to_string(bytes_at(data, 4, 16)?);
+                let name = SHELL_FOLDERS
+                    .iter()
+                    .find(|(g, _)| g.eq_ignore_ascii_case(&guid))
+                    .map_or(guid, |(_, name)| name.to_string());
+                ShellItem::RootFolder(name)
+            }
+            0x20..=0x2F => ShellItem::Volume(ascii_at(data, 3)),
+            0x30..=0x3F => file_entry(data, class)?,
+            0x40..=0x4F => ShellItem::Network(ascii_at(data, 5)),
+            0x61 => {
+                // flags, data size then the URI (UTF-16 when flags & 0x80)
+                let flags = bytes_at(data, 3, 1)?[0];
+                let uri = if flags & 0x80 != 0 {
+                    utf16_at(data, 0x20, size.saturating_sub(0x20))?
+                } else {
+                    ascii_at(data, 0x20)
+                };
+                ShellItem::Uri(uri)
+            }
+            _ => ShellItem::Unknown(class),
+        };
+
+        Ok(item)
+    }
+}
+
+// items of an item ID list, which ends with a zero size
+pub fn item_id_list(data: &[u8]) -> anyhow::Result<Vec<ShellItem>> {
+    let mut items = Vec::new();
+    let mut offset = 0;
+
+    while offset + 2 <= data.len() {
+        let size = u16_at(data, offset)? as usize;
+        if size == 0 {
+            break;
+        }
+        items.push(ShellItem::try_from(&data[offset..])?);
+        offset += size;
+    }
+
+    Ok(items)
+}
+
+fn file_entry(data: &[u8], class: u8) -> anyhow::Result<ShellItem> {
+    let modified = dos_datetime_to_filetime(u32_at(data, 8)?);
+
+    // 8.3 name, aligned on 2 bytes
+    let (short_name, end) = if class & FILE_ENTRY_UNICODE != 0 {
+        let name = utf16_at(data, 14, data.len().saturating_sub(14))?;
+        (name.clone(), 14 + (name.encode_utf16().count() + 1) * 2)
+    } else {
+        let name = ascii_at(data, 14);
+        (name.clone(), (14 + name.len() + 1).next_multiple_of(2))
+    };
+
+    let mut item = ShellItem::FileEntry {
+        name: short_name,
+        is_directory: class & 0x01 != 0,
+        modified,
+        created: 0,
+        accessed: 0,
+    };
+
+    // extension block: size, version, signature, creation and access times, then the long name
+    // whose offset depends on the version
+    if let Ok(signature) = u32_at(data, end + 4)
+        && signature == BEEF0004
+        && let ShellItem::FileEntry {
+            name,
+            created,
+            accessed,
+            ..
+        } = &mut item
+    {
+        let version = u16_at(data, end + 2)?;
+        *created = dos_datetime_to_filetime(u32_at(data, end + 8)?);
+        *accessed = dos_datetime_to_filetime(u32_at(data, end + 12)?);
+
+        let name_offset = match version {
+            0..=6 => 20,
+            7 => 38,
+            8 => 42,
+            _ => 46,
+        };
+        let block_size = u16_at(data, end)? as usize;
+        if let Ok(long_name) = utf16_at(
+            data,
+            end + name_offset,
+            block_size.saturating_sub(name_offset),
+        ) && !long_name.is_empty()
+        {
+            *name = long_name;
+        }
+    }
+
+    Ok(item)
+}
+
+// null terminated ASCII string
+fn ascii_at(data: &[u8], offset: usize) -> String {
+    data.get(offset..)
+        .unwrap_or_default()
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect()
+}
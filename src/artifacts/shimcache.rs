@@ -0,0 +1,268 @@
+// ShimCache (AppCompatCache): executables checked by the application compatibility layer
+//
+// the AppCompatCache value of ControlSet*\Control\Session Manager\AppCompatCache (AppCompatibility on
+// Windows XP) holds the list of files, most recent first, in a format which changed with almost every
+// Windows version:
+//
+// - XP: 0xDEADBEEF header, fixed size entries with an inline path
+// - 2003/Vista: 0xBADC0FFE header, entries pointing to paths stored after the entries
+// - 7: 0xBADC0FEE header, same with insertion flags and a data blob
+// - 8 and 8.1: 128 bytes header, variable size entries with a "00ts" or "10ts" signature
+// - 10 and 11: 48 or 52 bytes header, "10ts" entries
+//
+// the timestamp is the last modification time of the file, not an execution time.
+//
+use std::fmt;
+
+use anyhow::Context;
+
+use crate::{
+    artifacts::{bytes_at, u16_at, u32_at, u64_at, utf16_at},
+    hive::RegistryHive,
+    utils::filetime_to_string,
+};
+
+const XP_MAGIC: u32 = 0xDEAD_BEEF;
+const VISTA_MAGIC: u32 = 0xBADC_0FFE;
+const WIN7_MAGIC: u32 = 0xBADC_0FEE;
+
+// insertion flag set when the file was executed (7 and 8)
+const INSERT_FLAG_EXECUTED: u32 = 0x0000_0002;
+
+// locations of the value in SYSTEM hives
+const SHIMCACHE_KEYS: &[&str] = &[
+    "ControlSet*\\Control\\Session Manager\\AppCompatCache",
+    "ControlSet*\\Control\\Session Manager\\AppCompatibility",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShimFormat {
+    WindowsXp,
+    WindowsVista,
+    Windows7,
+    Windows8,
+    Windows81,
+    Windows10,
+}
+
+impl fmt::Display for ShimFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShimFormat::WindowsXp => write!(f, "Windows XP"),
+            ShimFormat::WindowsVista => write!(f, "Windows 2003/Vista"),
+            ShimFormat::Windows7 => write!(f, "Windows 7"),
+            ShimFormat::Windows8 => write!(f, "Windows 8"),
+            ShimFormat::Windows81 => write!(f, "Windows 8.1"),
+            ShimFormat::Windows10 => write!(f, "Windows 10"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShimEntry {
+    // 0 for the most recent entry
+    pub position: usize,
+    pub path: String,
+
+    // FILETIME of the last modification of the file, 0 if unknown
+    pub modified: u64,
+
+    // None when the format doesn't record executions
+    pub executed: Option<bool>,
+}
+
+impl fmt::Display for ShimEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let executed = match self.executed {
+            Some(true) => "executed",
+            Some(false) => "not executed",
+            None => "-",
+        };
+        write!(
+            f,
+            "{}\t{}\t{executed}\t{}",
+            self.position,
+            filetime_to_string(self.modified),
+            self.path
+        )
+    }
+}
+
+// entries of one control set
+#[derive(Debug, Clone)]
+pub struct ShimCache {
+    pub key_path: String,
+    pub format: ShimFormat,
+    pub entries: Vec<ShimEntry>,
+}
+
+// ShimCache of each control set
+pub fn shimcaches(hive: &mut RegistryHive) -> anyhow::Result<Vec<ShimCache>> {
+    let mut caches = Vec::new();
+
+    for pattern in SHIMCACHE_KEYS {
+        for (key_path, key) in hive.find_keys(pattern)? {
+            let Some(value) = hive
+                .values(&key)?
+                .into_iter()
+                .find(|v| v.name.eq_ignore_ascii_case("AppCompatCache"))
+            else {
+                continue;
+            };
+            let data = hive.value_bytes(&value)?;
+            let (format, entries) = parse_shimcache(&data)
+                .with_context(|| format!("can't parse the ShimCache of {key_path}"))?;
+            caches.push(ShimCache {
+                key_path,
+                format,
+                entries,
+            });
+        }
+    }
+
+    Ok(caches)
+}
+
+// detect the format from the header and parse all entries
+pub fn parse_shimcache(data: &[u8]) -> anyhow::Result<(ShimFormat, Vec<ShimEntry>)> {
+    let magic = u32_at(data, 0)?;
+
+    let parsed = match magic {
+        XP_MAGIC => (ShimFormat::WindowsXp, parse_xp(data)?),
+        VISTA_MAGIC => (ShimFormat::WindowsVista, parse_vista_7(data, false)?),
+        WIN7_MAGIC => (ShimFormat::Windows7, parse_vista_7(data, true)?),
+        0x80 => match bytes_at(data, 0x80, 4)? {
+            b"00ts" => (
+                ShimFormat::Windows8,
+                parse_ts(data, 0x80, ShimFormat::Windows8)?,
+            ),
+            b"10ts" => (
+                ShimFormat::Windows81,
+                parse_ts(data, 0x80, ShimFormat::Windows81)?,
+            ),
+            _ => anyhow::bail!("unknown Windows 8 ShimCache entry signature"),
+        },
+        0x30 | 0x34 => (
+            ShimFormat::Windows10,
+            parse_ts(data, magic as usize, ShimFormat::Windows10)?,
+        ),
+        _ => anyhow::bail!("unknown ShimCache format 0x{magic:08X}"),
+    };
+
+    Ok(parsed)
+}
+
+// 400 bytes header, 552 bytes entries: 520 bytes path, modification time, file size and update time
+fn parse_xp(data: &[u8]) -> anyhow::Result<Vec<ShimEntry>> {
+    const HEADER_SIZE: usize = 0x190;
+    const ENTRY_SIZE: usize = 0x228;
+    const PATH_SIZE: usize = 0x208;
+
+    let count = u32_at(data, 4)? as usize;
+    let mut entries = Vec::new();
+    for position in 0..count {
+        let start = HEADER_SIZE + position * ENTRY_SIZE;
+        if start + ENTRY_SIZE > data.len() {
+            break;
+        }
+        entries.push(ShimEntry {
+            position,
+            path: utf16_at(data, start, PATH_SIZE)?,
+            modified: u64_at(data, start + PATH_SIZE)?,
+            executed: None,
+        });
+    }
+
+    Ok(entries)
+}
+
+// 8 bytes (2003/Vista) or 128 bytes (7) header, entries are UNICODE_STRING followed by the
+// modification time, their layout depends on the pointer size of the system
+fn parse_vista_7(data: &[u8], win7: bool) -> anyhow::Result<Vec<ShimEntry>> {
+    let count = u32_at(data, 4)? as usize;
+    let header_size = if win7 { 0x80 } else { 8 };
+
+    // the padding after the string lengths is only found on 64-bit systems
+    let x64 = count > 0 && u32_at(data, header_size + 4)? == 0;
+    let entry_size = match (win7, x64) {
+        (false, false) => 24,
+        (false, true) => 32,
+        (true, false) => 32,
+        (true, true) => 48,
+    };
+
+    let mut entries = Vec::new();
+    for position in 0..count {
+        let start = header_size + position * entry_size;
+        if start + entry_size > data.len() {
+            break;
+        }
+        let len = u16_at(data, start)? as usize;
+        let (path_offset, rest) = if x64 {
+            (u64_at(data, start + 8)? as usize, start + 16)
+        } else {
+            (u32_at(data, start + 4)? as usize, start + 8)
+        };
+
+        let executed = if win7 {
+            Some(u32_at(data, rest + 8)? & INSERT_FLAG_EXECUTED != 0)
+        } else {
+            None
+        };
+
+        entries.push(ShimEntry {
+            position,
+            path: utf16_at(data, path_offset, len)?,
+            modified: u64_at(data, rest)?,
+            executed,
+        });
+    }
+
+    Ok(entries)
+}
+
+// signature, unknown, size of the rest of the entry, then the path and fields specific to each
+// version
+fn parse_ts(data: &[u8], header_size: usize, format: ShimFormat) -> anyhow::Result<Vec<ShimEntry>> {
+    let mut entries = Vec::new();
+    let mut start = header_size;
+
+    while start + 12 <= data.len() {
+        let signature = bytes_at(data, start, 4)?;
+        anyhow::ensure!(
+            signature == b"00ts" || signature == b"10ts",
+            "invalid ShimCache entry signature at 0x{start:X}"
+        );
+        let entry_size = u32_at(data, start + 8)? as usize;
+
+        let mut pos = start + 12;
+        let path_len = u16_at(data, pos)? as usize;
+        let path = utf16_at(data, pos + 2, path_len)?;
+        pos += 2 + path_len;
+
+        let (modified, executed) = match format {
+            ShimFormat::Windows10 => (u64_at(data, pos)?, None),
+            _ => {
+                // package name of store applications
+                if format == ShimFormat::Windows81 {
+                    pos += 2 + u16_at(data, pos)? as usize;
+                }
+                let insert_flags = u32_at(data, pos)?;
+                (
+                    u64_at(data, pos + 8)?,
+                    Some(insert_flags & INSERT_FLAG_EXECUTED != 0),
+                )
+            }
+        };
+
+        entries.push(ShimEntry {
+            position: entries.len(),
+            path,
+            modified,
+            executed,
+        });
+        start += 12 + entry_size;
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,209 @@
+// USB storage devices connected to the system
+//
+// in the current control set of a SYSTEM hive:
+// - Enum\USBSTOR\Disk&Ven_<vendor>&Prod_<product>&Rev_<revision>\<serial> has the friendly name and,
+//   as of Windows 7, the first install, last arrival and last removal times as device properties in
+//   Properties\{83da6326-97a6-4088-9453-a1923f573b29}\0064, 0066 and 0067
+// - Enum\USB\VID_<vendor id>&PID_<product id>\<serial> gives the USB ids of the device
+//
+// MountedDevices at the root of the hive maps drive letters to the devices they were last assigned
+// to, and Microsoft\Windows Portable Devices\Devices in SOFTWARE hives has the volume names.
+//
+// serials whose second character is '&' were generated by Windows, the device having none.
+//
+use std::fmt;
+
+use crate::{
+    artifacts::{current_control_set, string_value},
+    hive::{Key, RegistryHive, join_path},
+    utils::filetime_to_string,
+    value::utf16_string,
+};
+
+const DEVICE_PROPERTIES: &str = "Properties\\{83da6326-97a6-4088-9453-a1923f573b29}";
+const FIRST_INSTALL: &str = "0064";
+const LAST_ARRIVAL: &str = "0066";
+const LAST_REMOVAL: &str = "0067";
+
+const PORTABLE_DEVICES: &str = "Microsoft\\Windows Portable Devices\\Devices";
+
+#[derive(Debug, Clone, Default)]
+pub struct UsbDevice {
+    // USBSTOR key of the device
+    pub key_path: String,
+    pub serial: String,
+    pub vendor: String,
+    pub product: String,
+    pub revision: String,
+    pub friendly_name: Option<String>,
+
+    // "VID_xxxx&PID_xxxx"
+    pub usb_ids: Option<String>,
+
+    // FILETIME, 0 if unknown
+    pub first_install: u64,
+    pub last_arrival: u64,
+    pub last_removal: u64,
+
+    // last written time of the serial key, updated when the device is connected
+    pub key_timestamp: u64,
+
+    // "E:"
+    pub drive_letters: Vec<String>,
+    pub volume_names: Vec<String>,
+}
+
+impl fmt::Display for UsbDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = |t: u64| {
+            if t == 0 {
+                "-".to_string()
+            } else {
+                filetime_to_string(t)
+            }
+        };
+
+        writeln!(f, "{}", self.serial)?;
+        writeln!(
+            f,
+            "    device: {} {} {}",
+            self.vendor, self.product, self.revision
+        )?;
+        writeln!(
+            f,
+            "    friendly name: {}",
+            self.friendly_name.as_deref().unwrap_or("-")
+        )?;
+        writeln!(f, "    USB ids: {}", self.usb_ids.as_deref().unwrap_or("-"))?;
+        writeln!(f, "    first install: {}", time(self.first_install))?;
+        writeln!(f, "    last arrival: {}", time(self.last_arrival))?;
+        writeln!(f, "    last removal: {}", time(self.last_removal))?;
+        writeln!(f, "    key last written: {}", time(self.key_timestamp))?;
+        writeln!(f, "    drive letters: {}", self.drive_letters.join(", "))?;
+        write!(f, "    volume names: {}", self.volume_names.join(", "))
+    }
+}
+
+// devices of the current control set of a SYSTEM hive, volume names come from an optional SOFTWARE
+// hive
+pub fn usb_devices(
+    system: &mut RegistryHive,
+    software: Option<&mut RegistryHive>,
+) -> anyhow::Result<Vec<UsbDevice>> {
+    let Some((control_set, _)) = current_control_set(system)? else {
+        anyhow::bail!("no control set found, not a SYSTEM hive");
+    };
+
+    // serial without the instance suffix to USB ids
+    let mut usb_ids = Vec::new();
+    for (path, key) in system.find_keys(&join_path(&control_set, "Enum\\USB\\VID_*\\*"))? {
+        let ids = path.rsplit('\\').nth(1).unwrap_or_default().to_string();
+        usb_ids.push((key.name().to_uppercase(), ids));
+    }
+
+    let mounted = mounted_devices(system)?;
+
+    let mut devices = Vec::new();
+    for (path, key) in system.find_keys(&join_path(&control_set, "Enum\\USBSTOR\\*\\*"))? {
+        let device_name = path.rsplit('\\').nth(1).unwrap_or_default();
+        let mut device = UsbDevice {
+            key_path: path.clone(),
+            serial: key.name().to_string(),
+            key_timestamp: key.node.header.last_written_timestamp,
+            friendly_name: string_value(system, &key, "FriendlyName")?,
+            ..Default::default()
+        };
+        for part in device_name.split('&') {
+            if let Some(v) = part.strip_prefix("Ven_") {
+                device.vendor = v.to_string();
+            } else if let Some(p) = part.strip_prefix("Prod_") {
+                device.product = p.to_string();
+            } else if let Some(r) = part.strip_prefix("Rev_") {
+                device.revision = r.to_string();
+            }
+        }
+
+        let properties = join_path(&path, DEVICE_PROPERTIES);
+        device.first_install = property_time(system, &properties, FIRST_INSTALL)?;
+        device.last_arrival = property_time(system, &properties, LAST_ARRIVAL)?;
+        device.last_removal = property_time(system, &properties, LAST_REMOVAL)?;
+
+        let serial = device_serial(&device.serial).to_uppercase();
+        device.usb_ids = usb_ids
+            .iter()
+            .find(|(s, _)| *s == serial)
+            .map(|(_, ids)| ids.clone());
+        device.drive_letters = mounted
+            .iter()
+            .filter(|(_, target)| target.contains(&serial))
+            .map(|(letter, _)| letter.clone())
+            .collect();
+        devices.push(device);
+    }
+
+    if let Some(software) = software {
+        for (_, key) in software.find_keys(&format!("{PORTABLE_DEVICES}\\*"))? {
+            let id = key.name().to_uppercase();
+            let Some(name) = string_value(software, &key, "FriendlyName")? else {
+                continue;
+            };
+            for device in &mut devices {
+                if id.contains(&device_serial(&device.serial).to_uppercase()) {
+                    device.volume_names.push(name.clone());
+                }
+            }
+        }
+    }
+
+    Ok(devices)
+}
+
+// serial without the "&<instance>" suffix added by USBSTOR
+fn device_serial(serial: &str) -> &str {
+    match serial.rsplit_once('&') {
+        Some((s, instance)) if instance.len() == 1 && !s.is_empty() => s,
+        _ => serial,
+    }
+}
+
+// FILETIME stored as the default value of a device property key
+fn property_time(hive: &mut RegistryHive, properties: &str, id: &str) -> anyhow::Result<u64> {
+    let Some(key) = hive.open_key(&join_path(properties, id))? else {
+        return Ok(0);
+    };
+    Ok(default_value_bytes(hive, &key)?
+        .and_then(|data| {
+            data.get(..8)
+                .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        })
+        .unwrap_or(0))
+}
+
+fn default_value_bytes(hive: &mut RegistryHive, key: &Key) -> anyhow::Result<Option<Vec<u8>>> {
+    match hive.values(key)?.into_iter().find(|v| v.name.is_empty()) {
+        Some(value) => Ok(Some(hive.value_bytes(&value)?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+// drive letters with the uppercase device path they were assigned to: MountedDevices values for
+// removable devices are device paths as UTF-16 strings, for fixed disks they are binary signatures
+fn mounted_devices(hive: &mut RegistryHive) -> anyhow::Result<Vec<(String, String)>> {
+    let mut mounted = Vec::new();
+    let Some(key) = hive.open_key("MountedDevices")? else {
+        return Ok(mounted);
+    };
+
+    for value in hive.values(&key)? {
+        let Some(letter) = value.name.strip_prefix("\\DosDevices\\") else {
+            continue;
+        };
+        let data = hive.value_bytes(&value)?;
+        let target = utf16_string(&data).to_uppercase();
+        if target.starts_with("_??_") || target.starts_with("\\??\\") {
+            mounted.push((letter.to_string(), target));
+        }
+    }
+
+    Ok(mounted)
+}
@@ -0,0 +1,197 @@
+// UserAssist: programs and shortcuts launched from the Explorer shell
+//
+// each GUID subkey of Software\Microsoft\Windows\CurrentVersion\Explorer\UserAssist in NTUSER.DAT has a
+// Count subkey whose value names are ROT13 encoded paths, paths often starting with a known folder
+// GUID. The data is a 16 bytes structure on XP and a 72 bytes one as of Windows 7:
+//
+// XP:  0 session id   4 run count (starting at 5)   8 last executed
+// 7+:  0 session id   4 run count   8 focus count   12 focus time (ms)   60 last executed
+//
+use std::{cmp::Reverse, fmt};
+
+use crate::{
+    artifacts::{u32_at, u64_at},
+    hive::RegistryHive,
+    utils::filetime_to_string,
+};
+
+const USERASSIST_KEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\UserAssist";
+
+const XP_ENTRY_SIZE: usize = 16;
+const WIN7_ENTRY_SIZE: usize = 72;
+
+// XP run counts start at this value
+const XP_COUNT_BASE: u32 = 5;
+
+// GUID subkeys of UserAssist
+const USERASSIST_GUIDS: &[(&str, &str)] = &[
+    ("{CEBFF5CD-ACE2-4F4F-9178-9926F41749EA}", "executables"),
+    ("{F4E57C4B-2036-45F0-A9AB-443BCFE33D9F}", "shortcuts"),
+    ("{75048700-EF1F-11D0-9888-006097DEACF9}", "active desktop"),
+    ("{5E6AB780-7743-11CF-A12B-00AA004AE837}", "internet toolbar"),
+];
+
+// known folders found at the beginning of the names
+const KNOWN_FOLDERS: &[(&str, &str)] = &[
+    (
+        "{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}",
+        "%SystemRoot%\\System32",
+    ),
+    (
+        "{D65231B0-B2F1-4857-A4CE-A8E7C6EA7D27}",
+        "%SystemRoot%\\SysWOW64",
+    ),
+    ("{F38BF404-1D43-42F2-9305-67DE0B28FC23}", "%SystemRoot%"),
+    ("{6D809377-6AF0-444B-8957-A3773F02200E}", "%ProgramFiles%"),
+    (
+        "{7C5A40EF-A0FB-4BFC-874A-C0F2E0B9FA8E}",
+        "%ProgramFiles(x86)%",
+    ),
+    ("{905E63B6-C1BF-494E-B29C-65B732D3D21A}", "%ProgramFiles%"),
+    (
+        "{F7F1ED05-9F6D-47A2-AAAE-29D317C6F066}",
+        "%CommonProgramFiles%",
+    ),
+    (
+        "{0139D44E-6AFE-49F2-8690-3DAFCAE6FFB8}",
+        "%ProgramData%\\Microsoft\\Windows\\Start Menu\\Programs",
+    ),
+    (
+        "{A77F5D77-2E2B-44C3-A6A2-ABA601054A51}",
+        "%AppData%\\Microsoft\\Windows\\Start Menu\\Programs",
+    ),
+    (
+        "{9E3995AB-1F9C-4F13-B827-48B24B6C7174}",
+        "%AppData%\\Microsoft\\Internet Explorer\\Quick Launch\\User Pinned",
+    ),
+    (
+        "{B4BFCC3A-DB2C-424C-B029-7FE99A87C641}",
+        "%UserProfile%\\Desktop",
+    ),
+    (
+        "{FDD39AD0-238F-46AF-ADB4-6C85480369C7}",
+        "%UserProfile%\\Documents",
+    ),
+    (
+        "{374DE290-123F-4565-9164-39C4925E467B}",
+        "%UserProfile%\\Downloads",
+    ),
+];
+
+#[derive(Debug, Clone)]
+pub struct UserAssistEntry {
+    // GUID of the UserAssist subkey
+    pub guid: String,
+
+    // decoded name, with its known folder GUID replaced by the folder path
+    pub name: String,
+    pub run_count: u32,
+
+    // focus count and time (ms) are only recorded as of Windows 7
+    pub focus_count: Option<u32>,
+    pub focus_time: Option<u32>,
+
+    // FILETIME, 0 if never executed
+    pub last_executed: u64,
+}
+
+impl UserAssistEntry {
+    // kind of entries of the GUID subkey
+    pub fn kind(&self) -> &str {
+        USERASSIST_GUIDS
+            .iter()
+            .find(|(guid, _)| guid.eq_ignore_ascii_case(&self.guid))
+            .map_or("unknown", |(_, kind)| kind)
+    }
+}
+
+impl fmt::Display for UserAssistEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let optional = |v: Option<u32>| v.map_or("-".to_string(), |v| v.to_string());
+        let last_executed = if self.last_executed == 0 {
+            "-".to_string()
+        } else {
+            filetime_to_string(self.last_executed)
+        };
+        write!(
+            f,
+            "{}\t{last_executed}\t{}\t{}\t{}\t{}",
+            self.kind(),
+            self.run_count,
+            optional(self.focus_count),
+            optional(self.focus_time),
+            self.name
+        )
+    }
+}
+
+// entries of all GUID subkeys, most recently executed first
+pub fn userassist(hive: &mut RegistryHive) -> anyhow::Result<Vec<UserAssistEntry>> {
+    let mut entries = Vec::new();
+
+    let pattern = format!("{USERASSIST_KEY}\\*\\Count");
+    for (path, key) in hive.find_keys(&pattern)? {
+        let guid = path.rsplit('\\').nth(1).unwrap_or_default().to_string();
+
+        for value in hive.values(&key)? {
+            let data = hive.value_bytes(&value)?;
+            let Some(entry) = parse_entry(&guid, &value.name, &data)? else {
+                continue;
+            };
+            entries.push(entry);
+        }
+    }
+
+    entries.sort_by_key(|e| Reverse(e.last_executed));
+    Ok(entries)
+}
+
+// None for values which are not entries (e.g. UEME_CTLSESSION holding the session)
+fn parse_entry(guid: &str, name: &str, data: &[u8]) -> anyhow::Result<Option<UserAssistEntry>> {
+    let name = rot13(name);
+    let name = expand_known_folder(&name);
+
+    let entry = match data.len() {
+        XP_ENTRY_SIZE => UserAssistEntry {
+            guid: guid.to_string(),
+            name,
+            run_count: u32_at(data, 4)?.saturating_sub(XP_COUNT_BASE),
+            focus_count: None,
+            focus_time: None,
+            last_executed: u64_at(data, 8)?,
+        },
+        WIN7_ENTRY_SIZE => UserAssistEntry {
+            guid: guid.to_string(),
+            name,
+            run_count: u32_at(data, 4)?,
+            focus_count: Some(u32_at(data, 8)?),
+            focus_time: Some(u32_at(data, 12)?),
+            last_executed: u64_at(data, 60)?,
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(entry))
+}
+
+// letters are rotated by 13, other characters are unchanged
+pub fn rot13(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            'a'..='z' => (((c as u8 - b'a') + 13) % 26 + b'a') as char,
+            'A'..='Z' => (((c as u8 - b'A') + 13) % 26 + b'A') as char,
+            _ => c,
+        })
+        .collect()
+}
+
+fn expand_known_folder(name: &str) -> String {
+    for (guid, folder) in KNOWN_FOLDERS {
+        if let Some(head) = name.get(..guid.len())
+            && head.eq_ignore_ascii_case(guid)
+        {
+            return format!("{folder}{}", &name[guid.len()..]);
+        }
+    }
+    name.to_string()
+}
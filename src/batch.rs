@@ -0,0 +1,23 @@
+// Processing of many hives at once, e.g. the NTUSER.DAT files of a triage collection
+//
+// hives are processed concurrently by a bounded number of workers, the parallel walks of each hive
+// running on the same workers. Results come back in the order of the paths, whatever the order the
+// hives are done in.
+//
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+// result of f for each hive, jobs being the number of workers (0 for the number of CPUs)
+pub fn process_hives<T, F>(
+    paths: &[PathBuf],
+    jobs: usize,
+    f: F,
+) -> anyhow::Result<Vec<anyhow::Result<T>>>
+where
+    T: Send,
+    F: Fn(&Path) -> anyhow::Result<T> + Sync,
+{
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(jobs).build()?;
+    Ok(pool.install(|| paths.par_iter().map(|path| f(path)).collect()))
+}
@@ -0,0 +1,211 @@
+// Content of a hive shared by the records read out of it
+//
+// a hive file is mapped in memory, other hives (standard input, recovered, extracted from an image)
+// are held in a single allocation. Hive bins and cells are views into the same content: slicing a
+// buffer or cloning one doesn't copy any byte, a reference count keeps the content alive.
+//
+// how a hive file is read depends on the storage it's on: mapping suits local disks, network shares
+// and mounted images can be faster read in large blocks. Files opened without options are read with
+// the options set for the process, e.g. from the command line.
+//
+use std::{
+    fmt,
+    fs::File,
+    io::Read,
+    ops::{Deref, Range},
+    path::Path,
+    str::FromStr,
+    sync::{Arc, OnceLock},
+};
+
+use memmap2::Mmap;
+
+static DEFAULT_OPTIONS: OnceLock<ParserOptions> = OnceLock::new();
+
+// hive bins data is addressed by u32 offsets, so nothing read from a hive is larger than 4 GiB
+pub const DEFAULT_MEMORY_LIMIT: u64 = 4 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum IoBackend {
+    // pages are read when first accessed
+    #[default]
+    Mmap,
+
+    // the whole file is read when opened
+    Buffered,
+}
+
+impl FromStr for IoBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mmap" => Ok(IoBackend::Mmap),
+            "buffered" => Ok(IoBackend::Buffered),
+            _ => anyhow::bail!("unknown I/O backend '{s}' (mmap or buffered)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParserOptions {
+    pub backend: IoBackend,
+
+    // size of each read of the buffered backend
+    pub buffer_size: usize,
+
+    // bytes at the start of a mapped file paged in when it's opened, the rest of the file being
+    // read ahead as it's accessed sequentially (0 leaves paging to the system)
+    pub read_ahead: usize,
+
+    // most bytes allocated at once for a hive: sizes read from headers (hive bins data size in a
+    // base block or log entry, file size in an NTFS record) can be anything in damaged files
+    pub memory_limit: u64,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            backend: IoBackend::Mmap,
+            buffer_size: 1024 * 1024,
+            read_ahead: 0,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        }
+    }
+}
+
+impl ParserOptions {
+    // fails before allocating size bytes for what when over the memory limit
+    pub fn check_size(&self, size: u64, what: &str) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            size <= self.memory_limit,
+            "{what} of {size} bytes is over the memory limit of {} bytes",
+            self.memory_limit
+        );
+        Ok(())
+    }
+}
+
+// options of the files opened without any, to be set before the first one is opened
+pub fn set_default_options(options: ParserOptions) -> anyhow::Result<()> {
+    DEFAULT_OPTIONS
+        .set(options)
+        .map_err(|_| anyhow::anyhow!("default parser options already in use"))
+}
+
+pub fn default_options() -> &'static ParserOptions {
+    DEFAULT_OPTIONS.get_or_init(ParserOptions::default)
+}
+
+enum Storage {
+    Mapped(Mmap),
+    Owned(Box<[u8]>),
+}
+
+impl Storage {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Storage::Mapped(map) => map,
+            Storage::Owned(bytes) => bytes,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HiveBuffer {
+    storage: Arc<Storage>,
+
+    // view of the content
+    start: usize,
+    end: usize,
+}
+
+impl HiveBuffer {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::open_with(path, default_options())
+    }
+
+    pub fn open_with(path: &Path, options: &ParserOptions) -> anyhow::Result<Self> {
+        let mut f = File::open(path)?;
+        let len = f.metadata()?.len() as usize;
+
+        if options.backend == IoBackend::Buffered {
+            anyhow::ensure!(options.buffer_size > 0, "the buffer size can't be 0");
+            options.check_size(len as u64, &format!("hive file {}", path.display()))?;
+            let mut bytes = vec![0; len];
+            for block in bytes.chunks_mut(options.buffer_size) {
+                f.read_exact(block)?;
+            }
+            return Ok(Self::from(bytes));
+        }
+
+        // empty files can't be mapped
+        if len == 0 {
+            return Ok(Self::from(Vec::new()));
+        }
+
+        // SAFETY: the file mustn't be truncated while mapped. Hives of a running system are locked,
+        // files being written to (e.g. watched hives) are read with read() instead.
+        let map = unsafe { Mmap::map(&f)? };
+
+        // advice is only a hint, failing to give it doesn't prevent reading
+        #[cfg(unix)]
+        if options.read_ahead > 0 {
+            let _ = map.advise(memmap2::Advice::Sequential);
+            let _ = map.advise_range(memmap2::Advice::WillNeed, 0, options.read_ahead.min(len));
+        }
+        Ok(Self::new(Storage::Mapped(map)))
+    }
+
+    // a copy of the file content, for files which can be modified while being read
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self::from(std::fs::read(path)?))
+    }
+
+    fn new(storage: Storage) -> Self {
+        let end = storage.bytes().len();
+        Self {
+            storage: Arc::new(storage),
+            start: 0,
+            end,
+        }
+    }
+
+    // view of a part of this buffer, None if out of it
+    pub fn slice(&self, range: Range<usize>) -> Option<Self> {
+        if range.start > range.end || range.end > self.len() {
+            return None;
+        }
+        Some(Self {
+            storage: Arc::clone(&self.storage),
+            start: self.start + range.start,
+            end: self.start + range.end,
+        })
+    }
+}
+
+impl From<Vec<u8>> for HiveBuffer {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(Storage::Owned(bytes.into_boxed_slice()))
+    }
+}
+
+impl Deref for HiveBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.storage.bytes()[self.start..self.end]
+    }
+}
+
+impl AsRef<[u8]> for HiveBuffer {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for HiveBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HiveBuffer({} bytes)", self.len())
+    }
+}
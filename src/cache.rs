@@ -0,0 +1,65 @@
+// Least recently used cache of reassembled big data values
+//
+// a big data value is split into 16 KB segments which are copied into a single buffer each time the
+// value is read. Exports and plugins reading the same large values again get the buffer from the
+// cache instead. Entries are keyed by the offset of the db record and the size of the value, the
+// cache is bounded by the total size of the buffers.
+//
+use std::{collections::HashMap, sync::Arc};
+
+// total size of the cached values
+pub const BIG_DATA_CACHE_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+struct Entry {
+    data: Arc<[u8]>,
+    last_use: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct BigDataCache {
+    // by db record offset and value size
+    entries: HashMap<(u32, usize), Entry>,
+    bytes: usize,
+    clock: u64,
+}
+
+impl BigDataCache {
+    pub fn get(&mut self, offset: u32, size: usize) -> Option<Arc<[u8]>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(&(offset, size))?;
+        entry.last_use = self.clock;
+        Some(Arc::clone(&entry.data))
+    }
+
+    // least recently used values are evicted to make room, values bigger than the cache aren't kept
+    pub fn insert(&mut self, offset: u32, size: usize, data: Arc<[u8]>) {
+        if data.len() > BIG_DATA_CACHE_SIZE {
+            return;
+        }
+
+        while self.bytes + data.len() > BIG_DATA_CACHE_SIZE {
+            let Some(lru) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_use)
+                .map(|(key, _)| *key)
+            else {
+                break;
+            };
+            if let Some(evicted) = self.entries.remove(&lru) {
+                self.bytes -= evicted.data.len();
+            }
+        }
+
+        self.clock += 1;
+        self.bytes += data.len();
+        let entry = Entry {
+            data,
+            last_use: self.clock,
+        };
+        if let Some(replaced) = self.entries.insert((offset, size), entry) {
+            self.bytes -= replaced.data.len();
+        }
+    }
+}
@@ -0,0 +1,483 @@
+// Carving registry records out of arbitrary binary data (memory dumps, pagefiles, unallocated clusters)
+//
+// the blob is scanned at each cell boundary for plausible hbin, nk, vk and sk structures. When a record
+// lies inside a carved hbin, the hbin offset field gives the position of the hive bins data start, so
+// offsets found in records (parent, values list, data) can be followed to rebuild key paths and values.
+//
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    key::{KEY_HIVE_ENTRY, KEY_NODE_HEADER_SIZE, KeyNode},
+    reg::{HiveBinHeader, bincode_config},
+    security::{KEY_SECURITY_HEADER_SIZE, KeySecurity},
+    value::{BIG_DATA_SEGMENT_SIZE, BigData, KEY_VALUE_HEADER_SIZE, KeyValue, RegData},
+};
+
+// FILETIME of 1990-01-01 and 2100-01-01: keys outside this range are not considered plausible
+const MIN_PLAUSIBLE_FILETIME: u64 = 122_756_256_000_000_000;
+const MAX_PLAUSIBLE_FILETIME: u64 = 157_469_184_000_000_000;
+
+// no sane cell is bigger than this
+const MAX_CELL_SIZE: usize = 1024 * 1024;
+
+// a key name can't exceed 255 characters and a value name 16383 characters
+const MAX_KEY_NAME_LENGTH: usize = 255 * 2;
+const MAX_VALUE_NAME_LENGTH: usize = 16383 * 2;
+
+// don't follow paths deeper than this (loops in corrupted data)
+const MAX_PATH_DEPTH: usize = 512;
+
+// size of the parts of the data scanned in parallel
+const SCAN_CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CarveOptions {
+    // scan step: cells are 8-byte aligned, but 1 allows to find records in unaligned data
+    pub step: usize,
+
+    // also report records found in free cells
+    pub include_free: bool,
+}
+
+impl Default for CarveOptions {
+    fn default() -> Self {
+        Self {
+            step: 8,
+            include_free: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CarvedBin {
+    pub file_offset: usize,
+    pub header: HiveBinHeader,
+}
+
+#[derive(Debug)]
+pub struct CarvedValue {
+    pub file_offset: usize,
+    pub allocated: bool,
+    pub value: KeyValue,
+
+    // None when data couldn't be located
+    pub data: Option<RegData>,
+}
+
+#[derive(Debug)]
+pub struct CarvedKey {
+    pub file_offset: usize,
+    pub allocated: bool,
+    pub node: KeyNode,
+
+    // path is prefixed by "?" when not all ancestors were found
+    pub path: String,
+    pub values: Vec<CarvedValue>,
+}
+
+#[derive(Debug)]
+pub struct CarvedSecurity {
+    pub file_offset: usize,
+    pub allocated: bool,
+    pub security: KeySecurity,
+}
+
+#[derive(Debug, Default)]
+pub struct CarveResult {
+    pub bins: Vec<CarvedBin>,
+    pub keys: Vec<CarvedKey>,
+
+    // values not referenced by any carved key
+    pub orphan_values: Vec<CarvedValue>,
+    pub security: Vec<CarvedSecurity>,
+}
+
+// records found in a part of the data: position, allocated, record
+#[derive(Default)]
+struct Scan {
+    bins: Vec<CarvedBin>,
+    keys: Vec<(usize, bool, KeyNode)>,
+    values: Vec<(usize, bool, KeyValue)>,
+    security: Vec<CarvedSecurity>,
+}
+
+pub struct Carver<'a> {
+    data: &'a [u8],
+    options: CarveOptions,
+}
+
+impl<'a> Carver<'a> {
+    pub fn new(data: &'a [u8], options: CarveOptions) -> Self {
+        Self { data, options }
+    }
+
+    pub fn carve(&self) -> CarveResult {
+        let mut result = CarveResult::default();
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+
+        // chunks are scanned in parallel, their records gathered in the order of the data. A chunk
+        // size multiple of the step keeps the positions scanned those of a single scan.
+        let step = self.options.step.max(1);
+        let chunk_size = SCAN_CHUNK_SIZE.next_multiple_of(step);
+        let scans: Vec<Scan> = (0..self.data.len().div_ceil(chunk_size))
+            .into_par_iter()
+            .map(|i| self.scan(i * chunk_size, (i + 1) * chunk_size, step))
+            .collect();
+        for scan in scans {
+            result.bins.extend(scan.bins);
+            result.security.extend(scan.security);
+            keys.extend(scan.keys);
+            values.extend(scan.values);
+        }
+
+        // index keys by (hive bins data start, offset) to follow parent links
+        let mut key_index = HashMap::new();
+        for (i, (pos, _, _)) in keys.iter().enumerate() {
+            if let Some(base) = self.base_of(&result.bins, *pos) {
+                key_index.insert((base, (*pos - base) as u32), i);
+            }
+        }
+
+        // values referenced by keys are attached to them, others are orphans
+        result.keys = (0..keys.len())
+            .into_par_iter()
+            .map(|i| {
+                let (pos, allocated, node) = &keys[i];
+                let path = self.rebuild_path(&result.bins, &keys, &key_index, i);
+                let key_values = match self.base_of(&result.bins, *pos) {
+                    Some(base) => self.key_values(base, node),
+                    None => Vec::new(),
+                };
+
+                CarvedKey {
+                    file_offset: *pos,
+                    allocated: *allocated,
+                    node: node.clone(),
+                    path,
+                    values: key_values,
+                }
+            })
+            .collect();
+        let attached: HashSet<usize> = result
+            .keys
+            .iter()
+            .flat_map(|k| k.values.iter().map(|v| v.file_offset))
+            .collect();
+
+        for (pos, allocated, value) in values {
+            if attached.contains(&pos) {
+                continue;
+            }
+            let data = self
+                .base_of(&result.bins, pos)
+                .and_then(|base| self.value_data(base, &value));
+            result.orphan_values.push(CarvedValue {
+                file_offset: pos,
+                allocated,
+                value,
+                data,
+            });
+        }
+
+        result
+    }
+
+    // records starting at the positions of [start, end)
+    fn scan(&self, start: usize, end: usize, step: usize) -> Scan {
+        let mut scan = Scan::default();
+
+        let mut pos = start;
+        while pos < end && pos + 8 <= self.data.len() {
+            if let Some(header) = self.hbin_at(pos) {
+                scan.bins.push(CarvedBin {
+                    file_offset: pos,
+                    header,
+                });
+            } else if let Some((size, content)) = self.cell_at(pos) {
+                let allocated = size < 0;
+                if allocated || self.options.include_free {
+                    match &content[..2] {
+                        b"nk" => {
+                            if let Some(node) = plausible_key(content) {
+                                scan.keys.push((pos, allocated, node));
+                            }
+                        }
+                        b"vk" => {
+                            if let Some(value) = plausible_value(content) {
+                                scan.values.push((pos, allocated, value));
+                            }
+                        }
+                        b"sk" => {
+                            if let Some(security) = plausible_security(content, allocated) {
+                                scan.security.push(CarvedSecurity {
+                                    file_offset: pos,
+                                    allocated,
+                                    security,
+                                });
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            pos += step;
+        }
+
+        scan
+    }
+
+    // a hbin header is 4096-aligned inside the hive and its size a multiple of 4096
+    fn hbin_at(&self, pos: usize) -> Option<HiveBinHeader> {
+        if self.data.get(pos..pos + 4)? != b"hbin" {
+            return None;
+        }
+        let (header, _): (HiveBinHeader, usize) =
+            bincode::decode_from_slice(&self.data[pos..], bincode_config()).ok()?;
+        (header.offset.is_multiple_of(4096) && header.size > 0 && header.size.is_multiple_of(4096))
+            .then_some(header)
+    }
+
+    // returns the cell size and its content (after the size field) if it looks like a cell
+    fn cell_at(&self, pos: usize) -> Option<(i32, &'a [u8])> {
+        let size = i32::from_le_bytes(self.data.get(pos..pos + 4)?.try_into().ok()?);
+        let abs_size = size.unsigned_abs() as usize;
+        if abs_size < 8 || !abs_size.is_multiple_of(8) || abs_size > MAX_CELL_SIZE {
+            return None;
+        }
+        let content = self.data.get(pos + 4..pos + abs_size)?;
+        Some((size, content))
+    }
+
+    // start of the hive bins data for a record found at pos, if it lies inside a carved hbin
+    fn base_of(&self, bins: &[CarvedBin], pos: usize) -> Option<usize> {
+        // bins are found in ascending order
+        let i = bins
+            .partition_point(|b| b.file_offset <= pos)
+            .checked_sub(1)?;
+        let bin = &bins[i];
+        if pos >= bin.file_offset + bin.header.size as usize {
+            return None;
+        }
+        bin.file_offset.checked_sub(bin.header.offset as usize)
+    }
+
+    // when the base block precedes the hive bins data, it gives the root key offset
+    fn root_offset(&self, base: usize) -> Option<usize> {
+        let block = self.data.get(base.checked_sub(4096)?..base)?;
+        if !block.starts_with(b"regf") {
+            return None;
+        }
+        Some(u32::from_le_bytes(block[36..40].try_into().ok()?) as usize)
+    }
+
+    // cell content at an offset relative to the hive bins data start
+    fn cell_at_offset(&self, base: usize, offset: u32) -> Option<&'a [u8]> {
+        self.cell_at(base.checked_add(offset as usize)?)
+            .map(|(_, content)| content)
+    }
+
+    fn rebuild_path(
+        &self,
+        bins: &[CarvedBin],
+        keys: &[(usize, bool, KeyNode)],
+        key_index: &HashMap<(usize, u32), usize>,
+        index: usize,
+    ) -> String {
+        let mut names = Vec::new();
+        let mut complete = false;
+        let mut current = &keys[index];
+
+        for _ in 0..MAX_PATH_DEPTH {
+            let node = &current.2;
+            let base = self.base_of(bins, current.0);
+            if node.header.flags & KEY_HIVE_ENTRY != 0
+                || base.is_some_and(|base| self.root_offset(base) == Some(current.0 - base))
+            {
+                complete = true;
+                break;
+            }
+            names.push(node.name.clone());
+
+            let parent = base.and_then(|base| key_index.get(&(base, node.header.parent)));
+            match parent {
+                Some(i) => current = &keys[*i],
+                None => break,
+            }
+        }
+
+        names.reverse();
+        if complete {
+            format!("\\{}", names.join("\\"))
+        } else {
+            format!("?\\{}", names.join("\\"))
+        }
+    }
+
+    fn key_values(&self, base: usize, node: &KeyNode) -> Vec<CarvedValue> {
+        let mut values = Vec::new();
+
+        let count = node.header.number_of_key_values as usize;
+        let Some(list) = self.cell_at_offset(base, node.header.key_values_list_offset) else {
+            return values;
+        };
+
+        for chunk in list.chunks_exact(4).take(count) {
+            let offset = u32::from_le_bytes(chunk.try_into().unwrap());
+            let file_offset = base + offset as usize;
+            let Some((size, content)) = self.cell_at(file_offset) else {
+                continue;
+            };
+            let Some(value) = plausible_value(content) else {
+                continue;
+            };
+            let data = self.value_data(base, &value);
+            values.push(CarvedValue {
+                file_offset,
+                allocated: size < 0,
+                value,
+                data,
+            });
+        }
+
+        values
+    }
+
+    // locate value data: resident, in a data cell or split into big data segments
+    fn value_data(&self, base: usize, value: &KeyValue) -> Option<RegData> {
+        let size = value.data_size() as usize;
+        if value.is_resident() {
+            return Some(RegData::new(value.reg_type(), &value.resident_data()));
+        }
+        if size == 0 {
+            return Some(RegData::new(value.reg_type(), &[]));
+        }
+
+        let cell = self.cell_at_offset(base, value.header.data_offset)?;
+        if size > BIG_DATA_SEGMENT_SIZE && cell.starts_with(b"db") {
+            let (db, _): (BigData, usize) =
+                bincode::decode_from_slice(cell, bincode_config()).ok()?;
+            let segments = self.cell_at_offset(base, db.segments_list_offset)?;
+
+            let mut data = Vec::with_capacity(size);
+            for chunk in segments
+                .chunks_exact(4)
+                .take(db.number_of_segments as usize)
+            {
+                let offset = u32::from_le_bytes(chunk.try_into().unwrap());
+                let segment = self.cell_at_offset(base, offset)?;
+                let len = segment
+                    .len()
+                    .min(BIG_DATA_SEGMENT_SIZE)
+                    .min(size - data.len());
+                data.extend_from_slice(&segment[..len]);
+            }
+            return Some(RegData::new(value.reg_type(), &data));
+        }
+
+        let data = cell.get(..size)?;
+        Some(RegData::new(value.reg_type(), data))
+    }
+}
+
+pub(crate) fn plausible_key(content: &[u8]) -> Option<KeyNode> {
+    let node = KeyNode::try_from(content).ok()?;
+    let h = &node.header;
+
+    let name_length = h.key_name_length as usize;
+    if name_length == 0 || name_length > MAX_KEY_NAME_LENGTH {
+        return None;
+    }
+    if content.len() < KEY_NODE_HEADER_SIZE + name_length {
+        return None;
+    }
+    if h.flags >= 0x4000 {
+        return None;
+    }
+    if !(MIN_PLAUSIBLE_FILETIME..MAX_PLAUSIBLE_FILETIME).contains(&h.last_written_timestamp) {
+        return None;
+    }
+    if node.name.chars().any(|c| c.is_control()) {
+        return None;
+    }
+
+    Some(node)
+}
+
+pub(crate) fn plausible_value(content: &[u8]) -> Option<KeyValue> {
+    let value = KeyValue::try_from(content).ok()?;
+    let h = &value.header;
+
+    if h.name_length as usize > MAX_VALUE_NAME_LENGTH {
+        return None;
+    }
+    if content.len() < KEY_VALUE_HEADER_SIZE + h.name_length as usize {
+        return None;
+    }
+    if h.data_type > 11 || h.flags > 3 {
+        return None;
+    }
+    if !value.is_resident() && value.data_size() > 0 && !h.data_offset.is_multiple_of(8) {
+        return None;
+    }
+    if value.is_resident() && value.data_size() > 4 {
+        return None;
+    }
+    if value.name.chars().any(|c| c.is_control()) {
+        return None;
+    }
+
+    Some(value)
+}
+
+fn plausible_security(content: &[u8], allocated: bool) -> Option<KeySecurity> {
+    let security = KeySecurity::try_from(content).ok()?;
+
+    // a self-relative security descriptor starts with revision 1
+    if security.descriptor.first() != Some(&1) {
+        return None;
+    }
+    if content.len() < KEY_SECURITY_HEADER_SIZE + security.descriptor.len() {
+        return None;
+    }
+    if allocated && security.header.reference_count == 0 {
+        return None;
+    }
+
+    Some(security)
+}
+
+impl fmt::Display for CarvedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:X} [{}] {} {}",
+            self.file_offset,
+            if self.allocated { "allocated" } else { "free" },
+            self.value.display_name(),
+            self.value.reg_type()
+        )?;
+        match &self.data {
+            Some(data) => write!(f, " = {data}"),
+            None => write!(f, " = <data not found>"),
+        }
+    }
+}
+
+impl fmt::Display for CarvedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:X} [{}] {} {}",
+            self.file_offset,
+            if self.allocated { "allocated" } else { "free" },
+            self.node.timestamp(),
+            self.path
+        )
+    }
+}
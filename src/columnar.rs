@@ -0,0 +1,138 @@
+// Keys and values written as a Parquet file, to query fleets of hives with DuckDB or Spark
+//
+// one row per value, keys without values getting a single row with empty value columns (same as
+// the CSV export). Rows are written by row groups of ROW_GROUP_SIZE rows: once full, a row group is
+// encoded and handed to the output, so memory doesn't grow with the size of the hive. The writer
+// encodes into a buffer drained after each row group, the output doesn't need to be sent across
+// threads.
+//
+use std::{io::Write, sync::Arc};
+
+use arrow_array::{
+    ArrayRef, RecordBatch,
+    builder::{BinaryBuilder, StringBuilder, TimestampMicrosecondBuilder, UInt32Builder},
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+
+use crate::{record::Record, utils::filetime_to_unix};
+
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+pub struct ParquetWriter {
+    schema: SchemaRef,
+    writer: ArrowWriter<Vec<u8>>,
+
+    // rows of the row group being filled
+    rows: usize,
+    path: StringBuilder,
+    last_written: TimestampMicrosecondBuilder,
+    value_name: StringBuilder,
+    reg_type: StringBuilder,
+    size: UInt32Builder,
+    data: StringBuilder,
+    raw: BinaryBuilder,
+}
+
+impl ParquetWriter {
+    pub fn new() -> anyhow::Result<Self> {
+        let timestamp = DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()));
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("path", DataType::Utf8, false),
+            Field::new("last_written", timestamp, false),
+            Field::new("value_name", DataType::Utf8, true),
+            Field::new("type", DataType::Utf8, true),
+            Field::new("size", DataType::UInt32, true),
+            Field::new("data", DataType::Utf8, true),
+            Field::new("raw", DataType::Binary, true),
+        ]));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_SIZE)
+            .build();
+
+        Ok(Self {
+            writer: ArrowWriter::try_new(Vec::new(), schema.clone(), Some(properties))?,
+            schema,
+            rows: 0,
+            path: StringBuilder::new(),
+            last_written: TimestampMicrosecondBuilder::new().with_timezone("UTC"),
+            value_name: StringBuilder::new(),
+            reg_type: StringBuilder::new(),
+            size: UInt32Builder::new(),
+            data: StringBuilder::new(),
+            raw: BinaryBuilder::new(),
+        })
+    }
+
+    pub fn write_key<W: Write>(
+        &mut self,
+        path: &str,
+        timestamp: u64,
+        records: &[Record],
+        out: &mut W,
+    ) -> anyhow::Result<()> {
+        // FILETIME to microseconds since the unix epoch
+        let last_written =
+            filetime_to_unix(timestamp) * 1_000_000 + (timestamp % 10_000_000 / 10) as i64;
+
+        if records.is_empty() {
+            self.path.append_value(path);
+            self.last_written.append_value(last_written);
+            self.value_name.append_null();
+            self.reg_type.append_null();
+            self.size.append_null();
+            self.data.append_null();
+            self.raw.append_null();
+            self.rows += 1;
+        }
+        for record in records {
+            self.path.append_value(path);
+            self.last_written.append_value(last_written);
+            self.value_name.append_value(&record.value_name);
+            self.reg_type.append_value(record.reg_type.to_string());
+            self.size.append_value(record.data.len() as u32);
+            self.data.append_value(record.data().to_string());
+            self.raw.append_value(&record.data);
+            self.rows += 1;
+        }
+
+        if self.rows >= ROW_GROUP_SIZE {
+            self.write_row_group(out)?;
+        }
+        Ok(())
+    }
+
+    // the last row group and the footer
+    pub fn finish<W: Write>(mut self, out: &mut W) -> anyhow::Result<()> {
+        self.write_row_group(out)?;
+        self.writer.finish()?;
+        out.write_all(self.writer.inner_mut())?;
+        Ok(())
+    }
+
+    fn write_row_group<W: Write>(&mut self, out: &mut W) -> anyhow::Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.path.finish()),
+            Arc::new(self.last_written.finish()),
+            Arc::new(self.value_name.finish()),
+            Arc::new(self.reg_type.finish()),
+            Arc::new(self.size.finish()),
+            Arc::new(self.data.finish()),
+            Arc::new(self.raw.finish()),
+        ];
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.writer.write(&batch)?;
+        self.writer.flush()?;
+        self.rows = 0;
+
+        // offsets in the file are counted by the writer, the buffer can be emptied
+        let buffer = self.writer.inner_mut();
+        out.write_all(buffer)?;
+        buffer.clear();
+        Ok(())
+    }
+}
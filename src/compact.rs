@@ -0,0 +1,266 @@
+// Keys and values encoded as CBOR or MessagePack, compact binary forms of the export to move a
+// parsed hive from an endpoint agent to an analysis service
+//
+// the output is a sequence of maps, one per key written as soon as it's read (a CBOR sequence as in
+// RFC 8742, or a stream of MessagePack objects):
+//   {"path": text, "last_written": uint, "values": [{"name": text, "type": uint, "data": bytes}]}
+// last_written is the FILETIME of the key, type the number of the value type and data the raw
+// value data: nothing is lost, the receiver interprets the data as readregf does (RegData::new).
+// CompactReader reads the keys back.
+//
+use std::{fmt, io::Write};
+
+use crate::{record::Record, value::RegType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Cbor,
+    MessagePack,
+}
+
+// the data items written, numbered as the CBOR major types
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    Uint = 0,
+    Bytes = 2,
+    Text = 3,
+    Array = 4,
+    Map = 5,
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Item::Uint => write!(f, "unsigned integer"),
+            Item::Bytes => write!(f, "byte string"),
+            Item::Text => write!(f, "text string"),
+            Item::Array => write!(f, "array"),
+            Item::Map => write!(f, "map"),
+        }
+    }
+}
+
+// a key and its values
+pub fn write_key<W: Write>(
+    out: &mut W,
+    encoding: Encoding,
+    path: &str,
+    timestamp: u64,
+    records: &[Record],
+) -> anyhow::Result<()> {
+    let mut encoder = Encoder {
+        encoding,
+        buffer: Vec::new(),
+    };
+
+    encoder.header(Item::Map, 3);
+    encoder.text("path");
+    encoder.text(path);
+    encoder.text("last_written");
+    encoder.header(Item::Uint, timestamp);
+    encoder.text("values");
+    encoder.header(Item::Array, records.len() as u64);
+    for record in records {
+        encoder.header(Item::Map, 3);
+        encoder.text("name");
+        encoder.text(&record.value_name);
+        encoder.text("type");
+        encoder.header(Item::Uint, u32::from(record.reg_type) as u64);
+        encoder.text("data");
+        encoder.header(Item::Bytes, record.data.len() as u64);
+        encoder.buffer.extend_from_slice(&record.data);
+    }
+
+    out.write_all(&encoder.buffer)?;
+    Ok(())
+}
+
+struct Encoder {
+    encoding: Encoding,
+    buffer: Vec<u8>,
+}
+
+impl Encoder {
+    // type and length (or value of an integer) of an item, in the smallest form
+    fn header(&mut self, item: Item, n: u64) {
+        let (marker, size) = match self.encoding {
+            Encoding::Cbor => {
+                let major = (item as u8) << 5;
+                match n {
+                    0..24 => (major | n as u8, 0),
+                    24..=0xFF => (major | 24, 1),
+                    0x100..=0xFFFF => (major | 25, 2),
+                    0x1_0000..=0xFFFF_FFFF => (major | 26, 4),
+                    _ => (major | 27, 8),
+                }
+            }
+            Encoding::MessagePack => match (item, n) {
+                (Item::Uint, 0..128) => (n as u8, 0),
+                (Item::Text, 0..32) => (0xA0 | n as u8, 0),
+                (Item::Array, 0..16) => (0x90 | n as u8, 0),
+                (Item::Map, 0..16) => (0x80 | n as u8, 0),
+                (Item::Uint, 0..=0xFF) => (0xCC, 1),
+                (Item::Uint, 0x100..=0xFFFF) => (0xCD, 2),
+                (Item::Uint, 0x1_0000..=0xFFFF_FFFF) => (0xCE, 4),
+                (Item::Uint, _) => (0xCF, 8),
+                (Item::Text, 0..=0xFF) => (0xD9, 1),
+                (Item::Text, 0x100..=0xFFFF) => (0xDA, 2),
+                (Item::Text, _) => (0xDB, 4),
+                (Item::Bytes, 0..=0xFF) => (0xC4, 1),
+                (Item::Bytes, 0x100..=0xFFFF) => (0xC5, 2),
+                (Item::Bytes, _) => (0xC6, 4),
+                (Item::Array, 0..=0xFFFF) => (0xDC, 2),
+                (Item::Array, _) => (0xDD, 4),
+                (Item::Map, 0..=0xFFFF) => (0xDE, 2),
+                (Item::Map, _) => (0xDF, 4),
+            },
+        };
+        self.buffer.push(marker);
+        self.buffer.extend_from_slice(&n.to_be_bytes()[8 - size..]);
+    }
+
+    fn text(&mut self, s: &str) {
+        self.header(Item::Text, s.len() as u64);
+        self.buffer.extend_from_slice(s.as_bytes());
+    }
+}
+
+// a key read back
+#[derive(Debug, Clone)]
+pub struct CompactKey {
+    pub path: String,
+    pub last_written: u64,
+    pub values: Vec<Record>,
+}
+
+// keys of a sequence written by write_key
+pub struct CompactReader<'a> {
+    encoding: Encoding,
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CompactReader<'a> {
+    pub fn new(encoding: Encoding, data: &'a [u8]) -> Self {
+        Self {
+            encoding,
+            data,
+            pos: 0,
+        }
+    }
+
+    // None at the end of the sequence
+    pub fn read_key(&mut self) -> anyhow::Result<Option<CompactKey>> {
+        if self.pos == self.data.len() {
+            return Ok(None);
+        }
+
+        let mut key = CompactKey {
+            path: String::new(),
+            last_written: 0,
+            values: Vec::new(),
+        };
+        for _ in 0..self.expect(Item::Map)? {
+            match self.text()?.as_str() {
+                "path" => key.path = self.text()?,
+                "last_written" => key.last_written = self.expect(Item::Uint)?,
+                "values" => {
+                    for _ in 0..self.expect(Item::Array)? {
+                        let mut record = Record {
+                            path: String::new(),
+                            timestamp: 0,
+                            value_name: String::new(),
+                            reg_type: RegType::None,
+                            data: Vec::new(),
+                        };
+                        for _ in 0..self.expect(Item::Map)? {
+                            match self.text()?.as_str() {
+                                "name" => record.value_name = self.text()?,
+                                "type" => {
+                                    let reg_type = u32::try_from(self.expect(Item::Uint)?)?;
+                                    record.reg_type = RegType::from(reg_type);
+                                }
+                                "data" => {
+                                    let len = self.expect(Item::Bytes)?;
+                                    record.data = self.bytes(len)?.to_vec();
+                                }
+                                field => anyhow::bail!("unknown value field '{field}'"),
+                            }
+                        }
+                        key.values.push(record);
+                    }
+                }
+                field => anyhow::bail!("unknown key field '{field}'"),
+            }
+        }
+
+        for record in &mut key.values {
+            record.path.clone_from(&key.path);
+            record.timestamp = key.last_written;
+        }
+        Ok(Some(key))
+    }
+
+    fn bytes(&mut self, len: u64) -> anyhow::Result<&'a [u8]> {
+        let bytes = usize::try_from(len)
+            .ok()
+            .and_then(|len| self.data.get(self.pos..self.pos.checked_add(len)?))
+            .ok_or_else(|| anyhow::anyhow!("truncated item at offset {}", self.pos))?;
+        self.pos += bytes.len();
+        Ok(bytes)
+    }
+
+    fn number(&mut self, size: usize) -> anyhow::Result<u64> {
+        let bytes = self.bytes(size as u64)?;
+        Ok(bytes.iter().fold(0, |n, b| n << 8 | *b as u64))
+    }
+
+    // length of the item, or its value for an integer
+    fn expect(&mut self, expected: Item) -> anyhow::Result<u64> {
+        let offset = self.pos;
+        let marker = self.bytes(1)?[0];
+
+        let (item, n) = match self.encoding {
+            Encoding::Cbor => {
+                let item = match marker >> 5 {
+                    0 => Some(Item::Uint),
+                    2 => Some(Item::Bytes),
+                    3 => Some(Item::Text),
+                    4 => Some(Item::Array),
+                    5 => Some(Item::Map),
+                    _ => None,
+                };
+                let n = match marker & 0x1F {
+                    n @ 0..24 => Some(n as u64),
+                    n @ 24..=27 => Some(self.number(1 << (n - 24))?),
+                    _ => None,
+                };
+                (item, n)
+            }
+            Encoding::MessagePack => match marker {
+                0x00..=0x7F => (Some(Item::Uint), Some(marker as u64)),
+                0x80..=0x8F => (Some(Item::Map), Some((marker & 0x0F) as u64)),
+                0x90..=0x9F => (Some(Item::Array), Some((marker & 0x0F) as u64)),
+                0xA0..=0xBF => (Some(Item::Text), Some((marker & 0x1F) as u64)),
+                0xC4..=0xC6 => (Some(Item::Bytes), Some(self.number(1 << (marker - 0xC4))?)),
+                0xCC..=0xCF => (Some(Item::Uint), Some(self.number(1 << (marker - 0xCC))?)),
+                0xD9..=0xDB => (Some(Item::Text), Some(self.number(1 << (marker - 0xD9))?)),
+                0xDC | 0xDD => (Some(Item::Array), Some(self.number(2 << (marker - 0xDC))?)),
+                0xDE | 0xDF => (Some(Item::Map), Some(self.number(2 << (marker - 0xDE))?)),
+                _ => (None, None),
+            },
+        };
+
+        match (item, n) {
+            (Some(item), Some(n)) if item == expected => Ok(n),
+            _ => anyhow::bail!(
+                "expected a {expected} at offset {offset}, found marker 0x{marker:02X}"
+            ),
+        }
+    }
+
+    fn text(&mut self) -> anyhow::Result<String> {
+        let len = self.expect(Item::Text)?;
+        Ok(std::str::from_utf8(self.bytes(len)?)?.to_string())
+    }
+}
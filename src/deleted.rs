@@ -0,0 +1,253 @@
+// Recovery of deleted keys and values from free cells
+//
+// a deleted key cell is marked free (positive size) and possibly coalesced with adjacent free cells,
+// but its content stays until the space is reused. Free cells are scanned at each 8 bytes boundary for
+// the records they used to hold, and parent offsets are followed through live keys or other deleted
+// keys to rebuild paths. Deleted values are attached to the key whose values list still references
+// them: the list of a deleted key, or the slack of a live key list which shrank when they were deleted.
+//
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    carve::{plausible_key, plausible_value},
+    hive::{MAX_DEPTH, RegistryHive, join_path},
+    key::{KeyNode, NO_OFFSET},
+    list::key_values_list,
+    metrics::Metrics,
+    value::{KeyValue, RegData},
+};
+
+#[derive(Debug, Clone)]
+pub struct DeletedKey {
+    // offset relative to the start of the hive bins data
+    pub offset: u32,
+    pub node: KeyNode,
+
+    // path is prefixed by "?" when not all ancestors were found
+    pub path: String,
+}
+
+impl fmt::Display for DeletedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:X}\t{}\t{}",
+            self.offset,
+            self.node.timestamp(),
+            self.path
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeletedValue {
+    // offset relative to the start of the hive bins data
+    pub offset: u32,
+    pub value: KeyValue,
+
+    // path of the live or deleted key, None when no values list references the value
+    pub path: Option<String>,
+
+    // None when data couldn't be read, it may have been overwritten when its cell is reused
+    pub data: Option<RegData>,
+}
+
+impl fmt::Display for DeletedValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:X}\t{}\t{}\t{}\t",
+            self.offset,
+            self.path.as_deref().unwrap_or("?"),
+            self.value.display_name(),
+            self.value.reg_type()
+        )?;
+        match &self.data {
+            Some(data) => write!(f, "{data}"),
+            None => write!(f, "<data not found>"),
+        }
+    }
+}
+
+// free cells in order, with their offset
+pub(crate) fn free_cells(hive: &RegistryHive) -> anyhow::Result<Vec<(u32, &[u8])>> {
+    let index = hive.index()?;
+    Ok(index
+        .cells()
+        .iter()
+        .filter(|c| c.is_free())
+        .map(|c| (c.offset, index.content(c)))
+        .collect())
+}
+
+// records previously held by a free cell: its own content and the cells coalesced into it, whose
+// size fields are still in place. Returns the offset of each record with its content.
+pub(crate) fn free_records(offset: u32, content: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut records = Vec::new();
+
+    for start in (0..content.len().saturating_sub(2)).step_by(8) {
+        let end = if start == 0 {
+            content.len()
+        } else {
+            let size = i32::from_le_bytes(content[start - 4..start].try_into().unwrap());
+            let size = size.unsigned_abs() as usize;
+            if size < 8 || !size.is_multiple_of(8) {
+                continue;
+            }
+            (start - 4 + size).min(content.len())
+        };
+        records.push((offset + start as u32, &content[start..end]));
+    }
+
+    records
+}
+
+// live keys paths by offset
+pub(crate) fn live_keys(hive: &mut RegistryHive) -> anyhow::Result<HashMap<u32, String>> {
+    let mut paths = HashMap::new();
+
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |_, path, key| {
+        paths.insert(key.offset, path.to_string());
+        Ok(())
+    })?;
+
+    Ok(paths)
+}
+
+pub fn deleted_keys(hive: &mut RegistryHive) -> anyhow::Result<Vec<DeletedKey>> {
+    let live = live_keys(hive)?;
+    let bins_size = hive.base_block.hive_bins_data_size;
+
+    let mut found = Vec::new();
+    for (offset, content) in free_cells(hive)? {
+        for (offset, record) in free_records(offset, content) {
+            if !record.starts_with(b"nk") || live.contains_key(&offset) {
+                continue;
+            }
+            let Some(node) = plausible_key(record) else {
+                continue;
+            };
+
+            // cell offsets are 8 bytes aligned
+            let parent = node.header.parent;
+            if !node.is_root() && (parent == NO_OFFSET || parent % 8 != 0 || parent >= bins_size) {
+                continue;
+            }
+            found.push((offset, node));
+        }
+    }
+
+    let index: HashMap<u32, usize> = found
+        .iter()
+        .enumerate()
+        .map(|(i, (offset, _))| (*offset, i))
+        .collect();
+
+    let keys = found
+        .iter()
+        .map(|(offset, node)| DeletedKey {
+            offset: *offset,
+            node: node.clone(),
+            path: rebuild_path(&found, &index, &live, node),
+        })
+        .collect();
+
+    Metrics::add(&hive.metrics().records_recovered, found.len() as u64);
+    Ok(keys)
+}
+
+fn rebuild_path(
+    found: &[(u32, KeyNode)],
+    index: &HashMap<u32, usize>,
+    live: &HashMap<u32, String>,
+    node: &KeyNode,
+) -> String {
+    let mut names = vec![node.name.as_str()];
+    let mut parent = node.header.parent;
+
+    for _ in 0..MAX_DEPTH {
+        if let Some(path) = live.get(&parent) {
+            return names
+                .iter()
+                .rev()
+                .fold(path.clone(), |p, n| join_path(&p, n));
+        }
+        match index.get(&parent) {
+            Some(i) if !found[*i].1.is_root() => {
+                names.push(&found[*i].1.name);
+                parent = found[*i].1.header.parent;
+            }
+            _ => break,
+        }
+    }
+
+    names.reverse();
+    format!("?\\{}", names.join("\\"))
+}
+
+// values found in free cells, keys are the deleted keys found by deleted_keys()
+pub fn deleted_values(
+    hive: &mut RegistryHive,
+    keys: &[DeletedKey],
+) -> anyhow::Result<Vec<DeletedValue>> {
+    let mut found = HashMap::new();
+    for (offset, content) in free_cells(hive)? {
+        for (offset, record) in free_records(offset, content) {
+            if record.starts_with(b"vk")
+                && let Some(value) = plausible_value(record)
+            {
+                found.insert(offset, value);
+            }
+        }
+    }
+
+    // values lists of deleted keys first, then the whole cells of the live keys lists
+    let mut lists = Vec::new();
+    for key in keys {
+        let h = &key.node.header;
+        if h.number_of_key_values > 0 {
+            lists.push((
+                key.path.clone(),
+                h.key_values_list_offset,
+                h.number_of_key_values,
+            ));
+        }
+    }
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |_, path, key| {
+        let offset = key.node.header.key_values_list_offset;
+        if offset != NO_OFFSET {
+            lists.push((path.to_string(), offset, u32::MAX));
+        }
+        Ok(())
+    })?;
+
+    let mut owners = HashMap::new();
+    for (path, offset, count) in lists {
+        let Ok((_, list)) = hive.cell(offset) else {
+            continue;
+        };
+        let count = (count as usize).min(list.len() / 4);
+        for offset in key_values_list(list, count)? {
+            if found.contains_key(&offset) {
+                owners.entry(offset).or_insert_with(|| path.clone());
+            }
+        }
+    }
+
+    let mut values: Vec<DeletedValue> = found
+        .into_iter()
+        .map(|(offset, value)| DeletedValue {
+            offset,
+            path: owners.get(&offset).cloned(),
+            data: hive.value_data(&value).ok(),
+            value,
+        })
+        .collect();
+    values.sort_by_key(|v| v.offset);
+
+    Metrics::add(&hive.metrics().records_recovered, values.len() as u64);
+
+    Ok(values)
+}
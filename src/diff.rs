@@ -0,0 +1,176 @@
+// Differences between two states of the same hive (or subtree)
+use std::{collections::BTreeMap, fmt, path::Path, sync::Arc};
+
+use crate::{
+    hive::{Key, RegistryHive},
+    record::{Record, key_records},
+    utils::filetime_to_string,
+};
+
+#[derive(Debug, Clone)]
+pub struct KeySnapshot {
+    pub timestamp: u64,
+
+    // values by name, shared by the snapshots of an unchanged key
+    pub values: Arc<BTreeMap<String, Record>>,
+}
+
+// keys of a subtree by path
+pub type Snapshot = BTreeMap<String, KeySnapshot>;
+
+pub fn snapshot(hive: &mut RegistryHive, key: &Key, path: &str) -> anyhow::Result<Snapshot> {
+    let mut snapshot = Snapshot::new();
+
+    hive.walk(key, path, &mut |hive, path, key| {
+        let values = key_records(hive, path, key)?
+            .into_iter()
+            .map(|r| (r.value_name.clone(), r))
+            .collect();
+        snapshot.insert(
+            path.to_string(),
+            KeySnapshot {
+                timestamp: key.node.header.last_written_timestamp,
+                values: Arc::new(values),
+            },
+        );
+        Ok(())
+    })?;
+
+    Ok(snapshot)
+}
+
+// snapshot of the subtree under key of a hive file, which can be written to meanwhile (watched)
+pub fn load_snapshot(path: &Path, key: &str) -> anyhow::Result<Snapshot> {
+    let mut hive = RegistryHive::read(path)?;
+    let (key_path, start) = hive
+        .open_key_path(key)?
+        .ok_or_else(|| anyhow::anyhow!("key '{key}' not found in {}", path.display()))?;
+    snapshot(&mut hive, &start, &key_path)
+}
+
+#[derive(Debug, Clone)]
+pub enum Change {
+    KeyAdded(String),
+    KeyDeleted(String),
+
+    // last written timestamp changed: path, old and new timestamps
+    KeyTouched(String, u64, u64),
+    ValueAdded(Record),
+    ValueDeleted(Record),
+    ValueModified(Record, Record),
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |r: &Record| format!("{}\t{}\t{}", r.display_name(), r.reg_type, r.data());
+
+        match self {
+            Change::KeyAdded(path) => write!(f, "+ {path}"),
+            Change::KeyDeleted(path) => write!(f, "- {path}"),
+            Change::KeyTouched(path, old, new) => write!(
+                f,
+                "~ {path}\t{} -> {}",
+                filetime_to_string(*old),
+                filetime_to_string(*new)
+            ),
+            Change::ValueAdded(r) => write!(f, "+ {}\t{}", r.path, value(r)),
+            Change::ValueDeleted(r) => write!(f, "- {}\t{}", r.path, value(r)),
+            Change::ValueModified(old, new) => {
+                write!(f, "~ {}\t{} -> {}", new.path, value(old), value(new))
+            }
+        }
+    }
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Change::KeyAdded(path) | Change::KeyDeleted(path) | Change::KeyTouched(path, ..) => {
+                path
+            }
+            Change::ValueAdded(r) | Change::ValueDeleted(r) | Change::ValueModified(_, r) => {
+                &r.path
+            }
+        }
+    }
+}
+
+// changes from old to new, in path order
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for (path, key) in old {
+        if !new.contains_key(path) {
+            changes.push(Change::KeyDeleted(path.clone()));
+            changes.extend(key.values.values().cloned().map(Change::ValueDeleted));
+        }
+    }
+
+    for (path, key) in new {
+        let Some(old_key) = old.get(path) else {
+            changes.push(Change::KeyAdded(path.clone()));
+            changes.extend(key.values.values().cloned().map(Change::ValueAdded));
+            continue;
+        };
+
+        if old_key.timestamp != key.timestamp {
+            changes.push(Change::KeyTouched(
+                path.clone(),
+                old_key.timestamp,
+                key.timestamp,
+            ));
+        }
+
+        if Arc::ptr_eq(&old_key.values, &key.values) {
+            continue;
+        }
+        for (name, value) in old_key.values.iter() {
+            if !key.values.contains_key(name) {
+                changes.push(Change::ValueDeleted(value.clone()));
+            }
+        }
+        for (name, value) in key.values.iter() {
+            match old_key.values.get(name) {
+                None => changes.push(Change::ValueAdded(value.clone())),
+                Some(old_value)
+                    if old_value.reg_type != value.reg_type || old_value.data != value.data =>
+                {
+                    changes.push(Change::ValueModified(old_value.clone(), value.clone()))
+                }
+                _ => (),
+            }
+        }
+    }
+
+    // deleted keys were pushed first
+    changes.sort_by(|a, b| a.path().cmp(b.path()));
+    changes
+}
+
+// changes of a hive from a known-good reference hive. Last written times differ between any two
+// machines and are left out, keys and values missing from the hive are only kept when asked for
+pub fn deviations(reference: &Snapshot, hive: &Snapshot, missing: bool) -> Vec<Change> {
+    diff(reference, hive)
+        .into_iter()
+        .filter(|change| match change {
+            Change::KeyTouched(..) => false,
+            Change::KeyDeleted(_) | Change::ValueDeleted(_) => missing,
+            _ => true,
+        })
+        .collect()
+}
+
+// changes across successive snapshots of a hive (e.g. from volume shadow copies) by key, each with
+// the index of the snapshot where it appears, in the order of the snapshots
+pub fn history(snapshots: &[Snapshot]) -> BTreeMap<String, Vec<(usize, Change)>> {
+    let mut history: BTreeMap<String, Vec<(usize, Change)>> = BTreeMap::new();
+    for (i, pair) in snapshots.windows(2).enumerate() {
+        for change in diff(&pair[0], &pair[1]) {
+            history
+                .entry(change.path().to_string())
+                .or_default()
+                .push((i + 1, change));
+        }
+    }
+    history
+}
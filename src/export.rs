@@ -0,0 +1,714 @@
+// Exporters writing the keys and values of a subtree as text, JSON, JSON lines, .reg, CSV, bodyfile,
+// Elasticsearch/OpenSearch bulk requests, rows of the osquery registry table, CBOR, MessagePack,
+// GraphViz or Mermaid graphs of the keys or Parquet (with the parquet feature). SQLite databases are
+// written by sqlite.rs (with the sqlite feature)
+//
+// keys are written as soon as they are read: memory doesn't grow with the size of the hive
+use std::{collections::HashSet, fmt, io::Write, str::FromStr};
+
+#[cfg(feature = "parquet")]
+use crate::columnar::ParquetWriter;
+use crate::{
+    compact::{Encoding, write_key},
+    filter::ValueFilter,
+    hive::{Key, RegistryHive, join_path},
+    record::{Record, key_records},
+    template::Template,
+    utils::{filetime_to_iso8601, filetime_to_string, filetime_to_unix},
+    value::{REG_DEVPROP_FILETIME, RegData, RegType, base64_string, hex_string},
+};
+
+// keys of a mermaid diagram, larger ones aren't rendered by default (maxEdges is 500)
+const MERMAID_MAX_KEYS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Text,
+    Json,
+    Jsonl,
+    Reg,
+    Csv,
+    Bodyfile,
+    Bulk,
+    Osquery,
+    Cbor,
+    Msgpack,
+    Dot,
+    Mermaid,
+    Parquet,
+    Sqlite,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ExportFormat::Text),
+            "json" => Ok(ExportFormat::Json),
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "reg" => Ok(ExportFormat::Reg),
+            "csv" => Ok(ExportFormat::Csv),
+            "bodyfile" => Ok(ExportFormat::Bodyfile),
+            "bulk" => Ok(ExportFormat::Bulk),
+            "osquery" => Ok(ExportFormat::Osquery),
+            "cbor" => Ok(ExportFormat::Cbor),
+            "msgpack" => Ok(ExportFormat::Msgpack),
+            "dot" => Ok(ExportFormat::Dot),
+            "mermaid" => Ok(ExportFormat::Mermaid),
+            "parquet" => Ok(ExportFormat::Parquet),
+            "sqlite" => Ok(ExportFormat::Sqlite),
+            _ => anyhow::bail!(
+                "unknown export format '{s}' (text, json, jsonl, reg, csv, bodyfile, bulk, osquery, \
+                 cbor, msgpack, dot, mermaid, parquet or sqlite)"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportFormat::Text => write!(f, "text"),
+            ExportFormat::Json => write!(f, "json"),
+            ExportFormat::Jsonl => write!(f, "jsonl"),
+            ExportFormat::Reg => write!(f, "reg"),
+            ExportFormat::Csv => write!(f, "csv"),
+            ExportFormat::Bodyfile => write!(f, "bodyfile"),
+            ExportFormat::Bulk => write!(f, "bulk"),
+            ExportFormat::Osquery => write!(f, "osquery"),
+            ExportFormat::Cbor => write!(f, "cbor"),
+            ExportFormat::Msgpack => write!(f, "msgpack"),
+            ExportFormat::Dot => write!(f, "dot"),
+            ExportFormat::Mermaid => write!(f, "mermaid"),
+            ExportFormat::Parquet => write!(f, "parquet"),
+            ExportFormat::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+
+    // line format of the text exporter
+    pub template: Template,
+
+    // key the hive is mounted on, used by the .reg, bulk and osquery exporters (e.g.
+    // "HKEY_LOCAL_MACHINE\SYSTEM")
+    pub reg_root: String,
+
+    // index of the documents of the bulk exporter
+    pub index: String,
+
+    // keys of the dot and mermaid graphs labelled with their number of values and last written timestamp
+    pub annotate: bool,
+
+    // keys without any matching value are skipped when filtering
+    pub filter: ValueFilter,
+}
+
+// write the subtree starting at key
+pub fn export<W: Write>(
+    hive: &mut RegistryHive,
+    key: &Key,
+    path: &str,
+    options: &ExportOptions,
+    out: &mut W,
+) -> anyhow::Result<()> {
+    match options.format {
+        ExportFormat::Json => write!(out, "[")?,
+        ExportFormat::Reg => write!(out, "Windows Registry Editor Version 5.00\r\n")?,
+        ExportFormat::Csv => writeln!(out, "path,last_written,value_name,type,size,data")?,
+        ExportFormat::Osquery => writeln!(out, "key,path,name,type,data,mtime")?,
+        ExportFormat::Dot => {
+            writeln!(out, "digraph registry {{")?;
+            writeln!(out, "  rankdir=LR;")?;
+            writeln!(out, "  node [shape=box, fontname=\"monospace\"];")?;
+        }
+        ExportFormat::Mermaid => writeln!(out, "graph TD")?,
+        ExportFormat::Sqlite => anyhow::bail!("sqlite export is written to a database file"),
+        _ => (),
+    }
+    #[cfg(not(feature = "parquet"))]
+    anyhow::ensure!(
+        options.format != ExportFormat::Parquet,
+        "parquet export needs readregf built with the parquet feature"
+    );
+    #[cfg(feature = "parquet")]
+    let mut parquet = match options.format {
+        ExportFormat::Parquet => Some(ParquetWriter::new()?),
+        _ => None,
+    };
+
+    let mut first = true;
+    let start = key.offset;
+    // nodes of the graph, keys skipped by the filter have none
+    let mut nodes = HashSet::new();
+    // values are read and filtered in parallel
+    let read_records = |hive: &RegistryHive, path: &str, key: &Key| {
+        Ok(key_records(hive, path, key)?
+            .into_iter()
+            .filter(|r| options.filter.matches(r))
+            .collect::<Vec<Record>>())
+    };
+    hive.par_walk(key, path, read_records, &mut |path, key, records| {
+        if records.is_empty() && !options.filter.is_empty() {
+            return Ok(());
+        }
+
+        let timestamp = key.node.header.last_written_timestamp;
+
+        match options.format {
+            ExportFormat::Text => {
+                for record in &records {
+                    writeln!(out, "{}", options.template.render(record))?;
+                }
+            }
+            ExportFormat::Json => {
+                if !first {
+                    write!(out, ",")?;
+                }
+                write_json_key(out, path, timestamp, &records, false)?;
+            }
+            // one object per line, same as json
+            ExportFormat::Jsonl => write_json_key(out, path, timestamp, &records, true)?,
+            ExportFormat::Reg => write_reg_key(out, &options.reg_root, path, &records)?,
+            ExportFormat::Bulk => write_bulk_key(out, options, path, timestamp, &records)?,
+            ExportFormat::Cbor => write_key(out, Encoding::Cbor, path, timestamp, &records)?,
+            ExportFormat::Msgpack => {
+                write_key(out, Encoding::MessagePack, path, timestamp, &records)?
+            }
+            ExportFormat::Dot | ExportFormat::Mermaid => {
+                // the subtree starts at the full path of its key, other keys are named
+                let name = if key.offset == start {
+                    path
+                } else {
+                    key.name()
+                };
+                let mut lines = vec![name.to_string()];
+                if options.annotate {
+                    let plural = if records.len() == 1 { "" } else { "s" };
+                    lines.push(format!("{} value{plural}", records.len()));
+                    lines.push(filetime_to_string(timestamp));
+                }
+
+                let (offset, parent) = (key.offset, key.node.header.parent);
+                if options.format == ExportFormat::Dot {
+                    let lines: Vec<String> = lines.iter().map(|l| dot_escape(l)).collect();
+                    writeln!(out, "  k{offset} [label=\"{}\"];", lines.join("\\n"))?;
+                    if nodes.contains(&parent) {
+                        writeln!(out, "  k{parent} -> k{offset};")?;
+                    }
+                } else {
+                    // diagrams of a few hundred nodes at most are rendered
+                    anyhow::ensure!(
+                        nodes.len() < MERMAID_MAX_KEYS,
+                        "more than {MERMAID_MAX_KEYS} keys for a mermaid diagram, choose a smaller \
+                         subtree with --key"
+                    );
+                    let lines: Vec<String> = lines.iter().map(|l| mermaid_escape(l)).collect();
+                    writeln!(out, "  k{offset}[\"{}\"]", lines.join("<br/>"))?;
+                    if nodes.contains(&parent) {
+                        writeln!(out, "  k{parent} --> k{offset}")?;
+                    }
+                }
+                nodes.insert(offset);
+            }
+            ExportFormat::Osquery => {
+                let subkey = key.offset != start;
+                write_osquery_key(out, &options.reg_root, path, timestamp, &records, subkey)?
+            }
+            ExportFormat::Csv => {
+                // keys without values still get a row
+                if records.is_empty() {
+                    writeln!(
+                        out,
+                        "{},{},,,,",
+                        csv_field(path),
+                        filetime_to_string(timestamp)
+                    )?;
+                }
+                for record in &records {
+                    writeln!(
+                        out,
+                        "{},{},{},{},{},{}",
+                        csv_field(path),
+                        filetime_to_string(timestamp),
+                        csv_field(&record.value_name),
+                        record.reg_type,
+                        record.data.len(),
+                        csv_field(&record.data().to_string())
+                    )?;
+                }
+            }
+            ExportFormat::Bodyfile => {
+                // mactime format: MD5|name|inode|mode|UID|GID|size|atime|mtime|ctime|crtime
+                writeln!(
+                    out,
+                    "0|[Registry] {}|0|0|0|0|0|0|{}|0|0",
+                    path.replace('|', "_"),
+                    filetime_to_unix(timestamp)
+                )?;
+            }
+            ExportFormat::Sqlite => (),
+            ExportFormat::Parquet =>
+            {
+                #[cfg(feature = "parquet")]
+                if let Some(parquet) = &mut parquet {
+                    parquet.write_key(path, timestamp, &records, out)?;
+                }
+            }
+        }
+
+        first = false;
+        Ok(())
+    })?;
+
+    match options.format {
+        ExportFormat::Json => writeln!(out, "\n]")?,
+        ExportFormat::Dot => writeln!(out, "}}")?,
+        _ => (),
+    }
+    #[cfg(feature = "parquet")]
+    if let Some(parquet) = parquet {
+        parquet.finish(out)?;
+    }
+
+    Ok(())
+}
+
+// an object on a single line for JSON lines, indented inside the array otherwise
+fn write_json_key<W: Write>(
+    out: &mut W,
+    path: &str,
+    timestamp: u64,
+    records: &[Record],
+    single_line: bool,
+) -> anyhow::Result<()> {
+    let (key_indent, value_indent) = if single_line {
+        ("", "")
+    } else {
+        ("\n  ", "\n    ")
+    };
+
+    write!(
+        out,
+        "{key_indent}{{\"path\": {}, \"last_written\": {}, \"values\": [",
+        json_string(path),
+        json_string(&filetime_to_iso8601(timestamp))
+    )?;
+
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            write!(out, ",")?;
+        }
+        write!(
+            out,
+            "{value_indent}{{\"name\": {}, \"type\": {}, \"size\": {}, \"data\": {}}}",
+            json_string(&record.value_name),
+            json_string(&record.reg_type.to_string()),
+            record.data.len(),
+            json_data(&record.data())
+        )?;
+    }
+
+    if !records.is_empty() {
+        write!(out, "{key_indent}")?;
+    }
+    write!(out, "]}}")?;
+    if single_line {
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+// strings and numbers are kept as such, device property FILETIMEs are dates, anything else is
+// base64 encoded with its length
+fn json_data(data: &RegData) -> String {
+    match data {
+        RegData::Sz(s) | RegData::ExpandSz(s) | RegData::Link(s) => json_string(s),
+        RegData::Dword(d) | RegData::DwordBigEndian(d) => d.to_string(),
+        RegData::Qword(q) => q.to_string(),
+        RegData::MultiSz(v) => {
+            let strings: Vec<String> = v.iter().map(|s| json_string(s)).collect();
+            format!("[{}]", strings.join(", "))
+        }
+        RegData::Other(RegType::Unknown(REG_DEVPROP_FILETIME), b) if b.len() == 8 => json_string(
+            &filetime_to_iso8601(u64::from_le_bytes(b[..].try_into().unwrap())),
+        ),
+        RegData::None(b) | RegData::Binary(b) | RegData::Other(_, b) => format!(
+            "{{\"base64\": {}, \"length\": {}}}",
+            json_string(&base64_string(b)),
+            b.len()
+        ),
+    }
+}
+
+// types whose data isn't base64 encoded
+const JSON_TYPED: [RegType; 8] = [
+    RegType::Sz,
+    RegType::ExpandSz,
+    RegType::Link,
+    RegType::Dword,
+    RegType::DwordBigEndian,
+    RegType::Qword,
+    RegType::MultiSz,
+    RegType::Unknown(REG_DEVPROP_FILETIME),
+];
+
+// schema of the data json_data writes for a type; numbers and dates too short for their type are
+// written as binary
+fn json_data_schema(reg_type: RegType) -> String {
+    let binary = r##"{"$ref": "#/$defs/binary"}"##;
+    let or_binary = |schema: &str, description: &str| {
+        format!(r#"{{"description": "{description}", "oneOf": [{schema}, {binary}]}}"#)
+    };
+    match reg_type {
+        RegType::Sz | RegType::ExpandSz | RegType::Link => {
+            r#"{"description": "UTF-16 string, up to its first NUL", "type": "string"}"#.into()
+        }
+        RegType::Dword | RegType::DwordBigEndian => or_binary(
+            r#"{"type": "integer", "minimum": 0, "maximum": 4294967295}"#,
+            "32-bit unsigned number",
+        ),
+        RegType::Qword => or_binary(
+            r#"{"type": "integer", "minimum": 0, "maximum": 18446744073709551615}"#,
+            "64-bit unsigned number",
+        ),
+        RegType::MultiSz => {
+            r#"{"description": "UTF-16 strings", "type": "array", "items": {"type": "string"}}"#
+                .into()
+        }
+        RegType::Unknown(REG_DEVPROP_FILETIME) => or_binary(
+            r#"{"type": "string", "format": "date-time"}"#,
+            "FILETIME of a device property, ISO-8601 UTC",
+        ),
+        _ => binary.into(),
+    }
+}
+
+// JSON Schema of the json export, a jsonl line being one of its items
+pub fn json_schema() -> String {
+    let typed: Vec<String> = JSON_TYPED
+        .iter()
+        .map(|reg_type| {
+            format!(
+                r#"        {{"if": {{"properties": {{"type": {{"const": "{reg_type}"}}}}}},
+         "then": {{"properties": {{"data": {}}}}}}}"#,
+                json_data_schema(*reg_type)
+            )
+        })
+        .collect();
+    let names: Vec<String> = JSON_TYPED.iter().map(|t| format!("\"{t}\"")).collect();
+
+    format!(
+        r##"{{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "readregf export",
+  "description": "keys of a hive as written by export --format json; each line of --format jsonl is one of the keys",
+  "type": "array",
+  "items": {{"$ref": "#/$defs/key"}},
+  "$defs": {{
+    "key": {{
+      "type": "object",
+      "required": ["path", "last_written", "values"],
+      "properties": {{
+        "path": {{"description": "path of the key from the root key", "type": "string"}},
+        "last_written": {{"description": "last written timestamp, ISO-8601 UTC", "type": "string", "format": "date-time"}},
+        "values": {{"type": "array", "items": {{"$ref": "#/$defs/value"}}}}
+      }}
+    }},
+    "value": {{
+      "type": "object",
+      "required": ["name", "type", "size", "data"],
+      "properties": {{
+        "name": {{"description": "empty for the default value", "type": "string"}},
+        "type": {{"description": "REG_ name, or the hex number of other types", "type": "string"}},
+        "size": {{"description": "size of the data in bytes", "type": "integer", "minimum": 0}},
+        "data": true
+      }},
+      "allOf": [
+{},
+        {{"if": {{"properties": {{"type": {{"not": {{"enum": [{}]}}}}}}}},
+         "then": {{"properties": {{"data": {binary}}}}}}}
+      ]
+    }},
+    "binary": {{
+      "description": "data of the other types",
+      "type": "object",
+      "required": ["base64", "length"],
+      "properties": {{
+        "base64": {{"description": "data encoded with the standard alphabet, padded", "type": "string", "contentEncoding": "base64"}},
+        "length": {{"description": "size of the data in bytes", "type": "integer", "minimum": 0}}
+      }}
+    }}
+  }}
+}}"##,
+        typed.join(",\n"),
+        names.join(", "),
+        binary = json_data_schema(RegType::Binary),
+    )
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+// quote fields containing separators, quotes or line breaks
+pub fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// regedit format: CRLF line endings, a blank line before each key
+fn write_reg_key<W: Write>(
+    out: &mut W,
+    root: &str,
+    path: &str,
+    records: &[Record],
+) -> anyhow::Result<()> {
+    write!(out, "\r\n[{root}{}]\r\n", path.trim_end_matches('\\'))?;
+
+    for record in records {
+        let name = if record.value_name.is_empty() {
+            "@".to_string()
+        } else {
+            format!("\"{}\"", reg_escape(&record.value_name))
+        };
+
+        let data = match record.data() {
+            // regedit writes the string back with a terminating NUL, other data (odd size, no or
+            // several NULs, invalid UTF-16) wouldn't be restored as it is
+            RegData::Sz(s) if reg_sz_bytes(&s) == record.data => {
+                format!("\"{}\"", reg_escape(&s))
+            }
+            RegData::Dword(d) if record.data.len() == 4 => format!("dword:{d:08x}"),
+            RegData::Binary(b) => format!("hex:{}", reg_hex(&b)),
+            _ => format!(
+                "hex({:x}):{}",
+                u32::from(record.reg_type),
+                reg_hex(&record.data)
+            ),
+        };
+
+        write!(out, "{name}={data}\r\n")?;
+    }
+
+    Ok(())
+}
+
+// a document per value, or per key without values, each preceded by its index action. Fields are
+// the ones of the Elastic Common Schema (ECS) registry fields set, e.g.
+// registry.path: HKLM\SYSTEM\ControlSet001\Control\Lsa\RunAsPPL
+fn write_bulk_key<W: Write>(
+    out: &mut W,
+    options: &ExportOptions,
+    path: &str,
+    timestamp: u64,
+    records: &[Record],
+) -> anyhow::Result<()> {
+    // the hive is abbreviated, the key starts with the rest of the root (e.g. SYSTEM)
+    let (hive, root) = options
+        .reg_root
+        .split_once('\\')
+        .unwrap_or((&options.reg_root, ""));
+    let hive = match hive.to_uppercase().as_str() {
+        "HKEY_LOCAL_MACHINE" => "HKLM",
+        "HKEY_CURRENT_USER" => "HKCU",
+        "HKEY_USERS" => "HKU",
+        "HKEY_CLASSES_ROOT" => "HKCR",
+        "HKEY_CURRENT_CONFIG" => "HKCC",
+        _ => hive,
+    };
+    let key = format!("{root}{}", path.trim_end_matches('\\'))
+        .trim_start_matches('\\')
+        .to_string();
+    let key_path = format!("{hive}\\{key}");
+
+    let action = format!(
+        r#"{{"index": {{"_index": {}}}}}"#,
+        json_string(&options.index)
+    );
+    let document = |fields: String| {
+        format!(
+            r#"{{"@timestamp": {}, "registry": {{"hive": {}, "key": {}, {fields}}}}}"#,
+            json_string(&filetime_to_iso8601(timestamp)),
+            json_string(hive),
+            json_string(&key)
+        )
+    };
+
+    if records.is_empty() {
+        writeln!(out, "{action}")?;
+        writeln!(
+            out,
+            "{}",
+            document(format!(r#""path": {}"#, json_string(&key_path)))
+        )?;
+    }
+    for record in records {
+        // strings and numbers as strings, the bytes of anything else
+        let strings = match record.data() {
+            RegData::Sz(s) | RegData::ExpandSz(s) | RegData::Link(s) => Some(vec![s]),
+            RegData::MultiSz(v) => Some(v),
+            RegData::Dword(d) | RegData::DwordBigEndian(d) => Some(vec![d.to_string()]),
+            RegData::Qword(q) => Some(vec![q.to_string()]),
+            _ => None,
+        };
+        let data = match strings {
+            Some(strings) => {
+                let strings: Vec<String> = strings.iter().map(|s| json_string(s)).collect();
+                format!(r#""strings": [{}]"#, strings.join(", "))
+            }
+            None => format!(r#""bytes": {}"#, json_string(&base64_string(&record.data))),
+        };
+
+        writeln!(out, "{action}")?;
+        writeln!(
+            out,
+            "{}",
+            document(format!(
+                r#""path": {}, "value": {}, "data": {{"type": {}, "size": {}, {data}}}"#,
+                json_string(&join_path(&key_path, &record.value_name)),
+                json_string(&record.value_name),
+                json_string(&record.reg_type.to_string()),
+                record.data.len()
+            ))
+        )?;
+    }
+    Ok(())
+}
+
+// rows of the osquery registry table (key, path, name, type, data, mtime) as osqueryi --csv would
+// print them with a comma separator: a "subkey" row in its parent key for each key but the first
+// one, then a row per value, its mtime being the one of the key. The default value is named
+// "(Default)", numbers are written in decimal, lists of strings joined with commas and data of the
+// other types as uppercase hex
+fn write_osquery_key<W: Write>(
+    out: &mut W,
+    root: &str,
+    path: &str,
+    timestamp: u64,
+    records: &[Record],
+    subkey: bool,
+) -> anyhow::Result<()> {
+    let key = format!("{root}{}", path.trim_end_matches('\\'));
+    let mtime = filetime_to_unix(timestamp);
+
+    if subkey && let Some((parent, name)) = key.rsplit_once('\\') {
+        writeln!(
+            out,
+            "{},{},{},subkey,,{mtime}",
+            csv_field(parent),
+            csv_field(&key),
+            csv_field(name)
+        )?;
+    }
+    for record in records {
+        let name = match record.value_name.as_str() {
+            "" => "(Default)",
+            name => name,
+        };
+        let reg_type = match record.reg_type {
+            RegType::Unknown(_) => "UNKNOWN".to_string(),
+            reg_type => reg_type.to_string(),
+        };
+        let data = match record.data() {
+            RegData::Sz(s) | RegData::ExpandSz(s) | RegData::Link(s) => s,
+            RegData::MultiSz(v) => v.join(","),
+            RegData::Dword(d) | RegData::DwordBigEndian(d) => d.to_string(),
+            RegData::Qword(q) => q.to_string(),
+            _ => hex_string(&record.data),
+        };
+        writeln!(
+            out,
+            "{},{},{},{reg_type},{},{mtime}",
+            csv_field(&key),
+            csv_field(&join_path(&key, name)),
+            csv_field(name),
+            csv_field(&data)
+        )?;
+    }
+    Ok(())
+}
+
+// index mapping of the documents of the bulk exporter, to create the index with
+pub fn bulk_mapping() -> String {
+    r#"{
+  "mappings": {
+    "properties": {
+      "@timestamp": {"type": "date_nanos"},
+      "registry": {
+        "properties": {
+          "hive": {"type": "keyword"},
+          "key": {"type": "keyword"},
+          "path": {"type": "keyword"},
+          "value": {"type": "keyword"},
+          "data": {
+            "properties": {
+              "type": {"type": "keyword"},
+              "size": {"type": "long"},
+              "strings": {"type": "keyword"},
+              "bytes": {"type": "binary"}
+            }
+          }
+        }
+      }
+    }
+  }
+}"#
+    .to_string()
+}
+
+// inside double quotes, characters of the markup are written as entity codes
+fn mermaid_escape(s: &str) -> String {
+    s.replace('#', "#35;")
+        .replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+        .replace(['\r', '\n'], " ")
+}
+
+// inside double quotes, line breaks are kept as \n
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+fn reg_sz_bytes(s: &str) -> Vec<u8> {
+    s.encode_utf16()
+        .chain([0])
+        .flat_map(|u| u.to_le_bytes())
+        .collect()
+}
+
+fn reg_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn reg_hex(data: &[u8]) -> String {
+    let bytes: Vec<String> = data.iter().map(|b| format!("{b:02x}")).collect();
+    bytes.join(",")
+}
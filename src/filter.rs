@@ -0,0 +1,27 @@
+// Filters on values used by exports and searches
+use crate::{record::Record, utils::glob_match, value::RegType};
+
+#[derive(Debug, Clone, Default)]
+pub struct ValueFilter {
+    // wildcard pattern on the value name ("(default)" for the default value)
+    pub name_glob: Option<String>,
+
+    // any of these types, all types when empty
+    pub types: Vec<RegType>,
+    pub min_data_size: usize,
+}
+
+impl ValueFilter {
+    // no filtering at all
+    pub fn is_empty(&self) -> bool {
+        self.name_glob.is_none() && self.types.is_empty() && self.min_data_size == 0
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        self.name_glob
+            .as_ref()
+            .is_none_or(|glob| glob_match(glob, record.display_name()))
+            && (self.types.is_empty() || self.types.contains(&record.reg_type))
+            && record.data.len() >= self.min_data_size
+    }
+}
@@ -0,0 +1,69 @@
+// Findings reported by analysis passes (anomalies, suspicious permissions, ...)
+use std::fmt;
+
+use crate::utils::escape_name;
+
+// ordered from the least to the most important
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "INFO"),
+            Severity::Low => write!(f, "LOW"),
+            Severity::Medium => write!(f, "MEDIUM"),
+            Severity::High => write!(f, "HIGH"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+
+    // short identifier of the check which produced the finding
+    pub category: String,
+
+    // key path the finding is about
+    pub path: String,
+    pub description: String,
+}
+
+impl Finding {
+    pub fn new(severity: Severity, category: &str, path: &str, description: String) -> Self {
+        Self {
+            severity,
+            category: category.to_string(),
+            path: path.to_string(),
+            description,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} {}: {}",
+            self.severity,
+            self.category,
+            escape_name(&self.path),
+            self.description
+        )
+    }
+}
+
+// most important first, then by path
+pub fn sort_findings(findings: &mut [Finding]) {
+    findings.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+}
@@ -0,0 +1,733 @@
+// Random access to a hive: cells are resolved by their offset to walk the keys tree
+//
+// all offsets found in records are relative to the start of the hive bins data, which immediately
+// follows the 4096 bytes base block:
+//
+// +--------------------+------------------------------+
+// |     Base block     |        Hive bins data        |
+// +--------------------+------------------------------+
+// 0                  4096 <- offset 0
+//
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+    sync::{Arc, Mutex, OnceLock},
+    time::Instant,
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    buffer::{HiveBuffer, ParserOptions, default_options},
+    cache::BigDataCache,
+    index::CellIndex,
+    key::{KeyNode, NO_OFFSET},
+    list::{SubkeysList, key_values_list, name_hash, name_hint, upcase_unit},
+    metrics::{Metrics, totals},
+    reg::{BaseBlock, bincode_config},
+    security::KeySecurity,
+    sidecar::KeyPathIndex,
+    utils::{escape_name, glob_match},
+    value::{BIG_DATA_SEGMENT_SIZE, BigData, KeyValue, RegData, ValueBytes},
+};
+
+// size of the base block, hive bins data start right after
+pub const BASE_BLOCK_SIZE: u64 = 4096;
+
+// don't walk deeper than this (loops in corrupted hives)
+pub const MAX_DEPTH: usize = 512;
+
+// number of keys processed in parallel by par_walk and upper bound of the size of their value data,
+// bounding the results held in memory
+const PAR_WALK_BATCH: usize = 1024;
+const PAR_WALK_BATCH_DATA: u64 = 16 * 1024 * 1024;
+
+// a key node along with its offset
+#[derive(Debug, Clone)]
+pub struct Key {
+    pub offset: u32,
+    pub node: KeyNode,
+}
+
+impl Key {
+    pub fn name(&self) -> &str {
+        &self.node.name
+    }
+}
+
+// what a subkeys list tells about the name of a key: its first 4 characters (lf lists) or a hash of
+// it (lh lists)
+enum NameCheck {
+    None,
+    Hint([u8; 4]),
+    Hash(u32),
+}
+
+impl NameCheck {
+    // hint characters which are not Latin-1 are 0
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NameCheck::None => true,
+            NameCheck::Hint(hint) => hint
+                .iter()
+                .zip(name_hint(name))
+                .all(|(a, b)| *a == 0 || b == 0 || a.eq_ignore_ascii_case(&b)),
+            NameCheck::Hash(hash) => *hash == name_hash(name),
+        }
+    }
+}
+
+// records are parsed from views into the content of the hive, cells aren't copied
+pub struct RegistryHive {
+    data: HiveBuffer,
+    pub base_block: BaseBlock,
+
+    // built on first use
+    index: OnceLock<CellIndex>,
+
+    // reassembled big data values
+    big_data: Mutex<BigDataCache>,
+
+    // keys by path, loaded from the sidecar file when asked for
+    key_paths: Option<KeyPathIndex>,
+
+    metrics: Metrics,
+}
+
+impl RegistryHive {
+    // the file is mapped in memory, unless the default options say otherwise
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        Self::open_with(path, default_options())
+    }
+
+    // the file is mapped or read as set by the options
+    pub fn open_with(path: &Path, options: &ParserOptions) -> anyhow::Result<Self> {
+        let start = Instant::now();
+        let hive = Self::from_buffer(HiveBuffer::open_with(path, options)?)?;
+        hive.metrics.record_phase("open", start.elapsed());
+        Ok(hive)
+    }
+
+    // the file is read, for files which can be modified while being read
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let start = Instant::now();
+        let hive = Self::from_buffer(HiveBuffer::read(path)?)?;
+        hive.metrics.record_phase("open", start.elapsed());
+        Ok(hive)
+    }
+
+    // the whole content of the reader is read
+    pub fn new<R: Read + Seek>(mut reader: R) -> anyhow::Result<Self> {
+        let start = Instant::now();
+        reader.seek(SeekFrom::Start(0))?;
+        let limit = default_options().memory_limit;
+        let mut bytes = Vec::new();
+        reader
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut bytes)?;
+        default_options().check_size(bytes.len() as u64, "hive")?;
+        let hive = Self::from_buffer(HiveBuffer::from(bytes))?;
+        hive.metrics.record_phase("open", start.elapsed());
+        Ok(hive)
+    }
+
+    // same as new for async services (hives streamed from object storage or remote agents): the
+    // content is read without blocking the executor, the hive is then parsed from memory
+    #[cfg(feature = "async")]
+    pub async fn new_async<R>(mut reader: R) -> anyhow::Result<Self>
+    where
+        R: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin,
+    {
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        let start = Instant::now();
+        reader.seek(SeekFrom::Start(0)).await?;
+        let limit = default_options().memory_limit;
+        let mut bytes = Vec::new();
+        reader
+            .take(limit.saturating_add(1))
+            .read_to_end(&mut bytes)
+            .await?;
+        default_options().check_size(bytes.len() as u64, "hive")?;
+        let hive = Self::from_buffer(HiveBuffer::from(bytes))?;
+        hive.metrics.record_phase("open", start.elapsed());
+        Ok(hive)
+    }
+
+    pub fn from_buffer(data: HiveBuffer) -> anyhow::Result<Self> {
+        anyhow::ensure!(data.starts_with(b"regf"), "not a registry hive");
+        anyhow::ensure!(
+            data.len() as u64 >= BASE_BLOCK_SIZE,
+            "base block truncated to {} bytes",
+            data.len()
+        );
+        let (base_block, _): (BaseBlock, usize) =
+            bincode::decode_from_slice(&data, bincode_config())?;
+
+        Ok(Self {
+            data,
+            base_block,
+            index: OnceLock::new(),
+            big_data: Mutex::new(BigDataCache::default()),
+            key_paths: None,
+            metrics: Metrics::new(),
+        })
+    }
+
+    // counters of this hive so far
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    // content of the whole file, base block included
+    pub fn buffer(&self) -> &HiveBuffer {
+        &self.data
+    }
+
+    // all the cells of the hbins, swept once
+    pub fn index(&self) -> anyhow::Result<&CellIndex> {
+        if let Some(index) = self.index.get() {
+            return Ok(index);
+        }
+        let index = self
+            .metrics
+            .time("index", || CellIndex::build(&self.data))?;
+        Ok(self.index.get_or_init(|| index))
+    }
+
+    // the cell at an offset, returns its size and content (after the size field)
+    pub fn cell(&self, offset: u32) -> anyhow::Result<(i32, &[u8])> {
+        anyhow::ensure!(
+            offset != NO_OFFSET && offset < self.base_block.hive_bins_data_size,
+            "cell offset 0x{offset:X} out of hive bins data"
+        );
+        let start = BASE_BLOCK_SIZE as usize + offset as usize;
+
+        let Some(bytes) = self.data.get(start..start + 4) else {
+            anyhow::bail!("cell offset 0x{offset:X} past the end of the file");
+        };
+        let size = i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        let abs_size = size.unsigned_abs() as usize;
+        anyhow::ensure!(
+            abs_size >= 4
+                && offset as usize + abs_size <= self.base_block.hive_bins_data_size as usize,
+            "invalid cell size {size} at offset 0x{offset:X}"
+        );
+
+        let Some(data) = self.data.get(start + 4..start + abs_size) else {
+            anyhow::bail!("cell at offset 0x{offset:X} truncated by the end of the file");
+        };
+        Metrics::add(&self.metrics.cells_read, 1);
+        Metrics::add(&self.metrics.bytes_read, data.len() as u64);
+        Ok((size, data))
+    }
+
+    pub fn key(&self, offset: u32) -> anyhow::Result<Key> {
+        let (_, data) = self.cell(offset)?;
+        let node = KeyNode::try_from(data)?;
+        Metrics::add(&self.metrics.keys_parsed, 1);
+        Ok(Key { offset, node })
+    }
+
+    pub fn root(&self) -> anyhow::Result<Key> {
+        self.key(self.base_block.root_cell_offset)
+    }
+
+    pub fn subkeys(&self, key: &Key) -> anyhow::Result<Vec<Key>> {
+        self.subkey_offsets(key)?
+            .into_iter()
+            .map(|o| self.key(o))
+            .collect()
+    }
+
+    // offsets of the subkeys, without reading them
+    pub fn subkey_offsets(&self, key: &Key) -> anyhow::Result<Vec<u32>> {
+        if key.node.header.number_of_subkeys == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut offsets = Vec::new();
+        self.subkeys_offsets(key.node.header.subkeys_list_offset, &mut offsets, 0)?;
+        Ok(offsets)
+    }
+
+    // an Index root points to other lists
+    fn subkeys_offsets(
+        &self,
+        list_offset: u32,
+        offsets: &mut Vec<u32>,
+        depth: usize,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(depth < 2, "nested index roots at 0x{list_offset:X}");
+
+        let (_, data) = self.cell(list_offset)?;
+        match SubkeysList::try_from(data)? {
+            SubkeysList::IndexRoot(lists) => {
+                for list in lists {
+                    self.subkeys_offsets(list, offsets, depth + 1)?;
+                }
+            }
+            list => offsets.extend(list.offsets()),
+        }
+
+        Ok(())
+    }
+
+    // subkey with this name. Subkeys lists are sorted by name: a binary search reads a few key nodes
+    // only, checking they're in order and match their name hint or hash. When they don't (corrupted
+    // or tampered lists), the key nodes whose name hint or hash matches are read, then all the
+    // subkeys. Escaped names (<U+XXXX>) are always searched among all the subkeys.
+    pub fn subkey(&self, key: &Key, name: &str) -> anyhow::Result<Option<Key>> {
+        if key.node.header.number_of_subkeys == 0 {
+            return Ok(None);
+        }
+
+        let mut entries = Vec::new();
+        self.subkey_entries(key.node.header.subkeys_list_offset, &mut entries, 0)?;
+
+        // sort keys of the closest nodes read before and after the name
+        let target = name_sort_key(name);
+        let (mut low, mut high) = (0, entries.len());
+        let (mut below, mut above): (Option<Vec<u16>>, Option<Vec<u16>>) = (None, None);
+        let mut consistent = true;
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let (offset, check) = &entries[mid];
+            let Ok(subkey) = self.key(*offset) else {
+                consistent = false;
+                break;
+            };
+            let sort_key = name_sort_key(subkey.name());
+            if !check.matches(subkey.name())
+                || below.as_ref().is_some_and(|b| *b >= sort_key)
+                || above.as_ref().is_some_and(|a| *a <= sort_key)
+            {
+                consistent = false;
+                break;
+            }
+
+            match sort_key.cmp(&target) {
+                Ordering::Equal => return Ok(Some(subkey)),
+                Ordering::Less => {
+                    low = mid + 1;
+                    below = Some(sort_key);
+                }
+                Ordering::Greater => {
+                    high = mid;
+                    above = Some(sort_key);
+                }
+            }
+        }
+        if consistent && !name.contains("<U+") {
+            return Ok(None);
+        }
+
+        for (offset, check) in &entries {
+            if !check.matches(name) {
+                continue;
+            }
+            let subkey = self.key(*offset)?;
+            if names_equal(subkey.name(), name) {
+                return Ok(Some(subkey));
+            }
+        }
+
+        Ok(self
+            .subkeys(key)?
+            .into_iter()
+            .find(|k| names_equal(k.name(), name) || names_equal(&escape_name(k.name()), name)))
+    }
+
+    // key node offsets of the leaves, with the name hint or hash they hold
+    fn subkey_entries(
+        &self,
+        list_offset: u32,
+        entries: &mut Vec<(u32, NameCheck)>,
+        depth: usize,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(depth < 2, "nested index roots at 0x{list_offset:X}");
+
+        let (_, data) = self.cell(list_offset)?;
+        match SubkeysList::try_from(data)? {
+            SubkeysList::IndexRoot(lists) => {
+                for list in lists {
+                    self.subkey_entries(list, entries, depth + 1)?;
+                }
+            }
+            SubkeysList::FastLeaf(v) => {
+                entries.extend(v.into_iter().map(|(o, h)| (o, NameCheck::Hint(h))))
+            }
+            SubkeysList::HashLeaf(v) => {
+                entries.extend(v.into_iter().map(|(o, h)| (o, NameCheck::Hash(h))))
+            }
+            SubkeysList::IndexLeaf(v) => {
+                entries.extend(v.into_iter().map(|o| (o, NameCheck::None)))
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn values(&self, key: &Key) -> anyhow::Result<Vec<KeyValue>> {
+        self.value_offsets(key)?
+            .into_iter()
+            .map(|o| self.value(o))
+            .collect()
+    }
+
+    // offsets of the values, without reading them
+    pub fn value_offsets(&self, key: &Key) -> anyhow::Result<Vec<u32>> {
+        let count = key.node.header.number_of_key_values as usize;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let (_, data) = self.cell(key.node.header.key_values_list_offset)?;
+        key_values_list(data, count)
+    }
+
+    pub fn value(&self, offset: u32) -> anyhow::Result<KeyValue> {
+        let (_, data) = self.cell(offset)?;
+        let value = KeyValue::try_from(data)?;
+        Metrics::add(&self.metrics.values_parsed, 1);
+        Ok(value)
+    }
+
+    // raw value data: resident, in a data cell or split into big data segments
+    pub fn value_bytes(&self, value: &KeyValue) -> anyhow::Result<ValueBytes<'_>> {
+        let size = value.data_size() as usize;
+        if value.is_resident() {
+            return Ok(value.resident_data());
+        }
+        if size == 0 {
+            return Ok(ValueBytes::Borrowed(&[]));
+        }
+
+        let (_, cell) = self.cell(value.header.data_offset)?;
+        if size > BIG_DATA_SEGMENT_SIZE
+            && self.base_block.minor_version > 3
+            && cell.starts_with(b"db")
+        {
+            // the size is part of the key: values of different sizes can share a db record in
+            // tampered hives
+            let offset = value.header.data_offset;
+            if let Some(data) = self.big_data.lock().unwrap().get(offset, size) {
+                Metrics::add(&self.metrics.cache_hits, 1);
+                return Ok(ValueBytes::Shared(data));
+            }
+            Metrics::add(&self.metrics.cache_misses, 1);
+
+            let (db, _): (BigData, usize) = bincode::decode_from_slice(cell, bincode_config())?;
+            let (_, segments) = self.cell(db.segments_list_offset)?;
+
+            let mut data = Vec::with_capacity(size);
+            for offset in key_values_list(segments, db.number_of_segments as usize)? {
+                let (_, segment) = self.cell(offset)?;
+                let len = segment
+                    .len()
+                    .min(BIG_DATA_SEGMENT_SIZE)
+                    .min(size - data.len());
+                data.extend_from_slice(&segment[..len]);
+            }
+
+            let data: Arc<[u8]> = data.into();
+            self.big_data
+                .lock()
+                .unwrap()
+                .insert(offset, size, Arc::clone(&data));
+            return Ok(ValueBytes::Shared(data));
+        }
+
+        let data = cell
+            .get(..size)
+            .ok_or_else(|| anyhow::anyhow!("value data overflows cell"))?;
+        Ok(ValueBytes::Borrowed(data))
+    }
+
+    // offsets of the cells a key and its values are read from: the key node, the values list, the
+    // key values and their data, big data segments included
+    pub fn value_cells(&self, key: &Key) -> anyhow::Result<Vec<u32>> {
+        let mut cells = vec![key.offset];
+        if key.node.header.number_of_key_values > 0 {
+            cells.push(key.node.header.key_values_list_offset);
+        }
+
+        for offset in self.value_offsets(key)? {
+            cells.push(offset);
+            let value = self.value(offset)?;
+            if value.is_resident() || value.data_size() == 0 {
+                continue;
+            }
+
+            let data_offset = value.header.data_offset;
+            cells.push(data_offset);
+            let (_, cell) = self.cell(data_offset)?;
+            if value.data_size() as usize > BIG_DATA_SEGMENT_SIZE
+                && self.base_block.minor_version > 3
+                && cell.starts_with(b"db")
+            {
+                let (db, _): (BigData, usize) = bincode::decode_from_slice(cell, bincode_config())?;
+                let (_, segments) = self.cell(db.segments_list_offset)?;
+                cells.push(db.segments_list_offset);
+                cells.extend(key_values_list(segments, db.number_of_segments as usize)?);
+            }
+        }
+
+        Ok(cells)
+    }
+
+    pub fn value_data(&self, value: &KeyValue) -> anyhow::Result<RegData> {
+        let data = self.value_bytes(value)?;
+        Ok(RegData::new(value.reg_type(), &data))
+    }
+
+    // security record of a key, usually shared with other keys
+    pub fn security(&self, key: &Key) -> anyhow::Result<KeySecurity> {
+        let (_, data) = self.cell(key.node.header.key_security_offset)?;
+        KeySecurity::try_from(data)
+    }
+
+    // path is relative to the root key, with or without a leading backslash, case insensitive. Names
+    // with hidden characters can also be given in their escaped form.
+    // keys are then opened by path from the index next to the hive file (see sidecar.rs)
+    pub fn use_key_index(&mut self, hive_path: &Path) -> anyhow::Result<()> {
+        self.key_paths = Some(KeyPathIndex::load_or_build(hive_path, self)?);
+        Ok(())
+    }
+
+    pub fn open_key(&self, path: &str) -> anyhow::Result<Option<Key>> {
+        Ok(self.open_key_path(path)?.map(|(_, key)| key))
+    }
+
+    // same as open_key, also returns the path of the key with the case of the names in the hive
+    pub fn open_key_path(&self, path: &str) -> anyhow::Result<Option<(String, Key)>> {
+        // names not in the index can still match the escaped form of a name
+        if let Some(location) = self.key_paths.as_ref().and_then(|k| k.get(path)) {
+            return Ok(Some((location.path.clone(), self.key(location.offset)?)));
+        }
+
+        let mut key = self.root()?;
+        let mut key_path = "\\".to_string();
+
+        for name in path.split('\\').filter(|n| !n.is_empty()) {
+            match self.subkey(&key, name)? {
+                Some(k) => {
+                    key_path = join_path(&key_path, k.name());
+                    key = k;
+                }
+                None => return Ok(None),
+            }
+        }
+
+        Ok(Some((key_path, key)))
+    }
+
+    // keys matching a path pattern, each path component can contain '*' and '?' wildcards
+    pub fn find_keys(&self, pattern: &str) -> anyhow::Result<Vec<(String, Key)>> {
+        let mut current = vec![("\\".to_string(), self.root()?)];
+
+        for component in pattern.split('\\').filter(|n| !n.is_empty()) {
+            let mut next = Vec::new();
+            for (path, key) in &current {
+                // names without wildcards are looked up
+                if !component.contains(['*', '?']) {
+                    if let Some(subkey) = self.subkey(key, component)? {
+                        next.push((join_path(path, subkey.name()), subkey));
+                    }
+                    continue;
+                }
+                for subkey in self.subkeys(key)? {
+                    if glob_match(component, subkey.name()) {
+                        next.push((join_path(path, subkey.name()), subkey));
+                    }
+                }
+            }
+            current = next;
+        }
+
+        Ok(current)
+    }
+
+    // depth-first walk of the keys tree starting at key, calling f with the path of each key
+    pub fn walk<F>(&mut self, key: &Key, path: &str, f: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(&mut Self, &str, &Key) -> anyhow::Result<()>,
+    {
+        let mut visited = HashSet::new();
+        self.walk_inner(key, path, f, &mut visited, 0)
+    }
+
+    fn walk_inner<F>(
+        &mut self,
+        key: &Key,
+        path: &str,
+        f: &mut F,
+        visited: &mut HashSet<u32>,
+        depth: usize,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(&mut Self, &str, &Key) -> anyhow::Result<()>,
+    {
+        if depth > MAX_DEPTH || !visited.insert(key.offset) {
+            return Ok(());
+        }
+        f(self, path, key)?;
+
+        for subkey in self.subkeys(key)? {
+            let subpath = join_path(path, subkey.name());
+            self.walk_inner(&subkey, &subpath, f, visited, depth + 1)?;
+        }
+
+        Ok(())
+    }
+
+    // walk where the keys are processed by map on the threads of the rayon pool, in batches of
+    // keys read in walk order. f is called with the results in walk order, an error stopping the
+    // walk at the same key as walk does.
+    pub fn par_walk<T, M, F>(
+        &mut self,
+        key: &Key,
+        path: &str,
+        map: M,
+        f: &mut F,
+    ) -> anyhow::Result<()>
+    where
+        T: Send,
+        M: Fn(&Self, &str, &Key) -> anyhow::Result<T> + Sync,
+        F: FnMut(&str, &Key, T) -> anyhow::Result<()>,
+    {
+        let mut batch = Vec::with_capacity(PAR_WALK_BATCH);
+        let mut batch_data = 0u64;
+        let walked = self.walk(key, path, &mut |hive, path, key| {
+            batch.push((path.to_string(), key.clone()));
+
+            // the largest value data size of a key node bounds the data of its values
+            let header = &key.node.header;
+            batch_data +=
+                header.number_of_key_values as u64 * header.largest_value_data_size as u64;
+            if batch.len() == PAR_WALK_BATCH || batch_data >= PAR_WALK_BATCH_DATA {
+                hive.par_process(&mut batch, &map, f)?;
+                batch_data = 0;
+            }
+            Ok(())
+        });
+
+        // keys read before a walk error are processed first, their errors coming first
+        self.par_process(&mut batch, &map, f)?;
+        walked
+    }
+
+    fn par_process<T, M, F>(
+        &self,
+        batch: &mut Vec<(String, Key)>,
+        map: &M,
+        f: &mut F,
+    ) -> anyhow::Result<()>
+    where
+        T: Send,
+        M: Fn(&Self, &str, &Key) -> anyhow::Result<T> + Sync,
+        F: FnMut(&str, &Key, T) -> anyhow::Result<()>,
+    {
+        let results: Vec<anyhow::Result<T>> = batch
+            .par_iter()
+            .map(|(path, key)| map(self, path, key))
+            .collect();
+        for ((path, key), result) in batch.drain(..).zip(results) {
+            f(&path, &key, result?)?;
+        }
+        Ok(())
+    }
+}
+
+// counters are added to the totals of the process
+impl Drop for RegistryHive {
+    fn drop(&mut self) {
+        totals().merge(&self.metrics);
+    }
+}
+
+// key paths start with a backslash, the root key path being "\"
+pub fn join_path(parent: &str, name: &str) -> String {
+    if parent.ends_with('\\') {
+        format!("{parent}{name}")
+    } else {
+        format!("{parent}\\{name}")
+    }
+}
+
+// "A\B\" or "\A\B" become "\A\B"
+pub fn normalize_path(path: &str) -> String {
+    let names: Vec<&str> = path.split('\\').filter(|n| !n.is_empty()).collect();
+    format!("\\{}", names.join("\\"))
+}
+
+// key names are case insensitive, compared as subkeys lists sort them
+pub fn names_equal(a: &str, b: &str) -> bool {
+    a.encode_utf16()
+        .map(upcase_unit)
+        .eq(b.encode_utf16().map(upcase_unit))
+}
+
+// subkeys lists are sorted by uppercase name, compared as UTF-16 code units uppercased one by one
+pub fn name_sort_key(name: &str) -> Vec<u16> {
+    name.encode_utf16().map(upcase_unit).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::{tree::TreeKey, writer::HiveWriter};
+
+    // a root key with this number of subkeys, named KEY0000, KEY0001...
+    fn wide_hive(subkeys: usize) -> RegistryHive {
+        let mut root = TreeKey::new("ROOT", 0);
+        root.subkeys = (0..subkeys)
+            .map(|i| TreeKey::new(&format!("KEY{i:04}"), 0))
+            .collect();
+
+        let mut bytes = Vec::new();
+        HiveWriter::new(5, "HIVE").write(&root, &mut bytes).unwrap();
+        RegistryHive::from_buffer(HiveBuffer::from(bytes)).unwrap()
+    }
+
+    // key nodes parsed by a lookup
+    fn parsed(hive: &RegistryHive, root: &Key, name: &str) -> (Option<String>, u64) {
+        let before = hive.metrics().keys_parsed.load(Ordering::Relaxed);
+        let subkey = hive.subkey(root, name).unwrap();
+        let after = hive.metrics().keys_parsed.load(Ordering::Relaxed);
+        (subkey.map(|k| k.name().to_string()), after - before)
+    }
+
+    #[test]
+    fn subkey_binary_search() {
+        let hive = wide_hive(1500);
+        let root = hive.root().unwrap();
+
+        let (found, hit) = parsed(&hive, &root, "key0742");
+        assert_eq!(found.as_deref(), Some("KEY0742"));
+        assert!(hit <= 12, "{hit} key nodes parsed");
+
+        // between two subkeys, after the last one
+        for missing in ["KEY0742A", "KEY9999"] {
+            let (found, miss) = parsed(&hive, &root, missing);
+            assert_eq!(found, None);
+            assert!(miss <= 12, "{miss} key nodes parsed for {missing}");
+        }
+    }
+
+    #[test]
+    fn names_equal_code_units() {
+        assert!(names_equal("Software", "SOFTWARE"));
+        assert!(names_equal("\u{e9}t\u{e9}", "\u{c9}T\u{c9}"));
+
+        // uppercase longer than a code unit or outside the BMP, kept as is by lists and hashes
+        for (a, b) in [("stra\u{df}e", "STRASSE"), ("\u{10428}", "\u{10400}")] {
+            assert!(!names_equal(a, b));
+            assert_eq!(names_equal(a, b), name_hash(a) == name_hash(b));
+        }
+    }
+}
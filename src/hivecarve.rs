@@ -0,0 +1,217 @@
+// Carving whole hives out of raw disk images
+//
+// the image is scanned at each sector boundary for the base block of a primary file: "regf" signature,
+// hive bins data size multiple of 4096 and a first hive bin right after it. The hive bins are then
+// checked one after the other (signature, offset matching their position, size multiple of 4096): the
+// file of a hive may be fragmented, or partly overwritten when it was deleted, so a hive ends at the
+// first bad hive bin and is extracted truncated, with its base block fixed to be parsed.
+//
+use std::{
+    fmt,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+use crate::{
+    hive::BASE_BLOCK_SIZE,
+    reg::{BaseBlock, HiveBinHeader, base_block_checksum, bincode_config},
+    utils::filetime_to_string,
+};
+
+// files are at least sector aligned on disk
+const SECTOR_SIZE: u64 = 512;
+
+// size of the image parts scanned for signatures
+const CHUNK_SIZE: u64 = 16 * 1024 * 1024;
+
+const HIVE_BIN_HEADER_SIZE: usize = 32;
+const HIVE_BIN_ALIGNMENT: u32 = 4096;
+
+#[derive(Debug, Clone)]
+pub struct CarvedHive {
+    pub image_offset: u64,
+    pub base_block: BaseBlock,
+    pub checksum_valid: bool,
+
+    // size of the hive bins found, less than the size in the base block when truncated
+    pub hive_bins_size: u32,
+}
+
+impl fmt::Display for CarvedHive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:010X}\t{}\tlast written {}\t{} bytes",
+            self.image_offset,
+            self.file_name(),
+            filetime_to_string(self.base_block.last_written_timestamp),
+            self.size()
+        )?;
+        if self.truncated() {
+            write!(
+                f,
+                ", truncated from {}",
+                BASE_BLOCK_SIZE + self.base_block.hive_bins_data_size as u64
+            )?;
+        }
+        if !self.checksum_valid {
+            write!(f, ", bad base block checksum")?;
+        }
+        Ok(())
+    }
+}
+
+impl CarvedHive {
+    pub fn truncated(&self) -> bool {
+        self.hive_bins_size < self.base_block.hive_bins_data_size
+    }
+
+    // size of the extracted hive
+    pub fn size(&self) -> u64 {
+        BASE_BLOCK_SIZE + self.hive_bins_size as u64
+    }
+
+    // name of the primary file recorded in the base block (e.g. "SYSTEM" for
+    // \??\C:\Windows\System32\config\SYSTEM), usually a partial path
+    pub fn file_name(&self) -> String {
+        let name = String::from_utf16_lossy(&self.base_block.file_name);
+        let name = name.trim_end_matches('\0');
+        match name.rsplit('\\').next() {
+            Some(base) if !base.is_empty() => base.to_string(),
+            _ => "hive".to_string(),
+        }
+    }
+}
+
+// hives found in the image, in the order of their offsets
+pub fn find_hives<R: Read + Seek>(image: &mut R) -> anyhow::Result<Vec<CarvedHive>> {
+    let image_size = image.seek(SeekFrom::End(0))?;
+    let mut hives = Vec::new();
+
+    let mut chunk = Vec::new();
+    let mut chunk_start = 0;
+    while chunk_start < image_size {
+        image.seek(SeekFrom::Start(chunk_start))?;
+        chunk.clear();
+        image.by_ref().take(CHUNK_SIZE).read_to_end(&mut chunk)?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let mut next = chunk_start + chunk.len() as u64;
+        for position in (0..chunk.len()).step_by(SECTOR_SIZE as usize) {
+            if !chunk[position..].starts_with(b"regf") {
+                continue;
+            }
+            let offset = chunk_start + position as u64;
+            if let Some(hive) = check_hive(image, offset, image_size)? {
+                // the scan goes on after the hive
+                next = offset + hive.size();
+                hives.push(hive);
+                break;
+            }
+        }
+        chunk_start = next;
+    }
+
+    Ok(hives)
+}
+
+// None if there's no plausible hive at offset
+fn check_hive<R: Read + Seek>(
+    image: &mut R,
+    offset: u64,
+    image_size: u64,
+) -> anyhow::Result<Option<CarvedHive>> {
+    if offset + BASE_BLOCK_SIZE + HIVE_BIN_HEADER_SIZE as u64 > image_size {
+        return Ok(None);
+    }
+
+    let mut block = vec![0; BASE_BLOCK_SIZE as usize];
+    image.seek(SeekFrom::Start(offset))?;
+    image.read_exact(&mut block)?;
+    let (base_block, _): (BaseBlock, usize) = bincode::decode_from_slice(&block, bincode_config())?;
+
+    // transaction logs have the same signature
+    if base_block.file_type != 0
+        || base_block.hive_bins_data_size == 0
+        || !base_block
+            .hive_bins_data_size
+            .is_multiple_of(HIVE_BIN_ALIGNMENT)
+    {
+        return Ok(None);
+    }
+
+    let start = offset + BASE_BLOCK_SIZE;
+    let mut hive_bins_size = 0;
+    while hive_bins_size < base_block.hive_bins_data_size {
+        let position = start + hive_bins_size as u64;
+        if position + HIVE_BIN_HEADER_SIZE as u64 > image_size {
+            break;
+        }
+
+        let mut bytes = [0; HIVE_BIN_HEADER_SIZE];
+        image.seek(SeekFrom::Start(position))?;
+        image.read_exact(&mut bytes)?;
+        let (header, _): (HiveBinHeader, usize) =
+            bincode::decode_from_slice(&bytes, bincode_config())?;
+
+        let valid = &header.signature == b"hbin"
+            && header.offset == hive_bins_size
+            && header.size != 0
+            && header.size.is_multiple_of(HIVE_BIN_ALIGNMENT)
+            && header.size <= base_block.hive_bins_data_size - hive_bins_size
+            && position + header.size as u64 <= image_size;
+        if !valid {
+            break;
+        }
+        hive_bins_size += header.size;
+    }
+
+    if hive_bins_size == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(CarvedHive {
+        image_offset: offset,
+        checksum_valid: base_block.checksum == base_block_checksum(&block),
+        base_block,
+        hive_bins_size,
+    }))
+}
+
+// write the hive out of the image, with the size of a truncated hive fixed in its base block. Hive
+// bins are copied a chunk at a time, whatever the size of the hive
+pub fn extract_hive<R: Read + Seek, W: Write>(
+    image: &mut R,
+    hive: &CarvedHive,
+    w: &mut W,
+) -> anyhow::Result<()> {
+    let mut block = vec![0; BASE_BLOCK_SIZE as usize];
+    image.seek(SeekFrom::Start(hive.image_offset))?;
+    image.read_exact(&mut block)?;
+
+    if hive.truncated() {
+        let mut base_block = hive.base_block.clone();
+        base_block.hive_bins_data_size = hive.hive_bins_size;
+
+        block = bincode::encode_to_vec(&base_block, bincode_config())?;
+        let checksum = base_block_checksum(&block);
+        block[508..512].copy_from_slice(&checksum.to_le_bytes());
+    }
+    w.write_all(&block)?;
+
+    let mut chunk = vec![0; CHUNK_SIZE as usize];
+    let mut left = hive.hive_bins_size as u64;
+    while left > 0 {
+        let n = left.min(CHUNK_SIZE) as usize;
+        image.read_exact(&mut chunk[..n]).map_err(|_| {
+            anyhow::anyhow!(
+                "hive at 0x{:X} is past the end of the image",
+                hive.image_offset
+            )
+        })?;
+        w.write_all(&chunk[..n])?;
+        left -= n as u64;
+    }
+    Ok(())
+}
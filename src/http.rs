@@ -0,0 +1,140 @@
+// Files read over HTTP(S) with range requests
+//
+// a disk image or an archive sitting on an evidence server or in S3 (e.g. behind a pre-signed URL)
+// is read in blocks requested when first read: only the parts actually read are downloaded, e.g. the
+// MFT and the clusters of a hive file in a disk image, or the directory and a single file of a zip
+// archive. Blocks are kept in a bounded cache, the oldest being dropped first.
+//
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use ureq::Agent;
+
+// size of each range requested
+const BLOCK_SIZE: u64 = 64 * 1024;
+
+// total size of the cached blocks
+const BLOCK_CACHE_SIZE: u64 = 64 * 1024 * 1024;
+
+pub fn is_url(location: &str) -> bool {
+    let location = location.to_lowercase();
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+pub struct HttpRangeReader {
+    agent: Agent,
+    url: String,
+    size: u64,
+    position: u64,
+
+    // by block number, in the order they were downloaded
+    blocks: HashMap<u64, Vec<u8>>,
+    order: VecDeque<u64>,
+}
+
+impl HttpRangeReader {
+    // the size of the file is read from the answer to a first range request
+    pub fn open(url: &str) -> anyhow::Result<Self> {
+        let agent = Agent::new_with_defaults();
+        let response = agent.get(url).header("Range", "bytes=0-0").call()?;
+        anyhow::ensure!(
+            response.status() == 206,
+            "{url} doesn't support range requests (status {})",
+            response.status()
+        );
+
+        // bytes 0-0/size
+        let size = response
+            .headers()
+            .get("Content-Range")
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.rsplit_once('/'))
+            .and_then(|(_, size)| size.parse().ok())
+            .ok_or_else(|| anyhow::anyhow!("no file size in the answer of {url}"))?;
+
+        Ok(Self {
+            agent,
+            url: url.to_string(),
+            size,
+            position: 0,
+            blocks: HashMap::new(),
+            order: VecDeque::new(),
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn block(&mut self, number: u64) -> io::Result<&[u8]> {
+        if !self.blocks.contains_key(&number) {
+            let start = number * BLOCK_SIZE;
+            let end = (start + BLOCK_SIZE).min(self.size) - 1;
+            let bytes = self
+                .agent
+                .get(&self.url)
+                .header("Range", format!("bytes={start}-{end}"))
+                .call()
+                .and_then(|mut response| response.body_mut().read_to_vec())
+                .map_err(io::Error::other)?;
+            if bytes.len() as u64 != end - start + 1 {
+                return Err(io::Error::other(format!(
+                    "{} bytes read at 0x{start:X} of {} instead of {}",
+                    bytes.len(),
+                    self.url,
+                    end - start + 1
+                )));
+            }
+
+            if self.order.len() as u64 >= BLOCK_CACHE_SIZE / BLOCK_SIZE
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.blocks.remove(&oldest);
+            }
+            self.blocks.insert(number, bytes);
+            self.order.push_back(number);
+        }
+        Ok(&self.blocks[&number])
+    }
+}
+
+impl Read for HttpRangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let offset = (self.position % BLOCK_SIZE) as usize;
+        let block = self.block(self.position / BLOCK_SIZE)?;
+        let n = buf.len().min(block.len() - offset);
+        buf[..n].copy_from_slice(&block[offset..offset + n]);
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for HttpRangeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the file",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+// the whole file, e.g. a hive, in a single request
+pub fn download<W: Write>(url: &str, out: &mut W) -> anyhow::Result<()> {
+    let response = ureq::get(url).call()?;
+    io::copy(&mut response.into_body().into_reader(), out)?;
+    Ok(())
+}
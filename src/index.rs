@@ -0,0 +1,104 @@
+// Index of the cells of a hive, built by a single sweep of the hbins
+//
+// the offset, size and hbin of each cell are recorded once: the cell starting at an offset is then
+// found in constant time, and the cell containing an offset (a reference into the middle of a cell) by
+// a binary search, without reading the hbins again. Contents stay in the hive buffer.
+//
+use std::collections::HashMap;
+
+use crate::{
+    buffer::HiveBuffer,
+    hive::BASE_BLOCK_SIZE,
+    reg::{CellType, RegistryFile},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct IndexedCell {
+    // offset relative from the start of the hive bins data
+    pub offset: u32,
+
+    // negative when allocated
+    pub size: i32,
+
+    // offset of the hbin holding the cell
+    pub hbin: u32,
+}
+
+impl IndexedCell {
+    pub fn is_free(&self) -> bool {
+        self.size > 0
+    }
+
+    // offset right after the cell
+    pub fn end(&self) -> u32 {
+        self.offset + self.size.unsigned_abs()
+    }
+}
+
+#[derive(Debug)]
+pub struct CellIndex {
+    data: HiveBuffer,
+
+    // in the order of the hbins, i.e. by offset
+    cells: Vec<IndexedCell>,
+
+    // position in cells of the cell starting at each offset
+    positions: HashMap<u32, usize>,
+}
+
+impl CellIndex {
+    // cells of the hbins up to the first inconsistent one, as read by RegistryFile
+    pub fn build(data: &HiveBuffer) -> anyhow::Result<Self> {
+        let mut regf = RegistryFile::from(data.clone());
+        regf.read_header()?;
+
+        let mut cells = Vec::new();
+        for mut hbin in &mut regf {
+            let hbin_offset = hbin.header.offset;
+            for cell in &mut hbin {
+                cells.push(IndexedCell {
+                    offset: cell.offset,
+                    size: cell.size,
+                    hbin: hbin_offset,
+                });
+            }
+        }
+
+        let positions = cells
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (c.offset, i))
+            .collect();
+        Ok(Self {
+            data: data.clone(),
+            cells,
+            positions,
+        })
+    }
+
+    pub fn cells(&self) -> &[IndexedCell] {
+        &self.cells
+    }
+
+    // the cell starting at an offset
+    pub fn get(&self, offset: u32) -> Option<&IndexedCell> {
+        self.positions.get(&offset).map(|i| &self.cells[*i])
+    }
+
+    // the cell an offset falls into, its size field included
+    pub fn containing(&self, offset: u32) -> Option<&IndexedCell> {
+        let i = self.cells.partition_point(|c| c.offset <= offset);
+        self.cells[..i].last().filter(|c| offset < c.end())
+    }
+
+    // content of a cell, after the size field
+    pub fn content(&self, cell: &IndexedCell) -> &[u8] {
+        let start = BASE_BLOCK_SIZE as usize + cell.offset as usize;
+        &self.data[start + 4..start + cell.size.unsigned_abs() as usize]
+    }
+
+    pub fn cell_type(&self, cell: &IndexedCell) -> CellType {
+        let content = self.content(cell);
+        CellType::from([content[0], content[1]])
+    }
+}
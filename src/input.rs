@@ -0,0 +1,65 @@
+// Hives which are not read from their own file: the standard input ("-" on the command line), a hive
+// recovered from its transaction logs or read out of a disk image or an archive
+//
+// the parsers need to seek and some commands read the file twice, so the content is spooled to a
+// temporary file which is removed when the spool is dropped.
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// several spools can exist at the same time
+static SPOOL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Spool {
+    path: PathBuf,
+}
+
+impl Spool {
+    fn create() -> anyhow::Result<(Self, File)> {
+        let n = SPOOL_COUNT.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("readregf-{}-{n}.hive", std::process::id()));
+
+        let f = File::create_new(&path)?;
+        Ok((Self { path }, f))
+    }
+
+    pub fn stdin() -> anyhow::Result<Self> {
+        let (spool, mut f) = Self::create()?;
+        io::copy(&mut io::stdin().lock(), &mut f)?;
+        Ok(spool)
+    }
+
+    // content written by f, e.g. decompressed out of an archive
+    pub fn write_with<F>(f: F) -> anyhow::Result<Self>
+    where
+        F: FnOnce(&mut File) -> anyhow::Result<()>,
+    {
+        let (spool, mut file) = Self::create()?;
+        f(&mut file)?;
+        Ok(spool)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let (spool, mut f) = Self::create()?;
+        f.write_all(bytes)?;
+        Ok(spool)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// "-" stands for the standard input
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
@@ -0,0 +1,226 @@
+// Indicators of compromise matched against keys and values
+//
+// an IOC file has one indicator per line, its type and its pattern separated by a colon, optionally
+// followed by a tab and a description. Empty lines and lines starting with '#' are skipped:
+//
+// key:\Software\Microsoft\Windows\CurrentVersion\Run*     key path ('*' and '?' wildcards)
+// value:Updater                                           value name ('*' and '?' wildcards)
+// string:evil.exe                                         substring of value data, case insensitive
+// regex:(?i)\\appdata\\.*\.exe                            regular expression on value data
+// md5:...  sha1:...  sha256:...                           digest of the raw value data
+//
+// strings and regular expressions are matched against string values and against the strings found in
+// the data of the other types.
+//
+use std::fmt;
+
+use regex::Regex;
+
+use crate::{
+    findings::{Finding, Severity},
+    hash::HashAlgorithm,
+    hive::{RegistryHive, join_path, normalize_path},
+    strings::{Encoding, extract_strings},
+    utils::{filetime_to_string, glob_match},
+    value::RegData,
+};
+
+// minimum length of the strings extracted from binary data
+const MIN_STRING_LEN: usize = 4;
+
+// characters of value data shown in the context of a match
+const CONTEXT_LEN: usize = 120;
+
+#[derive(Debug, Clone)]
+pub enum IocKind {
+    Key(String),
+    Value(String),
+    // lowercase
+    String(String),
+    Regex(Regex),
+    // lowercase hex digest
+    Hash(HashAlgorithm, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Ioc {
+    // line in the IOC file
+    pub line: usize,
+    pub kind: IocKind,
+
+    // as written in the file
+    pub pattern: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IocMatch {
+    pub hive: String,
+    pub ioc: Ioc,
+
+    // key path, or value path for indicators about values
+    pub path: String,
+
+    // last written time of keys, type and data of values
+    pub context: String,
+}
+
+// every match is worth a look, e.g. for SIEM events
+impl From<&IocMatch> for Finding {
+    fn from(m: &IocMatch) -> Self {
+        let mut description = format!("IOC line {} '{}' matched", m.ioc.line, m.ioc.pattern);
+        if let Some(ioc_description) = &m.ioc.description {
+            description.push_str(&format!(" ({ioc_description})"));
+        }
+        if !m.context.is_empty() {
+            description.push_str(&format!(": {}", m.context));
+        }
+        Finding::new(Severity::High, "ioc", &m.path, description)
+    }
+}
+
+impl fmt::Display for IocMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}:{}\t{}\t{}\t{}",
+            self.hive,
+            self.ioc.line,
+            self.ioc.pattern,
+            self.path,
+            self.context,
+            self.ioc.description.as_deref().unwrap_or("")
+        )
+    }
+}
+
+pub fn parse_iocs(source: &str) -> anyhow::Result<Vec<Ioc>> {
+    let mut iocs = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let (pattern, description) = match line.split_once('\t') {
+            Some((pattern, description)) => (pattern, Some(description.trim().to_string())),
+            None => (line, None),
+        };
+        let Some((kind, value)) = pattern.split_once(':') else {
+            anyhow::bail!("line {line_number}: expected <type>:<pattern>");
+        };
+        let value = value.trim();
+        anyhow::ensure!(!value.is_empty(), "line {line_number}: empty pattern");
+
+        let kind = match kind.trim().to_lowercase().as_str() {
+            "key" => IocKind::Key(normalize_path(value)),
+            "value" => IocKind::Value(value.to_string()),
+            "string" => IocKind::String(value.to_lowercase()),
+            "regex" => IocKind::Regex(
+                Regex::new(value).map_err(|e| anyhow::anyhow!("line {line_number}: {e}"))?,
+            ),
+            algorithm @ ("md5" | "sha1" | "sha256") => {
+                IocKind::Hash(algorithm.parse()?, value.to_lowercase())
+            }
+            other => anyhow::bail!(
+                "line {line_number}: unknown IOC type '{other}' (key, value, string, regex, md5, sha1 or sha256)"
+            ),
+        };
+
+        iocs.push(Ioc {
+            line: line_number,
+            kind,
+            pattern: pattern.trim().to_string(),
+            description,
+        });
+    }
+
+    Ok(iocs)
+}
+
+pub fn ioc_matches(
+    hive: &mut RegistryHive,
+    hive_name: &str,
+    iocs: &[Ioc],
+) -> anyhow::Result<Vec<IocMatch>> {
+    let mut matches = Vec::new();
+
+    // digests are only computed for the algorithms used
+    let mut algorithms = Vec::new();
+    for ioc in iocs {
+        if let IocKind::Hash(algorithm, _) = &ioc.kind
+            && !algorithms.contains(algorithm)
+        {
+            algorithms.push(*algorithm);
+        }
+    }
+
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |hive, path, key| {
+        let mut found = |ioc: &Ioc, path: String, context: String| {
+            matches.push(IocMatch {
+                hive: hive_name.to_string(),
+                ioc: ioc.clone(),
+                path,
+                context,
+            })
+        };
+
+        for ioc in iocs {
+            if let IocKind::Key(pattern) = &ioc.kind
+                && glob_match(pattern, path)
+            {
+                found(
+                    ioc,
+                    path.to_string(),
+                    format!(
+                        "last written {}",
+                        filetime_to_string(key.node.header.last_written_timestamp)
+                    ),
+                );
+            }
+        }
+
+        for value in hive.values(key)? {
+            let bytes = hive.value_bytes(&value)?;
+            let data = RegData::new(value.reg_type(), &bytes);
+            let value_path = join_path(path, value.display_name());
+            let context = || {
+                let data: String = data.to_string().chars().take(CONTEXT_LEN).collect();
+                format!("{} {data}", value.reg_type())
+            };
+
+            // text of string values, strings found in the data of others
+            let text = match &data {
+                RegData::Sz(s) | RegData::ExpandSz(s) | RegData::Link(s) => vec![s.clone()],
+                RegData::MultiSz(v) => v.clone(),
+                _ => extract_strings(&bytes, MIN_STRING_LEN, Encoding::Both)
+                    .into_iter()
+                    .map(|s| s.value)
+                    .collect(),
+            };
+            let digests: Vec<_> = algorithms.iter().map(|a| (*a, a.digest(&bytes))).collect();
+
+            for ioc in iocs {
+                let is_match = match &ioc.kind {
+                    IocKind::Key(_) => false,
+                    IocKind::Value(pattern) => glob_match(pattern, value.display_name()),
+                    IocKind::String(s) => text.iter().any(|t| t.to_lowercase().contains(s)),
+                    IocKind::Regex(regex) => text.iter().any(|t| regex.is_match(t)),
+                    IocKind::Hash(algorithm, digest) => {
+                        digests.iter().any(|(a, d)| a == algorithm && d == digest)
+                    }
+                };
+                if is_match {
+                    found(ioc, value_path.clone(), context());
+                }
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok(matches)
+}
@@ -0,0 +1,204 @@
+// Key node (nk) record
+// see: https://github.com/msuhanov/regf/blob/master/Windows%20registry%20file%20format%20specification.md#key-node
+//
+// offsets are relative to the start of the cell data (i.e. after the cell size):
+//
+// +------+-------+-----------+-------+--------+-----+------------+------------+
+// | "nk" | flags | timestamp | ...   | parent | ... | name len   | name ...   |
+// +------+-------+-----------+-------+--------+-----+------------+------------+
+//
+use std::fmt;
+
+use bincode::{Decode, Encode};
+
+use crate::{reg::bincode_config, utils::filetime_to_string};
+
+// key node flags
+pub const KEY_VOLATILE: u16 = 0x0001;
+pub const KEY_HIVE_EXIT: u16 = 0x0002;
+pub const KEY_HIVE_ENTRY: u16 = 0x0004;
+pub const KEY_NO_DELETE: u16 = 0x0008;
+pub const KEY_SYM_LINK: u16 = 0x0010;
+pub const KEY_COMP_NAME: u16 = 0x0020;
+pub const KEY_PREDEF_HANDLE: u16 = 0x0040;
+pub const KEY_VIRTUAL_SOURCE: u16 = 0x0080;
+pub const KEY_VIRTUAL_TARGET: u16 = 0x0100;
+pub const KEY_VIRTUAL_STORE: u16 = 0x0200;
+
+// an offset which is not set
+pub const NO_OFFSET: u32 = 0xFFFF_FFFF;
+
+// layered key bit fields, in the second byte of the access bits (differencing hives, version 1.6)
+pub const LAYER_SEMANTICS_MASK: u32 = 0x0300;
+pub const LAYER_INHERIT_CLASS: u32 = 0x8000;
+
+// how a key of a differencing hive combines with the same key in the layers below
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerSemantics {
+    // values and subkeys are merged with the ones below
+    Merge,
+
+    // the key is deleted
+    Tombstone,
+
+    // values and class name replace the ones below, subkeys are merged
+    SupersedeLocal,
+
+    // the key and its subtree replace the ones below
+    SupersedeTree,
+}
+
+impl fmt::Display for LayerSemantics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayerSemantics::Merge => write!(f, "merge"),
+            LayerSemantics::Tombstone => write!(f, "tombstone"),
+            LayerSemantics::SupersedeLocal => write!(f, "supersede local"),
+            LayerSemantics::SupersedeTree => write!(f, "supersede tree"),
+        }
+    }
+}
+
+// fixed part of a key node, the key name follows
+#[derive(Debug, Clone, Decode, Encode)]
+pub struct KeyNodeHeader {
+    // ASCII string
+    pub signature: [u8; 2],
+
+    // Bit mask
+    pub flags: u16,
+
+    // FILETIME (UTC)
+    pub last_written_timestamp: u64,
+
+    // Bit mask (this field is used as of Windows 8 and Windows Server 2012, in previous versions of Windows, this field is reserved and called *Spare*)
+    // the second byte holds the layered key bit fields in differencing hives
+    pub access_bits: u32,
+
+    // Offset of a parent key node in bytes, relative from the start of the hive bins data (this field has no meaning on a disk for a root key node)
+    pub parent: u32,
+
+    // Number of subkeys
+    pub number_of_subkeys: u32,
+
+    // Number of volatile subkeys
+    pub number_of_volatile_subkeys: u32,
+
+    // In bytes, relative from the start of the hive bins data (also, this field may point to an Index root)
+    pub subkeys_list_offset: u32,
+
+    // This field has no meaning on a disk (volatile keys are not written to a file)
+    pub volatile_subkeys_list_offset: u32,
+
+    // Number of key values
+    pub number_of_key_values: u32,
+
+    // In bytes, relative from the start of the hive bins data
+    pub key_values_list_offset: u32,
+
+    // In bytes, relative from the start of the hive bins data
+    pub key_security_offset: u32,
+
+    // In bytes, relative from the start of the hive bins data
+    pub class_name_offset: u32,
+
+    // In bytes, a subkey name is treated as a UTF-16LE string
+    pub largest_subkey_name_length: u32,
+
+    // In bytes
+    pub largest_subkey_class_name_length: u32,
+
+    // In bytes, a value name is treated as a UTF-16LE string
+    pub largest_value_name_length: u32,
+
+    // In bytes
+    pub largest_value_data_size: u32,
+
+    // Cached index
+    pub work_var: u32,
+
+    // In bytes
+    pub key_name_length: u16,
+
+    // In bytes
+    pub class_name_length: u16,
+}
+
+// size of the fixed part
+pub const KEY_NODE_HEADER_SIZE: usize = 76;
+
+#[derive(Debug, Clone)]
+pub struct KeyNode {
+    pub header: KeyNodeHeader,
+    pub name: String,
+}
+
+impl KeyNode {
+    pub fn is_root(&self) -> bool {
+        self.header.flags & KEY_HIVE_ENTRY != 0
+    }
+
+    // only meaningful in differencing hives, merge otherwise
+    pub fn layer_semantics(&self) -> LayerSemantics {
+        match (self.header.access_bits & LAYER_SEMANTICS_MASK) >> 8 {
+            0 => LayerSemantics::Merge,
+            1 => LayerSemantics::Tombstone,
+            2 => LayerSemantics::SupersedeLocal,
+            _ => LayerSemantics::SupersedeTree,
+        }
+    }
+
+    // the class name of the key below is kept
+    pub fn inherits_class(&self) -> bool {
+        self.header.access_bits & LAYER_INHERIT_CLASS != 0
+    }
+
+    pub fn timestamp(&self) -> String {
+        filetime_to_string(self.header.last_written_timestamp)
+    }
+}
+
+// bytes start at the signature
+impl TryFrom<&[u8]> for KeyNode {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let (header, _): (KeyNodeHeader, usize) =
+            bincode::decode_from_slice(data, bincode_config())?;
+        anyhow::ensure!(&header.signature == b"nk", "not a key node");
+
+        let name_length = header.key_name_length as usize;
+        let name_bytes = data
+            .get(KEY_NODE_HEADER_SIZE..KEY_NODE_HEADER_SIZE + name_length)
+            .ok_or_else(|| anyhow::anyhow!("key name overflows cell"))?;
+        let name = decode_name(name_bytes, header.flags & KEY_COMP_NAME != 0);
+
+        Ok(Self { header, name })
+    }
+}
+
+impl fmt::Display for KeyNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "name: {} timestamp: {} subkeys: {} values: {}",
+            self.name,
+            self.timestamp(),
+            self.header.number_of_subkeys,
+            self.header.number_of_key_values
+        )
+    }
+}
+
+// key and value names are either stored as extended ASCII (Latin-1) or UTF-16LE
+pub fn decode_name(bytes: &[u8], compressed: bool) -> String {
+    if compressed {
+        bytes.iter().map(|b| *b as char).collect()
+    } else {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+}
@@ -0,0 +1,131 @@
+// Layered keys of differencing hives, as used by Windows containers
+// see: https://github.com/msuhanov/regf/blob/master/Windows%20registry%20file%20format%20specification.md#layered-keys
+//
+// a container sees the registry through layers: each differencing hive (version 1.6) holds the
+// changes made over the layers below it, down to a base hive (e.g. the one of the container image).
+// Keys of a differencing hive combine with the same keys below according to their layer semantics:
+// - merge: the values are added or replace the ones with the same name, a value flagged as a
+//   tombstone deletes the one below, subkeys are merged the same way (a key missing below is added)
+// - tombstone: the key below is deleted with its subtree
+// - supersede local: the values replace all the ones below, subkeys are merged
+// - supersede tree: the key and its subtree replace the ones below
+// A key with the inherit class bit keeps the class name of the key below. The effective view is a
+// keys tree, which can be written to a new hive.
+//
+use crate::{
+    hive::{Key, MAX_DEPTH, RegistryHive, names_equal},
+    key::LayerSemantics,
+    tree::{TreeKey, TreeValue},
+    value::VALUE_TOMBSTONE,
+};
+
+// changes a layer made to the effective view
+#[derive(Debug, Default)]
+pub struct LayerReport {
+    pub keys_added: usize,
+    pub keys_deleted: usize,
+    pub keys_superseded: usize,
+    pub values_set: usize,
+    pub values_deleted: usize,
+}
+
+// the keys of a differencing hive applied over the effective view of the layers below, e.g. the
+// keys tree of the base hive
+pub fn apply_layer(
+    effective: &mut TreeKey,
+    layer: &mut RegistryHive,
+) -> anyhow::Result<LayerReport> {
+    let root = layer.root()?;
+    let mut report = LayerReport::default();
+    overlay(effective, layer, &root, 0, &mut report)?;
+    Ok(report)
+}
+
+// the content of key is applied to target, the same key in the view below
+fn overlay(
+    target: &mut TreeKey,
+    layer: &mut RegistryHive,
+    key: &Key,
+    depth: usize,
+    report: &mut LayerReport,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(depth <= MAX_DEPTH, "keys tree too deep at '{}'", key.name());
+
+    let node = TreeKey::load_node(layer, key)?;
+    target.flags = node.flags;
+    target.timestamp = node.timestamp;
+    if !key.node.inherits_class() {
+        target.class_name = node.class_name;
+    }
+    if !node.security.is_empty() {
+        target.security = node.security;
+    }
+
+    for value in layer.values(key)? {
+        let index = target
+            .values
+            .iter()
+            .position(|v| names_equal(&v.name, &value.name));
+        if value.header.flags & VALUE_TOMBSTONE != 0 {
+            if let Some(index) = index {
+                target.values.remove(index);
+                report.values_deleted += 1;
+            }
+            continue;
+        }
+
+        let tree_value = TreeValue {
+            name: value.name.clone(),
+            reg_type: value.reg_type(),
+            data: layer.value_bytes(&value)?.into_owned(),
+        };
+        match index {
+            Some(index) => target.values[index] = tree_value,
+            None => target.values.push(tree_value),
+        }
+        report.values_set += 1;
+    }
+
+    for subkey in layer.subkeys(key)? {
+        let name = subkey.name().to_string();
+        let index = target
+            .subkeys
+            .iter()
+            .position(|k| names_equal(&k.name, &name));
+
+        let semantics = subkey.node.layer_semantics();
+        if semantics == LayerSemantics::Tombstone {
+            if let Some(index) = index {
+                target.subkeys.remove(index);
+                report.keys_deleted += 1;
+            }
+            continue;
+        }
+
+        let index = match index {
+            Some(index) => index,
+            None => {
+                target.subkeys.push(TreeKey::new(&name, 0));
+                report.keys_added += 1;
+                target.subkeys.len() - 1
+            }
+        };
+        let below = &mut target.subkeys[index];
+        match semantics {
+            LayerSemantics::SupersedeTree => {
+                let mut replaced = TreeKey::new(&name, 0);
+                replaced.class_name = std::mem::take(&mut below.class_name);
+                *below = replaced;
+                report.keys_superseded += 1;
+            }
+            LayerSemantics::SupersedeLocal => {
+                below.values.clear();
+                report.keys_superseded += 1;
+            }
+            _ => (),
+        }
+        overlay(below, layer, &subkey, depth + 1, report)?;
+    }
+
+    Ok(())
+}
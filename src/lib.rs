@@ -0,0 +1,60 @@
+pub mod anomalies;
+pub mod archive;
+pub mod artifacts;
+pub mod batch;
+pub mod buffer;
+pub mod cache;
+pub mod carve;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+pub mod compact;
+pub mod deleted;
+pub mod diff;
+pub mod export;
+pub mod filter;
+pub mod findings;
+pub mod hash;
+pub mod hive;
+pub mod hivecarve;
+pub mod http;
+pub mod index;
+pub mod input;
+pub mod ioc;
+pub mod key;
+pub mod layered;
+pub mod list;
+pub mod merge;
+pub mod metrics;
+pub mod mutable;
+pub mod mutate;
+pub mod ntfs;
+pub mod payloads;
+pub mod permissions;
+pub mod plugins;
+pub mod reachability;
+pub mod record;
+pub mod redact;
+pub mod reg;
+pub mod regfile;
+pub mod remnants;
+pub mod repair;
+pub mod reparse;
+pub mod report;
+pub mod salvage;
+pub mod search;
+pub mod security;
+pub mod sidecar;
+pub mod siem;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod strings;
+pub mod template;
+pub mod timeline;
+pub mod translog;
+pub mod tree;
+pub mod utils;
+pub mod value;
+pub mod watch;
+pub mod writer;
+pub mod yara;
@@ -0,0 +1,137 @@
+// Subkeys lists (li, lf, lh, ri) and key values lists
+// see: https://github.com/msuhanov/regf/blob/master/Windows%20registry%20file%20format%20specification.md#subkeys-list
+//
+// +------+--------------------+---------+---------+-----+
+// | "lh" | number of elements | element | element | ... |
+// +------+--------------------+---------+---------+-----+
+//
+// - an Index leaf element is a key node offset
+// - a Fast leaf element is a key node offset followed by a name hint (first 4 ASCII characters of the name)
+// - a Hash leaf element is a key node offset followed by a name hash
+// - an Index root element is the offset of another subkeys list (li, lf or lh)
+//
+// max number of elements in a subkeys leaf written, above that leaves are held by an index root
+pub const LEAF_MAX_ELEMENTS: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubkeysList {
+    IndexLeaf(Vec<u32>),
+    FastLeaf(Vec<(u32, [u8; 4])>),
+    HashLeaf(Vec<(u32, u32)>),
+    IndexRoot(Vec<u32>),
+}
+
+impl SubkeysList {
+    // key node offsets (or subkeys list offsets for an Index root)
+    pub fn offsets(&self) -> Vec<u32> {
+        match self {
+            SubkeysList::IndexLeaf(v) | SubkeysList::IndexRoot(v) => v.clone(),
+            SubkeysList::FastLeaf(v) => v.iter().map(|e| e.0).collect(),
+            SubkeysList::HashLeaf(v) => v.iter().map(|e| e.0).collect(),
+        }
+    }
+
+    // content of the list cell, signature included
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (signature, count) = match self {
+            SubkeysList::IndexLeaf(v) => (b"li", v.len()),
+            SubkeysList::FastLeaf(v) => (b"lf", v.len()),
+            SubkeysList::HashLeaf(v) => (b"lh", v.len()),
+            SubkeysList::IndexRoot(v) => (b"ri", v.len()),
+        };
+
+        let mut bytes = signature.to_vec();
+        bytes.extend_from_slice(&(count as u16).to_le_bytes());
+        match self {
+            SubkeysList::IndexLeaf(v) | SubkeysList::IndexRoot(v) => v
+                .iter()
+                .for_each(|o| bytes.extend_from_slice(&o.to_le_bytes())),
+            SubkeysList::FastLeaf(v) => v.iter().for_each(|(o, hint)| {
+                bytes.extend_from_slice(&o.to_le_bytes());
+                bytes.extend_from_slice(hint);
+            }),
+            SubkeysList::HashLeaf(v) => v.iter().for_each(|(o, hash)| {
+                bytes.extend_from_slice(&o.to_le_bytes());
+                bytes.extend_from_slice(&hash.to_le_bytes());
+            }),
+        }
+        bytes
+    }
+}
+
+// bytes start at the signature
+impl TryFrom<&[u8]> for SubkeysList {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        anyhow::ensure!(data.len() >= 4, "subkeys list too small");
+        let count = u16::from_le_bytes([data[2], data[3]]) as usize;
+
+        // element size depends on the list type
+        let element_size = match &data[..2] {
+            b"li" | b"ri" => 4,
+            b"lf" | b"lh" => 8,
+            sig => anyhow::bail!("unknown subkeys list signature {:X?}", sig),
+        };
+        let elements = data
+            .get(4..4 + count * element_size)
+            .ok_or_else(|| anyhow::anyhow!("subkeys list overflows cell"))?
+            .chunks_exact(element_size);
+
+        let list = match &data[..2] {
+            b"li" => SubkeysList::IndexLeaf(elements.map(|e| u32_at(e, 0)).collect()),
+            b"ri" => SubkeysList::IndexRoot(elements.map(|e| u32_at(e, 0)).collect()),
+            b"lf" => SubkeysList::FastLeaf(
+                elements
+                    .map(|e| (u32_at(e, 0), e[4..8].try_into().unwrap()))
+                    .collect(),
+            ),
+            _ => SubkeysList::HashLeaf(elements.map(|e| (u32_at(e, 0), u32_at(e, 4))).collect()),
+        };
+
+        Ok(list)
+    }
+}
+
+// first 4 characters of the name, used by lf lists to speed up lookups
+pub fn name_hint(name: &str) -> [u8; 4] {
+    let mut hint = [0u8; 4];
+    for (h, c) in hint.iter_mut().zip(name.chars()) {
+        *h = if (c as u32) < 0x100 { c as u8 } else { 0 };
+    }
+    hint
+}
+
+// hash of a name used by lh lists: hash = hash * 37 + uppercase code unit, over the UTF-16 code
+// units of the name. Uppercasing the whole string instead would change the hash of names whose
+// uppercase is longer (e.g. "ß" becoming "SS").
+pub fn name_hash(name: &str) -> u32 {
+    name.encode_utf16().fold(0u32, |hash, c| {
+        hash.wrapping_mul(37).wrapping_add(upcase_unit(c) as u32)
+    })
+}
+
+// uppercase of a single UTF-16 code unit as Windows does it (RtlUpcaseUnicodeChar): code units whose
+// uppercase isn't a single code unit, surrogates included, are kept
+pub fn upcase_unit(unit: u16) -> u16 {
+    let Some(c) = char::from_u32(unit as u32) else {
+        return unit;
+    };
+    let mut upper = c.to_uppercase();
+    match (upper.next(), upper.next()) {
+        (Some(u), None) if (u as u32) <= 0xFFFF => u as u16,
+        _ => unit,
+    }
+}
+
+// a key values list has no header: it's only an array of key value offsets
+pub fn key_values_list(data: &[u8], count: usize) -> anyhow::Result<Vec<u32>> {
+    let list = data
+        .get(..count * 4)
+        .ok_or_else(|| anyhow::anyhow!("key values list overflows cell"))?;
+    Ok(list.chunks_exact(4).map(|e| u32_at(e, 0)).collect())
+}
+
+fn u32_at(data: &[u8], index: usize) -> u32 {
+    u32::from_le_bytes(data[index..index + 4].try_into().unwrap())
+}
@@ -2,21 +2,1368 @@
 // https://googleprojectzero.blogspot.com/2024/12/the-windows-registry-adventure-5-regf.html
 //
 use std::{
+    collections::{HashMap, hash_map::Entry},
     fs::File,
-    io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom},
-    path::PathBuf,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
 };
 
-use crate::reg::{BaseBlock, Cell, CellType, HiveBin, HiveBinHeader, RegistryFile};
+use clap::{Args, Parser, Subcommand};
 
-mod reg;
+#[cfg(feature = "sqlite")]
+use readreg::sqlite::export_sqlite;
+use readreg::{
+    anomalies::anomaly_findings,
+    archive::{copy_archive_file, copy_archive_file_from},
+    artifacts::{
+        autoruns::autoruns,
+        bam::bam_entries,
+        layers::layers,
+        mru::mru_lists,
+        muicache::muicache,
+        network::{interfaces, profiles},
+        sam::sam_users,
+        services::services,
+        shellbags::shellbags,
+        shimcache::shimcaches,
+        usb::usb_devices,
+        userassist::userassist,
+    },
+    batch::process_hives,
+    buffer::{DEFAULT_MEMORY_LIMIT, HiveBuffer, IoBackend, ParserOptions, set_default_options},
+    carve::{CarveOptions, Carver},
+    deleted::{deleted_keys, deleted_values},
+    diff::{Snapshot, deviations, history, load_snapshot, snapshot},
+    export::{ExportFormat, ExportOptions, bulk_mapping, export, json_schema},
+    filter::ValueFilter,
+    findings::{Finding, Severity, sort_findings},
+    hash::HashAlgorithm,
+    hive::{RegistryHive, names_equal, normalize_path},
+    hivecarve::{extract_hive, find_hives},
+    http::{HttpRangeReader, download, is_url},
+    input::{Spool, is_stdin},
+    ioc::{ioc_matches, parse_iocs},
+    layered::apply_layer,
+    merge::{apply_reg, apply_reg_in_place, merge_tree},
+    metrics::totals,
+    mutable::MutableHive,
+    mutate::{Outcome, mutations, outcome},
+    ntfs::{read_image_file, read_image_file_from},
+    payloads::find_payloads,
+    permissions::permission_findings,
+    plugins::{
+        HiveType, PluginRegistry, ReportFormat, open_plugin_hive, regripper::write_regripper,
+        run_plugins,
+    },
+    reachability::{orphan_cells, reachable_cells},
+    redact::Redaction,
+    reg::RegistryFile,
+    regfile::RegFile,
+    remnants::base_block_remnants,
+    repair::repair,
+    report::write_html_report,
+    salvage::salvage,
+    search::search,
+    security::{SecurityDescriptor, Sid},
+    siem::{SiemFormat, siem_event},
+    stats::{HiveStats, cell_stats, tree_stats},
+    strings::{Encoding, extract_strings},
+    template::Template,
+    timeline::timeline,
+    translog::{TransactionLog, applicable_entries, find_logs, is_dirty, replay, replay_changes},
+    tree::TreeKey,
+    utils::{
+        days_from_civil, escape_name, filetime_to_string, hex_dump, now_filetime, unix_to_filetime,
+    },
+    value::RegType,
+    watch::watch,
+    writer::{self, HiveWriter},
+    yara::{parse_rules, yara_scan},
+};
+
+#[derive(Parser)]
+#[command(name = "readregf", version, about = "Windows registry hive reader")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Apply the transaction logs found next to the hive file (.LOG, .LOG1, .LOG2) before reading it
+    #[arg(long, global = true)]
+    apply_logs: bool,
+
+    /// Read the hive out of the NTFS file system of this disk image, the hive path being a path in
+    /// the file system (e.g. Windows/System32/config/SYSTEM); its transaction logs aren't read. An
+    /// http(s) URL of an image is read with range requests, only the parts needed being downloaded
+    #[arg(long, global = true)]
+    image: Option<PathBuf>,
+
+    /// Read the hive out of this zip, 7z or gzip archive (e.g. a KAPE or Velociraptor collection),
+    /// the hive path being its path in the archive or the end of it (e.g.
+    /// Windows/System32/config/SYSTEM); any path for a gzip file. Its transaction logs aren't read.
+    /// Archives can be http(s) URLs too
+    #[arg(long, global = true, conflicts_with = "image")]
+    archive: Option<PathBuf>,
+
+    /// Print parse counters (cells read, records parsed, cache hits and misses, recovered records)
+    /// and the time of each phase to stderr
+    #[arg(long, global = true)]
+    stats: bool,
+
+    /// How hive files are read: mmap (pages read when accessed) or buffered (whole file read at
+    /// once, e.g. from network shares)
+    #[arg(long, global = true, default_value = "mmap")]
+    io_backend: IoBackend,
+
+    /// Size in bytes of each read of the buffered backend
+    #[arg(long, global = true, default_value_t = 1024 * 1024)]
+    buffer_size: usize,
+
+    /// Bytes at the start of a mapped hive file read ahead when it's opened
+    #[arg(long, global = true, default_value_t = 0)]
+    read_ahead: usize,
+
+    /// Most bytes allocated at once for a hive, whatever sizes damaged headers declare
+    #[arg(long, global = true, default_value_t = DEFAULT_MEMORY_LIMIT)]
+    memory_limit: u64,
+
+    /// Open keys by path from an index kept next to the hive file (NAME.keyidx), built by walking
+    /// the tree the first time and whenever the hive changes
+    #[arg(long, global = true)]
+    key_index: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    // clap uses doc comments as help text
+    // hive paths can be "-" to read the hive from stdin, or an http(s) URL to download it
+    /// Dump the base block, hive bins and cells of a hive
+    Dump {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Go on after inconsistent hbin headers
+        #[arg(long)]
+        tolerant: bool,
+    },
+
+    /// Check the consistency of hbin headers (offset, size, hive bins data size)
+    Check {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Go on after inconsistent hbin headers
+        #[arg(long)]
+        tolerant: bool,
+    },
+
+    /// Scan any file (memory dump, pagefile, unallocated clusters) for registry records
+    Carve {
+        /// Path to the file to scan
+        file: PathBuf,
+
+        /// Scan every byte instead of every 8 bytes (slower, finds unaligned records)
+        #[arg(long)]
+        unaligned: bool,
+
+        /// Only report records found in allocated cells
+        #[arg(long)]
+        allocated_only: bool,
+    },
+
+    /// Find the hives in a raw disk image and optionally extract them, truncated ones included
+    CarveHives {
+        /// Path to the disk image
+        image: PathBuf,
+
+        /// Directory where the hives found are written, named after their offset and file name
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// List all keys sorted by last written timestamp
+    Timeline {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Group keys by day
+        #[arg(long)]
+        by_day: bool,
+
+        /// Only show the N most recently written keys, most recent first
+        #[arg(long, value_name = "N")]
+        top: Option<usize>,
+    },
+
+    /// Compute digests of value data (tab separated output)
+    Hash {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Digest algorithms: md5, sha1, sha256
+        #[arg(long, value_delimiter = ',', default_value = "md5,sha1,sha256")]
+        algo: Vec<HashAlgorithm>,
+
+        /// Only hash REG_BINARY values
+        #[arg(long)]
+        binary_only: bool,
+
+        /// Only hash values whose data is at least this size in bytes
+        #[arg(long, default_value_t = 1)]
+        min_size: usize,
+    },
+
+    /// Report owner, group and DACL of each key
+    Acl {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Only report keys under this path
+        #[arg(long, default_value = "\\")]
+        key: String,
+
+        /// Only report keys writable by this SID, SDDL alias or account name (e.g. Everyone)
+        #[arg(long, value_name = "SID")]
+        writable_by: Option<Sid>,
+
+        /// Print owner, group and ACEs in expanded form instead of SDDL
+        #[arg(long)]
+        expanded: bool,
+    },
+
+    /// Report Services, Run, Winlogon and IFEO keys writable by non-admin accounts or with unexpected owners
+    Permissions {
+        /// Path to the hive file
+        hive: PathBuf,
+    },
+
+    /// Report structural anomalies and signs of tampering: lh hashes and lf hints not matching the
+    /// subkeys names, manipulated timestamps, names with hidden or illegal characters, values data
+    /// contradicting their type or holding executables
+    Anomalies {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Print the findings as CEF or LEEF events, one per line, for SIEM log collectors
+        #[arg(long)]
+        siem: Option<SiemFormat>,
+    },
+
+    /// Find PE images and base64 or hex encoded payloads in value data, including payloads split
+    /// among the values of a key
+    Payloads {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Directory where the decoded payloads are written
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Report hive composition metrics
+    Stats {
+        /// Path to the hive file
+        hive: PathBuf,
+    },
+
+    /// Extract strings from allocated cells, free cells and slack space
+    Strings {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Minimum string length in characters
+        #[arg(long, default_value_t = 4)]
+        min_len: usize,
+
+        /// String encoding: ascii, utf16 or both
+        #[arg(long, default_value = "both")]
+        encoding: Encoding,
+    },
+
+    /// Export keys and values as text, json, jsonl, reg, csv, bodyfile, bulk, osquery, cbor,
+    /// msgpack, dot, mermaid, parquet or sqlite
+    Export {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Path of the database file, required for sqlite output
+        #[arg(required_if_eq("format", "sqlite"))]
+        output: Option<PathBuf>,
+
+        /// Output format: text, json, jsonl (a JSON object per key and line), reg, csv, bodyfile,
+        /// bulk (Elasticsearch/OpenSearch _bulk API body, ECS registry fields), osquery (CSV rows of
+        /// the osquery registry table: key, path, name, type, data, mtime), cbor or msgpack (a
+        /// sequence of maps, one per key, with raw value data, written to stdout), dot (GraphViz graph
+        /// of the keys), mermaid (graph TD diagram of up to 500 keys, for markdown reports), parquet
+        /// (built with the parquet feature, written to stdout) or sqlite (built with the sqlite
+        /// feature: keys, values, security_descriptors and deleted_records tables)
+        #[arg(long, default_value = "text")]
+        format: ExportFormat,
+
+        /// Index of the documents in bulk output
+        #[arg(long, default_value = "registry")]
+        index: String,
+
+        /// Only export the subtree under this key
+        #[arg(long, default_value = "\\")]
+        key: String,
+
+        /// Label the keys of dot and mermaid output with their number of values and last written timestamp
+        #[arg(long)]
+        annotate: bool,
+
+        /// Key the hive is mounted on in reg, bulk and osquery output (default:
+        /// HKEY_LOCAL_MACHINE\<hive file name>)
+        #[arg(long)]
+        reg_root: Option<String>,
+
+        /// Line format of text output, fields: path, key_name, timestamp, value_name, type, size, data, hex
+        #[arg(long, default_value = "{path}\\t{value_name}\\t{type}\\t{data}")]
+        template: Template,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Print the JSON Schema of the json and jsonl exports: value data is typed by value type
+    /// (strings, numbers, arrays of strings, dates, base64 with the length otherwise)
+    Schema {
+        /// Print the index mapping of the bulk export instead
+        #[arg(long)]
+        mapping: bool,
+    },
+
+    /// List the subkeys and values of a key
+    Ls {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Path of the key, relative to the root key
+        #[arg(default_value = "\\")]
+        key: String,
+
+        /// Only print the number of subkeys and values
+        #[arg(long)]
+        count: bool,
+    },
+
+    /// Print the data of a value, or all values of a key
+    Get {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Path of the key, relative to the root key
+        key: String,
+
+        /// Name of the value, "@" for the default value
+        value: Option<String>,
+
+        /// Only print the number of matching values
+        #[arg(long)]
+        count: bool,
+    },
+
+    /// Search key names, value names and string data with a wildcard pattern (e.g. "*evil*")
+    Search {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Case insensitive pattern, '*' and '?' wildcards
+        pattern: String,
+
+        /// Only search the subtree under this key
+        #[arg(long, default_value = "\\")]
+        key: String,
+
+        #[command(flatten)]
+        filter: FilterArgs,
+
+        /// Only print the number of matches
+        #[arg(long)]
+        count: bool,
+    },
+    /// Print the changes of keys and values each time the hive file is updated
+    Watch {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Only watch the subtree under this key
+        #[arg(long, default_value = "\\")]
+        key: String,
+    },
+    /// Write a defragmented copy of a hive, with only the cells reachable from the root key, checked
+    /// against the original
+    Compact {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Path of the new hive file
+        output: PathBuf,
+
+        /// Normalize the copy: this FILETIME or UTC date (e.g. 0 or 2020-01-01) is written in every
+        /// key and the base block, with no file name, so that hives with the same keys and values
+        /// give identical files
+        #[arg(long, value_parser = parse_filetime)]
+        timestamp: Option<u64>,
+    },
+    /// Write a copy of a hive in another version of the format, 1.3 (lf lists, big data in a
+    /// single cell), 1.4 (big data records) or 1.5 (lh lists), checked against the original
+    Convert {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Path of the new hive file
+        output: PathBuf,
+
+        /// Minor version of the new hive file
+        #[arg(long, value_parser = clap::value_parser!(u32).range(3..=5))]
+        minor_version: u32,
+
+        /// Normalize the copy: this FILETIME or UTC date (e.g. 0 or 2020-01-01) is written in every
+        /// key and the base block, with no file name, so that hives with the same keys and values
+        /// give identical files
+        #[arg(long, value_parser = parse_filetime)]
+        timestamp: Option<u64>,
+    },
+    /// Write the subtree under a key to a new hive file that can be loaded on its own (reg load)
+    Extract {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Key whose subtree is written, e.g. ControlSet001\Services
+        key: String,
+
+        /// Path of the new hive file
+        output: PathBuf,
+
+        /// Name of the root key of the new hive, by default the name of the key
+        #[arg(long)]
+        root_name: Option<String>,
+
+        /// Normalize the copy: this FILETIME or UTC date (e.g. 0 or 2020-01-01) is written in every
+        /// key and the base block, with no file name, so that hives with the same keys and values
+        /// give identical files
+        #[arg(long, value_parser = parse_filetime)]
+        timestamp: Option<u64>,
+    },
+    /// Repair a hive truncated by the end of the file, and list what was lost
+    Repair {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Path of the repaired hive file
+        output: PathBuf,
+    },
+    /// Parse malformed variants of a valid hive (flipped sizes, broken offsets and counts, bad
+    /// signatures, truncations) and report the ones that make the parser panic
+    Stress {
+        /// Path to the valid hive file
+        hive: PathBuf,
+
+        /// Also write the variants to this directory, as a corpus
+        #[arg(long)]
+        corpus: Option<PathBuf>,
+
+        /// Most variants parsed, evenly picked among all of them
+        #[arg(long)]
+        max: Option<usize>,
+    },
+    /// Write a new hive with only a root key
+    Create {
+        /// Path of the new hive file
+        output: PathBuf,
+
+        /// Name of the root key
+        #[arg(long, default_value = "ROOT")]
+        root_name: String,
+
+        /// Minor version of the format: 3, 4 or 5
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(3..=5))]
+        minor_version: u32,
+    },
+    /// Recover deleted keys and values from free cells
+    Deleted {
+        /// Path to the hive file
+        hive: PathBuf,
+    },
+    /// Report allocated cells not reachable from the root key
+    Orphans {
+        /// Path to the hive file
+        hive: PathBuf,
+    },
+    /// Report non-zero content in the reserved regions of the base block
+    Remnants {
+        /// Path to the hive file
+        hive: PathBuf,
+    },
+    /// Parse what can be parsed of a damaged hive (e.g. extracted from memory): bad regions, keys and
+    /// values reachable from the root or from unreachable subtrees, and the records which can't be read
+    Salvage {
+        /// Path to the hive file
+        hive: PathBuf,
+    },
+    /// Print the log entries of a transaction log file (.LOG1, .LOG2)
+    Log {
+        /// Path to the log file
+        log: PathBuf,
+
+        /// Also print the content of dirty pages
+        #[arg(long)]
+        payload: bool,
+    },
+    /// Apply transaction logs to a hive, write the recovered hive and print the changes of keys and values
+    Replay {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Path of the recovered hive file
+        output: PathBuf,
+
+        /// Paths to the log files (.LOG1, .LOG2), by default the ones next to the hive file
+        logs: Vec<PathBuf>,
+    },
+    /// Write a copy of a hive with the keys and values of another hive or of a .reg file added
+    Merge {
+        /// Path to the target hive file
+        hive: PathBuf,
+
+        /// Path to the source hive or .reg file
+        source: PathBuf,
+
+        /// Path of the new hive file
+        output: PathBuf,
+
+        /// Merge into the subtree under this key of the target
+        #[arg(long, default_value = "\\")]
+        key: String,
+
+        /// Subtree of the source hive to merge
+        #[arg(long, default_value = "\\")]
+        source_key: String,
+
+        /// .reg key standing for the target key, by default HKEY_LOCAL_MACHINE\<TARGET FILE NAME>
+        #[arg(long)]
+        reg_root: Option<String>,
+    },
+    /// Merge differencing hives (layered keys of Windows containers, version 1.6) over a base hive,
+    /// lowest layer first, and write the effective view to a new hive
+    Overlay {
+        /// Path to the base hive file
+        hive: PathBuf,
+
+        /// Path of the new hive file
+        output: PathBuf,
+
+        /// Path to a differencing hive, lowest layer first
+        #[arg(long = "layer", required = true)]
+        layers: Vec<PathBuf>,
+    },
+    /// Apply the changes of a .reg file to the cells of a hive, written to a new file
+    Apply {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Path to the .reg file
+        changes: PathBuf,
+
+        /// Path of the new hive file
+        #[arg(long, required_unless_present = "dry_run")]
+        out: Option<PathBuf>,
+
+        /// Print the keys and values the changes would add, modify or delete, without writing
+        /// anything
+        #[arg(long, conflicts_with = "out")]
+        dry_run: bool,
+
+        /// .reg key standing for the root key, by default HKEY_LOCAL_MACHINE\<HIVE FILE NAME>
+        #[arg(long)]
+        reg_root: Option<String>,
+
+        /// Zero the cells freed by deletions, so that deleted keys and values can't be recovered
+        #[arg(long)]
+        wipe: bool,
+
+        /// Also write a transaction log OUT.LOG1 with the pages modified, as Windows does
+        #[arg(long, requires = "out")]
+        log: bool,
+
+        /// Leave OUT as if the system stopped after writing the log: the changes are only in the
+        /// log, to be replayed
+        #[arg(long, requires = "log")]
+        interrupted: bool,
+    },
+    /// Overwrite bytes of the hive bins data, e.g. the resident data of a value, the base block
+    /// being updated as by a write of Windows (sequence numbers, last written time, checksum) so
+    /// that the hive is consistent and its former transaction logs aren't replayed over the patch
+    Patch {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Offset relative to the start of the hive bins data, as printed by the other commands,
+        /// e.g. 0x1A2C
+        #[arg(long, value_parser = parse_offset)]
+        offset: u32,
+
+        /// Bytes written, in hex, e.g. 04000000 or 04,00,00,00
+        #[arg(long)]
+        bytes: String,
+
+        /// Path of the patched hive file, by default the hive file itself. Needed when the hive is
+        /// read from stdin, an image, an archive, a URL or with its logs applied
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Write a copy of a hive with user names, machine names, domain SIDs and chosen values
+    /// anonymized, to be shared
+    Redact {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Path of the new hive file
+        output: PathBuf,
+
+        /// User name replaced by user1, user2...
+        #[arg(long)]
+        user: Vec<String>,
+
+        /// Machine name replaced by MACHINE1, MACHINE2...
+        #[arg(long)]
+        machine: Vec<String>,
+
+        /// Other string to hide, as TEXT=REPLACEMENT
+        #[arg(long)]
+        replace: Vec<String>,
+
+        /// Value whose data is zeroed, as KEY\VALUE with wildcards, e.g. Software\*\Password
+        #[arg(long)]
+        wipe_value: Vec<String>,
+
+        /// Keep domain SIDs (S-1-5-21-...)
+        #[arg(long)]
+        keep_sids: bool,
+    },
+
+    /// Parse the ShimCache (AppCompatCache) of each control set of a SYSTEM hive
+    Shimcache {
+        /// Path to the SYSTEM hive file
+        hive: PathBuf,
+    },
+
+    /// Report the USB storage devices of a SYSTEM hive: make, model, serial, connection times and
+    /// drive letters
+    Usb {
+        /// Path to the SYSTEM hive file
+        hive: PathBuf,
+
+        /// SOFTWARE hive of the same system, for the volume names
+        #[arg(long)]
+        software: Option<PathBuf>,
+    },
+
+    /// Run YARA rules on value data, free cells and slack space (conditions on strings only)
+    Yara {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// File with the rules
+        #[arg(long)]
+        rules: PathBuf,
+    },
+
+    /// Match indicators of compromise (key paths, value names, strings, regular expressions, hashes)
+    /// against the keys and values of hives
+    Ioc {
+        /// File with one indicator per line: key:, value:, string:, regex:, md5:, sha1: or sha256:
+        /// followed by the pattern, then optionally a tab and a description
+        #[arg(long)]
+        iocs: PathBuf,
+
+        /// Paths to the hive files
+        #[arg(required = true)]
+        hives: Vec<PathBuf>,
+
+        /// Number of hives read at the same time, 0 for the number of CPUs
+        #[arg(long, default_value_t = 0)]
+        jobs: usize,
+
+        /// Print the findings as CEF or LEEF events, one per line, for SIEM log collectors
+        #[arg(long)]
+        siem: Option<SiemFormat>,
+    },
+
+    /// Compare a hive with a known-good reference hive and report only the keys and values deviating
+    /// from it (last written times are ignored)
+    Baseline {
+        /// Path to the reference hive file (e.g. from a gold image)
+        #[arg(long)]
+        reference: PathBuf,
+
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Only compare the subtree under this key
+        #[arg(long, default_value = "\\")]
+        key: String,
+
+        /// Also report keys and values of the reference missing from the hive
+        #[arg(long)]
+        missing: bool,
+    },
+
+    /// Print the history of the changes of keys and values across successive copies of a hive (e.g.
+    /// from volume shadow copies)
+    History {
+        /// Paths to the hive files, from the oldest to the most recent
+        #[arg(num_args = 2.., required = true)]
+        hives: Vec<PathBuf>,
+
+        /// Only compare the subtree under this key
+        #[arg(long, default_value = "\\")]
+        key: String,
+    },
+
+    /// List or run the artifact plugins
+    Plugins {
+        #[command(subcommand)]
+        action: PluginsCommand,
+    },
+
+    /// Run the plugins on many hives at the same time (e.g. the NTUSER.DAT files of a triage
+    /// collection) and print a single report
+    Triage {
+        /// Paths to the hive files
+        #[arg(required = true)]
+        hives: Vec<PathBuf>,
+
+        /// Number of hives processed at the same time, 0 for the number of CPUs
+        #[arg(long, default_value_t = 0)]
+        jobs: usize,
+
+        /// Plugins to run on every hive, by default the plugins applicable to each hive
+        #[arg(long, value_delimiter = ',')]
+        plugin: Vec<String>,
+
+        /// Print the findings as CEF or LEEF events, one per line, for SIEM log collectors
+        #[arg(long)]
+        siem: Option<SiemFormat>,
+    },
+
+    /// Write a standalone HTML report of a hive: collapsible keys tree, searchable values table,
+    /// anomaly findings and the findings of the plugins applicable to the hive
+    Report {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// HTML file written
+        #[arg(long)]
+        html: PathBuf,
+    },
+
+    /// Decode the MRU lists of an NTUSER.DAT hive: RecentDocs, OpenSavePidlMRU, RunMRU and TypedURLs
+    Mru {
+        /// Path to the NTUSER.DAT hive file
+        hive: PathBuf,
+    },
+
+    /// Report the network interfaces of a SYSTEM hive and the network profiles of a SOFTWARE hive:
+    /// IP configuration, DNS servers, network names and connection times
+    Network {
+        /// Path to the SYSTEM or SOFTWARE hive file
+        hive: PathBuf,
+    },
+
+    /// Sweep the persistence locations of a SOFTWARE, NTUSER.DAT or SYSTEM hive: Run keys, Winlogon,
+    /// Image File Execution Options debuggers, AppInit_DLLs, Active Setup and services
+    Autoruns {
+        /// Path to the hive file
+        hive: PathBuf,
 
-fn main() -> anyhow::Result<()> {
-    let path = PathBuf::from("./data/SYSTEM");
+        /// Only list suspicious entries
+        #[arg(long)]
+        flagged: bool,
+
+        /// Print the findings as CEF or LEEF events, one per line, for SIEM log collectors
+        #[arg(long)]
+        siem: Option<SiemFormat>,
+    },
+
+    /// List the services and drivers of each control set of a SYSTEM hive, flagging recently modified
+    /// ones and binaries in unusual locations
+    Services {
+        /// Path to the SYSTEM hive file
+        hive: PathBuf,
+
+        /// Services last written less than this number of days before the hive are flagged
+        #[arg(long, default_value_t = 30)]
+        recent_days: u64,
+
+        /// Only list flagged services
+        #[arg(long)]
+        flagged: bool,
+    },
+
+    /// Report the local user accounts of a SAM hive: names, flags, logon and password times, counters
+    Sam {
+        /// Path to the SAM hive file
+        hive: PathBuf,
+    },
+
+    /// Rebuild the folders browsed with Explorer from the ShellBags of an NTUSER.DAT or UsrClass.dat hive
+    Shellbags {
+        /// Path to the NTUSER.DAT or UsrClass.dat hive file
+        hive: PathBuf,
+    },
+
+    /// Decode the UserAssist entries of an NTUSER.DAT hive: run counts, focus times and last executions
+    Userassist {
+        /// Path to the NTUSER.DAT hive file
+        hive: PathBuf,
+    },
+
+    /// List the programs of the MUICache of an NTUSER.DAT (XP) or UsrClass.dat hive with their display
+    /// names, cached when they were first run
+    Muicache {
+        /// Path to the NTUSER.DAT or UsrClass.dat hive file
+        hive: PathBuf,
+    },
+
+    /// Build the execution timeline of the programs of each user from the BAM and DAM keys of a SYSTEM
+    /// hive, most recent first
+    Bam {
+        /// Path to the SYSTEM hive file
+        hive: PathBuf,
+    },
+
+    /// List the compatibility modes set on programs (AppCompatFlags\Layers) in an NTUSER.DAT or
+    /// SOFTWARE hive
+    Layers {
+        /// Path to the NTUSER.DAT or SOFTWARE hive file
+        hive: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum PluginsCommand {
+    /// List the plugins with the hive types and keys they apply to
+    List,
+
+    /// Run plugins on a hive and print their findings
+    Run {
+        /// Path to the hive file
+        hive: PathBuf,
+
+        /// Run all the plugins applicable to the hive
+        #[arg(long, conflicts_with = "plugin")]
+        all: bool,
+
+        /// Plugins to run, whatever the hive type
+        #[arg(long, value_delimiter = ',', required_unless_present = "all")]
+        plugin: Vec<String>,
+
+        /// Output format: text or regripper
+        #[arg(long, default_value = "text")]
+        format: ReportFormat,
+    },
+}
+
+#[derive(Args)]
+struct FilterArgs {
+    /// Only values whose name matches this pattern ('*' and '?' wildcards)
+    #[arg(long, value_name = "PATTERN")]
+    value_name_glob: Option<String>,
+
+    /// Only values of these types (e.g. REG_SZ,REG_BINARY)
+    #[arg(long = "type", value_delimiter = ',')]
+    types: Vec<RegType>,
+
+    /// Only values whose data is at least this size in bytes
+    #[arg(long, default_value_t = 0)]
+    min_data_size: usize,
+}
+
+impl From<FilterArgs> for ValueFilter {
+    fn from(args: FilterArgs) -> Self {
+        ValueFilter {
+            name_glob: args.value_name_glob,
+            types: args.types,
+            min_data_size: args.min_data_size,
+        }
+    }
+}
+
+impl Command {
+    // path of the file to read, None for commands reading no file
+    fn input_mut(&mut self) -> Option<&mut PathBuf> {
+        Some(match self {
+            Command::Carve { file, .. } => file,
+            Command::Log { log, .. } => log,
+            Command::Plugins {
+                action: PluginsCommand::List,
+            } => return None,
+            // each hive is recovered when read
+            Command::Ioc { .. } | Command::History { .. } | Command::Triage { .. } => {
+                return None;
+            }
+            // a disk image, not a hive
+            Command::CarveHives { .. } => return None,
+            Command::Create { .. } | Command::Schema { .. } => return None,
+            Command::Plugins {
+                action: PluginsCommand::Run { hive, .. },
+            } => hive,
+            Command::Dump { hive, .. }
+            | Command::Check { hive, .. }
+            | Command::Timeline { hive, .. }
+            | Command::Hash { hive, .. }
+            | Command::Acl { hive, .. }
+            | Command::Permissions { hive }
+            | Command::Anomalies { hive, .. }
+            | Command::Report { hive, .. }
+            | Command::Payloads { hive, .. }
+            | Command::Stats { hive }
+            | Command::Strings { hive, .. }
+            | Command::Export { hive, .. }
+            | Command::Ls { hive, .. }
+            | Command::Get { hive, .. }
+            | Command::Search { hive, .. }
+            | Command::Watch { hive, .. }
+            | Command::Compact { hive, .. }
+            | Command::Convert { hive, .. }
+            | Command::Extract { hive, .. }
+            | Command::Merge { hive, .. }
+            | Command::Overlay { hive, .. }
+            | Command::Apply { hive, .. }
+            | Command::Patch { hive, .. }
+            | Command::Redact { hive, .. }
+            | Command::Deleted { hive }
+            | Command::Orphans { hive }
+            | Command::Remnants { hive }
+            | Command::Salvage { hive }
+            | Command::Repair { hive, .. }
+            | Command::Stress { hive, .. }
+            | Command::Shimcache { hive }
+            | Command::Userassist { hive }
+            | Command::Muicache { hive }
+            | Command::Layers { hive }
+            | Command::Bam { hive }
+            | Command::Shellbags { hive }
+            | Command::Sam { hive }
+            | Command::Services { hive, .. }
+            | Command::Autoruns { hive, .. }
+            | Command::Usb { hive, .. }
+            | Command::Network { hive }
+            | Command::Mru { hive }
+            | Command::Yara { hive, .. }
+            | Command::Baseline { hive, .. }
+            | Command::Replay { hive, .. } => hive,
+        })
+    }
+}
+
+// ls, get, search, yara, ioc and baseline exit codes are grep like: 0 when found, 1 when not found, 2 on errors.
+// check exits with 1 when issues are found.
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let print_stats = cli.stats;
+
+    let start = Instant::now();
+    let result = run(cli);
+    if print_stats {
+        totals().record_phase("total", start.elapsed());
+        eprint!("{}", totals());
+    }
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        // stdout piped into a command which exited early (e.g. head)
+        Err(e) if is_broken_pipe(&e) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn is_broken_pipe(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == ErrorKind::BrokenPipe)
+    })
+}
+
+// returns false when nothing was found
+fn run(mut cli: Cli) -> anyhow::Result<bool> {
+    // before any hive is opened
+    set_default_options(ParserOptions {
+        backend: cli.io_backend,
+        buffer_size: cli.buffer_size,
+        read_ahead: cli.read_ahead,
+        memory_limit: cli.memory_limit,
+    })?;
+
+    // the .reg root key defaults to the hive file name, which is lost when reading stdin
+    if let Command::Export {
+        hive,
+        reg_root: reg_root @ None,
+        ..
+    }
+    | Command::Merge {
+        hive,
+        reg_root: reg_root @ None,
+        ..
+    }
+    | Command::Apply {
+        hive,
+        reg_root: reg_root @ None,
+        ..
+    } = &mut cli.command
+    {
+        let name = match hive.file_name() {
+            Some(name) if !is_stdin(hive) => name.to_string_lossy().to_uppercase(),
+            _ => "HIVE".to_string(),
+        };
+        *reg_root = Some(format!("HKEY_LOCAL_MACHINE\\{name}"));
+    }
+
+    // the report names the hive given, not the copy read from stdin, an image or an archive
+    let report_title = match &cli.command {
+        Command::Report { hive, .. } => hive.to_string_lossy().to_string(),
+        _ => String::new(),
+    };
+
+    // the recovered hive, stdin or the file of the image is spooled for the whole run
+    let reads_hive = !matches!(
+        cli.command,
+        Command::Carve { .. }
+            | Command::Log { .. }
+            | Command::Replay { .. }
+            | Command::Watch { .. }
+    );
+    let _spool = match (cli.command.input_mut(), &cli.image, &cli.archive) {
+        (Some(input), Some(image), _) => {
+            let bytes = image_file(image, &input.to_string_lossy())?;
+            let spool = Spool::from_bytes(&bytes)?;
+            *input = spool.path().to_path_buf();
+            Some(spool)
+        }
+        (None, Some(_), _) => anyhow::bail!("--image isn't supported by this command"),
+        (Some(input), _, Some(archive)) => {
+            let spool = Spool::write_with(|f| archive_file(archive, &input.to_string_lossy(), f))?;
+            *input = spool.path().to_path_buf();
+            Some(spool)
+        }
+        (None, _, Some(_)) => anyhow::bail!("--archive isn't supported by this command"),
+        (Some(input), None, None) if is_url(&input.to_string_lossy()) => {
+            let spool = Spool::write_with(|f| download(&input.to_string_lossy(), f))?;
+            *input = spool.path().to_path_buf();
+            Some(spool)
+        }
+        (Some(input), None, None) if is_stdin(input) => {
+            let spool = Spool::stdin()?;
+            *input = spool.path().to_path_buf();
+            Some(spool)
+        }
+        (Some(input), None, None) if reads_hive => recover_hive(input, cli.apply_logs)?,
+        _ => None,
+    };
+
+    // not worth it for copies of the hive
+    let key_index = cli.key_index && _spool.is_none();
+
+    match cli.command {
+        Command::Dump { hive, tolerant } => dump(hive, tolerant)?,
+        Command::Check { hive, tolerant } => return check(hive, tolerant),
+        Command::Carve {
+            file,
+            unaligned,
+            allocated_only,
+        } => {
+            let options = CarveOptions {
+                step: if unaligned { 1 } else { 8 },
+                include_free: !allocated_only,
+            };
+            carve(file, options)?
+        }
+        Command::CarveHives { image, output } => carve_hives(image, output)?,
+        Command::Timeline { hive, by_day, top } => timeline_cmd(hive, by_day, top)?,
+        Command::Hash {
+            hive,
+            algo,
+            binary_only,
+            min_size,
+        } => hash(hive, &algo, binary_only, min_size)?,
+        Command::Acl {
+            hive,
+            key,
+            writable_by,
+            expanded,
+        } => acl(hive, &key, writable_by.as_ref(), expanded, key_index)?,
+        Command::Permissions { hive } => permissions(hive)?,
+        Command::Anomalies { hive, siem } => anomalies(hive, siem)?,
+        Command::Payloads { hive, output } => payloads_cmd(hive, output)?,
+        Command::Stats { hive } => stats(hive)?,
+        Command::Strings {
+            hive,
+            min_len,
+            encoding,
+        } => strings(hive, min_len, encoding)?,
+        Command::Export {
+            hive,
+            output,
+            format,
+            index,
+            key,
+            annotate,
+            reg_root,
+            template,
+            filter,
+        } => {
+            let options = ExportOptions {
+                format,
+                template,
+                reg_root: reg_root.unwrap_or_default(),
+                index,
+                annotate,
+                filter: filter.into(),
+            };
+            export_cmd(hive, output, &key, &options, key_index)?
+        }
+        Command::Ls { hive, key, count } => return ls(hive, &key, count, key_index),
+        Command::Get {
+            hive,
+            key,
+            value,
+            count,
+        } => return get(hive, &key, value.as_deref(), count, key_index),
+        Command::Search {
+            hive,
+            pattern,
+            key,
+            filter,
+            count,
+        } => return search_cmd(hive, &pattern, &key, &filter.into(), count, key_index),
+        Command::Watch { hive, key } => watch_cmd(hive, &key)?,
+        Command::Compact {
+            hive,
+            output,
+            timestamp,
+        } => compact(hive, output, timestamp)?,
+        Command::Convert {
+            hive,
+            output,
+            minor_version,
+            timestamp,
+        } => convert(hive, output, minor_version, timestamp)?,
+        Command::Extract {
+            hive,
+            key,
+            output,
+            root_name,
+            timestamp,
+        } => extract(hive, &key, output, root_name, timestamp)?,
+        Command::Create {
+            output,
+            root_name,
+            minor_version,
+        } => create(output, &root_name, minor_version)?,
+        Command::Schema { mapping: false } => println!("{}", json_schema()),
+        Command::Schema { mapping: true } => println!("{}", bulk_mapping()),
+        Command::Deleted { hive } => deleted(hive)?,
+        Command::Orphans { hive } => orphans(hive)?,
+        Command::Remnants { hive } => remnants(hive)?,
+        Command::Salvage { hive } => salvage_cmd(hive)?,
+        Command::Repair { hive, output } => repair_cmd(hive, output)?,
+        Command::Stress { hive, corpus, max } => stress(hive, corpus, max)?,
+        Command::Shimcache { hive } => shimcache(hive)?,
+        Command::Userassist { hive } => userassist_cmd(hive)?,
+        Command::Muicache { hive } => muicache_cmd(hive)?,
+        Command::Layers { hive } => layers_cmd(hive)?,
+        Command::Bam { hive } => bam(hive)?,
+        Command::Shellbags { hive } => shellbags_cmd(hive)?,
+        Command::Sam { hive } => sam(hive)?,
+        Command::Autoruns {
+            hive,
+            flagged,
+            siem,
+        } => autoruns_cmd(hive, flagged, siem)?,
+        Command::Usb { hive, software } => usb(hive, software)?,
+        Command::Network { hive } => network(hive)?,
+        Command::Mru { hive } => mru(hive)?,
+        Command::Plugins { action } => plugins(action)?,
+        Command::Triage {
+            hives,
+            jobs,
+            plugin,
+            siem,
+        } => return triage(hives, jobs, plugin, siem, cli.apply_logs),
+        Command::Report { hive, html } => report(hive, html, &report_title)?,
+        Command::Yara { hive, rules } => return yara(hive, rules),
+        Command::Ioc {
+            iocs,
+            hives,
+            jobs,
+            siem,
+        } => return ioc(iocs, hives, jobs, siem, cli.apply_logs),
+        Command::History { hives, key } => history_cmd(hives, &key, cli.apply_logs)?,
+        Command::Baseline {
+            reference,
+            hive,
+            key,
+            missing,
+        } => return baseline(reference, hive, &key, missing, cli.apply_logs),
+        Command::Services {
+            hive,
+            recent_days,
+            flagged,
+        } => services_cmd(hive, recent_days, flagged)?,
+        Command::Log { log, payload } => log_cmd(log, payload)?,
+        Command::Replay { hive, output, logs } => replay_cmd(hive, output, logs)?,
+        Command::Merge {
+            hive,
+            source,
+            output,
+            key,
+            source_key,
+            reg_root,
+        } => merge(
+            hive,
+            source,
+            output,
+            &key,
+            &source_key,
+            &reg_root.unwrap_or_default(),
+        )?,
+        Command::Overlay {
+            hive,
+            output,
+            layers,
+        } => overlay(hive, output, layers)?,
+        Command::Apply {
+            hive,
+            changes,
+            out,
+            dry_run: _,
+            reg_root,
+            wipe,
+            log,
+            interrupted,
+        } => apply(
+            hive,
+            changes,
+            out,
+            &reg_root.unwrap_or_default(),
+            wipe,
+            log,
+            interrupted,
+        )?,
+        Command::Patch {
+            hive,
+            offset,
+            bytes,
+            out,
+        } => patch(hive, offset, &bytes, out, _spool.is_some())?,
+        Command::Redact {
+            hive,
+            output,
+            user,
+            machine,
+            replace,
+            wipe_value,
+            keep_sids,
+        } => redact(
+            hive,
+            output,
+            &user,
+            &machine,
+            &replace,
+            &wipe_value,
+            keep_sids,
+        )?,
+    }
+
+    Ok(true)
+}
+
+// a file of a disk image, read over HTTP when the image is a URL
+fn image_file(image: &Path, path: &str) -> anyhow::Result<Vec<u8>> {
+    let location = image.to_string_lossy();
+    if is_url(&location) {
+        return read_image_file_from(&mut HttpRangeReader::open(&location)?, &location, path);
+    }
+    read_image_file(image, path)
+}
+
+// a file of an archive, read over HTTP when the archive is a URL
+fn archive_file(archive: &Path, path: &str, out: &mut File) -> anyhow::Result<()> {
+    let location = archive.to_string_lossy();
+    if is_url(&location) {
+        let mut reader = HttpRangeReader::open(&location)?;
+        return copy_archive_file_from(&mut reader, &location, path, out);
+    }
+    copy_archive_file(archive, path, out)
+}
+
+// replay the logs found next to a hive, the path is replaced by the one of the recovered hive. Logs
+// are only reported when they're not applied.
+fn recover_hive(path: &mut PathBuf, apply: bool) -> anyhow::Result<Option<Spool>> {
+    let log_paths = find_logs(path);
+    if log_paths.is_empty() {
+        return Ok(None);
+    }
+
+    let mut logs = Vec::new();
+    for log_path in &log_paths {
+        match TransactionLog::open(log_path) {
+            Ok(log) => logs.push(log),
+            Err(e) => eprintln!("warning: can't parse {}: {e}", log_path.display()),
+        }
+    }
+
+    // the primary is only copied when the logs are applied
+    let hive = RegistryHive::open(path)?;
+    let pending = applicable_entries(&hive.base_block, &logs).len();
+    if pending == 0 {
+        return Ok(None);
+    }
+
+    if !apply {
+        eprintln!(
+            "warning: {pending} log entries not applied to {}{}, use --apply-logs",
+            path.display(),
+            if is_dirty(&hive.base_block) {
+                " (dirty)"
+            } else {
+                ""
+            }
+        );
+        return Ok(None);
+    }
+
+    let replay = replay(hive.buffer(), &logs)?;
+    for (log, entry) in &replay.applied {
+        for page in &entry.pages {
+            eprintln!(
+                "{}: sequence {} patched 0x{:08X}-0x{:08X}",
+                log_paths[*log].display(),
+                entry.sequence_number,
+                page.offset,
+                page.offset as usize + page.data.len()
+            );
+        }
+    }
+
+    let spool = Spool::from_bytes(&replay.hive)?;
+    *path = spool.path().to_path_buf();
+    Ok(Some(spool))
+}
+
+fn dump(path: PathBuf, tolerant: bool) -> anyhow::Result<()> {
     let mut regf = RegistryFile::try_from(path.as_path())?;
+    regf.set_tolerant(tolerant);
 
     let base_block = regf.read_header()?;
-    println!("{:?}", base_block);
+    println!("{}", base_block);
 
     for mut hbin in &mut regf {
         println!("{hbin}");
@@ -24,86 +1371,1469 @@ fn main() -> anyhow::Result<()> {
         for cell in &mut hbin {
             println!("{cell}");
         }
+        for issue in &hbin.issues {
+            eprintln!("warning: {issue}");
+        }
+    }
+
+    for issue in &regf.issues {
+        eprintln!("warning: {issue}");
+    }
+    Ok(())
+}
+
+// returns false when issues are found
+fn check(path: PathBuf, tolerant: bool) -> anyhow::Result<bool> {
+    let mut regf = RegistryFile::try_from(path.as_path())?;
+    regf.set_tolerant(tolerant);
+
+    let base_block = regf.read_header()?;
+    let (mut count, mut size) = (0, 0u64);
+    let mut cell_issues = Vec::new();
+    for mut hbin in &mut regf {
+        count += 1;
+        size += hbin.header.size as u64;
+
+        // invalid cells are reported while iterating
+        for _ in &mut hbin {}
+        cell_issues.append(&mut hbin.issues);
+    }
+
+    println!(
+        "hbins: {count} size: 0x{size:X} hive bins data size: 0x{:X}",
+        base_block.hive_bins_data_size
+    );
+    for issue in regf.issues.iter().chain(&cell_issues) {
+        println!("{issue}");
+    }
+    Ok(regf.issues.is_empty() && cell_issues.is_empty())
+}
+
+fn carve(path: PathBuf, options: CarveOptions) -> anyhow::Result<()> {
+    let data = HiveBuffer::open(&path)?;
+    let result = Carver::new(&data, options).carve();
+
+    println!(
+        "hbins: {} keys: {} orphan values: {} security: {}",
+        result.bins.len(),
+        result.keys.len(),
+        result.orphan_values.len(),
+        result.security.len()
+    );
+
+    for key in &result.keys {
+        println!("{key}");
+        for value in &key.values {
+            println!("    {value}");
+        }
     }
 
-    // let file = File::open("./data/SYSTEM")?;
-    // let mut reader = BufReader::new(file);
+    if !result.orphan_values.is_empty() {
+        println!("orphan values:");
+        for value in &result.orphan_values {
+            println!("    {value}");
+        }
+    }
+
+    Ok(())
+}
+
+fn carve_hives(path: PathBuf, output: Option<PathBuf>) -> anyhow::Result<()> {
+    let mut image = BufReader::new(File::open(&path)?);
+    let hives = find_hives(&mut image)?;
+
+    for hive in &hives {
+        println!("{hive}");
+        if let Some(dir) = &output {
+            // the name comes from the image
+            let name = hive
+                .file_name()
+                .replace(|c: char| !c.is_alphanumeric() && !"._-".contains(c), "_");
+            let file = dir.join(format!("{:010X}_{name}", hive.image_offset));
+            let mut w = BufWriter::new(File::create(&file)?);
+            extract_hive(&mut image, hive, &mut w)?;
+            w.flush()?;
+        }
+    }
+    println!("{} hives found", hives.len());
+    Ok(())
+}
+
+fn timeline_cmd(path: PathBuf, by_day: bool, top: Option<usize>) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let mut entries = timeline(&mut hive)?;
+
+    // recent changes view
+    if let Some(n) = top {
+        entries.reverse();
+        entries.truncate(n);
+    }
+
+    let mut current_day = String::new();
+    for entry in &entries {
+        if by_day {
+            let day = entry.day();
+            if day != current_day {
+                println!("{day}");
+                current_day = day;
+            }
+            print!("    ");
+        }
+        println!("{entry}");
+    }
 
-    // let mut buffer = [0u8; 4096];
+    Ok(())
+}
 
-    // let config = bincode::config::standard()
-    //     .with_little_endian()
-    //     .with_fixed_int_encoding();
+fn hash(
+    path: PathBuf,
+    algorithms: &[HashAlgorithm],
+    binary_only: bool,
+    min_size: usize,
+) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
 
-    // let header: BaseBlock = bincode::decode_from_reader(&mut reader, config).unwrap();
-    // let total_hbins_size = header.hive_bins_data_size;
-    // let mut current_hbins_size = 0u32;
-    // // let pos = reader.seek(SeekFrom::Current(0))?;
-    // println!("{}", header);
-    // // println!("pos after base block = 0x{:X?}", pos);
+    let header: Vec<String> = algorithms.iter().map(|a| a.to_string()).collect();
+    println!("path\tvalue\ttype\tsize\t{}", header.join("\t"));
 
-    // while current_hbins_size < total_hbins_size {
-    //     let hive_bin = HiveBin::try_from(&mut reader)?;
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |hive, path, key| {
+        for value in hive.values(key)? {
+            if binary_only && value.reg_type() != RegType::Binary {
+                continue;
+            }
+            let data = hive.value_bytes(&value)?;
+            if data.len() < min_size {
+                continue;
+            }
 
-    //     current_hbins_size += hive_bin.header.size;
-    //     // let pos = reader.seek(SeekFrom::Current(0))?;
-    //     // println!("pos: {:X?} {}", pos, hive_bin);
+            let digests: Vec<String> = algorithms.iter().map(|a| a.digest(&data)).collect();
+            println!(
+                "{path}\t{}\t{}\t{}\t{}",
+                value.display_name(),
+                value.reg_type(),
+                data.len(),
+                digests.join("\t")
+            );
+        }
+        Ok(())
+    })
+}
 
-    //     let total_cells_size = hive_bin.header.size - 32;
-    //     let mut current_cells_size = 0u32;
+// with the index of its key paths when asked for
+fn open_hive(path: &Path, key_index: bool) -> anyhow::Result<RegistryHive> {
+    let mut hive = RegistryHive::open(path)?;
+    if key_index {
+        hive.use_key_index(path)?;
+    }
+    Ok(hive)
+}
 
-    //     while current_cells_size <= total_cells_size {
-    //         let mut cursor = Cursor::new(hive_bin.cells.as_slice());
+fn acl(
+    path: PathBuf,
+    key: &str,
+    writable_by: Option<&Sid>,
+    expanded: bool,
+    key_index: bool,
+) -> anyhow::Result<()> {
+    let mut hive = open_hive(&path, key_index)?;
+    let (path, start) = hive
+        .open_key_path(key)?
+        .ok_or_else(|| anyhow::anyhow!("key '{key}' not found"))?;
 
-    //         let cell = Cell::try_from(&mut cursor)?;
-    //         assert!(cell.size % 8 == 0);
+    // security descriptors are shared between keys
+    let mut descriptors: HashMap<u32, SecurityDescriptor> = HashMap::new();
 
-    //         current_cells_size += cell.size.unsigned_abs();
+    hive.walk(&start, &path, &mut |hive, path, key| {
+        let offset = key.node.header.key_security_offset;
+        let sd = match descriptors.entry(offset) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                let security = hive.security(key)?;
+                e.insert(SecurityDescriptor::try_from(
+                    security.descriptor.as_slice(),
+                )?)
+            }
+        };
 
-    //         println!("cell={}", cell);
-    //     }
-    //     // println!("pos after hive_bin = 0x{:X?}", pos);
-    //     // read hive bin header
-    //     // println!("{:X?} len={}", hive_bin.header, hive_bin.cells.len());
+        if writable_by.is_some_and(|sid| !sd.writable_by(sid)) {
+            return Ok(());
+        }
 
-    //     // // read all cells which follow the hive bin header
-    //     // let all_cells_size = hive_bin_header.size as usize - 32;
-    //     // println!("all_cells_size={all_cells_size}");
-    //     // let mut current_size = 0usize;
+        if expanded {
+            println!("{path}");
+            let name = |sid: &Option<Sid>| match sid {
+                Some(sid) => match sid.account_name() {
+                    Some(name) => format!("{name} ({sid})"),
+                    None => sid.to_string(),
+                },
+                None => "-".to_string(),
+            };
+            println!("    owner: {}", name(&sd.owner));
+            println!("    group: {}", name(&sd.group));
+            for ace in sd.dacl.iter().flat_map(|d| &d.aces) {
+                println!("    {}", ace.expanded());
+            }
+        } else {
+            println!("{path}\t{}", sd.to_sddl());
+        }
+        Ok(())
+    })
+}
 
-    //     // // read each individual cell including its data
-    //     // // A free (unused) cell is indicated by a positive size, and an allocated cell is indicated by a negative one.
-    //     // // For example, a free cell of 32 bytes has a length marker of 0x00000020, while an active cell of 128 bytes
-    //     // // has its size encoded as 0xFFFFFF80
-    //     // while current_size < all_cells_size {
-    //     //     let mut cell_size = [0u8; 4];
-    //     //     let _ = reader.read_exact(&mut cell_size);
-    //     //     let cell_size = i32::from_le_bytes(cell_size) as isize;
-    //     //     println!("cell_size={cell_size}");
+fn permissions(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let mut findings = permission_findings(&mut hive)?;
+    sort_findings(&mut findings);
 
-    //     //     if cell_size % 8 != 0 {
-    //     //         panic!("error");
-    //     //     }
+    for finding in &findings {
+        println!("{finding}");
+    }
 
-    //     //     // now we know cell data size, so read cell data
-    //     //     // read cell type
-    //     //     let mut cell_type = [0u8; 2];
-    //     //     let _ = reader.read_exact(&mut cell_type);
-    //     //     let cell_type = CellType::try_from(cell_type).unwrap();
-    //     //     println!("cell type={:?}", cell_type);
+    Ok(())
+}
 
-    //     //     let mut buf = vec![0u8; cell_size.unsigned_abs() - 6];
-    //     //     let _ = reader.read_exact(&mut buf);
+fn anomalies(path: PathBuf, siem: Option<SiemFormat>) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let mut findings = anomaly_findings(&mut hive)?;
+    sort_findings(&mut findings);
 
-    //     //     current_size += cell_size.unsigned_abs();
-    //     //     println!("current_size={current_size}");
-    //     // }
-    //     /*         let mut buf = vec![0u8; hive_bin_header.size as usize - 32];
-    //     reader.read_exact(&mut buf);  */
-    // }
+    for finding in &findings {
+        match siem {
+            Some(format) => println!("{}", siem_event(format, finding, &path.to_string_lossy())),
+            None => println!("{finding}"),
+        }
+    }
 
     Ok(())
+}
 
-    /*     let hive_bin_header: HiveBinHeader = bincode::decode_from_reader(&mut reader, config).unwrap();
-    println!("{}", hive_bin_header); */
+fn payloads_cmd(path: PathBuf, output: Option<PathBuf>) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let payloads = find_payloads(&mut hive)?;
+
+    for (i, payload) in payloads.iter().enumerate() {
+        println!("{payload}");
+        if let Some(dir) = &output {
+            // the names come from the hive
+            let name = payload
+                .value_names
+                .join("+")
+                .replace(|c: char| !c.is_alphanumeric() && !"._-+".contains(c), "_");
+            let extension = if payload.is_pe() { "exe" } else { "bin" };
+            std::fs::write(
+                dir.join(format!("{i:04}_{name}.{extension}")),
+                &payload.data,
+            )?;
+        }
+    }
+    println!("{} payloads found", payloads.len());
+    Ok(())
+}
+
+fn stats(path: PathBuf) -> anyhow::Result<()> {
+    let mut stats = HiveStats::default();
+    cell_stats(&path, &mut stats)?;
+
+    let mut hive = RegistryHive::open(&path)?;
+    tree_stats(&mut hive, &mut stats)?;
+
+    print!("{stats}");
+    Ok(())
+}
+
+fn strings(path: PathBuf, min_len: usize, encoding: Encoding) -> anyhow::Result<()> {
+    // owning key and used bytes of each referenced cell
+    let mut hive = RegistryHive::open(&path)?;
+    let owners = reachable_cells(&mut hive)?;
+
+    let index = hive.index()?;
+    for cell in index.cells() {
+        let content = index.content(cell);
+        let owner = owners.get(&cell.offset);
+
+        for s in extract_strings(content, min_len, encoding) {
+            let region = match owner {
+                _ if cell.is_free() => "free",
+                Some(owner) if s.position >= owner.used => "slack",
+                Some(_) => "allocated",
+                None => "unreferenced",
+            };
+            let path = owner.map(|o| o.path.as_str()).unwrap_or("-");
+
+            // file offset: base block, cell offset, cell size field
+            let file_offset = 4096 + cell.offset as usize + 4 + s.position;
+            println!("0x{file_offset:08X}\t{region}\t{path}\t{s}");
+        }
+    }
+
+    Ok(())
+}
+
+fn export_cmd(
+    path: PathBuf,
+    output: Option<PathBuf>,
+    key: &str,
+    options: &ExportOptions,
+    key_index: bool,
+) -> anyhow::Result<()> {
+    let mut hive = open_hive(&path, key_index)?;
+    let (path, start) = hive
+        .open_key_path(key)?
+        .ok_or_else(|| anyhow::anyhow!("key '{key}' not found"))?;
+
+    if options.format == ExportFormat::Sqlite {
+        let db = output.ok_or_else(|| anyhow::anyhow!("no database file given"))?;
+        #[cfg(feature = "sqlite")]
+        {
+            let report = export_sqlite(&mut hive, &start, &path, &options.filter, &db)?;
+            eprintln!(
+                "{} keys, {} values, {} security descriptors, {} deleted records written to {}",
+                report.keys,
+                report.values,
+                report.descriptors,
+                report.deleted,
+                db.display()
+            );
+            return Ok(());
+        }
+        #[cfg(not(feature = "sqlite"))]
+        anyhow::bail!(
+            "sqlite export to {} needs readregf built with the sqlite feature",
+            db.display()
+        );
+    }
+    anyhow::ensure!(
+        output.is_none(),
+        "{} output is written to stdout",
+        options.format
+    );
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    export(&mut hive, &start, &path, options, &mut out)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+fn ls(path: PathBuf, key: &str, count: bool, key_index: bool) -> anyhow::Result<bool> {
+    let hive = open_hive(&path, key_index)?;
+    let Some((path, key)) = hive.open_key_path(key)? else {
+        eprintln!("key '{key}' not found");
+        return Ok(false);
+    };
+
+    let mut out = std::io::stdout().lock();
+    let subkeys = hive.subkeys(&key)?;
+    let values = hive.values(&key)?;
+    if count {
+        writeln!(out, "{}", subkeys.len() + values.len())?;
+        return Ok(true);
+    }
+
+    writeln!(out, "{}", escape_name(&path))?;
+
+    for subkey in &subkeys {
+        writeln!(
+            out,
+            "    {}  {}\\",
+            filetime_to_string(subkey.node.header.last_written_timestamp),
+            escape_name(subkey.name())
+        )?;
+    }
+
+    for value in &values {
+        let data = hive.value_data(value)?;
+        writeln!(
+            out,
+            "    {}\t{}\t{data}",
+            escape_name(value.display_name()),
+            value.reg_type()
+        )?;
+    }
+
+    Ok(true)
+}
+
+fn get(
+    path: PathBuf,
+    key: &str,
+    name: Option<&str>,
+    count: bool,
+    key_index: bool,
+) -> anyhow::Result<bool> {
+    let hive = open_hive(&path, key_index)?;
+    let Some(key) = hive.open_key(key)? else {
+        eprintln!("key '{key}' not found");
+        return Ok(false);
+    };
+
+    let mut out = std::io::stdout().lock();
+    let mut values = hive.values(&key)?;
+    if let Some(name) = name {
+        let name = if name == "@" { "" } else { name };
+        values.retain(|v| names_equal(&v.name, name));
+    }
+
+    if count {
+        writeln!(out, "{}", values.len())?;
+    } else {
+        for value in &values {
+            let data = hive.value_data(value)?;
+            // data alone when asking for a single value
+            if name.is_some() {
+                writeln!(out, "{data}")?;
+            } else {
+                writeln!(
+                    out,
+                    "{}\t{}\t{data}",
+                    value.display_name(),
+                    value.reg_type()
+                )?;
+            }
+        }
+    }
+
+    Ok(!values.is_empty())
+}
+
+fn search_cmd(
+    path: PathBuf,
+    pattern: &str,
+    key: &str,
+    filter: &ValueFilter,
+    count: bool,
+    key_index: bool,
+) -> anyhow::Result<bool> {
+    let mut hive = open_hive(&path, key_index)?;
+    let Some((path, start)) = hive.open_key_path(key)? else {
+        eprintln!("key '{key}' not found");
+        return Ok(false);
+    };
+
+    let mut out = std::io::stdout().lock();
+    let mut matches = 0;
+    search(&mut hive, &start, &path, pattern, filter, &mut |m| {
+        matches += 1;
+        if !count {
+            writeln!(out, "{m}")?;
+        }
+        Ok(())
+    })?;
+
+    if count {
+        writeln!(out, "{matches}")?;
+    }
+
+    Ok(matches > 0)
+}
+
+fn watch_cmd(path: PathBuf, key: &str) -> anyhow::Result<()> {
+    eprintln!("watching {}", path.display());
+
+    watch(&path, key, |changes| {
+        match changes {
+            Ok(changes) => {
+                println!("{}", filetime_to_string(now_filetime()));
+                for change in &changes {
+                    println!("    {change}");
+                }
+            }
+            Err(e) => eprintln!("can't parse {}: {e}", path.display()),
+        }
+        Ok(())
+    })
+}
+
+fn compact(path: PathBuf, output: PathBuf, timestamp: Option<u64>) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    std::fs::write(&output, writer::compact(&mut hive, &name, timestamp)?)?;
+
+    println!(
+        "{}: {} bytes -> {}: {} bytes",
+        path.display(),
+        std::fs::metadata(&path)?.len(),
+        output.display(),
+        std::fs::metadata(&output)?.len()
+    );
+
+    Ok(())
+}
+
+fn convert(
+    path: PathBuf,
+    output: PathBuf,
+    minor_version: u32,
+    timestamp: Option<u64>,
+) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    let from = hive.base_block.minor_version;
+    std::fs::write(
+        &output,
+        writer::convert(&mut hive, minor_version, &name, timestamp)?,
+    )?;
+
+    println!(
+        "{}: version 1.{from}, {} bytes -> {}: version 1.{minor_version}, {} bytes",
+        path.display(),
+        std::fs::metadata(&path)?.len(),
+        output.display(),
+        std::fs::metadata(&output)?.len()
+    );
+
+    Ok(())
+}
+
+fn extract(
+    path: PathBuf,
+    key: &str,
+    output: PathBuf,
+    root_name: Option<String>,
+    timestamp: Option<u64>,
+) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let (key_path, start) = hive
+        .open_key_path(key)?
+        .ok_or_else(|| anyhow::anyhow!("key '{key}' not found"))?;
+    let root_name = root_name.unwrap_or_else(|| start.name().to_string());
+
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    std::fs::write(
+        &output,
+        writer::extract(&mut hive, &start, &root_name, &name, timestamp)?,
+    )?;
+
+    println!(
+        "{}{key_path}: {} bytes written to {}",
+        path.display(),
+        std::fs::metadata(&output)?.len(),
+        output.display()
+    );
+
+    Ok(())
+}
+
+fn create(output: PathBuf, root_name: &str, minor_version: u32) -> anyhow::Result<()> {
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    let writer = HiveWriter::new(minor_version, &name);
+
+    let mut out = BufWriter::new(File::create(&output)?);
+    writer.write_empty(root_name, &mut out)?;
+    out.flush()?;
+
+    Ok(())
+}
+
+fn deleted(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let keys = deleted_keys(&mut hive)?;
+    let values = deleted_values(&mut hive, &keys)?;
+
+    println!(
+        "deleted keys: {} deleted values: {}",
+        keys.len(),
+        values.len()
+    );
+    for key in &keys {
+        println!("{key}");
+    }
+    for value in &values {
+        println!("{value}");
+    }
+    Ok(())
+}
+
+fn orphans(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let orphans = orphan_cells(&mut hive)?;
+
+    println!(
+        "orphan cells: {} bytes: {}",
+        orphans.len(),
+        orphans.iter().map(|c| c.size as u64).sum::<u64>()
+    );
+    for cell in &orphans {
+        println!("{cell}");
+    }
+    Ok(())
+}
+
+// keys of unreachable subtrees are flagged, data which can't be read is printed as "?"
+fn salvage_cmd(path: PathBuf) -> anyhow::Result<()> {
+    let salvage = salvage(&path)?;
+
+    println!(
+        "bad regions: {} bytes: {}",
+        salvage.bad_regions.len(),
+        salvage
+            .bad_regions
+            .iter()
+            .map(|r| r.size as u64)
+            .sum::<u64>()
+    );
+    for region in &salvage.bad_regions {
+        println!("    {region}");
+    }
+
+    println!(
+        "keys: {} unreachable: {}",
+        salvage.keys.len(),
+        salvage.keys.iter().filter(|k| !k.reachable).count()
+    );
+    for key in &salvage.keys {
+        println!(
+            "{}  {}{}",
+            filetime_to_string(key.key.node.header.last_written_timestamp),
+            escape_name(&key.path),
+            if key.reachable { "" } else { "  (unreachable)" }
+        );
+        for (value, data) in &key.values {
+            let data = data.as_ref().map(|d| d.to_string());
+            println!(
+                "    {}\t{}\t{}",
+                escape_name(value.display_name()),
+                value.reg_type(),
+                data.as_deref().unwrap_or("?")
+            );
+        }
+    }
+
+    println!("damaged records: {}", salvage.damages.len());
+    for damage in &salvage.damages {
+        println!("    {damage}");
+    }
+    Ok(())
+}
+
+fn repair_cmd(path: PathBuf, output: PathBuf) -> anyhow::Result<()> {
+    let repair = repair(&path)?;
+    std::fs::write(&output, &repair.hive)?;
+
+    println!(
+        "hive bins data: declared: 0x{:X} in the file: 0x{:X} repaired: 0x{:X}",
+        repair.declared_size, repair.available_size, repair.repaired_size
+    );
+    println!("lost: {}", repair.losses.len());
+    for loss in &repair.losses {
+        println!("    {loss}");
+    }
+    eprintln!("repaired hive written to {}", output.display());
+    Ok(())
+}
+
+fn stress(path: PathBuf, corpus: Option<PathBuf>, max: Option<usize>) -> anyhow::Result<()> {
+    let data = std::fs::read(&path)?;
+    let mut mutations = mutations(&data)?;
+    if let Some(max) = max.filter(|max| *max > 0 && *max < mutations.len()) {
+        let step = mutations.len() as f64 / max as f64;
+        mutations = (0..max)
+            .map(|i| mutations[(i as f64 * step) as usize].clone())
+            .collect();
+    }
+    if let Some(corpus) = &corpus {
+        std::fs::create_dir_all(corpus)?;
+    }
+
+    // panic messages are printed with the variant instead
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+
+    let (mut parsed, mut rejected, mut panics) = (0, 0, 0);
+    for (i, mutation) in mutations.iter().enumerate() {
+        let mutant = mutation.apply(&data);
+        if let Some(corpus) = &corpus {
+            let name = format!("{i:05}-{}-0x{:X}", mutation.kind, mutation.offset);
+            std::fs::write(corpus.join(name), &mutant)?;
+        }
+        match outcome(mutant) {
+            Outcome::Parsed => parsed += 1,
+            Outcome::Rejected(_) => rejected += 1,
+            Outcome::Panicked(message) => {
+                println!("{i:05}\t{mutation}\tpanicked: {message}");
+                panics += 1;
+            }
+        }
+    }
+    std::panic::set_hook(hook);
+
+    eprintln!(
+        "{} variants: {parsed} parsed, {rejected} rejected, {panics} panics",
+        mutations.len()
+    );
+    anyhow::ensure!(panics == 0, "{panics} variants made the parser panic");
+    Ok(())
+}
+
+fn remnants(path: PathBuf) -> anyhow::Result<()> {
+    let hive = RegistryHive::open(&path)?;
+    for remnant in base_block_remnants(&hive.base_block)? {
+        println!("{remnant}");
+    }
+    Ok(())
+}
+
+fn shimcache(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for cache in shimcaches(&mut hive)? {
+        println!(
+            "{} ({}, {} entries)",
+            cache.key_path,
+            cache.format,
+            cache.entries.len()
+        );
+        for entry in &cache.entries {
+            println!("{entry}");
+        }
+    }
+    Ok(())
+}
+
+fn usb(path: PathBuf, software: Option<PathBuf>) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let mut software = software.map(|p| RegistryHive::open(&p)).transpose()?;
+    for device in usb_devices(&mut hive, software.as_mut())? {
+        println!("{device}");
+    }
+    Ok(())
+}
+
+// hive, IOC line and pattern, key or value path, context and IOC description
+fn ioc(
+    iocs: PathBuf,
+    hives: Vec<PathBuf>,
+    jobs: usize,
+    siem: Option<SiemFormat>,
+    apply_logs: bool,
+) -> anyhow::Result<bool> {
+    let source = std::fs::read_to_string(&iocs)?;
+    let iocs = parse_iocs(&source)
+        .map_err(|e| anyhow::anyhow!("can't parse IOCs {}: {e}", iocs.display()))?;
+
+    // hives are matched concurrently, matches printed in the order of the hives
+    let matches = process_hives(&hives, jobs, |path| {
+        let name = path.display().to_string();
+        let mut recovered = path.to_path_buf();
+        let _spool = recover_hive(&mut recovered, apply_logs)?;
+
+        let mut hive = RegistryHive::open(&recovered)?;
+        ioc_matches(&mut hive, &name, &iocs)
+    })?;
+
+    // a hive which can't be read doesn't stop the others, but the run ends with an error
+    let mut found = false;
+    let mut failed = 0;
+    for (path, hive_matches) in hives.iter().zip(matches) {
+        let hive_matches = match hive_matches {
+            Ok(m) => m,
+            Err(e) => {
+                eprintln!("{}: {e:#}", path.display());
+                failed += 1;
+                continue;
+            }
+        };
+        for m in hive_matches {
+            match siem {
+                Some(format) => println!("{}", siem_event(format, &Finding::from(&m), &m.hive)),
+                None => println!("{m}"),
+            }
+            found = true;
+        }
+    }
+    anyhow::ensure!(failed == 0, "{failed} of {} hives failed", hives.len());
+    Ok(found)
+}
+
+// findings of each hive under a line with its path and type, then the number of findings of all
+// hives by severity. Hives which can't be read don't stop the others.
+fn triage(
+    paths: Vec<PathBuf>,
+    jobs: usize,
+    names: Vec<String>,
+    siem: Option<SiemFormat>,
+    apply_logs: bool,
+) -> anyhow::Result<bool> {
+    let registry = PluginRegistry::default();
+    if let Some(name) = names.iter().find(|n| registry.get(n).is_none()) {
+        anyhow::bail!("unknown plugin '{name}'");
+    }
+
+    let reports = process_hives(&paths, jobs, |path| {
+        let mut recovered = path.to_path_buf();
+        let _spool = recover_hive(&mut recovered, apply_logs)?;
+        let mut hive = open_plugin_hive(&recovered)?;
+        let hive_type = HiveType::detect(&mut hive)?;
+
+        // plugins can't be shared between workers
+        let registry = PluginRegistry::default();
+        let selected = if names.is_empty() {
+            registry.applicable(&mut hive)?
+        } else {
+            names.iter().filter_map(|n| registry.get(n)).collect()
+        };
+
+        // a failing plugin doesn't stop the others
+        let mut findings = Vec::new();
+        for (plugin, result) in run_plugins(&selected, &mut hive) {
+            match result {
+                Ok(f) => findings.extend(f),
+                Err(e) => eprintln!("{}: plugin {}: {e:#}", path.display(), plugin.name()),
+            }
+        }
+        sort_findings(&mut findings);
+        Ok((hive_type, findings))
+    })?;
+
+    let mut severities = Vec::new();
+    let mut failed = 0;
+    for (path, report) in paths.iter().zip(reports) {
+        match report {
+            Ok((hive_type, findings)) => {
+                let hive = path.to_string_lossy();
+                if siem.is_none() {
+                    println!("== {hive} ({hive_type}): {} findings", findings.len());
+                }
+                for finding in &findings {
+                    match siem {
+                        Some(format) => println!("{}", siem_event(format, finding, &hive)),
+                        None => println!("{finding}"),
+                    }
+                }
+                severities.extend(findings.iter().map(|f| f.severity));
+            }
+            Err(e) => {
+                eprintln!("{}: {e:#}", path.display());
+                failed += 1;
+            }
+        }
+    }
+
+    let counts: Vec<String> = [
+        Severity::High,
+        Severity::Medium,
+        Severity::Low,
+        Severity::Info,
+    ]
+    .iter()
+    .map(|s| format!("{s} {}", severities.iter().filter(|f| *f == s).count()))
+    .collect();
+    let summary = format!(
+        "hives: {} ({failed} failed), findings: {} ({})",
+        paths.len(),
+        severities.len(),
+        counts.join(", ")
+    );
+    // only events on stdout for SIEM collectors
+    match siem {
+        Some(_) => eprintln!("{summary}"),
+        None => println!("{summary}"),
+    }
+
+    Ok(!severities.is_empty())
+}
+
+// + keys and values not in the reference, ~ values with other data, - keys and values of the
+// reference missing from the hive
+fn baseline(
+    reference: PathBuf,
+    path: PathBuf,
+    key: &str,
+    missing: bool,
+    apply_logs: bool,
+) -> anyhow::Result<bool> {
+    let mut reference = reference;
+    let _spool = recover_hive(&mut reference, apply_logs)?;
+
+    let changes = deviations(
+        &load_snapshot(&reference, key)?,
+        &load_snapshot(&path, key)?,
+        missing,
+    );
+    for change in &changes {
+        println!("{change}");
+    }
+    Ok(!changes.is_empty())
+}
+
+// changes by key, each with the copy of the hive where it appears
+fn history_cmd(paths: Vec<PathBuf>, key: &str, apply_logs: bool) -> anyhow::Result<()> {
+    let mut snapshots = Vec::new();
+    let mut copies = Vec::new();
+    for path in paths {
+        let mut recovered = path.clone();
+        let _spool = recover_hive(&mut recovered, apply_logs)?;
+
+        let mut hive = RegistryHive::open(&recovered)?;
+        // the key may not exist yet in the oldest copies
+        snapshots.push(match hive.open_key_path(key)? {
+            Some((key_path, start)) => snapshot(&mut hive, &start, &key_path)?,
+            None => Snapshot::new(),
+        });
+        copies.push(format!(
+            "{} ({})",
+            path.display(),
+            filetime_to_string(hive.base_block.last_written_timestamp)
+        ));
+    }
+
+    for (path, changes) in history(&snapshots) {
+        println!("{path}");
+        for (copy, change) in changes {
+            println!("    {}\t{change}", copies[copy]);
+        }
+    }
+    Ok(())
+}
+
+// rule, region, value or key path, matched strings with their offsets: in the data for values, in the
+// file for cells
+fn yara(path: PathBuf, rules: PathBuf) -> anyhow::Result<bool> {
+    let source = std::fs::read_to_string(&rules)?;
+    let rules = parse_rules(&source)
+        .map_err(|e| anyhow::anyhow!("can't parse rules {}: {e}", rules.display()))?;
+
+    let matches = yara_scan(&path, &rules)?;
+    for m in &matches {
+        println!("{m}");
+    }
+    Ok(!matches.is_empty())
+}
+
+// a failing plugin is reported in the page, it doesn't stop the others
+fn report(path: PathBuf, html: PathBuf, title: &str) -> anyhow::Result<()> {
+    let mut hive = open_plugin_hive(&path)?;
+    let anomalies = anomaly_findings(&mut hive)?;
+
+    let registry = PluginRegistry::default();
+    // anomalies have their own section
+    let mut selected = registry.applicable(&mut hive)?;
+    selected.retain(|p| p.name() != "anomalies");
+    let results = run_plugins(&selected, &mut hive);
+
+    let mut out = BufWriter::new(File::create(&html)?);
+    write_html_report(&mut out, &mut hive, title, &anomalies, &results)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn plugins(action: PluginsCommand) -> anyhow::Result<()> {
+    let registry = PluginRegistry::default();
+
+    let (hive_path, all, names, format) = match action {
+        PluginsCommand::List => {
+            for plugin in registry.plugins() {
+                let hive_types: Vec<_> =
+                    plugin.hive_types().iter().map(|t| t.to_string()).collect();
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    plugin.name(),
+                    if hive_types.is_empty() {
+                        "all".to_string()
+                    } else {
+                        hive_types.join(",")
+                    },
+                    plugin.key_globs().join(","),
+                    plugin.description()
+                );
+            }
+            return Ok(());
+        }
+        PluginsCommand::Run {
+            hive,
+            all,
+            plugin,
+            format,
+        } => (hive, all, plugin, format),
+    };
+
+    let mut hive = open_plugin_hive(&hive_path)?;
+    let selected = if all {
+        registry.applicable(&mut hive)?
+    } else {
+        let mut selected = Vec::new();
+        for name in &names {
+            let Some(plugin) = registry.get(name) else {
+                anyhow::bail!("unknown plugin '{name}'");
+            };
+            selected.push(plugin);
+        }
+        selected
+    };
+
+    // a failing plugin doesn't stop the others
+    let mut out = std::io::stdout().lock();
+    for (plugin, result) in run_plugins(&selected, &mut hive) {
+        match (format, result) {
+            (ReportFormat::RegRipper, result) => {
+                write_regripper(&mut out, &mut hive, plugin, &result)?
+            }
+            (ReportFormat::Text, Ok(mut findings)) => {
+                sort_findings(&mut findings);
+                for finding in &findings {
+                    writeln!(out, "{finding}")?;
+                }
+            }
+            (ReportFormat::Text, Err(e)) => eprintln!("plugin {}: {e:#}", plugin.name()),
+        }
+    }
+    Ok(())
+}
+
+// list, key, position, value, last used time (only known for the first entry of most lists) and item
+fn mru(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for entry in mru_lists(&mut hive)? {
+        println!("{entry}");
+    }
+    Ok(())
+}
+
+// SYSTEM hives have interfaces, SOFTWARE ones profiles
+fn network(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let interfaces = interfaces(&mut hive)?;
+    let profiles = profiles(&mut hive)?;
+    if interfaces.is_empty() && profiles.is_empty() {
+        anyhow::bail!("no network interface or profile found, not a SYSTEM or SOFTWARE hive");
+    }
+
+    for interface in interfaces {
+        println!("{interface}");
+    }
+    // guid, name, type, category, created, last connected (local times), gateway MAC, DNS suffix,
+    // description
+    for profile in profiles {
+        println!("{profile}");
+    }
+    Ok(())
+}
+
+// location, last written, key, value, command and why it's suspicious
+fn autoruns_cmd(path: PathBuf, flagged: bool, siem: Option<SiemFormat>) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for autorun in autoruns(&mut hive)? {
+        if flagged && autorun.suspicious.is_none() {
+            continue;
+        }
+        match siem {
+            Some(format) => println!(
+                "{}",
+                siem_event(format, &Finding::from(&autorun), &path.to_string_lossy())
+            ),
+            None => println!("{autorun}"),
+        }
+    }
+    Ok(())
+}
+
+// path, last written, start, type, account, image path, service DLL and flags
+fn services_cmd(path: PathBuf, recent_days: u64, flagged: bool) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for service in services(&mut hive, recent_days)? {
+        if !flagged || service.recent || service.suspicious.is_some() {
+            println!("{service}");
+        }
+    }
+    Ok(())
+}
+
+fn sam(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for user in sam_users(&mut hive)? {
+        println!("{user}");
+    }
+    Ok(())
+}
+
+// BagMRU value, MRU position, key, slot, modified, created and accessed times, path
+fn shellbags_cmd(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for bag in shellbags(&mut hive)? {
+        println!("{bag}");
+    }
+    Ok(())
+}
+
+fn userassist_cmd(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for entry in userassist(&mut hive)? {
+        println!("{entry}");
+    }
+    Ok(())
+}
+
+fn muicache_cmd(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for entry in muicache(&mut hive)? {
+        println!("{entry}");
+    }
+    Ok(())
+}
+
+fn layers_cmd(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for entry in layers(&mut hive)? {
+        println!("{entry}");
+    }
+    Ok(())
+}
+
+// last executed (local time), bam or dam, user SID and program
+fn bam(path: PathBuf) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    for entry in bam_entries(&mut hive)? {
+        println!("{entry}");
+    }
+    Ok(())
+}
+
+fn log_cmd(path: PathBuf, payload: bool) -> anyhow::Result<()> {
+    let log = TransactionLog::open(&path)?;
+    let b = &log.base_block;
+    println!(
+        "format: {} sequence numbers: {}/{} last written: {} entries: {}",
+        log.format,
+        b.primary_sequence_number,
+        b.secondary_sequence_number,
+        filetime_to_string(b.last_written_timestamp),
+        log.entries.len()
+    );
+
+    for entry in &log.entries {
+        println!("{entry}");
+        for page in &entry.pages {
+            println!(
+                "    0x{:08X}-0x{:08X}",
+                page.offset,
+                page.offset as usize + page.data.len()
+            );
+            if payload {
+                print!("{}", hex_dump(&page.data, page.offset as usize));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn replay_cmd(path: PathBuf, output: PathBuf, log_paths: Vec<PathBuf>) -> anyhow::Result<()> {
+    let log_paths = if log_paths.is_empty() {
+        find_logs(&path)
+    } else {
+        log_paths
+    };
+    anyhow::ensure!(
+        !log_paths.is_empty(),
+        "no log file found for {}",
+        path.display()
+    );
+
+    let primary = std::fs::read(&path)?;
+    let logs = log_paths
+        .iter()
+        .map(|p| TransactionLog::open(p))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let replay = replay(&primary, &logs)?;
+    std::fs::write(&output, &replay.hive)?;
+
+    for (log, entry) in &replay.applied {
+        println!("{}\t{entry}", log_paths[*log].display());
+    }
+    println!("{} entries applied", replay.applied.len());
+
+    for change in replay_changes(&primary, &replay)? {
+        println!("{change}");
+    }
+    Ok(())
+}
+
+fn overlay(path: PathBuf, output: PathBuf, layers: Vec<PathBuf>) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let root = hive.root()?;
+    let mut tree = TreeKey::load(&mut hive, &root)?;
+
+    for layer in &layers {
+        let mut layer_hive = RegistryHive::open(layer)?;
+        if layer_hive.base_block.minor_version < 6 {
+            eprintln!(
+                "{}: version 1.{} isn't a differencing hive, its keys are merged",
+                layer.display(),
+                layer_hive.base_block.minor_version
+            );
+        }
+        let report = apply_layer(&mut tree, &mut layer_hive)?;
+        eprintln!(
+            "{}: {} keys added, {} deleted, {} superseded, {} values set, {} deleted",
+            layer.display(),
+            report.keys_added,
+            report.keys_deleted,
+            report.keys_superseded,
+            report.values_set,
+            report.values_deleted
+        );
+    }
+
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    let writer = HiveWriter::new(hive.base_block.minor_version, &name);
+
+    let mut out = BufWriter::new(File::create(&output)?);
+    writer.write(&tree, &mut out)?;
+    out.flush()?;
+
+    eprintln!("{} keys written to {}", tree.count(), output.display());
+    Ok(())
+}
+
+fn merge(
+    path: PathBuf,
+    source: PathBuf,
+    output: PathBuf,
+    key: &str,
+    source_key: &str,
+    reg_root: &str,
+) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::open(&path)?;
+    let root = hive.root()?;
+    let mut tree = TreeKey::load(&mut hive, &root)?;
+
+    let (target_path, _) = hive
+        .open_key_path(key)?
+        .ok_or_else(|| anyhow::anyhow!("key '{key}' not found"))?;
+    let target = tree
+        .find_mut(&target_path)
+        .ok_or_else(|| anyhow::anyhow!("key '{key}' not found"))?;
+
+    // the source is a hive if it has the base block signature, a .reg file otherwise
+    let mut signature = [0u8; 4];
+    let is_hive = File::open(&source)?.read_exact(&mut signature).is_ok() && &signature == b"regf";
+
+    let conflicts = if is_hive {
+        let mut source_hive = RegistryHive::open(&source)?;
+        let source_root = source_hive
+            .open_key(source_key)?
+            .ok_or_else(|| anyhow::anyhow!("key '{source_key}' not found in source"))?;
+        let source_tree = TreeKey::load(&mut source_hive, &source_root)?;
+        merge_tree(target, &source_tree, &target_path)
+    } else {
+        let reg = RegFile::open(&source)?;
+        let mut conflicts = apply_reg(target, &reg.operations, reg_root, now_filetime());
+
+        // .reg paths are relative to the target key
+        for conflict in &mut conflicts {
+            if conflict.path.starts_with('\\') {
+                conflict.path = normalize_path(&format!("{target_path}{}", conflict.path));
+            }
+        }
+        conflicts
+    };
+
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    let writer = HiveWriter::new(hive.base_block.minor_version, &name);
+
+    let mut out = BufWriter::new(File::create(&output)?);
+    writer.write(&tree, &mut out)?;
+    out.flush()?;
+
+    for conflict in &conflicts {
+        println!("{conflict}");
+    }
+    eprintln!(
+        "{} conflicts, {} keys written to {}",
+        conflicts.len(),
+        tree.count(),
+        output.display()
+    );
+
+    Ok(())
+}
+
+// without output, a dry run
+fn apply(
+    path: PathBuf,
+    changes: PathBuf,
+    output: Option<PathBuf>,
+    reg_root: &str,
+    wipe: bool,
+    log: bool,
+    interrupted: bool,
+) -> anyhow::Result<()> {
+    let reg = RegFile::open(&changes)?;
+    let mut hive = MutableHive::open(&path)?;
+    hive.wipe = wipe;
+
+    let conflicts = apply_reg_in_place(&mut hive, &reg.operations, reg_root)?;
+    let Some(output) = output else {
+        let changes = hive.changes()?;
+        for change in &changes {
+            println!("{change}");
+        }
+        for conflict in &conflicts {
+            println!("{conflict}");
+        }
+        eprintln!(
+            "{} conflicts, {} changes, nothing written",
+            conflicts.len(),
+            changes.len()
+        );
+        return Ok(());
+    };
+
+    let mut log_name = output.file_name().unwrap_or_default().to_os_string();
+    log_name.push(".LOG1");
+    let log_path = output.with_file_name(log_name);
+    match (log, interrupted) {
+        (true, true) => hive.save_interrupted(&output, &log_path)?,
+        (true, false) => hive.save_with_log(&output, &log_path)?,
+        _ => hive.save(&output)?,
+    }
+
+    for conflict in &conflicts {
+        println!("{conflict}");
+    }
+    eprintln!(
+        "{} conflicts, {} operations applied to {}",
+        conflicts.len(),
+        reg.operations.len(),
+        output.display()
+    );
+
+    Ok(())
+}
+
+// spooled is true when the hive read is a temporary copy (stdin, image, archive, URL or logs applied),
+// which can't be patched in place
+fn patch(
+    path: PathBuf,
+    offset: u32,
+    bytes: &str,
+    output: Option<PathBuf>,
+    spooled: bool,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        output.is_some() || !spooled,
+        "the hive read is a temporary copy, use --out to write the patched hive"
+    );
+
+    let bytes: String = bytes
+        .chars()
+        .filter(|c| !matches!(c, ',' | ' ' | ':'))
+        .collect();
+    anyhow::ensure!(
+        !bytes.is_empty() && bytes.len().is_multiple_of(2),
+        "bytes must be pairs of hex digits"
+    );
+    let bytes = (0..bytes.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&bytes[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| anyhow::anyhow!("bytes must be pairs of hex digits"))?;
+
+    let mut hive = MutableHive::open(&path)?;
+    let replaced = hive.patch(offset, &bytes)?;
+    let output = output.unwrap_or(path);
+    hive.save(&output)?;
+
+    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02X}")).collect::<String>();
+    println!("0x{offset:X}\t{} -> {}", hex(&replaced), hex(&bytes));
+    eprintln!(
+        "{} bytes patched in {}, sequence number {}",
+        bytes.len(),
+        output.display(),
+        hive.base_block.primary_sequence_number
+    );
+
+    Ok(())
+}
+
+// FILETIME, or UTC date as YYYY-MM-DD with an optional HH:MM:SS time
+fn parse_filetime(s: &str) -> Result<u64, String> {
+    if let Ok(filetime) = s.parse() {
+        return Ok(filetime);
+    }
+
+    let invalid = || format!("invalid time '{s}', expected a FILETIME or YYYY-MM-DD [HH:MM:SS]");
+    let (date, time) = s.split_once([' ', 'T']).unwrap_or((s, "00:00:00"));
+    let date: Vec<u32> = date
+        .split('-')
+        .map(|n| n.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let time: Vec<u32> = time
+        .split(':')
+        .map(|n| n.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (&[year, month, day], &[hours, minutes, seconds]) = (&date[..], &time[..]) else {
+        return Err(invalid());
+    };
+    if year < 1601 || !(1..=12).contains(&month) || !(1..=31).contains(&day) || hours > 23 {
+        return Err(invalid());
+    }
+    if minutes > 59 || seconds > 59 {
+        return Err(invalid());
+    }
+
+    let days = days_from_civil(year as i64, month, day);
+    Ok(unix_to_filetime(
+        days * 86400 + (hours * 3600 + minutes * 60 + seconds) as i64,
+    ))
+}
+
+// decimal or 0x prefixed hex
+fn parse_offset(s: &str) -> Result<u32, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| format!("invalid offset '{s}': {e}"))
+}
+
+fn redact(
+    path: PathBuf,
+    output: PathBuf,
+    users: &[String],
+    machines: &[String],
+    replacements: &[String],
+    wiped_values: &[String],
+    keep_sids: bool,
+) -> anyhow::Result<()> {
+    let mut redaction = Redaction::new();
+    for name in users {
+        redaction.user(name);
+    }
+    for name in machines {
+        redaction.machine(name);
+    }
+    for replacement in replacements {
+        let (text, replacement) = replacement
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("'{replacement}' isn't TEXT=REPLACEMENT"))?;
+        redaction.replace(text, replacement);
+    }
+    for pattern in wiped_values {
+        redaction.wipe_value(pattern);
+    }
+    redaction.keep_sids = keep_sids;
+
+    let mut hive = RegistryHive::open(&path)?;
+    let root = hive.root()?;
+    let mut tree = TreeKey::load(&mut hive, &root)?;
+    let report = redaction.apply(&mut tree)?;
+
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    let writer = HiveWriter::new(hive.base_block.minor_version, &name);
+
+    let mut out = BufWriter::new(File::create(&output)?);
+    writer.write(&tree, &mut out)?;
+    out.flush()?;
+
+    eprintln!(
+        "{} names, {} data, {} security descriptors redacted, {} values wiped in {}",
+        report.names,
+        report.data,
+        report.descriptors,
+        report.wiped,
+        output.display()
+    );
+
+    Ok(())
 }
@@ -0,0 +1,234 @@
+// Overlay of keys and values onto a keys tree, from another tree or from a .reg file
+use std::fmt;
+
+use crate::{
+    hive::{join_path, names_equal, normalize_path},
+    mutable::MutableHive,
+    regfile::RegOperation,
+    tree::{TreeKey, TreeValue},
+    value::RegType,
+};
+
+// something of the target replaced or not found, merging goes on anyway
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub path: String,
+    pub description: String,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t{}", self.path, self.description)
+    }
+}
+
+fn value_name(name: &str) -> &str {
+    if name.is_empty() { "(default)" } else { name }
+}
+
+fn replaced(path: &str, old_type: RegType, old_data: &[u8], value: &TreeValue) -> Conflict {
+    Conflict {
+        path: path.to_string(),
+        description: format!(
+            "value '{}' replaced: {} ({} bytes) -> {} ({} bytes)",
+            value_name(&value.name),
+            old_type,
+            old_data.len(),
+            value.reg_type,
+            value.data.len()
+        ),
+    }
+}
+
+// set a value, reporting the one replaced if it's different
+fn set_value(key: &mut TreeKey, path: &str, value: TreeValue, conflicts: &mut Vec<Conflict>) {
+    match key
+        .values
+        .iter_mut()
+        .find(|v| names_equal(&v.name, &value.name))
+    {
+        Some(old) => {
+            if old.reg_type != value.reg_type || old.data != value.data {
+                conflicts.push(replaced(path, old.reg_type, &old.data, &value));
+            }
+            // the target name case is kept
+            old.reg_type = value.reg_type;
+            old.data = value.data;
+        }
+        None => key.values.push(value),
+    }
+}
+
+// source keys and values are added to target, source values win
+pub fn merge_tree(target: &mut TreeKey, source: &TreeKey, path: &str) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    merge_inner(target, source, path, &mut conflicts);
+    conflicts
+}
+
+fn merge_inner(target: &mut TreeKey, source: &TreeKey, path: &str, conflicts: &mut Vec<Conflict>) {
+    target.timestamp = target.timestamp.max(source.timestamp);
+
+    for value in &source.values {
+        set_value(target, path, value.clone(), conflicts);
+    }
+
+    for subkey in &source.subkeys {
+        match target.subkey_mut(&subkey.name) {
+            Some(existing) => {
+                let path = join_path(path, &existing.name);
+                merge_inner(existing, subkey, &path, conflicts);
+            }
+            None => target.subkeys.push(subkey.clone()),
+        }
+    }
+}
+
+// .reg operations are applied to target, their key paths start with reg_root which stands for
+// the target root key
+pub fn apply_reg(
+    target: &mut TreeKey,
+    operations: &[RegOperation],
+    reg_root: &str,
+    timestamp: u64,
+) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+
+    for op in operations {
+        let Some(relative) = strip_root(op.path(), reg_root) else {
+            conflicts.push(Conflict {
+                path: op.path().to_string(),
+                description: format!("key outside of '{reg_root}', ignored"),
+            });
+            continue;
+        };
+        let path = normalize_path(relative);
+
+        let missing = match op {
+            RegOperation::AddKey(_) => {
+                let key = target.create(relative, timestamp);
+                key.timestamp = key.timestamp.max(timestamp);
+                None
+            }
+            RegOperation::DeleteKey(_) => {
+                if path == "\\" {
+                    Some("root key can't be deleted".to_string())
+                } else if !target.remove(relative) {
+                    Some("key to delete not found".to_string())
+                } else {
+                    None
+                }
+            }
+            RegOperation::SetValue(_, value) => {
+                let key = target.create(relative, timestamp);
+                key.timestamp = key.timestamp.max(timestamp);
+                set_value(key, &path, value.clone(), &mut conflicts);
+                None
+            }
+            RegOperation::DeleteValue(_, name) => match target.find_mut(relative) {
+                Some(key) => {
+                    let count = key.values.len();
+                    key.values.retain(|v| !names_equal(&v.name, name));
+                    if key.values.len() == count {
+                        Some(format!("value '{}' to delete not found", value_name(name)))
+                    } else {
+                        key.timestamp = key.timestamp.max(timestamp);
+                        None
+                    }
+                }
+                None => Some(format!(
+                    "key of the value '{}' to delete not found",
+                    value_name(name)
+                )),
+            },
+        };
+
+        if let Some(description) = missing {
+            conflicts.push(Conflict { path, description });
+        }
+    }
+
+    conflicts
+}
+
+// same as apply_reg, the keys and values being modified in the cells of the hive
+pub fn apply_reg_in_place(
+    hive: &mut MutableHive,
+    operations: &[RegOperation],
+    reg_root: &str,
+) -> anyhow::Result<Vec<Conflict>> {
+    let mut conflicts = Vec::new();
+
+    for op in operations {
+        let Some(relative) = strip_root(op.path(), reg_root) else {
+            conflicts.push(Conflict {
+                path: op.path().to_string(),
+                description: format!("key outside of '{reg_root}', ignored"),
+            });
+            continue;
+        };
+        let path = normalize_path(relative);
+
+        let missing = match op {
+            RegOperation::AddKey(_) => {
+                hive.create_key(relative)?;
+                None
+            }
+            RegOperation::DeleteKey(_) => {
+                if path == "\\" {
+                    Some("root key can't be deleted".to_string())
+                } else if !hive.delete_key(relative)? {
+                    Some("key to delete not found".to_string())
+                } else {
+                    None
+                }
+            }
+            RegOperation::SetValue(_, value) => {
+                let key = hive.create_key(relative)?;
+                if let Some((_, old)) = hive.find_value(&key, &value.name)? {
+                    let old_data = hive.value_bytes(&old)?;
+                    if old.reg_type() != value.reg_type || old_data != value.data {
+                        conflicts.push(replaced(&path, old.reg_type(), &old_data, value));
+                    }
+                }
+                hive.set_value_bytes(&key, &value.name, value.reg_type, &value.data)?;
+                None
+            }
+            RegOperation::DeleteValue(_, name) => match hive.open_key(relative)? {
+                Some(key) => {
+                    if hive.delete_value(&key, name)? {
+                        None
+                    } else {
+                        Some(format!("value '{}' to delete not found", value_name(name)))
+                    }
+                }
+                None => Some(format!(
+                    "key of the value '{}' to delete not found",
+                    value_name(name)
+                )),
+            },
+        };
+
+        if let Some(description) = missing {
+            conflicts.push(Conflict { path, description });
+        }
+    }
+
+    Ok(conflicts)
+}
+
+// path relative to the root, None if not below it
+fn strip_root<'a>(path: &'a str, root: &str) -> Option<&'a str> {
+    let root = root.trim_end_matches('\\');
+    let head = path.get(..root.len())?;
+    if !names_equal(head, root) {
+        return None;
+    }
+
+    let rest = &path[root.len()..];
+    if rest.is_empty() {
+        Some(rest)
+    } else {
+        rest.strip_prefix('\\')
+    }
+}
@@ -0,0 +1,125 @@
+// Parse-time performance counters
+//
+// a hive counts the cells it reads, the key and value records it parses, the hits and misses of its
+// big data cache and the time spent in each phase (opening, indexing). The counters of a hive are
+// added to the totals of the process when it's dropped, which --stats prints.
+//
+use std::{
+    fmt,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+static TOTALS: Metrics = Metrics::new();
+
+#[derive(Debug)]
+pub struct Metrics {
+    pub cells_read: AtomicU64,
+    pub bytes_read: AtomicU64,
+    pub keys_parsed: AtomicU64,
+    pub values_parsed: AtomicU64,
+    pub cache_hits: AtomicU64,
+    pub cache_misses: AtomicU64,
+
+    // deleted keys and values found in free cells
+    pub records_recovered: AtomicU64,
+
+    // in the order phases first ran
+    phases: Mutex<Vec<(&'static str, Duration)>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Self {
+            cells_read: AtomicU64::new(0),
+            bytes_read: AtomicU64::new(0),
+            keys_parsed: AtomicU64::new(0),
+            values_parsed: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            records_recovered: AtomicU64::new(0),
+            phases: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    // time spent in a phase, added to the previous runs of the same phase
+    pub fn record_phase(&self, name: &'static str, duration: Duration) {
+        let mut phases = self.phases.lock().unwrap();
+        match phases.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, total)) => *total += duration,
+            None => phases.push((name, duration)),
+        }
+    }
+
+    pub fn time<T>(&self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record_phase(name, start.elapsed());
+        result
+    }
+
+    pub fn phases(&self) -> Vec<(&'static str, Duration)> {
+        self.phases.lock().unwrap().clone()
+    }
+
+    pub fn merge(&self, other: &Metrics) {
+        let counters = [
+            (&self.cells_read, &other.cells_read),
+            (&self.bytes_read, &other.bytes_read),
+            (&self.keys_parsed, &other.keys_parsed),
+            (&self.values_parsed, &other.values_parsed),
+            (&self.cache_hits, &other.cache_hits),
+            (&self.cache_misses, &other.cache_misses),
+            (&self.records_recovered, &other.records_recovered),
+        ];
+        for (total, counter) in counters {
+            Self::add(total, counter.load(Ordering::Relaxed));
+        }
+        for (name, duration) in other.phases() {
+            self.record_phase(name, duration);
+        }
+    }
+}
+
+// counters of all the hives dropped so far
+pub fn totals() -> &'static Metrics {
+    &TOTALS
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        writeln!(
+            f,
+            "cells read: {} ({} bytes)",
+            get(&self.cells_read),
+            get(&self.bytes_read)
+        )?;
+        writeln!(f, "keys parsed: {}", get(&self.keys_parsed))?;
+        writeln!(f, "values parsed: {}", get(&self.values_parsed))?;
+        writeln!(
+            f,
+            "big data cache: {} hits, {} misses",
+            get(&self.cache_hits),
+            get(&self.cache_misses)
+        )?;
+        writeln!(f, "records recovered: {}", get(&self.records_recovered))?;
+        for (name, duration) in self.phases() {
+            writeln!(f, "{name}: {:.3} ms", duration.as_secs_f64() * 1000.0)?;
+        }
+        Ok(())
+    }
+}
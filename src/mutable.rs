@@ -0,0 +1,1104 @@
+// Hive modified in place: keys and values are added to or deleted from the cells of an existing hive
+//
+// the whole file is held in memory and records are rewritten in their cells: edits accumulate until
+// they're committed, giving the content of the new file, or rolled back, and the keys and values they
+// change can be listed before. A new cell is taken from the first free cell large enough (split
+// when larger), otherwise from a new hive bin appended after the last one. A list growing past its
+// cell is moved to a new cell, the old one being freed. Freed cells are merged with the free cells
+// next to them in their hive bin, and keep their content as Windows does (deleted keys and values
+// can be recovered from them) unless asked to wipe it.
+// Subkeys leaves are rewritten in the layout of the hive version when a key is added (li before 1.3,
+// lf before 1.5, lh as of 1.5) and split in two under an index root past their max number of
+// elements; an index root left with a single leaf is replaced by it. Keys with the same security
+// descriptor share a security cell, new keys using the one of their parent. Changes can also be
+// saved along with a transaction log holding the pages they modified, as written by Windows before
+// modifying the primary file.
+//
+use std::{collections::HashSet, fs::File, io::Write, ops::Range, path::Path};
+
+use bincode::Encode;
+
+use crate::{
+    buffer::{HiveBuffer, default_options},
+    diff::{Change, diff, snapshot},
+    hive::{BASE_BLOCK_SIZE, Key, MAX_DEPTH, RegistryHive, name_sort_key, names_equal},
+    key::{KEY_COMP_NAME, KeyNode, KeyNodeHeader, NO_OFFSET},
+    list::{LEAF_MAX_ELEMENTS, SubkeysList, key_values_list, name_hash, name_hint},
+    reg::{BaseBlock, HiveBinHeader, base_block_checksum, bincode_config},
+    security::{KEY_SECURITY_HEADER_SIZE, KeySecurityHeader, SecurityDescriptor},
+    translog::write_log,
+    utils::now_filetime,
+    value::{
+        BIG_DATA_SEGMENT_SIZE, BigData, DATA_IS_RESIDENT, KeyValue, KeyValueHeader, RegData,
+        RegType, VALUE_COMP_NAME,
+    },
+    writer::{encode_name, offsets_bytes, utf16_size},
+};
+
+// hive bins are multiple of this size
+const HIVE_BIN_SIZE: usize = 4096;
+const HIVE_BIN_HEADER_SIZE: usize = 32;
+
+// smallest cell: size field and 4 bytes of content
+const MIN_CELL_SIZE: usize = 8;
+
+// longest key and value names, in characters
+const MAX_KEY_NAME_LENGTH: usize = 255;
+const MAX_VALUE_NAME_LENGTH: usize = 16383;
+
+pub struct MutableHive {
+    // whole file, base block included
+    data: Vec<u8>,
+    pub base_block: BaseBlock,
+
+    // FILETIME set on the keys created or modified, the time the hive was opened by default
+    pub timestamp: u64,
+
+    // freed cells are zeroed
+    pub wipe: bool,
+
+    // file content as of the last commit, i.e. as read or last saved
+    committed: Vec<u8>,
+}
+
+impl MutableHive {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        default_options().check_size(std::fs::metadata(path)?.len(), "hive")?;
+        Self::from_bytes(std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> anyhow::Result<Self> {
+        let (base_block, _): (BaseBlock, usize) =
+            bincode::decode_from_slice(&data, bincode_config())?;
+        anyhow::ensure!(&base_block.signature == b"regf", "not a registry hive");
+        anyhow::ensure!(
+            data.len() as u64 >= BASE_BLOCK_SIZE + base_block.hive_bins_data_size as u64,
+            "hive bins data truncated by the end of the file"
+        );
+
+        Ok(Self {
+            committed: data.clone(),
+            data,
+            base_block,
+            timestamp: now_filetime(),
+            wipe: false,
+        })
+    }
+
+    // editable copy of a hive read with the usual API
+    pub fn from_hive(hive: &RegistryHive) -> anyhow::Result<Self> {
+        Self::from_bytes(hive.buffer().to_vec())
+    }
+
+    // the edits made since the last commit are kept: the base block is updated as at the end of a
+    // write by Windows (sequence numbers incremented and equal, last written time, checksum) and the
+    // content of the new file is returned
+    pub fn commit(&mut self) -> anyhow::Result<&[u8]> {
+        let sequence_number = self
+            .base_block
+            .primary_sequence_number
+            .max(self.base_block.secondary_sequence_number)
+            .wrapping_add(1);
+        self.base_block.primary_sequence_number = sequence_number;
+        self.base_block.secondary_sequence_number = sequence_number;
+        self.base_block.last_written_timestamp = self.timestamp;
+        self.write_base_block()?;
+
+        self.committed = self.data.clone();
+        Ok(&self.data)
+    }
+
+    // the edits made since the last commit are dropped
+    pub fn rollback(&mut self) -> anyhow::Result<()> {
+        self.base_block = self.committed_base_block()?;
+        self.data = self.committed.clone();
+        Ok(())
+    }
+
+    // keys and values changed since the last commit, to preview edits before they're committed
+    pub fn changes(&self) -> anyhow::Result<Vec<Change>> {
+        let mut snapshots = Vec::new();
+        for bytes in [&self.committed, &self.data] {
+            let mut hive = RegistryHive::from_buffer(HiveBuffer::from(bytes.clone()))?;
+            let root = hive.root()?;
+            snapshots.push(snapshot(&mut hive, &root, "\\")?);
+        }
+        Ok(diff(&snapshots[0], &snapshots[1]))
+    }
+
+    // the edits are committed, then the hive is written to a file next to path renamed over it: the
+    // file at path is never partly written
+    pub fn save(&mut self, path: &Path) -> anyhow::Result<()> {
+        self.commit()?;
+        write_file(path, &self.data)
+    }
+
+    // the log (e.g. HIVE.LOG1) is written first, with a single entry holding the pages changed since
+    // the last commit, then the hive as by save: the pair is the one left by a write
+    // of Windows, the log not being replayed as the hive is clean
+    pub fn save_with_log(&mut self, path: &Path, log: &Path) -> anyhow::Result<()> {
+        self.write_log(log)?;
+        self.save(path)
+    }
+
+    // same as save_with_log, the system being stopped before the hive bins data was written: the
+    // hive keeps the content of the last commit with its primary sequence number incremented, the
+    // changes are only in the log and replaying it recovers the hive save would have written
+    pub fn save_interrupted(&mut self, path: &Path, log: &Path) -> anyhow::Result<()> {
+        self.write_log(log)?;
+
+        let mut base_block = self.committed_base_block()?;
+        base_block.primary_sequence_number = base_block
+            .primary_sequence_number
+            .max(base_block.secondary_sequence_number)
+            .wrapping_add(1);
+        let mut bytes = bincode::encode_to_vec(&base_block, bincode_config())?;
+        let checksum = base_block_checksum(&bytes);
+        bytes[508..512].copy_from_slice(&checksum.to_le_bytes());
+
+        let mut interrupted = self.committed.clone();
+        interrupted[..bytes.len()].copy_from_slice(&bytes);
+        write_file(path, &interrupted)
+    }
+
+    fn write_log(&self, log: &Path) -> anyhow::Result<()> {
+        let start = BASE_BLOCK_SIZE as usize;
+        let end = start + self.base_block.hive_bins_data_size as usize;
+        let bytes = write_log(
+            &self.committed_base_block()?,
+            &self.committed[start..],
+            &self.data[start..end],
+        )?;
+        write_file(log, &bytes)
+    }
+
+    fn committed_base_block(&self) -> anyhow::Result<BaseBlock> {
+        let (base_block, _): (BaseBlock, usize) =
+            bincode::decode_from_slice(&self.committed, bincode_config())?;
+        Ok(base_block)
+    }
+
+    // content of the whole file, base block included
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    // a copy to read with the usual API
+    pub fn to_hive(&self) -> anyhow::Result<RegistryHive> {
+        RegistryHive::from_buffer(HiveBuffer::from(self.data.clone()))
+    }
+
+    // raw bytes of the hive bins data overwritten at an offset, returns the bytes they replace: only
+    // the range is checked, records are left as they are
+    pub fn patch(&mut self, offset: u32, bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let size = self.base_block.hive_bins_data_size as usize;
+        let end = offset as usize + bytes.len();
+        anyhow::ensure!(
+            end <= size,
+            "0x{offset:X}-0x{end:X} out of hive bins data (0x{size:X} bytes)"
+        );
+
+        let range = BASE_BLOCK_SIZE as usize + offset as usize..BASE_BLOCK_SIZE as usize + end;
+        let replaced = self.data[range.clone()].to_vec();
+        self.data[range].copy_from_slice(bytes);
+        Ok(replaced)
+    }
+
+    // size and range of the content (after the size field) of the cell at an offset
+    fn cell_range(&self, offset: u32) -> anyhow::Result<(i32, Range<usize>)> {
+        anyhow::ensure!(
+            offset != NO_OFFSET && offset as u64 + 4 <= self.base_block.hive_bins_data_size as u64,
+            "cell offset 0x{offset:X} out of hive bins data"
+        );
+        let start = BASE_BLOCK_SIZE as usize + offset as usize;
+        let size = i32_at(&self.data, start);
+        let abs_size = size.unsigned_abs() as usize;
+        anyhow::ensure!(
+            abs_size >= MIN_CELL_SIZE
+                && offset as usize + abs_size <= self.base_block.hive_bins_data_size as usize,
+            "invalid cell size {size} at offset 0x{offset:X}"
+        );
+        Ok((size, start + 4..start + abs_size))
+    }
+
+    fn cell(&self, offset: u32) -> anyhow::Result<&[u8]> {
+        let (_, range) = self.cell_range(offset)?;
+        Ok(&self.data[range])
+    }
+
+    pub fn key(&self, offset: u32) -> anyhow::Result<Key> {
+        let node = KeyNode::try_from(self.cell(offset)?)?;
+        Ok(Key { offset, node })
+    }
+
+    pub fn root(&self) -> anyhow::Result<Key> {
+        self.key(self.base_block.root_cell_offset)
+    }
+
+    pub fn subkey_offsets(&self, key: &Key) -> anyhow::Result<Vec<u32>> {
+        if key.node.header.number_of_subkeys == 0 {
+            return Ok(Vec::new());
+        }
+
+        let list = SubkeysList::try_from(self.cell(key.node.header.subkeys_list_offset)?)?;
+        let SubkeysList::IndexRoot(leaves) = list else {
+            return Ok(list.offsets());
+        };
+
+        let mut offsets = Vec::new();
+        for leaf in leaves {
+            match SubkeysList::try_from(self.cell(leaf)?)? {
+                SubkeysList::IndexRoot(_) => anyhow::bail!("nested index roots at 0x{leaf:X}"),
+                list => offsets.extend(list.offsets()),
+            }
+        }
+        Ok(offsets)
+    }
+
+    // names are case insensitive
+    pub fn subkey(&self, key: &Key, name: &str) -> anyhow::Result<Option<Key>> {
+        for offset in self.subkey_offsets(key)? {
+            let subkey = self.key(offset)?;
+            if names_equal(subkey.name(), name) {
+                return Ok(Some(subkey));
+            }
+        }
+        Ok(None)
+    }
+
+    // path is relative to the root key, with or without a leading backslash
+    pub fn open_key(&self, path: &str) -> anyhow::Result<Option<Key>> {
+        let mut key = self.root()?;
+        for name in path.split('\\').filter(|n| !n.is_empty()) {
+            match self.subkey(&key, name)? {
+                Some(subkey) => key = subkey,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(key))
+    }
+
+    // offsets of the values, without reading them
+    pub fn value_offsets(&self, key: &Key) -> anyhow::Result<Vec<u32>> {
+        let count = key.node.header.number_of_key_values as usize;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        key_values_list(self.cell(key.node.header.key_values_list_offset)?, count)
+    }
+
+    pub fn value(&self, offset: u32) -> anyhow::Result<KeyValue> {
+        KeyValue::try_from(self.cell(offset)?)
+    }
+
+    // value with this name and its offset, names are case insensitive
+    // data of a value, reassembled from its segments for big data
+    pub fn value_bytes(&self, value: &KeyValue) -> anyhow::Result<Vec<u8>> {
+        if value.is_resident() {
+            return Ok(value.resident_data().into_owned());
+        }
+
+        let size = value.data_size() as usize;
+        let mut data = Vec::with_capacity(size);
+        let cells = self.data_cells(&value.header)?;
+
+        // db record, segments list, then the segments
+        let (segments, segment_size) = match cells.len() {
+            0 | 1 => (&cells[..], usize::MAX),
+            _ => (&cells[2..], BIG_DATA_SEGMENT_SIZE),
+        };
+        for offset in segments {
+            let segment = self.cell(*offset)?;
+            let len = segment.len().min(segment_size).min(size - data.len());
+            data.extend_from_slice(&segment[..len]);
+        }
+        anyhow::ensure!(data.len() == size, "value data overflows its cells");
+        Ok(data)
+    }
+
+    // value with this name and its offset
+    pub fn find_value(&self, key: &Key, name: &str) -> anyhow::Result<Option<(u32, KeyValue)>> {
+        for offset in self.value_offsets(key)? {
+            let value = self.value(offset)?;
+            if names_equal(&value.name, name) {
+                return Ok(Some((offset, value)));
+            }
+        }
+        Ok(None)
+    }
+
+    // key at path, missing keys being created along the way
+    pub fn create_key(&mut self, path: &str) -> anyhow::Result<Key> {
+        let names: Vec<_> = path.split('\\').filter(|n| !n.is_empty()).collect();
+        anyhow::ensure!(names.len() <= MAX_DEPTH, "key path '{path}' too deep");
+
+        let mut key = self.root()?;
+        for name in names {
+            key = match self.subkey(&key, name)? {
+                Some(subkey) => subkey,
+                None => self.add_subkey(&key, name)?,
+            };
+        }
+        Ok(key)
+    }
+
+    // new key node, sharing the security of its parent
+    fn add_subkey(&mut self, parent: &Key, name: &str) -> anyhow::Result<Key> {
+        anyhow::ensure!(
+            name.chars().count() <= MAX_KEY_NAME_LENGTH,
+            "key name '{name}' longer than {MAX_KEY_NAME_LENGTH} characters"
+        );
+        let (name_bytes, compressed) = encode_name(name);
+        let security_offset = parent.node.header.key_security_offset;
+
+        let header = KeyNodeHeader {
+            signature: *b"nk",
+            flags: if compressed { KEY_COMP_NAME } else { 0 },
+            last_written_timestamp: self.timestamp,
+            access_bits: 0,
+            parent: parent.offset,
+            number_of_subkeys: 0,
+            number_of_volatile_subkeys: 0,
+            subkeys_list_offset: NO_OFFSET,
+            volatile_subkeys_list_offset: NO_OFFSET,
+            number_of_key_values: 0,
+            key_values_list_offset: NO_OFFSET,
+            key_security_offset: security_offset,
+            class_name_offset: NO_OFFSET,
+            largest_subkey_name_length: 0,
+            largest_subkey_class_name_length: 0,
+            largest_value_name_length: 0,
+            largest_value_data_size: 0,
+            work_var: 0,
+            key_name_length: name_bytes.len() as u16,
+            class_name_length: 0,
+        };
+        let mut content = bincode::encode_to_vec(&header, bincode_config())?;
+        content.extend_from_slice(&name_bytes);
+        let offset = self.alloc(&content)?;
+
+        if security_offset != NO_OFFSET {
+            self.add_security_reference(security_offset)?;
+        }
+        self.link_subkey(parent.offset, offset, name)?;
+        self.key(offset)
+    }
+
+    // insert a key into the subkeys list of its parent, kept sorted by uppercase name
+    fn link_subkey(&mut self, parent: u32, offset: u32, name: &str) -> anyhow::Result<()> {
+        let mut header = self.key(parent)?.node.header;
+
+        header.subkeys_list_offset = if header.number_of_subkeys == 0 {
+            self.alloc(&self.leaf(&[(offset, name.to_string())]).to_bytes())?
+        } else {
+            self.insert_into_list(header.subkeys_list_offset, offset, name)?
+        };
+        header.number_of_subkeys += 1;
+        header.largest_subkey_name_length = header.largest_subkey_name_length.max(utf16_size(name));
+        header.last_written_timestamp = self.timestamp;
+
+        self.write_header(parent, &header)
+    }
+
+    // returns the offset of the list, moved when it grew past its cell
+    fn insert_into_list(
+        &mut self,
+        list_offset: u32,
+        offset: u32,
+        name: &str,
+    ) -> anyhow::Result<u32> {
+        let SubkeysList::IndexRoot(mut leaves) = SubkeysList::try_from(self.cell(list_offset)?)?
+        else {
+            let leaves = self.insert_into_leaf(list_offset, offset, name)?;
+            return match leaves[..] {
+                [leaf] => Ok(leaf),
+                _ => self.alloc(&SubkeysList::IndexRoot(leaves).to_bytes()),
+            };
+        };
+        anyhow::ensure!(!leaves.is_empty(), "empty index root at 0x{list_offset:X}");
+
+        // the last leaf whose first key sorts before the name
+        let target = name_sort_key(name);
+        let mut index = 0;
+        for (i, leaf) in leaves.iter().enumerate().skip(1) {
+            let list = SubkeysList::try_from(self.cell(*leaf)?)?;
+            match list.offsets().first() {
+                Some(first) if name_sort_key(self.key(*first)?.name()) < target => index = i,
+                _ => break,
+            }
+        }
+
+        let replaced = self.insert_into_leaf(leaves[index], offset, name)?;
+        leaves.splice(index..=index, replaced);
+        self.replace_cell(list_offset, &SubkeysList::IndexRoot(leaves).to_bytes())
+    }
+
+    // returns the offsets of the leaves replacing it, two when it grew past the max number of
+    // elements and was split
+    fn insert_into_leaf(&mut self, leaf: u32, offset: u32, name: &str) -> anyhow::Result<Vec<u32>> {
+        let list = SubkeysList::try_from(self.cell(leaf)?)?;
+        anyhow::ensure!(
+            !matches!(list, SubkeysList::IndexRoot(_)),
+            "nested index roots at 0x{leaf:X}"
+        );
+
+        let mut elements = Vec::new();
+        for o in list.offsets() {
+            elements.push((o, self.key(o)?.name().to_string()));
+        }
+        let target = name_sort_key(name);
+        let position = elements
+            .iter()
+            .position(|(_, n)| name_sort_key(n) > target)
+            .unwrap_or(elements.len());
+        elements.insert(position, (offset, name.to_string()));
+
+        if elements.len() <= LEAF_MAX_ELEMENTS {
+            return Ok(vec![
+                self.replace_cell(leaf, &self.leaf(&elements).to_bytes())?,
+            ]);
+        }
+        let (first, second) = elements.split_at(elements.len() / 2);
+        let first = self.replace_cell(leaf, &self.leaf(first).to_bytes())?;
+        let second = self.alloc(&self.leaf(second).to_bytes())?;
+        Ok(vec![first, second])
+    }
+
+    // leaf in the layout of the hive version: index leaves before 1.3, fast leaves before 1.5, hash
+    // leaves as of 1.5
+    fn leaf(&self, elements: &[(u32, String)]) -> SubkeysList {
+        match self.base_block.minor_version {
+            ..3 => SubkeysList::IndexLeaf(elements.iter().map(|(o, _)| *o).collect()),
+            3 | 4 => {
+                SubkeysList::FastLeaf(elements.iter().map(|(o, n)| (*o, name_hint(n))).collect())
+            }
+            _ => SubkeysList::HashLeaf(elements.iter().map(|(o, n)| (*o, name_hash(n))).collect()),
+        }
+    }
+
+    // the value with this name is added to the key, or has its type and data replaced
+    pub fn set_value(&mut self, key: &Key, name: &str, data: &RegData) -> anyhow::Result<()> {
+        self.set_value_bytes(key, name, data.reg_type(), &data.to_bytes())
+    }
+
+    // same as set_value with raw data
+    pub fn set_value_bytes(
+        &mut self,
+        key: &Key,
+        name: &str,
+        reg_type: RegType,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            name.chars().count() <= MAX_VALUE_NAME_LENGTH,
+            "value name longer than {MAX_VALUE_NAME_LENGTH} characters"
+        );
+        anyhow::ensure!(
+            data.len() < DATA_IS_RESIDENT as usize,
+            "value data of {} bytes too large",
+            data.len()
+        );
+
+        // counts and lists may have changed since the key was read
+        let key = self.key(key.offset)?;
+        let mut header = key.node.header.clone();
+        let existing = self.find_value(&key, name)?;
+
+        // cells of the previous data are freed first, to be reused
+        if let Some((_, value)) = &existing {
+            for cell in self.data_cells(&value.header)? {
+                self.free(cell)?;
+            }
+        }
+        let (data_size, data_offset) = self.write_data(data)?;
+
+        match existing {
+            // the name is kept with its case
+            Some((offset, value)) => {
+                let value_header = KeyValueHeader {
+                    data_size,
+                    data_offset,
+                    data_type: reg_type.into(),
+                    ..value.header
+                };
+                self.write_header(offset, &value_header)?;
+            }
+            None => {
+                let (name_bytes, compressed) = encode_name(name);
+                let value_header = KeyValueHeader {
+                    signature: *b"vk",
+                    name_length: name_bytes.len() as u16,
+                    data_size,
+                    data_offset,
+                    data_type: reg_type.into(),
+                    flags: if compressed { VALUE_COMP_NAME } else { 0 },
+                    spare: 0,
+                };
+                let mut content = bincode::encode_to_vec(&value_header, bincode_config())?;
+                content.extend_from_slice(&name_bytes);
+                let offset = self.alloc(&content)?;
+
+                let mut offsets = self.value_offsets(&key)?;
+                offsets.push(offset);
+                header.key_values_list_offset = if offsets.len() == 1 {
+                    self.alloc(&offsets_bytes(&offsets))?
+                } else {
+                    self.replace_cell(header.key_values_list_offset, &offsets_bytes(&offsets))?
+                };
+                header.number_of_key_values += 1;
+                header.largest_value_name_length =
+                    header.largest_value_name_length.max(utf16_size(name));
+            }
+        }
+
+        header.largest_value_data_size = header.largest_value_data_size.max(data.len() as u32);
+        header.last_written_timestamp = self.timestamp;
+        self.write_header(key.offset, &header)
+    }
+
+    // the value with this name is removed from the key, returns false if there's none
+    pub fn delete_value(&mut self, key: &Key, name: &str) -> anyhow::Result<bool> {
+        let key = self.key(key.offset)?;
+        let Some((offset, value)) = self.find_value(&key, name)? else {
+            return Ok(false);
+        };
+
+        let mut header = key.node.header.clone();
+        let mut offsets = self.value_offsets(&key)?;
+        offsets.retain(|o| *o != offset);
+        if offsets.is_empty() {
+            self.free(header.key_values_list_offset)?;
+            header.key_values_list_offset = NO_OFFSET;
+        } else {
+            header.key_values_list_offset =
+                self.replace_cell(header.key_values_list_offset, &offsets_bytes(&offsets))?;
+        }
+        header.number_of_key_values = offsets.len() as u32;
+        header.last_written_timestamp = self.timestamp;
+        self.write_header(key.offset, &header)?;
+
+        for cell in self.data_cells(&value.header)? {
+            self.free(cell)?;
+        }
+        self.free(offset)?;
+        Ok(true)
+    }
+
+    // the key uses the security cell holding this descriptor: a cell with the same descriptor gets one
+    // more reference, otherwise a new cell is added at the end of the list of security cells (before
+    // the one of the root key). The cell used before loses a reference
+    pub fn set_security(&mut self, key: &Key, descriptor: &[u8]) -> anyhow::Result<()> {
+        SecurityDescriptor::try_from(descriptor)?;
+        let mut header = self.key(key.offset)?.node.header;
+
+        let offset = match self.find_security(descriptor)? {
+            Some(offset) if offset == header.key_security_offset => return Ok(()),
+            Some(offset) => {
+                self.add_security_reference(offset)?;
+                offset
+            }
+            None => self.add_security(descriptor)?,
+        };
+        if header.key_security_offset != NO_OFFSET {
+            self.release_security(header.key_security_offset)?;
+        }
+        header.key_security_offset = offset;
+        self.write_header(key.offset, &header)
+    }
+
+    // the list of security cells is walked from the one of the root key
+    fn find_security(&self, descriptor: &[u8]) -> anyhow::Result<Option<u32>> {
+        let first = self.root()?.node.header.key_security_offset;
+        let mut visited = HashSet::new();
+        let mut offset = first;
+        while offset != NO_OFFSET && visited.insert(offset) {
+            let header = self.security_header(offset)?;
+            let size = header.security_descriptor_size as usize;
+            let cell = self.cell(offset)?;
+            if cell.get(KEY_SECURITY_HEADER_SIZE..KEY_SECURITY_HEADER_SIZE + size)
+                == Some(descriptor)
+            {
+                return Ok(Some(offset));
+            }
+            offset = header.flink;
+        }
+        anyhow::ensure!(
+            offset == first,
+            "list of security cells not looping back at 0x{offset:X}"
+        );
+        Ok(None)
+    }
+
+    // new security cell with a single reference
+    fn add_security(&mut self, descriptor: &[u8]) -> anyhow::Result<u32> {
+        let mut header = KeySecurityHeader {
+            signature: *b"sk",
+            reserved: 0,
+            flink: NO_OFFSET,
+            blink: NO_OFFSET,
+            reference_count: 1,
+            security_descriptor_size: descriptor.len() as u32,
+        };
+        let mut content = bincode::encode_to_vec(&header, bincode_config())?;
+        content.extend_from_slice(descriptor);
+        let offset = self.alloc(&content)?;
+
+        let first = self.root()?.node.header.key_security_offset;
+        if first == NO_OFFSET {
+            header.flink = offset;
+            header.blink = offset;
+            return self.write_header(offset, &header).map(|_| offset);
+        }
+
+        // the last cell may be the first one
+        let last = self.security_header(first)?.blink;
+        header.flink = first;
+        header.blink = last;
+        self.write_header(offset, &header)?;
+        let mut previous = self.security_header(last)?;
+        previous.flink = offset;
+        self.write_header(last, &previous)?;
+        let mut next = self.security_header(first)?;
+        next.blink = offset;
+        self.write_header(first, &next)?;
+        Ok(offset)
+    }
+
+    // the key at path is removed along with its subkeys and values, returns false if there's none
+    pub fn delete_key(&mut self, path: &str) -> anyhow::Result<bool> {
+        let Some(key) = self.open_key(path)? else {
+            return Ok(false);
+        };
+        anyhow::ensure!(
+            key.offset != self.base_block.root_cell_offset,
+            "the root key can't be deleted"
+        );
+
+        self.unlink_subkey(key.node.header.parent, key.offset)?;
+        self.delete_subtree(key.offset, 0)?;
+        Ok(true)
+    }
+
+    // all the cells of a key and of its subkeys are freed
+    fn delete_subtree(&mut self, offset: u32, depth: usize) -> anyhow::Result<()> {
+        anyhow::ensure!(depth <= MAX_DEPTH, "keys tree too deep at 0x{offset:X}");
+        let key = self.key(offset)?;
+        let h = &key.node.header;
+
+        for subkey in self.subkey_offsets(&key)? {
+            self.delete_subtree(subkey, depth + 1)?;
+        }
+        if h.number_of_subkeys > 0 {
+            for cell in self.list_cells(h.subkeys_list_offset)? {
+                self.free(cell)?;
+            }
+        }
+
+        for value in self.value_offsets(&key)? {
+            for cell in self.data_cells(&self.value(value)?.header)? {
+                self.free(cell)?;
+            }
+            self.free(value)?;
+        }
+        if h.number_of_key_values > 0 {
+            self.free(h.key_values_list_offset)?;
+        }
+
+        if h.class_name_offset != NO_OFFSET && h.class_name_length > 0 {
+            self.free(h.class_name_offset)?;
+        }
+        if h.key_security_offset != NO_OFFSET {
+            self.release_security(h.key_security_offset)?;
+        }
+        self.free(offset)
+    }
+
+    // a subkeys list and the leaves of an index root
+    fn list_cells(&self, list_offset: u32) -> anyhow::Result<Vec<u32>> {
+        let mut cells = vec![list_offset];
+        if let SubkeysList::IndexRoot(leaves) = SubkeysList::try_from(self.cell(list_offset)?)? {
+            cells.extend(leaves);
+        }
+        Ok(cells)
+    }
+
+    // remove a key from the subkeys list of its parent, emptied leaves and lists being freed
+    fn unlink_subkey(&mut self, parent: u32, offset: u32) -> anyhow::Result<()> {
+        let mut header = self.key(parent)?.node.header;
+        let list_offset = header.subkeys_list_offset;
+
+        let remaining = match SubkeysList::try_from(self.cell(list_offset)?)? {
+            SubkeysList::IndexRoot(leaves) => {
+                let mut kept = Vec::new();
+                for leaf in leaves {
+                    let list = without(SubkeysList::try_from(self.cell(leaf)?)?, offset);
+                    if list.offsets().is_empty() {
+                        self.free(leaf)?;
+                    } else {
+                        self.replace_cell(leaf, &list.to_bytes())?;
+                        kept.push(leaf);
+                    }
+                }
+                let count = kept.len();
+                if let [leaf] = kept[..] {
+                    self.free(list_offset)?;
+                    header.subkeys_list_offset = leaf;
+                } else {
+                    self.replace_cell(list_offset, &SubkeysList::IndexRoot(kept).to_bytes())?;
+                }
+                count
+            }
+            list => {
+                let list = without(list, offset);
+                self.replace_cell(list_offset, &list.to_bytes())?;
+                list.offsets().len()
+            }
+        };
+
+        header.number_of_subkeys = header.number_of_subkeys.saturating_sub(1);
+        if remaining == 0 {
+            self.free(list_offset)?;
+            header.subkeys_list_offset = NO_OFFSET;
+            header.number_of_subkeys = 0;
+        }
+        header.last_written_timestamp = self.timestamp;
+        self.write_header(parent, &header)
+    }
+
+    // one key less using a security cell, which is freed and removed from the list of security cells
+    // when no key uses it anymore
+    fn release_security(&mut self, offset: u32) -> anyhow::Result<()> {
+        let mut header = self.security_header(offset)?;
+        header.reference_count = header.reference_count.saturating_sub(1);
+        self.write_header(offset, &header)?;
+        if header.reference_count > 0 || header.flink == offset {
+            return Ok(());
+        }
+
+        let mut previous = self.security_header(header.blink)?;
+        previous.flink = header.flink;
+        self.write_header(header.blink, &previous)?;
+        let mut next = self.security_header(header.flink)?;
+        next.blink = header.blink;
+        self.write_header(header.flink, &next)?;
+        self.free(offset)
+    }
+
+    // data of up to 4 bytes is resident (stored in the data offset field), larger data is stored in a
+    // data cell, or split into big data segments when too large for a cell (as of version 1.4).
+    // Returns the data size and offset fields of the key value
+    fn write_data(&mut self, data: &[u8]) -> anyhow::Result<(u32, u32)> {
+        let size = data.len();
+        if size <= 4 {
+            let mut resident = [0u8; 4];
+            resident[..size].copy_from_slice(data);
+            return Ok((size as u32 | DATA_IS_RESIDENT, u32::from_le_bytes(resident)));
+        }
+
+        if size > BIG_DATA_SEGMENT_SIZE && self.base_block.minor_version > 3 {
+            let mut segments = Vec::new();
+            for chunk in data.chunks(BIG_DATA_SEGMENT_SIZE) {
+                segments.push(self.alloc(chunk)?);
+            }
+            let list_offset = self.alloc(&offsets_bytes(&segments))?;
+
+            let db = BigData {
+                signature: *b"db",
+                number_of_segments: segments.len() as u16,
+                segments_list_offset: list_offset,
+            };
+            let db = bincode::encode_to_vec(&db, bincode_config())?;
+            return Ok((size as u32, self.alloc(&db)?));
+        }
+
+        Ok((size as u32, self.alloc(data)?))
+    }
+
+    // cells holding the data of a value: a data cell, or a big data record with its segments list
+    // and segments
+    fn data_cells(&self, header: &KeyValueHeader) -> anyhow::Result<Vec<u32>> {
+        let size = (header.data_size & !DATA_IS_RESIDENT) as usize;
+        if header.data_size & DATA_IS_RESIDENT != 0 || size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut cells = vec![header.data_offset];
+        let cell = self.cell(header.data_offset)?;
+        if size > BIG_DATA_SEGMENT_SIZE
+            && self.base_block.minor_version > 3
+            && cell.starts_with(b"db")
+        {
+            let (db, _): (BigData, usize) = bincode::decode_from_slice(cell, bincode_config())?;
+            let segments = self.cell(db.segments_list_offset)?;
+            let segments = key_values_list(segments, db.number_of_segments as usize)?;
+            cells.push(db.segments_list_offset);
+            cells.extend(segments);
+        }
+        Ok(cells)
+    }
+
+    fn add_security_reference(&mut self, offset: u32) -> anyhow::Result<()> {
+        let mut header = self.security_header(offset)?;
+        header.reference_count = header.reference_count.saturating_add(1);
+        self.write_header(offset, &header)
+    }
+
+    fn security_header(&self, offset: u32) -> anyhow::Result<KeySecurityHeader> {
+        let (header, _): (KeySecurityHeader, usize) =
+            bincode::decode_from_slice(self.cell(offset)?, bincode_config())?;
+        anyhow::ensure!(
+            &header.signature == b"sk",
+            "not a key security at 0x{offset:X}"
+        );
+        Ok(header)
+    }
+
+    // fixed part of a record, at the start of its cell
+    fn write_header<T: Encode>(&mut self, offset: u32, header: &T) -> anyhow::Result<()> {
+        let (_, range) = self.cell_range(offset)?;
+        let bytes = bincode::encode_to_vec(header, bincode_config())?;
+        self.data[range.start..range.start + bytes.len()].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    // content written over the cell if it fits, otherwise into a new cell: returns the offset
+    fn replace_cell(&mut self, offset: u32, content: &[u8]) -> anyhow::Result<u32> {
+        let (_, range) = self.cell_range(offset)?;
+        if content.len() <= range.len() {
+            self.data[range.start..range.start + content.len()].copy_from_slice(content);
+            return Ok(offset);
+        }
+
+        // twice the room, for lists growing one element at a time
+        let new_offset = self.alloc_with_capacity(content, content.len().max(2 * range.len()))?;
+        self.free(offset)?;
+        Ok(new_offset)
+    }
+
+    // allocate a cell for content, returns its offset
+    fn alloc(&mut self, content: &[u8]) -> anyhow::Result<u32> {
+        self.alloc_with_capacity(content, content.len())
+    }
+
+    // the cell has room for capacity bytes, zeroed after content
+    fn alloc_with_capacity(&mut self, content: &[u8], capacity: usize) -> anyhow::Result<u32> {
+        let size = (capacity.max(content.len()) + 4).div_ceil(8) * 8;
+        let offset = match self.find_free_cell(size)? {
+            Some(offset) => offset,
+            None => self.add_hive_bin(size)?,
+        };
+
+        // the rest of a larger free cell stays free
+        let start = BASE_BLOCK_SIZE as usize + offset as usize;
+        let free_size = i32_at(&self.data, start) as usize;
+        let size = if free_size - size >= MIN_CELL_SIZE {
+            let rest = (free_size - size) as i32;
+            self.data[start + size..start + size + 4].copy_from_slice(&rest.to_le_bytes());
+            size
+        } else {
+            free_size
+        };
+
+        self.data[start..start + 4].copy_from_slice(&(-(size as i32)).to_le_bytes());
+        self.data[start + 4..start + 4 + content.len()].copy_from_slice(content);
+        self.data[start + 4 + content.len()..start + size].fill(0);
+        Ok(offset)
+    }
+
+    // the first free cell of at least size bytes
+    fn find_free_cell(&self, size: usize) -> anyhow::Result<Option<u32>> {
+        let hive_bins_end = self.base_block.hive_bins_data_size as usize;
+        let mut bin = 0;
+        while bin < hive_bins_end {
+            let bin_end = bin + self.hive_bin_size(bin)?;
+
+            let mut cell = bin + HIVE_BIN_HEADER_SIZE;
+            while cell + 4 <= bin_end {
+                let cell_size = i32_at(&self.data, BASE_BLOCK_SIZE as usize + cell);
+                let abs_size = cell_size.unsigned_abs() as usize;
+                anyhow::ensure!(
+                    abs_size >= MIN_CELL_SIZE && cell + abs_size <= bin_end,
+                    "invalid cell size {cell_size} at offset 0x{cell:X}"
+                );
+                if cell_size > 0 && abs_size >= size {
+                    return Ok(Some(cell as u32));
+                }
+                cell += abs_size;
+            }
+            bin = bin_end;
+        }
+        Ok(None)
+    }
+
+    fn hive_bin_size(&self, offset: usize) -> anyhow::Result<usize> {
+        let start = BASE_BLOCK_SIZE as usize + offset;
+        let bytes = self
+            .data
+            .get(start..start + HIVE_BIN_HEADER_SIZE)
+            .ok_or_else(|| anyhow::anyhow!("hive bin at offset 0x{offset:X} truncated"))?;
+        let header = HiveBinHeader::try_from(bytes)?;
+        anyhow::ensure!(
+            &header.signature == b"hbin"
+                && header.size as usize >= HIVE_BIN_SIZE
+                && offset + header.size as usize <= self.base_block.hive_bins_data_size as usize,
+            "invalid hive bin at offset 0x{offset:X}"
+        );
+        Ok(header.size as usize)
+    }
+
+    // new hive bin after the last one, holding a single free cell of at least cell_size bytes:
+    // returns the offset of this cell
+    fn add_hive_bin(&mut self, cell_size: usize) -> anyhow::Result<u32> {
+        let offset = self.base_block.hive_bins_data_size;
+        let size = (HIVE_BIN_HEADER_SIZE + cell_size).div_ceil(HIVE_BIN_SIZE) * HIVE_BIN_SIZE;
+        anyhow::ensure!(
+            offset.checked_add(size as u32).is_some(),
+            "hive bins data over 4 GB"
+        );
+
+        let header = HiveBinHeader {
+            signature: *b"hbin",
+            offset,
+            size: size as u32,
+            reserved: 0,
+            timestamp: 0,
+            spare: 0,
+        };
+        let mut bin = bincode::encode_to_vec(&header, bincode_config())?;
+        bin.extend_from_slice(&((size - HIVE_BIN_HEADER_SIZE) as i32).to_le_bytes());
+        bin.resize(size, 0);
+
+        // bytes past the hive bins data, if any, are kept after the new bin
+        let start = BASE_BLOCK_SIZE as usize + offset as usize;
+        self.data.splice(start..start, bin);
+
+        self.base_block.hive_bins_data_size += size as u32;
+        self.write_base_block()?;
+        Ok(offset + HIVE_BIN_HEADER_SIZE as u32)
+    }
+
+    // the cell becomes free, merged with the free cells before and after it
+    fn free(&mut self, offset: u32) -> anyhow::Result<()> {
+        let (size, range) = self.cell_range(offset)?;
+        if size > 0 {
+            return Ok(());
+        }
+        if self.wipe {
+            self.data[range.clone()].fill(0);
+        }
+
+        let (bin, bin_end) = self.hive_bin_of(offset)?;
+        let cell_at = |data: &[u8], cell: usize| i32_at(data, BASE_BLOCK_SIZE as usize + cell);
+        let mut start = offset as usize;
+        let mut end = start + range.len() + 4;
+
+        // cells are walked from the start of the bin to find the one before
+        let mut cell = bin + HIVE_BIN_HEADER_SIZE;
+        let mut previous = None;
+        while cell < start {
+            let cell_size = cell_at(&self.data, cell);
+            if (cell_size.unsigned_abs() as usize) < MIN_CELL_SIZE {
+                break;
+            }
+            previous = Some((cell, cell_size));
+            cell += cell_size.unsigned_abs() as usize;
+        }
+        if cell == start
+            && let Some((previous, previous_size)) = previous
+            && previous_size > 0
+        {
+            start = previous;
+        }
+
+        // size fields of the merged cells are left in the content, unless wiped
+        let base = BASE_BLOCK_SIZE as usize;
+        if end + 4 <= bin_end {
+            let next_size = cell_at(&self.data, end);
+            if next_size > 0 && end + next_size as usize <= bin_end {
+                if self.wipe {
+                    self.data[base + end..base + end + 4].fill(0);
+                }
+                end += next_size as usize;
+            }
+        }
+        if self.wipe && start != offset as usize {
+            self.data[base + offset as usize..base + offset as usize + 4].fill(0);
+        }
+
+        self.data[base + start..base + start + 4]
+            .copy_from_slice(&((end - start) as i32).to_le_bytes());
+        Ok(())
+    }
+
+    // start and end offsets of the hive bin holding an offset
+    fn hive_bin_of(&self, offset: u32) -> anyhow::Result<(usize, usize)> {
+        let offset = offset as usize;
+        let mut bin = 0;
+        while bin < self.base_block.hive_bins_data_size as usize {
+            let bin_end = bin + self.hive_bin_size(bin)?;
+            if offset < bin_end {
+                return Ok((bin, bin_end));
+            }
+            bin = bin_end;
+        }
+        anyhow::bail!("offset 0x{offset:X} out of hive bins data")
+    }
+
+    // base block fields are written back with their checksum
+    fn write_base_block(&mut self) -> anyhow::Result<()> {
+        let mut bytes = bincode::encode_to_vec(&self.base_block, bincode_config())?;
+        let checksum = base_block_checksum(&bytes);
+        bytes[508..512].copy_from_slice(&checksum.to_le_bytes());
+        self.base_block.checksum = checksum;
+        self.data[..bytes.len()].copy_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+// the list without the element pointing to offset
+fn without(list: SubkeysList, offset: u32) -> SubkeysList {
+    match list {
+        SubkeysList::IndexLeaf(mut v) => {
+            v.retain(|o| *o != offset);
+            SubkeysList::IndexLeaf(v)
+        }
+        SubkeysList::FastLeaf(mut v) => {
+            v.retain(|(o, _)| *o != offset);
+            SubkeysList::FastLeaf(v)
+        }
+        SubkeysList::HashLeaf(mut v) => {
+            v.retain(|(o, _)| *o != offset);
+            SubkeysList::HashLeaf(v)
+        }
+        SubkeysList::IndexRoot(v) => SubkeysList::IndexRoot(v),
+    }
+}
+
+// written to a file next to path renamed over it, so that the file at path is never partly written
+fn write_file(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("no file name in {}", path.display()))?
+        .to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(name);
+
+    let result = File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result.map_err(|e| anyhow::anyhow!("can't write {}: {e}", path.display()))
+}
+
+fn i32_at(data: &[u8], offset: usize) -> i32 {
+    i32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
@@ -0,0 +1,352 @@
+// Malformed hives made out of a valid one, to check the parser against damaged input
+//
+// mutations are systematic rather than random: each cell gets its size flipped (allocated <-> free),
+// zeroed, made huge or unaligned, its signature zeroed or swapped for another cell type, and the
+// offsets and counts of its structure (key parent and lists, value data, list elements, security
+// links) pointing out of the hive, into the middle of a cell, to a hbin header or to the cell itself.
+// The base block and hbin headers get broken signatures, offsets and sizes, and the file is
+// truncated in the base block and in the middle of each hbin. A mutant is the hive with a single
+// mutation: it's either parsed or rejected with an error, anything else (a panic) is a bug.
+//
+use std::{
+    fmt,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    buffer::HiveBuffer,
+    deleted::deleted_keys,
+    hive::{BASE_BLOCK_SIZE, RegistryHive},
+    index::{CellIndex, IndexedCell},
+    reg::CellType,
+};
+
+// offsets written in place of valid ones, besides the cell itself
+const OUT_OF_HIVE: u32 = 0x7FFF_FFF8;
+
+// position in the content of a cell and name of the fields of a structure
+type Fields = Vec<(usize, &'static str)>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MutationKind {
+    Size,
+    Signature,
+    Offset,
+    Count,
+    Truncation,
+}
+
+impl fmt::Display for MutationKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MutationKind::Size => write!(f, "size"),
+            MutationKind::Signature => write!(f, "signature"),
+            MutationKind::Offset => write!(f, "offset"),
+            MutationKind::Count => write!(f, "count"),
+            MutationKind::Truncation => write!(f, "truncation"),
+        }
+    }
+}
+
+// bytes written at an offset of the file, or the file truncated at offset when there are none
+#[derive(Debug, Clone)]
+pub struct Mutation {
+    pub kind: MutationKind,
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+    pub description: String,
+}
+
+impl Mutation {
+    fn write(kind: MutationKind, offset: usize, bytes: &[u8], description: String) -> Self {
+        Self {
+            kind,
+            offset,
+            bytes: bytes.to_vec(),
+            description,
+        }
+    }
+
+    pub fn apply(&self, data: &[u8]) -> Vec<u8> {
+        if self.kind == MutationKind::Truncation {
+            return data[..self.offset.min(data.len())].to_vec();
+        }
+        let mut mutant = data.to_vec();
+        let end = (self.offset + self.bytes.len()).min(mutant.len());
+        mutant[self.offset..end].copy_from_slice(&self.bytes[..end - self.offset]);
+        mutant
+    }
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t0x{:X}\t{}",
+            self.kind, self.offset, self.description
+        )
+    }
+}
+
+// what the parser made of a mutant
+#[derive(Debug)]
+pub enum Outcome {
+    Parsed,
+    Rejected(String),
+    Panicked(String),
+}
+
+// mutations of a valid hive, in the order of the file
+pub fn mutations(data: &[u8]) -> anyhow::Result<Vec<Mutation>> {
+    let index = CellIndex::build(&HiveBuffer::from(data.to_vec()))?;
+    let mut mutations = base_block_mutations();
+
+    let mut hbins: Vec<u32> = index.cells().iter().map(|c| c.hbin).collect();
+    hbins.dedup();
+    for (i, hbin) in hbins.iter().enumerate() {
+        let start = BASE_BLOCK_SIZE as usize + *hbin as usize;
+        let end = hbins
+            .get(i + 1)
+            .map_or(data.len(), |next| BASE_BLOCK_SIZE as usize + *next as usize);
+        mutations.extend(hbin_mutations(start, end, *hbin));
+
+        for cell in index.cells().iter().filter(|c| c.hbin == *hbin) {
+            mutations.extend(cell_mutations(&index, cell));
+        }
+    }
+    Ok(mutations)
+}
+
+fn base_block_mutations() -> Vec<Mutation> {
+    let write = |offset, bytes: &[u8], description: &str| {
+        let kind = match offset {
+            0 => MutationKind::Signature,
+            0x24 => MutationKind::Offset,
+            _ => MutationKind::Size,
+        };
+        Mutation::write(kind, offset, bytes, format!("base block {description}"))
+    };
+    let mut mutations = vec![
+        write(0, b"\0\0\0\0", "signature zeroed"),
+        write(
+            0x24,
+            &OUT_OF_HIVE.to_le_bytes(),
+            "root cell out of the hive",
+        ),
+        write(0x24, &1u32.to_le_bytes(), "root cell unaligned"),
+        write(
+            0x24,
+            &0u32.to_le_bytes(),
+            "root cell on the first hbin header",
+        ),
+        write(0x28, &0u32.to_le_bytes(), "hive bins data size zeroed"),
+        write(0x28, &u32::MAX.to_le_bytes(), "hive bins data size huge"),
+        write(
+            0x28,
+            &0x1001u32.to_le_bytes(),
+            "hive bins data size unaligned",
+        ),
+    ];
+    for offset in [512, BASE_BLOCK_SIZE as usize - 1, BASE_BLOCK_SIZE as usize] {
+        mutations.push(Mutation {
+            kind: MutationKind::Truncation,
+            offset,
+            bytes: Vec::new(),
+            description: format!("file truncated to {offset} bytes"),
+        });
+    }
+    mutations
+}
+
+// start and end in the file of the hbin at offset
+fn hbin_mutations(start: usize, end: usize, offset: u32) -> Vec<Mutation> {
+    let write = |kind, field, bytes: &[u8], description: &str| {
+        let description = format!("hbin 0x{offset:X} {description}");
+        Mutation::write(kind, start + field, bytes, description)
+    };
+    let middle = start + (end - start) / 2;
+    vec![
+        write(MutationKind::Signature, 0, b"\0\0\0\0", "signature zeroed"),
+        write(
+            MutationKind::Offset,
+            4,
+            &(offset + 0x1000).to_le_bytes(),
+            "offset wrong",
+        ),
+        write(MutationKind::Size, 8, &0u32.to_le_bytes(), "size zeroed"),
+        write(MutationKind::Size, 8, &u32::MAX.to_le_bytes(), "size huge"),
+        write(
+            MutationKind::Size,
+            8,
+            &0x1001u32.to_le_bytes(),
+            "size unaligned",
+        ),
+        Mutation {
+            kind: MutationKind::Truncation,
+            offset: middle,
+            bytes: Vec::new(),
+            description: format!("file truncated in the middle of hbin 0x{offset:X}"),
+        },
+    ]
+}
+
+fn cell_mutations(index: &CellIndex, cell: &IndexedCell) -> Vec<Mutation> {
+    let start = BASE_BLOCK_SIZE as usize + cell.offset as usize;
+    let content = index.content(cell);
+    let cell_type = if content.len() >= 2 {
+        Some(index.cell_type(cell))
+    } else {
+        None
+    };
+    let name = match &cell_type {
+        Some(CellType::Unknown(_)) | None if cell.is_free() => "free cell".to_string(),
+        Some(CellType::Unknown(_)) | None => "data cell".to_string(),
+        Some(cell_type) => format!("{cell_type} cell"),
+    };
+    let write = |kind, field: usize, bytes: &[u8], description: &str| {
+        let description = format!("{name} 0x{:X} {description}", cell.offset);
+        Mutation::write(kind, start + field, bytes, description)
+    };
+
+    let size = cell.size;
+    let mut mutations = vec![
+        write(
+            MutationKind::Size,
+            0,
+            &size.wrapping_neg().to_le_bytes(),
+            if cell.is_free() {
+                "size allocated"
+            } else {
+                "size freed"
+            },
+        ),
+        write(MutationKind::Size, 0, &0i32.to_le_bytes(), "size zeroed"),
+        write(MutationKind::Size, 0, &i32::MIN.to_le_bytes(), "size huge"),
+        write(
+            MutationKind::Size,
+            0,
+            &(size + size.signum() * 3).to_le_bytes(),
+            "size unaligned",
+        ),
+    ];
+
+    // type confusion: each structure read as another one
+    let swapped = match &cell_type {
+        Some(CellType::NamedKey) => Some(b"vk"),
+        Some(CellType::ValueKey) => Some(b"nk"),
+        Some(CellType::SecurityKey) => Some(b"nk"),
+        Some(CellType::RootIndex) => Some(b"li"),
+        Some(CellType::DataBlock) => Some(b"lf"),
+        Some(CellType::LeafIndex | CellType::LeafFast | CellType::LeafHash) => Some(b"ri"),
+        _ => None,
+    };
+    if let Some(swapped) = swapped {
+        mutations.push(write(
+            MutationKind::Signature,
+            4,
+            b"\0\0",
+            "signature zeroed",
+        ));
+        let description = format!("signature {}", String::from_utf8_lossy(swapped));
+        mutations.push(write(MutationKind::Signature, 4, swapped, &description));
+    }
+
+    // offsets and counts by field position in the content
+    let (offsets, counts): (Fields, Fields) = match &cell_type {
+        Some(CellType::NamedKey) => (
+            vec![
+                (0x10, "parent"),
+                (0x1C, "subkeys list"),
+                (0x28, "values list"),
+                (0x2C, "security"),
+                (0x30, "class name"),
+            ],
+            vec![
+                (0x14, "number of subkeys"),
+                (0x24, "number of values"),
+                (0x48, "name length"),
+            ],
+        ),
+        Some(CellType::ValueKey) => (
+            vec![(0x08, "data")],
+            vec![(0x02, "name length"), (0x04, "data size")],
+        ),
+        Some(CellType::SecurityKey) => (
+            vec![(0x04, "flink"), (0x08, "blink")],
+            vec![(0x10, "descriptor size")],
+        ),
+        Some(CellType::DataBlock) => (vec![(0x04, "segments list")], vec![(0x02, "segments")]),
+        Some(
+            CellType::LeafIndex | CellType::LeafFast | CellType::LeafHash | CellType::RootIndex,
+        ) => (vec![(0x04, "first element")], vec![(0x02, "elements")]),
+        _ => (Vec::new(), Vec::new()),
+    };
+
+    for (field, what) in offsets {
+        if field + 4 > content.len() {
+            continue;
+        }
+        let hbin = cell.hbin;
+        for (offset, description) in [
+            (OUT_OF_HIVE, "out of the hive"),
+            (cell.offset + 1, "unaligned"),
+            (hbin, "on the hbin header"),
+            (cell.offset, "on the cell itself"),
+        ] {
+            let description = format!("{what} offset {description}");
+            mutations.push(write(
+                MutationKind::Offset,
+                4 + field,
+                &offset.to_le_bytes(),
+                &description,
+            ));
+        }
+    }
+    for (field, what) in counts {
+        if field + 2 > content.len() {
+            continue;
+        }
+        // 16-bit counts of lists and names, 32-bit ones otherwise
+        let bytes = if field == 0x02 || field == 0x48 {
+            u16::MAX.to_le_bytes().to_vec()
+        } else {
+            u32::MAX.to_le_bytes().to_vec()
+        };
+        let description = format!("{what} huge");
+        mutations.push(write(MutationKind::Count, 4 + field, &bytes, &description));
+    }
+    mutations
+}
+
+// the mutant is parsed the way the commands do: keys tree walked with values, data and security
+// read, and deleted keys recovered from free cells
+pub fn parse(data: Vec<u8>) -> anyhow::Result<()> {
+    let mut hive = RegistryHive::from_buffer(HiveBuffer::from(data))?;
+    hive.index()?;
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |hive, _, key| {
+        for value in hive.values(key)? {
+            hive.value_data(&value)?;
+        }
+        hive.security(key)?;
+        Ok(())
+    })?;
+    deleted_keys(&mut hive)?;
+    Ok(())
+}
+
+// panics are caught, the panic hook is left to the caller (e.g. to silence the messages)
+pub fn outcome(mutant: Vec<u8>) -> Outcome {
+    match panic::catch_unwind(AssertUnwindSafe(|| parse(mutant))) {
+        Ok(Ok(())) => Outcome::Parsed,
+        Ok(Err(e)) => Outcome::Rejected(e.to_string()),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Outcome::Panicked(message)
+        }
+    }
+}
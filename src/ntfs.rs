@@ -0,0 +1,576 @@
+// Reading files out of NTFS file systems in disk images, to parse hives without extracting them first
+//
+// only what's needed to read a hive is supported: the MFT, directory indexes ($INDEX_ROOT and
+// $INDEX_ALLOCATION of $I30), resident and non-resident unnamed $DATA attributes, and attribute lists
+// for fragmented files. Compressed and encrypted files aren't. The image is either a volume, or a disk
+// with an MBR or GPT partition table whose NTFS partitions are searched in order.
+//
+// see: https://flatcap.github.io/linux-ntfs/ntfs/
+//
+use std::{
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    artifacts::{bytes_at, u16_at, u32_at, u64_at, utf16_at},
+    buffer::default_options,
+};
+
+const SECTOR_SIZE: u64 = 512;
+
+// update sequence arrays protect the last 2 bytes of each 512 bytes stride
+const STRIDE_SIZE: usize = 512;
+
+const ROOT_RECORD: u64 = 5;
+
+const ATTRIBUTE_LIST: u32 = 0x20;
+const FILE_NAME: u32 = 0x30;
+const DATA: u32 = 0x80;
+const INDEX_ROOT: u32 = 0x90;
+const INDEX_ALLOCATION: u32 = 0xA0;
+const END_MARKER: u32 = 0xFFFF_FFFF;
+
+const COMPRESSED: u16 = 0x0001;
+const ENCRYPTED: u16 = 0x4000;
+
+const RECORD_IN_USE: u16 = 0x0001;
+
+const ENTRY_SUBNODE: u32 = 0x01;
+const ENTRY_LAST: u32 = 0x02;
+
+// MBR partition types of unused entries and GPT protective MBR
+const MBR_EMPTY: u8 = 0x00;
+const MBR_GPT: u8 = 0xEE;
+
+// directories can't be nested deeper than this in an index B-tree (loops in corrupted data)
+const MAX_INDEX_DEPTH: usize = 64;
+
+// a run of clusters, None for sparse runs
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    lcn: Option<u64>,
+    clusters: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Attribute {
+    kind: u32,
+    name: String,
+    flags: u16,
+    content: AttributeContent,
+}
+
+#[derive(Debug, Clone)]
+enum AttributeContent {
+    Resident(Vec<u8>),
+    NonResident {
+        starting_vcn: u64,
+        runs: Vec<Run>,
+        data_size: u64,
+        initialized_size: u64,
+    },
+}
+
+pub struct NtfsVolume<R> {
+    reader: R,
+
+    // of the volume in the image
+    offset: u64,
+    cluster_size: u64,
+    record_size: u64,
+    index_record_size: u64,
+
+    // of the $DATA attribute of $MFT
+    mft_runs: Vec<Run>,
+}
+
+impl<R: Read + Seek> NtfsVolume<R> {
+    pub fn new(mut reader: R, offset: u64) -> anyhow::Result<Self> {
+        let mut boot = vec![0; SECTOR_SIZE as usize];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut boot)?;
+        anyhow::ensure!(
+            &boot[3..11] == b"NTFS    ",
+            "no NTFS volume at 0x{offset:X}"
+        );
+
+        let sector_size = u16_at(&boot, 0x0B)? as u64;
+        let cluster_size = match boot[0x0D] {
+            // 2^-n sectors for clusters of 64 KB and more
+            n @ 0x81.. => sector_size << (256 - n as u32),
+            n => sector_size * n as u64,
+        };
+        anyhow::ensure!(
+            sector_size >= SECTOR_SIZE && cluster_size != 0,
+            "invalid NTFS boot sector at 0x{offset:X}"
+        );
+
+        // sizes of records in clusters, or 2^-n bytes
+        let size = |value: u8| match value as i8 {
+            n if n < 0 => 1 << (-(n as i32)),
+            n => n as u64 * cluster_size,
+        };
+        let record_size = size(boot[0x40]);
+        let index_record_size = size(boot[0x44]);
+        let mft_lcn = u64_at(&boot, 0x30)?;
+
+        // the first records of the MFT are read with a run long enough for them, then the whole $MFT
+        let mut volume = Self {
+            reader,
+            offset,
+            cluster_size,
+            record_size,
+            index_record_size,
+            mft_runs: vec![Run {
+                lcn: Some(mft_lcn),
+                clusters: (16 * record_size).div_ceil(cluster_size),
+            }],
+        };
+        match volume.data_attribute(0)?.content {
+            AttributeContent::NonResident { runs, .. } => volume.mft_runs = runs,
+            AttributeContent::Resident(_) => anyhow::bail!("$DATA of $MFT is resident"),
+        }
+        Ok(volume)
+    }
+
+    // record with its fixups applied
+    fn record(&mut self, number: u64) -> anyhow::Result<Vec<u8>> {
+        let runs = self.mft_runs.clone();
+        let mut record =
+            self.read_runs(&runs, number * self.record_size, self.record_size as usize)?;
+        anyhow::ensure!(
+            record.starts_with(b"FILE"),
+            "MFT record {number} has no FILE signature"
+        );
+        apply_fixups(&mut record)?;
+        Ok(record)
+    }
+
+    // bytes of the runs from the offset, sparse runs reading as zeros
+    fn read_runs(&mut self, runs: &[Run], offset: u64, len: usize) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = vec![0; len];
+        let mut run_start = 0;
+        let mut done = 0;
+        for run in runs {
+            let run_size = run.clusters * self.cluster_size;
+            let position = offset + done as u64;
+            if done < len && position < run_start + run_size {
+                let n = ((run_start + run_size - position) as usize).min(len - done);
+                if let Some(lcn) = run.lcn {
+                    self.reader.seek(SeekFrom::Start(
+                        self.offset + lcn * self.cluster_size + position - run_start,
+                    ))?;
+                    self.reader.read_exact(&mut bytes[done..done + n])?;
+                }
+                done += n;
+            }
+            run_start += run_size;
+        }
+        anyhow::ensure!(
+            done == len,
+            "{len} bytes at 0x{offset:X} beyond the end of the runs"
+        );
+        Ok(bytes)
+    }
+
+    // attributes of a record and of its extension records listed in its attribute list
+    fn attributes(&mut self, number: u64) -> anyhow::Result<Vec<Attribute>> {
+        let record = self.record(number)?;
+        anyhow::ensure!(
+            u16_at(&record, 0x16)? & RECORD_IN_USE != 0,
+            "MFT record {number} isn't in use"
+        );
+        let mut attributes = parse_attributes(&record)?;
+
+        let Some(list) = attributes
+            .iter()
+            .find(|a| a.kind == ATTRIBUTE_LIST)
+            .cloned()
+        else {
+            return Ok(attributes);
+        };
+        let list = self.content(&list)?;
+        let mut extensions = Vec::new();
+        let mut position = 0;
+        while position + 0x1A <= list.len() {
+            let length = u16_at(&list, position + 4)? as usize;
+            let reference = u64_at(&list, position + 0x10)? & 0xFFFF_FFFF_FFFF;
+            if length == 0 {
+                break;
+            }
+            if reference != number && !extensions.contains(&reference) {
+                extensions.push(reference);
+            }
+            position += length;
+        }
+        for extension in extensions {
+            let record = self.record(extension)?;
+            attributes.extend(parse_attributes(&record)?);
+        }
+        Ok(attributes)
+    }
+
+    // unnamed $DATA attribute, with the runs of its parts in several records put together
+    fn data_attribute(&mut self, number: u64) -> anyhow::Result<Attribute> {
+        let mut parts: Vec<_> = self
+            .attributes(number)?
+            .into_iter()
+            .filter(|a| a.kind == DATA && a.name.is_empty())
+            .collect();
+        parts.sort_by_key(|a| match a.content {
+            AttributeContent::NonResident { starting_vcn, .. } => starting_vcn,
+            AttributeContent::Resident(_) => 0,
+        });
+
+        let mut parts = parts.into_iter();
+        let mut data = parts
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("MFT record {number} has no $DATA attribute"))?;
+        for part in parts {
+            // only the first part has the sizes
+            if let (
+                AttributeContent::NonResident { runs, .. },
+                AttributeContent::NonResident { runs: more, .. },
+            ) = (&mut data.content, part.content)
+            {
+                runs.extend(more);
+            }
+        }
+        Ok(data)
+    }
+
+    fn content(&mut self, attribute: &Attribute) -> anyhow::Result<Vec<u8>> {
+        anyhow::ensure!(
+            attribute.flags & (COMPRESSED | ENCRYPTED) == 0,
+            "compressed and encrypted attributes aren't supported"
+        );
+        match &attribute.content {
+            AttributeContent::Resident(bytes) => Ok(bytes.clone()),
+            AttributeContent::NonResident {
+                runs,
+                data_size,
+                initialized_size,
+                ..
+            } => {
+                default_options().check_size(*data_size, "attribute content")?;
+                let mut bytes = self.read_runs(runs, 0, *data_size as usize)?;
+                // past the initialized size, data reads as zeros
+                if let Some(uninitialized) = bytes.get_mut(*initialized_size as usize..) {
+                    uninitialized.fill(0);
+                }
+                Ok(bytes)
+            }
+        }
+    }
+
+    // content of the unnamed $DATA attribute of a record
+    fn file_data(&mut self, number: u64) -> anyhow::Result<Vec<u8>> {
+        let data = self.data_attribute(number)?;
+        self.content(&data)
+    }
+
+    // record of a file or directory in a directory, names being case insensitive
+    fn lookup(&mut self, directory: u64, name: &str) -> anyhow::Result<Option<u64>> {
+        let attributes = self.attributes(directory)?;
+        let is_i30 = |a: &&Attribute| a.name == "$I30";
+
+        let Some(root) = attributes
+            .iter()
+            .filter(is_i30)
+            .find(|a| a.kind == INDEX_ROOT)
+        else {
+            anyhow::bail!("MFT record {directory} isn't a directory");
+        };
+        let root = self.content(root)?;
+        let allocation = match attributes
+            .iter()
+            .filter(is_i30)
+            .find(|a| a.kind == INDEX_ALLOCATION)
+        {
+            Some(allocation) => self.content(allocation)?,
+            None => Vec::new(),
+        };
+
+        // the index header follows the 16 bytes of the index root header
+        let mut nodes = vec![(
+            bytes_at(&root, 0x10, root.len().saturating_sub(0x10))?.to_vec(),
+            0,
+        )];
+        while let Some((node, depth)) = nodes.pop() {
+            anyhow::ensure!(
+                depth < MAX_INDEX_DEPTH,
+                "index of MFT record {directory} is too deep"
+            );
+
+            let mut position = u32_at(&node, 0)? as usize;
+            let end = (u32_at(&node, 4)? as usize).min(node.len());
+            while position + 0x10 <= end {
+                let length = u16_at(&node, position + 8)? as usize;
+                let key_length = u16_at(&node, position + 10)? as usize;
+                let flags = u32_at(&node, position + 12)?;
+                if length < 0x10 {
+                    break;
+                }
+
+                if flags & ENTRY_SUBNODE != 0 {
+                    let vcn = u64_at(&node, position + length - 8)?;
+                    // VCNs count clusters, or 512 bytes blocks for index records smaller than clusters
+                    let unit = if self.index_record_size >= self.cluster_size {
+                        self.cluster_size
+                    } else {
+                        SECTOR_SIZE
+                    };
+                    let start = (vcn * unit) as usize;
+                    let mut record =
+                        bytes_at(&allocation, start, self.index_record_size as usize)?.to_vec();
+                    anyhow::ensure!(
+                        record.starts_with(b"INDX"),
+                        "index record at VCN {vcn} of MFT record {directory} has no INDX signature"
+                    );
+                    apply_fixups(&mut record)?;
+                    nodes.push((record[0x18..].to_vec(), depth + 1));
+                }
+
+                if flags & ENTRY_LAST != 0 {
+                    break;
+                }
+
+                // the key is the $FILE_NAME of the entry
+                if key_length >= 0x42 {
+                    let key = bytes_at(&node, position + 0x10, key_length)?;
+                    let name_length = key[0x40] as usize;
+                    let entry_name = utf16_at(key, 0x42, name_length * 2)?;
+                    if entry_name.to_uppercase() == name.to_uppercase() {
+                        return Ok(Some(u64_at(&node, position)? & 0xFFFF_FFFF_FFFF));
+                    }
+                }
+                position += length;
+            }
+        }
+
+        Ok(None)
+    }
+
+    // record of a path from the root directory, '/' or '\' separated
+    pub fn find(&mut self, path: &str) -> anyhow::Result<Option<u64>> {
+        let mut record = ROOT_RECORD;
+        for name in path.split(['/', '\\']).filter(|n| !n.is_empty()) {
+            match self.lookup(record, name)? {
+                Some(child) => record = child,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(record))
+    }
+
+    // content of a file
+    pub fn read_file(&mut self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let record = self
+            .find(path)?
+            .ok_or_else(|| anyhow::anyhow!("'{path}' not found"))?;
+        self.file_data(record)
+    }
+}
+
+// offsets of the NTFS volumes of a disk image, the image itself being possibly a volume
+pub fn ntfs_volumes<R: Read + Seek>(reader: &mut R) -> anyhow::Result<Vec<u64>> {
+    let mut sector = vec![0; SECTOR_SIZE as usize];
+    reader.seek(SeekFrom::Start(0))?;
+    reader.read_exact(&mut sector)?;
+    if &sector[3..11] == b"NTFS    " {
+        return Ok(vec![0]);
+    }
+    if sector[510..512] != [0x55, 0xAA] {
+        return Ok(Vec::new());
+    }
+
+    let mut starts = Vec::new();
+    for entry in sector[446..510].chunks_exact(16) {
+        let start = u32_at(entry, 8)? as u64 * SECTOR_SIZE;
+        match entry[4] {
+            MBR_EMPTY => (),
+            MBR_GPT => starts.extend(gpt_partitions(reader)?),
+            _ => starts.push(start),
+        }
+    }
+
+    // NTFS volumes are found by their boot sector whatever the partition type
+    let mut volumes = Vec::new();
+    for start in starts {
+        reader.seek(SeekFrom::Start(start))?;
+        if reader.read_exact(&mut sector).is_ok() && &sector[3..11] == b"NTFS    " {
+            volumes.push(start);
+        }
+    }
+    Ok(volumes)
+}
+
+// starts of the partitions of a GPT disk
+fn gpt_partitions<R: Read + Seek>(reader: &mut R) -> anyhow::Result<Vec<u64>> {
+    let mut header = vec![0; SECTOR_SIZE as usize];
+    reader.seek(SeekFrom::Start(SECTOR_SIZE))?;
+    reader.read_exact(&mut header)?;
+    anyhow::ensure!(header.starts_with(b"EFI PART"), "no GPT header");
+
+    let entries_lba = u64_at(&header, 0x48)?;
+    let count = u32_at(&header, 0x50)? as usize;
+    let entry_size = u32_at(&header, 0x54)? as usize;
+    anyhow::ensure!(
+        (128..=4096).contains(&entry_size) && count <= 1024,
+        "invalid GPT header"
+    );
+
+    let mut entries = vec![0; count * entry_size];
+    reader.seek(SeekFrom::Start(entries_lba * SECTOR_SIZE))?;
+    reader.read_exact(&mut entries)?;
+
+    let mut starts = Vec::new();
+    for entry in entries.chunks_exact(entry_size) {
+        // unused entries have a null type GUID
+        if entry[..16].iter().any(|b| *b != 0) {
+            starts.push(u64_at(entry, 0x20)? * SECTOR_SIZE);
+        }
+    }
+    Ok(starts)
+}
+
+// content of a file of the first NTFS volume of the image where it's found
+pub fn read_image_file(image: &Path, path: &str) -> anyhow::Result<Vec<u8>> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(image)?);
+    read_image_file_from(&mut reader, &image.display().to_string(), path)
+}
+
+// same as read_image_file for an image read from anything, e.g. over HTTP
+pub fn read_image_file_from<R: Read + Seek>(
+    reader: &mut R,
+    image: &str,
+    path: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let volumes = ntfs_volumes(reader)?;
+    anyhow::ensure!(!volumes.is_empty(), "no NTFS volume found in {image}");
+
+    for offset in volumes {
+        let mut volume = NtfsVolume::new(&mut *reader, offset)?;
+        if let Some(record) = volume.find(path)? {
+            return volume.file_data(record);
+        }
+    }
+    anyhow::bail!("'{path}' not found in {image}")
+}
+
+fn apply_fixups(record: &mut [u8]) -> anyhow::Result<()> {
+    let usa_offset = u16_at(record, 4)? as usize;
+    let usa_count = u16_at(record, 6)? as usize;
+    let usa = bytes_at(record, usa_offset, usa_count * 2)?.to_vec();
+
+    for i in 1..usa_count {
+        let end = i * STRIDE_SIZE;
+        let stride_end = bytes_at(record, end - 2, 2)?;
+        anyhow::ensure!(
+            stride_end == &usa[..2],
+            "update sequence mismatch at 0x{:X}",
+            end - 2
+        );
+        record[end - 2..end].copy_from_slice(&usa[i * 2..i * 2 + 2]);
+    }
+    Ok(())
+}
+
+fn parse_attributes(record: &[u8]) -> anyhow::Result<Vec<Attribute>> {
+    let mut attributes = Vec::new();
+    let mut position = u16_at(record, 0x14)? as usize;
+
+    loop {
+        let kind = u32_at(record, position)?;
+        if kind == END_MARKER {
+            break;
+        }
+        let length = u32_at(record, position + 4)? as usize;
+        anyhow::ensure!(
+            length >= 0x18 && position + length <= record.len(),
+            "invalid attribute length {length} at 0x{position:X}"
+        );
+        let attribute = bytes_at(record, position, length)?;
+
+        let name_length = attribute[9] as usize;
+        let name_offset = u16_at(attribute, 0x0A)? as usize;
+        let name = utf16_at(attribute, name_offset, name_length * 2)?;
+        let flags = u16_at(attribute, 0x0C)?;
+
+        let content = if attribute[8] == 0 {
+            let value_length = u32_at(attribute, 0x10)? as usize;
+            let value_offset = u16_at(attribute, 0x14)? as usize;
+            AttributeContent::Resident(bytes_at(attribute, value_offset, value_length)?.to_vec())
+        } else {
+            let runs_offset = u16_at(attribute, 0x20)? as usize;
+            AttributeContent::NonResident {
+                starting_vcn: u64_at(attribute, 0x10)?,
+                runs: parse_runs(bytes_at(
+                    attribute,
+                    runs_offset,
+                    length.saturating_sub(runs_offset),
+                )?)?,
+                data_size: u64_at(attribute, 0x30)?,
+                initialized_size: u64_at(attribute, 0x38)?,
+            }
+        };
+
+        if kind != FILE_NAME {
+            attributes.push(Attribute {
+                kind,
+                name,
+                flags,
+                content,
+            });
+        }
+        position += length;
+    }
+
+    Ok(attributes)
+}
+
+// runs are stored as a header byte with the sizes of the length and offset fields, the length in
+// clusters and the offset of the run from the previous one, a missing offset meaning a sparse run
+fn parse_runs(bytes: &[u8]) -> anyhow::Result<Vec<Run>> {
+    let mut runs = Vec::new();
+    let mut lcn: i64 = 0;
+    let mut position = 0;
+
+    while let Some(&header) = bytes.get(position) {
+        if header == 0 {
+            break;
+        }
+        let (length_size, offset_size) = ((header & 0x0F) as usize, (header >> 4) as usize);
+        anyhow::ensure!(
+            (1..=8).contains(&length_size) && offset_size <= 8,
+            "invalid run header 0x{header:02X}"
+        );
+
+        // lengths are unsigned, offsets signed
+        let field = |start: usize, size: usize, signed: bool| -> anyhow::Result<u64> {
+            let mut value = [0; 8];
+            value[..size].copy_from_slice(bytes_at(bytes, start, size)?);
+            if signed && size < 8 && value[size - 1] & 0x80 != 0 {
+                value[size..].fill(0xFF);
+            }
+            Ok(u64::from_le_bytes(value))
+        };
+        let clusters = field(position + 1, length_size, false)?;
+        let run_lcn = if offset_size == 0 {
+            None
+        } else {
+            lcn += field(position + 1 + length_size, offset_size, true)? as i64;
+            anyhow::ensure!(lcn >= 0, "negative LCN in runs");
+            Some(lcn as u64)
+        };
+
+        runs.push(Run {
+            lcn: run_lcn,
+            clusters,
+        });
+        position += 1 + length_size + offset_size;
+    }
+
+    Ok(runs)
+}
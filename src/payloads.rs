@@ -0,0 +1,267 @@
+// Executables and encoded payloads hidden in value data, the usual storage of fileless malware
+//
+// value data is searched for PE images (an "MZ" header whose e_lfanew points to a "PE\0\0"
+// signature) and, in strings and in the ASCII text of binary data, for long runs of base64 or hex
+// characters, which are decoded. Payloads split among the string values of a key are found in the
+// concatenation of these values, in the order of the values list.
+//
+use std::fmt;
+
+use crate::{
+    hive::RegistryHive,
+    value::{RegData, RegType},
+};
+
+// encoded runs shorter than this are ordinary strings (hashes, GUIDs, paths)
+const MIN_ENCODED_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PayloadEncoding {
+    Raw,
+    Base64,
+    Hex,
+}
+
+impl fmt::Display for PayloadEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadEncoding::Raw => write!(f, "raw"),
+            PayloadEncoding::Base64 => write!(f, "base64"),
+            PayloadEncoding::Hex => write!(f, "hex"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Payload {
+    pub key_path: String,
+
+    // several values for payloads split among values
+    pub value_names: Vec<String>,
+    pub encoding: PayloadEncoding,
+
+    // in the data for raw payloads, in the (concatenated) text for encoded ones
+    pub offset: usize,
+
+    // decoded
+    pub data: Vec<u8>,
+}
+
+impl fmt::Display for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t0x{:X}\t{} bytes",
+            self.key_path,
+            self.value_names.join("+"),
+            self.encoding,
+            self.offset,
+            self.data.len()
+        )?;
+        if self.is_pe() {
+            write!(f, ", PE image")?;
+        }
+        Ok(())
+    }
+}
+
+impl Payload {
+    pub fn is_pe(&self) -> bool {
+        is_pe_image(&self.data)
+    }
+}
+
+// "MZ" alone is too short to be conclusive: the PE signature must be found where the DOS header
+// points to
+pub fn is_pe_image(data: &[u8]) -> bool {
+    if !data.starts_with(b"MZ") {
+        return false;
+    }
+    let Some(lfanew) = data.get(0x3C..0x40) else {
+        return false;
+    };
+    let lfanew = u32::from_le_bytes(lfanew.try_into().unwrap()) as usize;
+    data.get(lfanew..lfanew.saturating_add(4)) == Some(b"PE\0\0")
+}
+
+pub fn find_payloads(hive: &mut RegistryHive) -> anyhow::Result<Vec<Payload>> {
+    let mut payloads = Vec::new();
+
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |hive, path, key| {
+        // text of the string values with their names and the runs found in them, for split
+        // payloads
+        let mut strings = Vec::new();
+
+        for value in hive.values(key)? {
+            let Ok(bytes) = hive.value_bytes(&value) else {
+                continue;
+            };
+            let name = value.display_name().to_string();
+            let mut found = |encoding, offset, data| {
+                payloads.push(Payload {
+                    key_path: path.to_string(),
+                    value_names: vec![name.clone()],
+                    encoding,
+                    offset,
+                    data,
+                })
+            };
+
+            let text = match RegData::new(value.reg_type(), &bytes) {
+                RegData::Sz(s) | RegData::ExpandSz(s) => s,
+                RegData::MultiSz(v) => v.concat(),
+                _ => {
+                    for offset in pe_offsets(&bytes) {
+                        found(PayloadEncoding::Raw, offset, bytes[offset..].to_vec());
+                    }
+                    // ASCII text of binary data
+                    bytes.iter().map(|b| *b as char).collect()
+                }
+            };
+            let mut runs = Vec::new();
+            for (offset, len, encoding, data) in encoded_runs(&text) {
+                runs.push(offset..offset + len);
+                found(encoding, offset, data);
+            }
+
+            if matches!(
+                value.reg_type(),
+                RegType::Sz | RegType::ExpandSz | RegType::MultiSz
+            ) {
+                strings.push((value.display_name().to_string(), text, runs));
+            }
+        }
+
+        // runs over several values, those extending a run already found in a value being the
+        // concatenation of adjacent but unrelated strings
+        if strings.len() > 1 {
+            let mut bounds = Vec::new();
+            let mut value_runs = Vec::new();
+            let mut concatenation = String::new();
+            for (name, text, runs) in &strings {
+                let start = concatenation.chars().count();
+                concatenation.push_str(text);
+                bounds.push((name, start, concatenation.chars().count()));
+                value_runs.extend(runs.iter().map(|r| start + r.start..start + r.end));
+            }
+
+            for (offset, len, encoding, data) in encoded_runs(&concatenation) {
+                if value_runs
+                    .iter()
+                    .any(|r| r.start < offset + len && offset < r.end)
+                {
+                    continue;
+                }
+                let value_names: Vec<String> = bounds
+                    .iter()
+                    .filter(|(_, start, end)| *start < offset + len && offset < *end)
+                    .map(|(name, ..)| name.to_string())
+                    .collect();
+                if value_names.len() > 1 {
+                    payloads.push(Payload {
+                        key_path: path.to_string(),
+                        value_names,
+                        encoding,
+                        offset,
+                        data,
+                    });
+                }
+            }
+        }
+        Ok(())
+    })?;
+
+    Ok(payloads)
+}
+
+// offsets of the PE images in data
+fn pe_offsets(data: &[u8]) -> Vec<usize> {
+    data.windows(2)
+        .enumerate()
+        .filter(|(i, w)| *w == b"MZ" && is_pe_image(&data[*i..]))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// runs of base64 or hex characters long enough to be payloads, line breaks allowed: character
+// offset, length in characters, encoding and decoded data
+fn encoded_runs(text: &str) -> Vec<(usize, usize, PayloadEncoding, Vec<u8>)> {
+    let chars: Vec<char> = text.chars().collect();
+    let is_base64 = |c: char| c.is_ascii_alphanumeric() || c == '+' || c == '/';
+
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_base64(chars[i]) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < chars.len() && (is_base64(chars[i]) || matches!(chars[i], '\r' | '\n')) {
+            i += 1;
+        }
+        while i < chars.len() && chars[i] == '=' {
+            i += 1;
+        }
+
+        let run: String = chars[start..i]
+            .iter()
+            .filter(|c| !c.is_ascii_whitespace())
+            .collect();
+        if run.len() < MIN_ENCODED_LEN {
+            continue;
+        }
+
+        // hex digits are also base64 characters
+        let decoded = if run.chars().all(|c| c.is_ascii_hexdigit()) {
+            decode_hex(&run).map(|data| (PayloadEncoding::Hex, data))
+        } else {
+            decode_base64(&run).map(|data| (PayloadEncoding::Base64, data))
+        };
+        if let Some((encoding, data)) = decoded {
+            runs.push((start, i - start, encoding, data));
+        }
+    }
+
+    runs
+}
+
+fn decode_hex(run: &str) -> Option<Vec<u8>> {
+    if !run.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..run.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&run[i..i + 2], 16).ok())
+        .collect()
+}
+
+// standard alphabet, padding optional
+fn decode_base64(run: &str) -> Option<Vec<u8>> {
+    let run = run.trim_end_matches('=');
+    if run.len() % 4 == 1 {
+        return None;
+    }
+
+    let sextet = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+
+    let mut data = Vec::with_capacity(run.len() * 3 / 4);
+    for chunk in run.as_bytes().chunks(4) {
+        let mut bits = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            bits |= (sextet(*c)? as u32) << (18 - 6 * i);
+        }
+        let bytes = bits.to_be_bytes();
+        data.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Some(data)
+}
@@ -0,0 +1,147 @@
+// Permission anomalies on keys commonly abused for persistence or privilege escalation
+//
+// a key under Services, Run or Image File Execution Options which can be modified by a non-admin
+// account allows to run code as another user (or as SYSTEM), so its DACL and owner are checked.
+//
+use std::collections::{HashMap, HashSet, hash_map::Entry};
+
+use crate::{
+    findings::{Finding, Severity},
+    hive::RegistryHive,
+    security::{
+        ACCESS_ALLOWED_ACE_TYPE, KEY_WRITE_RIGHTS, SecurityDescriptor, Sid, rights_to_names,
+    },
+};
+
+// sensitive locations in SYSTEM, SOFTWARE and NTUSER.DAT hives
+const SENSITIVE_LOCATIONS: &[&str] = &[
+    "ControlSet*\\Services",
+    "Microsoft\\Windows\\CurrentVersion\\Run*",
+    "Microsoft\\Windows\\CurrentVersion\\Policies\\Explorer\\Run",
+    "Microsoft\\Windows NT\\CurrentVersion\\Image File Execution Options",
+    "Microsoft\\Windows NT\\CurrentVersion\\Winlogon",
+    "Wow6432Node\\Microsoft\\Windows\\CurrentVersion\\Run*",
+    "Wow6432Node\\Microsoft\\Windows NT\\CurrentVersion\\Image File Execution Options",
+    "Software\\Microsoft\\Windows\\CurrentVersion\\Run*",
+    "Software\\Microsoft\\Windows NT\\CurrentVersion\\Winlogon",
+];
+
+// groups any user belongs to
+const BROAD_GROUPS: &[&str] = &[
+    "S-1-1-0",      // Everyone
+    "S-1-2-0",      // LOCAL
+    "S-1-5-2",      // NETWORK
+    "S-1-5-4",      // INTERACTIVE
+    "S-1-5-7",      // ANONYMOUS LOGON
+    "S-1-5-11",     // Authenticated Users
+    "S-1-5-32-545", // Users
+    "S-1-5-32-546", // Guests
+    "S-1-15-2-1",   // ALL APPLICATION PACKAGES
+];
+
+// SYSTEM, Administrators, CREATOR OWNER and service SIDs (including TrustedInstaller)
+fn is_privileged(sid: &Sid) -> bool {
+    let s = sid.to_string();
+    matches!(s.as_str(), "S-1-5-18" | "S-1-5-32-544" | "S-1-3-0") || s.starts_with("S-1-5-80-")
+}
+
+fn display_sid(sid: &Sid) -> String {
+    match sid.account_name() {
+        Some(name) => format!("{name} ({sid})"),
+        None => sid.to_string(),
+    }
+}
+
+// issues found in a security descriptor, independently of the key
+fn check_descriptor(sd: &SecurityDescriptor) -> Vec<(Severity, String)> {
+    let mut issues = Vec::new();
+
+    let Some(dacl) = &sd.dacl else {
+        issues.push((
+            Severity::High,
+            "no DACL: full access granted to everyone".to_string(),
+        ));
+        return issues;
+    };
+
+    let mut reported = HashSet::new();
+    for ace in dacl.aces.iter().filter(|a| a.applies_to_key()) {
+        let Some(sid) = &ace.sid else {
+            continue;
+        };
+        if ace.ace_type != ACCESS_ALLOWED_ACE_TYPE || is_privileged(sid) || !reported.insert(sid) {
+            continue;
+        }
+
+        // deny ACEs are taken into account
+        let rights = sd.granted_rights(sid) & KEY_WRITE_RIGHTS;
+        if rights == 0 {
+            continue;
+        }
+        let severity = if BROAD_GROUPS.contains(&sid.to_string().as_str()) {
+            Severity::High
+        } else {
+            Severity::Medium
+        };
+        issues.push((
+            severity,
+            format!(
+                "writable by {}: {}",
+                display_sid(sid),
+                rights_to_names(rights)
+            ),
+        ));
+    }
+
+    if let Some(owner) = &sd.owner
+        && !is_privileged(owner)
+    {
+        issues.push((
+            Severity::Medium,
+            format!("unexpected owner {}", display_sid(owner)),
+        ));
+    }
+
+    issues
+}
+
+pub fn permission_findings(hive: &mut RegistryHive) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    // security descriptors are shared, so check each of them once
+    let mut issues: HashMap<u32, Vec<(Severity, String)>> = HashMap::new();
+
+    // locations may overlap (Run*)
+    let mut visited = HashSet::new();
+
+    for location in SENSITIVE_LOCATIONS {
+        for (path, start) in hive.find_keys(location)? {
+            hive.walk(&start, &path, &mut |hive, path, key| {
+                if !visited.insert(key.offset) {
+                    return Ok(());
+                }
+
+                let key_issues = match issues.entry(key.node.header.key_security_offset) {
+                    Entry::Occupied(e) => e.into_mut(),
+                    Entry::Vacant(e) => {
+                        let security = hive.security(key)?;
+                        let sd = SecurityDescriptor::try_from(security.descriptor.as_slice())?;
+                        e.insert(check_descriptor(&sd))
+                    }
+                };
+
+                for (severity, description) in key_issues.iter() {
+                    findings.push(Finding::new(
+                        *severity,
+                        "permissions",
+                        path,
+                        description.clone(),
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    Ok(findings)
+}
@@ -0,0 +1,195 @@
+// Artifact plugins: analysis passes run on the hives they apply to
+//
+// a plugin tells which hive types and keys it's about and reports findings. Built-in plugins wrap the
+// artifact parsers and the analysis passes, other ones are added to a registry with register().
+//
+use std::{fmt, path::Path, str::FromStr};
+
+use crate::{
+    findings::Finding,
+    hive::{RegistryHive, names_equal},
+};
+
+pub mod builtin;
+pub mod regripper;
+
+pub type PluginHive = RegistryHive;
+
+pub fn open_plugin_hive(path: &Path) -> anyhow::Result<PluginHive> {
+    RegistryHive::open(path)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HiveType {
+    System,
+    Software,
+    Sam,
+    Security,
+    NtUser,
+    UsrClass,
+    Unknown,
+}
+
+impl HiveType {
+    // guessed from the keys found at the root of the hive
+    pub fn detect(hive: &mut RegistryHive) -> anyhow::Result<Self> {
+        let root = hive.root()?;
+        let names: Vec<String> = hive
+            .subkeys(&root)?
+            .iter()
+            .map(|k| k.name().to_string())
+            .collect();
+        let has = |name: &str| names.iter().any(|n| names_equal(n, name));
+
+        Ok(if has("SAM") {
+            HiveType::Sam
+        } else if has("Policy") && has("RXACT") {
+            HiveType::Security
+        } else if has("Select") || names.iter().any(|n| n.starts_with("ControlSet")) {
+            HiveType::System
+        } else if has("Software") && (has("Environment") || has("Control Panel")) {
+            HiveType::NtUser
+        } else if has("Local Settings") {
+            HiveType::UsrClass
+        } else if has("Microsoft") {
+            HiveType::Software
+        } else {
+            HiveType::Unknown
+        })
+    }
+}
+
+impl fmt::Display for HiveType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            HiveType::System => "SYSTEM",
+            HiveType::Software => "SOFTWARE",
+            HiveType::Sam => "SAM",
+            HiveType::Security => "SECURITY",
+            HiveType::NtUser => "NTUSER.DAT",
+            HiveType::UsrClass => "UsrClass.dat",
+            HiveType::Unknown => "unknown",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// how findings of plugins are printed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Text,
+    // layout of RegRipper plugin reports
+    RegRipper,
+}
+
+impl FromStr for ReportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(ReportFormat::Text),
+            "regripper" => Ok(ReportFormat::RegRipper),
+            _ => anyhow::bail!("unknown report format '{s}' (text or regripper)"),
+        }
+    }
+}
+
+impl fmt::Display for ReportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportFormat::Text => write!(f, "text"),
+            ReportFormat::RegRipper => write!(f, "regripper"),
+        }
+    }
+}
+
+pub trait RegPlugin {
+    fn name(&self) -> &str;
+    fn description(&self) -> &str;
+
+    // hive types the plugin is written for, all when empty
+    fn hive_types(&self) -> &[HiveType];
+
+    // keys the plugin reads ('*' and '?' wildcards), it's only run when one of them is found. Runs on
+    // all hives when empty.
+    fn key_globs(&self) -> &[&str];
+
+    fn run(&self, hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>>;
+}
+
+pub struct PluginRegistry {
+    plugins: Vec<Box<dyn RegPlugin>>,
+}
+
+// with the built-in plugins
+impl Default for PluginRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        for plugin in builtin::BUILTINS {
+            registry.register(Box::new(plugin.clone()));
+        }
+        registry
+    }
+}
+
+impl PluginRegistry {
+    pub fn empty() -> Self {
+        Self {
+            plugins: Vec::new(),
+        }
+    }
+
+    // a plugin with the name of a registered one replaces it
+    pub fn register(&mut self, plugin: Box<dyn RegPlugin>) {
+        self.plugins.retain(|p| p.name() != plugin.name());
+        self.plugins.push(plugin);
+    }
+
+    pub fn plugins(&self) -> &[Box<dyn RegPlugin>] {
+        &self.plugins
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn RegPlugin> {
+        self.plugins
+            .iter()
+            .find(|p| p.name().eq_ignore_ascii_case(name))
+            .map(|p| p.as_ref())
+    }
+
+    // plugins for this type of hive whose keys are found. The type of hives which can't be detected
+    // isn't checked.
+    pub fn applicable(&self, hive: &mut PluginHive) -> anyhow::Result<Vec<&dyn RegPlugin>> {
+        let hive_type = HiveType::detect(hive)?;
+        let mut applicable = Vec::new();
+
+        for plugin in &self.plugins {
+            if hive_type != HiveType::Unknown
+                && !plugin.hive_types().is_empty()
+                && !plugin.hive_types().contains(&hive_type)
+            {
+                continue;
+            }
+
+            let mut found = plugin.key_globs().is_empty();
+            for glob in plugin.key_globs() {
+                if !hive.find_keys(glob)?.is_empty() {
+                    found = true;
+                    break;
+                }
+            }
+            if found {
+                applicable.push(plugin.as_ref());
+            }
+        }
+
+        Ok(applicable)
+    }
+}
+
+// findings of each plugin, in the order they're given
+pub fn run_plugins<'a>(
+    plugins: &[&'a dyn RegPlugin],
+    hive: &mut PluginHive,
+) -> Vec<(&'a dyn RegPlugin, anyhow::Result<Vec<Finding>>)> {
+    plugins.iter().map(|p| (*p, p.run(hive))).collect()
+}
@@ -0,0 +1,452 @@
+// Built-in plugins: the artifact parsers and analysis passes, their records reported as findings
+//
+// records are informational, suspicious ones get a higher severity.
+//
+use crate::{
+    anomalies::anomaly_findings,
+    artifacts::{
+        autoruns::autoruns,
+        bam::bam_entries,
+        layers::layers,
+        mru::mru_lists,
+        muicache::muicache,
+        network::{interfaces, profiles},
+        sam::sam_users,
+        services::services,
+        shellbags::shellbags,
+        shimcache::shimcaches,
+        usb::usb_devices,
+        userassist::userassist,
+    },
+    findings::{Finding, Severity},
+    permissions::permission_findings,
+    plugins::{HiveType, PluginHive, RegPlugin},
+    utils::filetime_to_string,
+};
+
+const USERASSIST_KEY: &str = "\\Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\UserAssist";
+
+// services last written less than this number of days before the hive are reported
+const RECENT_DAYS: u64 = 30;
+
+#[derive(Clone)]
+pub struct Builtin {
+    name: &'static str,
+    description: &'static str,
+    hive_types: &'static [HiveType],
+    key_globs: &'static [&'static str],
+    run: fn(&mut PluginHive) -> anyhow::Result<Vec<Finding>>,
+}
+
+impl RegPlugin for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn description(&self) -> &str {
+        self.description
+    }
+
+    fn hive_types(&self) -> &[HiveType] {
+        self.hive_types
+    }
+
+    fn key_globs(&self) -> &[&str] {
+        self.key_globs
+    }
+
+    fn run(&self, hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+        (self.run)(hive)
+    }
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "anomalies",
+        description: "suspicious names, value data and timestamps",
+        hive_types: &[],
+        key_globs: &[],
+        run: anomaly_findings,
+    },
+    Builtin {
+        name: "permissions",
+        description: "keys writable by unprivileged users and unusual security descriptors",
+        hive_types: &[],
+        key_globs: &[],
+        run: permission_findings,
+    },
+    Builtin {
+        name: "autoruns",
+        description: "programs started at boot, at logon or with other programs",
+        hive_types: &[HiveType::Software, HiveType::NtUser, HiveType::System],
+        key_globs: &[
+            "Microsoft\\Windows\\CurrentVersion",
+            "Microsoft\\Windows NT\\CurrentVersion",
+            "Software\\Microsoft\\Windows\\CurrentVersion",
+            "ControlSet*\\Services",
+        ],
+        run: autoruns_plugin,
+    },
+    Builtin {
+        name: "services",
+        description: "services and drivers recently modified or in unusual locations",
+        hive_types: &[HiveType::System],
+        key_globs: &["ControlSet*\\Services"],
+        run: services_plugin,
+    },
+    Builtin {
+        name: "shimcache",
+        description: "programs of the ShimCache (AppCompatCache)",
+        hive_types: &[HiveType::System],
+        key_globs: &[
+            "ControlSet*\\Control\\Session Manager\\AppCompatCache",
+            "ControlSet*\\Control\\Session Manager\\AppCompatibility",
+        ],
+        run: shimcache_plugin,
+    },
+    Builtin {
+        name: "usb",
+        description: "USB storage devices",
+        hive_types: &[HiveType::System],
+        key_globs: &["ControlSet*\\Enum\\USBSTOR"],
+        run: usb_plugin,
+    },
+    Builtin {
+        name: "network",
+        description: "network interfaces and the networks connected to",
+        hive_types: &[HiveType::System, HiveType::Software],
+        key_globs: &[
+            "ControlSet*\\Services\\Tcpip\\Parameters\\Interfaces",
+            "Microsoft\\Windows NT\\CurrentVersion\\NetworkList\\Profiles",
+        ],
+        run: network_plugin,
+    },
+    Builtin {
+        name: "sam",
+        description: "local user accounts",
+        hive_types: &[HiveType::Sam],
+        key_globs: &["SAM\\Domains\\Account\\Users"],
+        run: sam_plugin,
+    },
+    Builtin {
+        name: "userassist",
+        description: "programs launched from the Explorer shell",
+        hive_types: &[HiveType::NtUser],
+        key_globs: &["Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\UserAssist"],
+        run: userassist_plugin,
+    },
+    Builtin {
+        name: "shellbags",
+        description: "folders browsed with Explorer",
+        hive_types: &[HiveType::NtUser, HiveType::UsrClass],
+        key_globs: &[
+            "Software\\Microsoft\\Windows\\Shell*",
+            "Local Settings\\Software\\Microsoft\\Windows\\Shell*",
+        ],
+        run: shellbags_plugin,
+    },
+    Builtin {
+        name: "mru",
+        description: "recently used files, commands and typed URLs",
+        hive_types: &[HiveType::NtUser],
+        key_globs: &[
+            "Software\\Microsoft\\Windows\\CurrentVersion\\Explorer",
+            "Software\\Microsoft\\Internet Explorer\\TypedURLs",
+        ],
+        run: mru_plugin,
+    },
+    Builtin {
+        name: "muicache",
+        description: "display names of the programs run by the user",
+        hive_types: &[HiveType::NtUser, HiveType::UsrClass],
+        key_globs: &[
+            "Software\\Microsoft\\Windows\\ShellNoRoam\\MUICache",
+            "Local Settings\\Software\\Microsoft\\Windows\\Shell\\MuiCache",
+        ],
+        run: muicache_plugin,
+    },
+    Builtin {
+        name: "layers",
+        description: "compatibility modes set on programs (AppCompatFlags\\Layers)",
+        hive_types: &[HiveType::NtUser, HiveType::Software],
+        key_globs: &[
+            "Software\\Microsoft\\Windows NT\\CurrentVersion\\AppCompatFlags\\Layers",
+            "Microsoft\\Windows NT\\CurrentVersion\\AppCompatFlags\\Layers",
+        ],
+        run: layers_plugin,
+    },
+    Builtin {
+        name: "bam",
+        description: "last execution of programs by user (BAM and DAM)",
+        hive_types: &[HiveType::System],
+        key_globs: &["ControlSet*\\Services\\bam", "ControlSet*\\Services\\dam"],
+        run: bam_plugin,
+    },
+];
+
+fn time(t: u64) -> String {
+    if t == 0 {
+        "-".to_string()
+    } else {
+        filetime_to_string(t)
+    }
+}
+
+fn autoruns_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    Ok(autoruns(hive)?
+        .into_iter()
+        .map(|a| {
+            let (severity, reason) = match a.suspicious {
+                Some(reason) => (Severity::Medium, format!(" ({reason})")),
+                None => (Severity::Info, String::new()),
+            };
+            Finding::new(
+                severity,
+                "autoruns",
+                &a.key_path,
+                format!("{} {}: {}{reason}", a.location, a.value_name, a.command),
+            )
+        })
+        .collect())
+}
+
+fn services_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    for s in services(hive, RECENT_DAYS)? {
+        let image = s.service_dll.as_deref().or(s.image_path.as_deref());
+        if let Some(reason) = s.suspicious {
+            findings.push(Finding::new(
+                Severity::Medium,
+                "services",
+                &s.path,
+                format!("{} {}: {reason}", s.start_name(), image.unwrap_or("-")),
+            ));
+        } else if s.recent {
+            findings.push(Finding::new(
+                Severity::Low,
+                "services",
+                &s.path,
+                format!(
+                    "{} {}: last written {}",
+                    s.start_name(),
+                    image.unwrap_or("-"),
+                    filetime_to_string(s.timestamp)
+                ),
+            ));
+        }
+    }
+    Ok(findings)
+}
+
+fn shimcache_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    for cache in shimcaches(hive)? {
+        for entry in &cache.entries {
+            let executed = match entry.executed {
+                Some(true) => ", executed",
+                _ => "",
+            };
+            findings.push(Finding::new(
+                Severity::Info,
+                "shimcache",
+                &cache.key_path,
+                format!(
+                    "{} {}: modified {}{executed}",
+                    entry.position,
+                    entry.path,
+                    time(entry.modified)
+                ),
+            ));
+        }
+    }
+    Ok(findings)
+}
+
+fn usb_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    Ok(usb_devices(hive, None::<&mut PluginHive>)?
+        .into_iter()
+        .map(|d| {
+            Finding::new(
+                Severity::Info,
+                "usb",
+                &d.key_path,
+                format!(
+                    "{} {} serial {}: first install {}, last arrival {}, last removal {}, drive letters {}",
+                    d.vendor,
+                    d.product,
+                    d.serial,
+                    time(d.first_install),
+                    time(d.last_arrival),
+                    time(d.last_removal),
+                    d.drive_letters.join(" ")
+                ),
+            )
+        })
+        .collect())
+}
+
+fn network_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    for i in interfaces(hive)? {
+        findings.push(Finding::new(
+            Severity::Info,
+            "network",
+            &i.key_path,
+            format!(
+                "{} {}, gateways {}, DNS servers {}",
+                if i.dhcp { "DHCP" } else { "static" },
+                i.ip_addresses.join(" "),
+                i.gateways.join(" "),
+                i.dns_servers.join(" ")
+            ),
+        ));
+    }
+    for p in profiles(hive)? {
+        findings.push(Finding::new(
+            Severity::Info,
+            "network",
+            &p.guid,
+            format!(
+                "{} ({} {}): created {}, last connected {}",
+                p.name,
+                p.kind(),
+                p.category_name(),
+                time(p.created),
+                time(p.last_connected)
+            ),
+        ));
+    }
+    Ok(findings)
+}
+
+fn sam_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    Ok(sam_users(hive)?
+        .into_iter()
+        .map(|u| {
+            Finding::new(
+                Severity::Info,
+                "sam",
+                &format!("\\SAM\\Domains\\Account\\Users\\{:08X}", u.rid),
+                format!(
+                    "{} ({}): last logon {}, logon count {}",
+                    u.name,
+                    u.flag_names().join(", "),
+                    time(u.last_logon),
+                    u.logon_count
+                ),
+            )
+        })
+        .collect())
+}
+
+fn userassist_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    Ok(userassist(hive)?
+        .into_iter()
+        .map(|e| {
+            Finding::new(
+                Severity::Info,
+                "userassist",
+                &format!("{USERASSIST_KEY}\\{}\\Count", e.guid),
+                format!(
+                    "{}: run {} times, last executed {}",
+                    e.name,
+                    e.run_count,
+                    time(e.last_executed)
+                ),
+            )
+        })
+        .collect())
+}
+
+fn shellbags_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    Ok(shellbags(hive)?
+        .into_iter()
+        .map(|b| {
+            Finding::new(
+                Severity::Info,
+                "shellbags",
+                &format!("{}\\{}", b.key_path, b.value_name),
+                format!("{}: key last written {}", b.path, time(b.key_timestamp)),
+            )
+        })
+        .collect())
+}
+
+fn mru_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    Ok(mru_lists(hive)?
+        .into_iter()
+        .map(|e| {
+            Finding::new(
+                Severity::Info,
+                "mru",
+                &e.key_path,
+                format!(
+                    "{} {} {}: {}, last used {}",
+                    e.kind,
+                    e.position,
+                    e.value_name,
+                    e.item,
+                    time(e.timestamp)
+                ),
+            )
+        })
+        .collect())
+}
+
+fn muicache_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    Ok(muicache(hive)?
+        .into_iter()
+        .map(|e| {
+            Finding::new(
+                Severity::Info,
+                "muicache",
+                &e.key_path,
+                format!(
+                    "{}: {}, key last written {}",
+                    e.program,
+                    e.friendly_name.as_deref().unwrap_or("-"),
+                    time(e.key_timestamp)
+                ),
+            )
+        })
+        .collect())
+}
+
+// programs elevated each time they're started are worth a look
+fn layers_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    Ok(layers(hive)?
+        .into_iter()
+        .map(|e| {
+            let severity = if e.runs_as_admin() {
+                Severity::Low
+            } else {
+                Severity::Info
+            };
+            Finding::new(
+                severity,
+                "layers",
+                &e.key_path,
+                format!(
+                    "{}: {}, key last written {}",
+                    e.program,
+                    e.layers.join(" "),
+                    time(e.key_timestamp)
+                ),
+            )
+        })
+        .collect())
+}
+
+fn bam_plugin(hive: &mut PluginHive) -> anyhow::Result<Vec<Finding>> {
+    Ok(bam_entries(hive)?
+        .into_iter()
+        .map(|e| {
+            Finding::new(
+                Severity::Info,
+                e.source,
+                &e.key_path,
+                format!("{}: last executed {}", e.program, time(e.last_executed)),
+            )
+        })
+        .collect())
+}
@@ -0,0 +1,86 @@
+// Plugin reports laid out like the ones of RegRipper, for reviews based on its output
+//
+// each plugin starts with its name and version, the hive types it's written for and its description.
+// Findings follow, grouped by key with the last written time of the key, and a line of dashes ends
+// the report:
+//
+// autoruns v.0.1.0
+// (SOFTWARE,NTUSER.DAT,SYSTEM) programs started at boot, at logon or with other programs
+//
+// Microsoft\Windows\CurrentVersion\Run
+// LastWrite Time 2022-06-18 04:26:40Z
+//   Run SecurityHealth: %windir%\system32\SecurityHealthSystray.exe
+//
+// ----------------------------------------
+//
+use std::io::Write;
+
+use crate::{
+    findings::{Finding, Severity},
+    plugins::{PluginHive, RegPlugin},
+    utils::filetime_to_string,
+};
+
+const SEPARATOR: &str = "----------------------------------------";
+
+pub fn write_regripper<W: Write>(
+    w: &mut W,
+    hive: &mut PluginHive,
+    plugin: &dyn RegPlugin,
+    result: &anyhow::Result<Vec<Finding>>,
+) -> anyhow::Result<()> {
+    let hive_types: Vec<_> = plugin.hive_types().iter().map(|t| t.to_string()).collect();
+    writeln!(w, "{} v.{}", plugin.name(), env!("CARGO_PKG_VERSION"))?;
+    writeln!(
+        w,
+        "({}) {}",
+        if hive_types.is_empty() {
+            "All".to_string()
+        } else {
+            hive_types.join(",")
+        },
+        plugin.description()
+    )?;
+    writeln!(w)?;
+
+    match result {
+        Err(e) => writeln!(w, "{e:#}")?,
+        Ok(findings) if findings.is_empty() => writeln!(w, "No findings.")?,
+        Ok(findings) => {
+            // keys in the order of their first finding
+            let mut paths: Vec<&str> = Vec::new();
+            for finding in findings {
+                if !paths.contains(&finding.path.as_str()) {
+                    paths.push(&finding.path);
+                }
+            }
+
+            for path in paths {
+                // paths relative to the root as RegRipper does, the root itself is kept
+                match path.trim_start_matches('\\') {
+                    "" => writeln!(w, "{path}")?,
+                    relative => writeln!(w, "{relative}")?,
+                }
+                // paths of some findings aren't keys
+                if let Some(key) = hive.open_key(path)? {
+                    writeln!(
+                        w,
+                        "LastWrite Time {}Z",
+                        filetime_to_string(key.node.header.last_written_timestamp)
+                    )?;
+                }
+                for finding in findings.iter().filter(|f| f.path == path) {
+                    if finding.severity == Severity::Info {
+                        writeln!(w, "  {}", finding.description)?;
+                    } else {
+                        writeln!(w, "  [{}] {}", finding.severity, finding.description)?;
+                    }
+                }
+                writeln!(w)?;
+            }
+        }
+    }
+
+    writeln!(w, "{SEPARATOR}")?;
+    Ok(())
+}
@@ -0,0 +1,262 @@
+// Cells reachable from the root key
+//
+// every record referenced while walking the keys tree is marked with the key owning it and the number
+// of bytes actually used by the record: bytes after that are slack space. Allocated cells which are
+// not reachable are orphans: corruption, tampering or data hidden by unlinking it.
+//
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    hive::{Key, RegistryHive},
+    key::{KEY_NODE_HEADER_SIZE, KeyNode, NO_OFFSET},
+    list::{SubkeysList, key_values_list},
+    security::KEY_SECURITY_HEADER_SIZE,
+    value::{BIG_DATA_SEGMENT_SIZE, KEY_VALUE_HEADER_SIZE, KeyValue, hex_string},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellKind {
+    Key,
+    SubkeysList,
+    ValuesList,
+    Value,
+    Data,
+    BigData,
+    SegmentsList,
+    Security,
+    ClassName,
+}
+
+impl fmt::Display for CellKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CellKind::Key => write!(f, "key node"),
+            CellKind::SubkeysList => write!(f, "subkeys list"),
+            CellKind::ValuesList => write!(f, "values list"),
+            CellKind::Value => write!(f, "key value"),
+            CellKind::Data => write!(f, "value data"),
+            CellKind::BigData => write!(f, "big data"),
+            CellKind::SegmentsList => write!(f, "segments list"),
+            CellKind::Security => write!(f, "key security"),
+            CellKind::ClassName => write!(f, "class name"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CellUse {
+    pub kind: CellKind,
+
+    // path of the key owning the cell (first key referencing it for shared security cells)
+    pub path: String,
+
+    // number of bytes used in the cell content (after the size field)
+    pub used: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct OrphanCell {
+    pub offset: u32,
+    pub size: u32,
+
+    // "nk", "vk", ... or "data"
+    pub cell_type: String,
+
+    // name of a key or value record, first bytes of other cells
+    pub preview: String,
+}
+
+impl fmt::Display for OrphanCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:X}\t{}\t{}\t{}",
+            self.offset, self.size, self.cell_type, self.preview
+        )
+    }
+}
+
+pub fn reachable_cells(hive: &mut RegistryHive) -> anyhow::Result<HashMap<u32, CellUse>> {
+    let mut cells = HashMap::new();
+
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |hive, path, key| {
+        mark_key(hive, path, key, &mut cells)
+    })?;
+
+    Ok(cells)
+}
+
+fn mark(cells: &mut HashMap<u32, CellUse>, offset: u32, kind: CellKind, path: &str, used: usize) {
+    cells.entry(offset).or_insert_with(|| CellUse {
+        kind,
+        path: path.to_string(),
+        used,
+    });
+}
+
+fn mark_key(
+    hive: &mut RegistryHive,
+    path: &str,
+    key: &Key,
+    cells: &mut HashMap<u32, CellUse>,
+) -> anyhow::Result<()> {
+    let h = &key.node.header;
+    mark(
+        cells,
+        key.offset,
+        CellKind::Key,
+        path,
+        KEY_NODE_HEADER_SIZE + h.key_name_length as usize,
+    );
+
+    if h.number_of_subkeys > 0 {
+        mark_subkeys_list(hive, h.subkeys_list_offset, path, cells, 0)?;
+    }
+
+    if h.key_security_offset != NO_OFFSET && !cells.contains_key(&h.key_security_offset) {
+        let security = hive.security(key)?;
+        mark(
+            cells,
+            h.key_security_offset,
+            CellKind::Security,
+            path,
+            KEY_SECURITY_HEADER_SIZE + security.descriptor.len(),
+        );
+    }
+
+    if h.class_name_offset != NO_OFFSET && h.class_name_length > 0 {
+        mark(
+            cells,
+            h.class_name_offset,
+            CellKind::ClassName,
+            path,
+            h.class_name_length as usize,
+        );
+    }
+
+    let count = h.number_of_key_values as usize;
+    if count > 0 {
+        mark(
+            cells,
+            h.key_values_list_offset,
+            CellKind::ValuesList,
+            path,
+            count * 4,
+        );
+        let (_, list) = hive.cell(h.key_values_list_offset)?;
+        for offset in key_values_list(list, count)? {
+            let (_, data) = hive.cell(offset)?;
+            let value = KeyValue::try_from(data)?;
+            mark(
+                cells,
+                offset,
+                CellKind::Value,
+                path,
+                KEY_VALUE_HEADER_SIZE + value.header.name_length as usize,
+            );
+            mark_value_data(hive, path, &value, cells)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn mark_subkeys_list(
+    hive: &mut RegistryHive,
+    offset: u32,
+    path: &str,
+    cells: &mut HashMap<u32, CellUse>,
+    depth: usize,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(depth < 2, "nested index roots at 0x{offset:X}");
+
+    let (_, data) = hive.cell(offset)?;
+    let list = SubkeysList::try_from(data)?;
+    let element_size = match list {
+        SubkeysList::FastLeaf(_) | SubkeysList::HashLeaf(_) => 8,
+        _ => 4,
+    };
+    let offsets = list.offsets();
+    mark(
+        cells,
+        offset,
+        CellKind::SubkeysList,
+        path,
+        4 + offsets.len() * element_size,
+    );
+
+    if let SubkeysList::IndexRoot(lists) = list {
+        for list in lists {
+            mark_subkeys_list(hive, list, path, cells, depth + 1)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn mark_value_data(
+    hive: &mut RegistryHive,
+    path: &str,
+    value: &KeyValue,
+    cells: &mut HashMap<u32, CellUse>,
+) -> anyhow::Result<()> {
+    let size = value.data_size() as usize;
+    if value.is_resident() || size == 0 {
+        return Ok(());
+    }
+
+    let offset = value.header.data_offset;
+    let (_, cell) = hive.cell(offset)?;
+    if size > BIG_DATA_SEGMENT_SIZE && hive.base_block.minor_version > 3 && cell.starts_with(b"db")
+    {
+        mark(cells, offset, CellKind::BigData, path, 8);
+
+        let count = u16::from_le_bytes([cell[2], cell[3]]) as usize;
+        let list_offset = u32::from_le_bytes(cell[4..8].try_into()?);
+        mark(cells, list_offset, CellKind::SegmentsList, path, count * 4);
+
+        let (_, segments) = hive.cell(list_offset)?;
+        let mut remaining = size;
+        for segment in key_values_list(segments, count)? {
+            let used = remaining.min(BIG_DATA_SEGMENT_SIZE);
+            mark(cells, segment, CellKind::Data, path, used);
+            remaining -= used;
+        }
+    } else {
+        mark(cells, offset, CellKind::Data, path, size.min(cell.len()));
+    }
+
+    Ok(())
+}
+
+// allocated cells not reachable from the root key
+pub fn orphan_cells(hive: &mut RegistryHive) -> anyhow::Result<Vec<OrphanCell>> {
+    let reachable = reachable_cells(hive)?;
+    let index = hive.index()?;
+
+    let mut orphans = Vec::new();
+    for cell in index.cells() {
+        if cell.is_free() || reachable.contains_key(&cell.offset) {
+            continue;
+        }
+
+        let content = index.content(cell);
+        let preview = match &content[..2] {
+            b"nk" => KeyNode::try_from(content).map(|k| k.name).ok(),
+            b"vk" => KeyValue::try_from(content)
+                .map(|v| v.display_name().to_string())
+                .ok(),
+            _ => None,
+        };
+
+        orphans.push(OrphanCell {
+            offset: cell.offset,
+            size: cell.size.unsigned_abs(),
+            cell_type: index.cell_type(cell).to_string(),
+            preview: preview.unwrap_or_else(|| hex_string(&content[..content.len().min(16)])),
+        });
+    }
+
+    Ok(orphans)
+}
@@ -0,0 +1,79 @@
+// A value of the hive together with the key owning it: the unit of all exports
+
+use crate::{
+    hive::{Key, RegistryHive},
+    value::{RegData, RegType},
+};
+
+#[derive(Debug, Clone)]
+pub struct Record {
+    // path of the owning key
+    pub path: String,
+
+    // last written timestamp of the owning key (FILETIME)
+    pub timestamp: u64,
+
+    // empty for the default value
+    pub value_name: String,
+    pub reg_type: RegType,
+
+    // raw value data
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    // last component of the key path
+    pub fn key_name(&self) -> &str {
+        match self.path.rsplit_once('\\') {
+            Some((_, name)) if !name.is_empty() => name,
+            _ => "\\",
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        if self.value_name.is_empty() {
+            "(default)"
+        } else {
+            &self.value_name
+        }
+    }
+
+    pub fn data(&self) -> RegData {
+        RegData::new(self.reg_type, &self.data)
+    }
+}
+
+// values of a key
+pub fn key_records(hive: &RegistryHive, path: &str, key: &Key) -> anyhow::Result<Vec<Record>> {
+    let mut records = Vec::new();
+
+    for value in hive.values(key)? {
+        records.push(Record {
+            path: path.to_string(),
+            timestamp: key.node.header.last_written_timestamp,
+            value_name: value.name.clone(),
+            reg_type: value.reg_type(),
+            data: hive.value_bytes(&value)?.into_owned(),
+        });
+    }
+
+    Ok(records)
+}
+
+// call f for each value of the subtree starting at key
+pub fn for_each_record<F>(
+    hive: &mut RegistryHive,
+    key: &Key,
+    path: &str,
+    f: &mut F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(&Record) -> anyhow::Result<()>,
+{
+    hive.walk(key, path, &mut |hive, path, key| {
+        for record in key_records(hive, path, key)? {
+            f(&record)?;
+        }
+        Ok(())
+    })
+}
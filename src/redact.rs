@@ -0,0 +1,396 @@
+// Redaction of a keys tree, to share hives in bug reports or as samples without personal data
+//
+// the configured strings (user names, machine names...) are replaced case-insensitively in key names,
+// value names, class names and value data. String data is rewritten with the replacement; in other
+// data, UTF-16LE and ASCII occurrences are overwritten by as many 'X' so that its layout is kept.
+// All the strings are replaced in a single pass, the longest first: a user name found in a machine
+// name doesn't keep the machine name from being replaced, and replacements are never matched again.
+// Domain SIDs (S-1-5-21-...) are replaced by made-up ones keeping the RID, both as strings and in
+// binary form, in value data and security descriptors: the same domain is always given the same SID.
+// The data of the configured values is zeroed, keeping its size.
+//
+use std::{cmp::Reverse, collections::HashMap};
+
+use crate::{
+    hive::{join_path, name_sort_key},
+    list::upcase_unit,
+    tree::TreeKey,
+    utils::glob_match,
+    value::RegType,
+};
+
+const DOMAIN_SID_PREFIX: &str = "S-1-5-21-";
+
+// binary SID start: revision, sub authority count (checked apart), NT authority, 21
+const DOMAIN_SID_AUTHORITY: [u8; 10] = [0, 0, 0, 0, 0, 5, 21, 0, 0, 0];
+
+#[derive(Debug, Default)]
+pub struct Redaction {
+    // uppercase UTF-16 string to hide and its replacement, the longest strings first
+    replacements: Vec<(Vec<u16>, Vec<u16>)>,
+
+    // KEY\VALUE patterns, relative to the root key
+    values: Vec<String>,
+
+    pub keep_sids: bool,
+
+    // replacements made up so far
+    users: usize,
+    machines: usize,
+
+    // made-up domain identifier of each domain found
+    domains: HashMap<[u32; 3], [u32; 3]>,
+}
+
+// number of items modified
+#[derive(Debug, Default)]
+pub struct RedactionReport {
+    pub names: usize,
+    pub data: usize,
+    pub descriptors: usize,
+    pub wiped: usize,
+}
+
+impl Redaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn replace(&mut self, text: &str, replacement: &str) {
+        if !text.is_empty() {
+            self.replacements
+                .push((name_sort_key(text), replacement.encode_utf16().collect()));
+            self.replacements
+                .sort_by_key(|(text, _)| Reverse(text.len()));
+        }
+    }
+
+    // user names become user1, user2...
+    pub fn user(&mut self, name: &str) {
+        self.users += 1;
+        self.replace(name, &format!("user{}", self.users));
+    }
+
+    // machine names become MACHINE1, MACHINE2...
+    pub fn machine(&mut self, name: &str) {
+        self.machines += 1;
+        self.replace(name, &format!("MACHINE{}", self.machines));
+    }
+
+    // the data of the values matching KEY\VALUE, with wildcards, is zeroed
+    pub fn wipe_value(&mut self, pattern: &str) {
+        self.values
+            .push(pattern.trim_start_matches('\\').to_string());
+    }
+
+    // the root key name is kept
+    pub fn apply(&mut self, root: &mut TreeKey) -> anyhow::Result<RedactionReport> {
+        let mut report = RedactionReport::default();
+        self.redact_key(root, "\\", &mut report)?;
+        Ok(report)
+    }
+
+    // path is the one of the key before redaction, the one matched by value patterns
+    fn redact_key(
+        &mut self,
+        key: &mut TreeKey,
+        path: &str,
+        report: &mut RedactionReport,
+    ) -> anyhow::Result<()> {
+        if let Some(class_name) = self.redact_string_bytes(&key.class_name) {
+            key.class_name = class_name;
+            report.data += 1;
+        }
+        if !self.keep_sids && self.redact_binary_sids(&mut key.security) {
+            report.descriptors += 1;
+        }
+
+        for value in &mut key.values {
+            let value_path = join_path(path, &value.name);
+            if self
+                .values
+                .iter()
+                .any(|p| glob_match(p, value_path.trim_start_matches('\\')))
+            {
+                value.data.fill(0);
+                report.wiped += 1;
+            } else if self.redact_data(value.reg_type, &mut value.data) {
+                report.data += 1;
+            }
+
+            if let Some(name) = self.redact_name(&value.name) {
+                value.name = name;
+                report.names += 1;
+            }
+        }
+
+        for subkey in &mut key.subkeys {
+            let subkey_path = join_path(path, &subkey.name);
+            self.redact_key(subkey, &subkey_path, report)?;
+            if let Some(name) = self.redact_name(&subkey.name) {
+                subkey.name = name;
+                report.names += 1;
+            }
+        }
+
+        let mut names: Vec<_> = key.subkeys.iter().map(|k| name_sort_key(&k.name)).collect();
+        names.sort();
+        if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+            anyhow::bail!(
+                "two subkeys of '{path}' are named '{}' once redacted",
+                String::from_utf16_lossy(&pair[0])
+            );
+        }
+        Ok(())
+    }
+
+    fn redact_name(&mut self, name: &str) -> Option<String> {
+        let units: Vec<u16> = name.encode_utf16().collect();
+        self.redact_text(&units)
+            .map(|units| String::from_utf16_lossy(&units))
+    }
+
+    // UTF-16LE bytes, an odd last byte being kept
+    fn redact_string_bytes(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        let units: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        let units = self.redact_text(&units)?;
+
+        let mut bytes: Vec<u8> = units.iter().flat_map(|u| u.to_le_bytes()).collect();
+        if data.len() % 2 == 1 {
+            bytes.push(data[data.len() - 1]);
+        }
+        Some(bytes)
+    }
+
+    fn redact_text(&mut self, units: &[u16]) -> Option<Vec<u16>> {
+        let mut redacted = replace_units(units, &self.replacements);
+        if !self.keep_sids {
+            let current = redacted.take().unwrap_or_else(|| units.to_vec());
+            redacted = Some(self.redact_sid_strings(&current).unwrap_or(current));
+        }
+        redacted.filter(|r| r != units)
+    }
+
+    fn redact_data(&mut self, reg_type: RegType, data: &mut Vec<u8>) -> bool {
+        if matches!(
+            reg_type,
+            RegType::Sz | RegType::ExpandSz | RegType::Link | RegType::MultiSz
+        ) {
+            return match self.redact_string_bytes(data) {
+                Some(redacted) => {
+                    *data = redacted;
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let mut modified = mask_data(data, &self.replacements);
+        if !self.keep_sids {
+            modified |= self.redact_binary_sids(data);
+        }
+        modified
+    }
+
+    fn domain(&mut self, domain: [u32; 3]) -> [u32; 3] {
+        let count = self.domains.len() as u32;
+        *self
+            .domains
+            .entry(domain)
+            .or_insert([count + 1, count + 1, count + 1])
+    }
+
+    // S-1-5-21-A-B-C followed by the RID, if any
+    fn redact_sid_strings(&mut self, units: &[u16]) -> Option<Vec<u16>> {
+        let prefix: Vec<u16> = DOMAIN_SID_PREFIX.encode_utf16().collect();
+        let is_sid_unit = |u: &u16| (b'0' as u16..=b'9' as u16).contains(u) || *u == b'-' as u16;
+
+        let mut redacted = Vec::with_capacity(units.len());
+        let mut modified = false;
+        let mut i = 0;
+        while i < units.len() {
+            let rest = &units[i..];
+            let is_prefix = rest.len() > prefix.len()
+                && rest[..prefix.len()]
+                    .iter()
+                    .zip(&prefix)
+                    .all(|(u, p)| upcase_unit(*u) == *p);
+            if !is_prefix {
+                redacted.push(units[i]);
+                i += 1;
+                continue;
+            }
+
+            let run = rest[prefix.len()..]
+                .iter()
+                .take_while(|u| is_sid_unit(u))
+                .count();
+            let numbers = String::from_utf16_lossy(&rest[prefix.len()..prefix.len() + run]);
+            let parts: Vec<&str> = numbers.splitn(4, '-').collect();
+            let domain: Option<Vec<u32>> = parts.iter().take(3).map(|p| p.parse().ok()).collect();
+            match domain {
+                Some(domain) if domain.len() == 3 => {
+                    let [a, b, c] = self.domain([domain[0], domain[1], domain[2]]);
+                    let mut sid = format!("{DOMAIN_SID_PREFIX}{a}-{b}-{c}");
+                    if let Some(rid) = parts.get(3) {
+                        sid.push('-');
+                        sid.push_str(rid);
+                    }
+                    redacted.extend(sid.encode_utf16());
+                    modified = true;
+                }
+                _ => redacted.extend_from_slice(&rest[..prefix.len() + run]),
+            }
+            i += prefix.len() + run;
+        }
+        modified.then_some(redacted)
+    }
+
+    // domain SIDs with at least their 3 domain sub authorities
+    fn redact_binary_sids(&mut self, data: &mut [u8]) -> bool {
+        let mut modified = false;
+        let mut i = 0;
+        while i + 2 + DOMAIN_SID_AUTHORITY.len() + 12 <= data.len() {
+            let count = data[i + 1] as usize;
+            let start = i + 2 + DOMAIN_SID_AUTHORITY.len();
+            if data[i] != 1
+                || !(4..=15).contains(&count)
+                || data[i + 2..start] != DOMAIN_SID_AUTHORITY
+                || start + 4 * (count - 1) > data.len()
+            {
+                i += 1;
+                continue;
+            }
+
+            let original: [u32; 3] = std::array::from_fn(|n| {
+                u32::from_le_bytes(data[start + 4 * n..start + 4 * n + 4].try_into().unwrap())
+            });
+            let domain = self.domain(original);
+            for (n, value) in domain.iter().enumerate() {
+                data[start + 4 * n..start + 4 * n + 4].copy_from_slice(&value.to_le_bytes());
+            }
+            modified |= domain != original;
+            i = start + 12;
+        }
+        modified
+    }
+}
+
+// occurrences of the texts of the replacements, uppercase, replaced by the first one matching at
+// each position
+fn replace_units(units: &[u16], replacements: &[(Vec<u16>, Vec<u16>)]) -> Option<Vec<u16>> {
+    let mut replaced = Vec::with_capacity(units.len());
+    let mut modified = false;
+    let mut i = 0;
+    while i < units.len() {
+        let found = replacements.iter().find(|(text, _)| {
+            units[i..].len() >= text.len()
+                && units[i..i + text.len()]
+                    .iter()
+                    .zip(text)
+                    .all(|(u, t)| upcase_unit(*u) == *t)
+        });
+        match found {
+            Some((text, replacement)) => {
+                replaced.extend_from_slice(replacement);
+                modified = true;
+                i += text.len();
+            }
+            None => {
+                replaced.push(units[i]);
+                i += 1;
+            }
+        }
+    }
+    modified.then_some(replaced)
+}
+
+// UTF-16LE occurrences of the texts of the replacements at any offset, and ASCII ones of the ASCII
+// texts, the first one matching at each position
+fn mask_data(data: &mut [u8], replacements: &[(Vec<u16>, Vec<u16>)]) -> bool {
+    let mut modified = false;
+    let mut i = 0;
+    while i < data.len() {
+        let found = replacements.iter().find_map(|(text, _)| {
+            let rest = &data[i..];
+            if utf16_matches(rest, text) {
+                Some((2 * text.len(), true))
+            } else if ascii_matches(rest, text) {
+                Some((text.len(), false))
+            } else {
+                None
+            }
+        });
+        match found {
+            Some((size, true)) => {
+                for c in data[i..i + size].chunks_exact_mut(2) {
+                    c.copy_from_slice(&(b'X' as u16).to_le_bytes());
+                }
+                modified = true;
+                i += size;
+            }
+            Some((size, false)) => {
+                data[i..i + size].fill(b'X');
+                modified = true;
+                i += size;
+            }
+            None => i += 1,
+        }
+    }
+    modified
+}
+
+// data starts with text in UTF-16LE, uppercase
+fn utf16_matches(data: &[u8], text: &[u16]) -> bool {
+    data.len() >= 2 * text.len()
+        && data
+            .chunks_exact(2)
+            .zip(text)
+            .all(|(c, t)| upcase_unit(u16::from_le_bytes([c[0], c[1]])) == *t)
+}
+
+// data starts with text in ASCII, when it's ASCII
+fn ascii_matches(data: &[u8], text: &[u16]) -> bool {
+    data.len() >= text.len()
+        && data.iter().zip(text).all(|(b, t)| {
+            u8::try_from(*t).is_ok_and(|t| t.is_ascii() && b.eq_ignore_ascii_case(&t))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the user name is also found in the machine name
+    fn nested() -> Redaction {
+        let mut redaction = Redaction::new();
+        redaction.user("alice");
+        redaction.machine("WKS-ALICE01");
+        redaction
+    }
+
+    #[test]
+    fn nested_names() {
+        let mut redaction = nested();
+        assert_eq!(
+            redaction.redact_name("wks-alice01\\alice").as_deref(),
+            Some("MACHINE1\\user1")
+        );
+        assert_eq!(redaction.redact_name("alice01").as_deref(), Some("user101"));
+    }
+
+    #[test]
+    fn nested_binary_data() {
+        let mut redaction = nested();
+        let mut data = b"host=WKS-ALICE01;user=alice".to_vec();
+        data.extend("WKS-ALICE01".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        assert!(redaction.redact_data(RegType::Binary, &mut data));
+
+        let mut expected = b"host=XXXXXXXXXXX;user=XXXXX".to_vec();
+        expected.extend("XXXXXXXXXXX".encode_utf16().flat_map(|u| u.to_le_bytes()));
+        assert_eq!(data, expected);
+    }
+}
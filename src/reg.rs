@@ -33,58 +33,97 @@
 // - Values may store data inline or in separate db cells.
 // - Subkeys may be organized in lists (lf, lh, ri) to optimize lookups.
 //
-use std::{
-    fmt,
-    fs::File,
-    io::{BufReader, Cursor, Read, Seek, SeekFrom},
-    path::{Path, PathBuf},
-};
+use std::{fmt, path::Path};
 
 use anyhow::Ok;
-use bincode::{Decode, error::DecodeError};
+use bincode::{Decode, Encode};
 
-// an overall structure keeping reader and current number of hbins read
+use crate::buffer::HiveBuffer;
+
+const BASE_BLOCK_SIZE: usize = 4096;
+const HIVE_BIN_HEADER_SIZE: usize = 32;
+
+// all on-disk structures are little endian with fixed size integers
+pub fn bincode_config() -> impl bincode::config::Config {
+    bincode::config::standard()
+        .with_little_endian()
+        .with_fixed_int_encoding()
+}
+
+// XOR-32 checksum of the first 508 bytes of a base block
+pub fn base_block_checksum(bytes: &[u8]) -> u32 {
+    let checksum = bytes[..508].chunks_exact(4).fold(0, |acc, c| {
+        acc ^ u32::from_le_bytes([c[0], c[1], c[2], c[3]])
+    });
+
+    // 0 and -1 are reserved values
+    match checksum {
+        0 => 1,
+        0xFFFF_FFFF => 0xFFFF_FFFE,
+        c => c,
+    }
+}
+
+// an overall structure keeping the file content and current number of hbins read, hbins and cells
+// being views into the content
 #[derive(Debug)]
 pub struct RegistryFile {
-    reader: BufReader<File>,
+    data: HiveBuffer,
 
     // a regf could contain left over data, need this to correctly read hbins
     total_hbins_size: u32,
     current_hbins_size: u32,
+
+    // go on reading hbins whose header is inconsistent instead of stopping
+    tolerant: bool,
+
+    // inconsistencies found in hbin headers so far
+    pub issues: Vec<String>,
 }
 
 impl TryFrom<&Path> for RegistryFile {
     type Error = anyhow::Error;
 
     fn try_from(path: &Path) -> Result<Self, Self::Error> {
-        let f = File::open(path)?;
-        let reader = BufReader::new(f);
+        Ok(Self::from(HiveBuffer::open(path)?))
+    }
+}
 
-        Ok(Self {
-            reader,
+impl From<HiveBuffer> for RegistryFile {
+    fn from(data: HiveBuffer) -> Self {
+        Self {
+            data,
             total_hbins_size: 0,
             current_hbins_size: 0,
-        })
+            tolerant: false,
+            issues: Vec::new(),
+        }
     }
 }
 
 impl RegistryFile {
     // read base block
     pub fn read_header(&mut self) -> anyhow::Result<BaseBlock> {
-        // base block is 4096 bytes
-        let mut buffer = [0u8; 4096];
-
-        // use bincode
-        let config = bincode::config::standard()
-            .with_little_endian()
-            .with_fixed_int_encoding();
-        let header: BaseBlock = bincode::decode_from_reader(&mut self.reader, config)?;
+        // base block is 4096 bytes, use bincode
+        let (header, _): (BaseBlock, usize) =
+            bincode::decode_from_slice(&self.data, bincode_config())?;
 
         // header is read: we have the theoretical total hbins size
         self.total_hbins_size = header.hive_bins_data_size;
 
         Ok(header)
     }
+
+    // with a wrong offset, the actual position is used; with a wrong size, it's rounded up to 4096
+    pub fn set_tolerant(&mut self, tolerant: bool) {
+        self.tolerant = tolerant;
+    }
+
+    // record an issue, returns true if reading can go on
+    fn issue(&mut self, issue: String) -> bool {
+        self.issues.push(issue);
+        self.tolerant
+    }
 }
 
 // we can loop through hbins
@@ -93,68 +132,136 @@ impl Iterator for RegistryFile {
 
     fn next(&mut self) -> Option<Self::Item> {
         // not at the end
-        if self.current_hbins_size < self.total_hbins_size {
-            let hive_bin = HiveBin::try_from(&mut self.reader).ok()?;
-            self.current_hbins_size += hive_bin.header.size;
-            Some(hive_bin)
-        } else {
-            None
+        let position = self.current_hbins_size;
+        if position >= self.total_hbins_size {
+            return None;
         }
+
+        // hbins follow each other from the end of the base block
+        let start = BASE_BLOCK_SIZE + position as usize;
+        let Some(mut header) = self
+            .data
+            .get(start..)
+            .and_then(|bytes| HiveBinHeader::try_from(bytes).ok())
+        else {
+            self.issue(format!(
+                "hive bins data truncated at 0x{position:X}, expected 0x{:X} bytes",
+                self.total_hbins_size
+            ));
+            return None;
+        };
+
+        if &header.signature != b"hbin" {
+            self.issue(format!("no hbin signature at 0x{position:X}"));
+            return None;
+        }
+
+        if header.offset != position
+            && !self.issue(format!(
+                "hbin at 0x{position:X} has offset 0x{:X}",
+                header.offset
+            ))
+        {
+            return None;
+        }
+        header.offset = position;
+
+        if (header.size == 0 || !header.size.is_multiple_of(4096))
+            && !self.issue(format!(
+                "hbin at 0x{position:X} has size 0x{:X}, not a multiple of 4096",
+                header.size
+            ))
+        {
+            return None;
+        }
+        let Some(size) = header.size.max(1).checked_next_multiple_of(4096) else {
+            self.issue(format!(
+                "hbin at 0x{position:X} has size 0x{:X}, beyond 4 GiB",
+                header.size
+            ));
+            return None;
+        };
+        header.size = size;
+
+        let end = position as u64 + header.size as u64;
+        if end > self.total_hbins_size as u64
+            && !self.issue(format!(
+                "hbin at 0x{position:X} ends at 0x{end:X}, beyond hive bins data size 0x{:X}",
+                self.total_hbins_size
+            ))
+        {
+            return None;
+        }
+
+        let cells_data = self
+            .data
+            .slice(start + HIVE_BIN_HEADER_SIZE..start + header.size as usize);
+        let Some(cells_data) = cells_data else {
+            self.issue(format!("hbin at 0x{position:X} is truncated"));
+            return None;
+        };
+        self.current_hbins_size = end.min(u32::MAX as u64) as u32;
+        Some(HiveBin {
+            header,
+            cells_data,
+            current_cells_size: 0,
+            issues: Vec::new(),
+        })
     }
 }
 
-#[derive(Debug, Decode)]
+#[derive(Debug, Clone, Decode, Encode)]
 pub struct BaseBlock {
     // ASCII string
-    signature: [u8; 4],
+    pub signature: [u8; 4],
 
     // This number is incremented by 1 in the beginning of a write operation on the primary file
-    primary_sequence_number: u32,
+    pub primary_sequence_number: u32,
 
     // This number is incremented by 1 at the end of a write operation on the primary file, a *primary sequence number* and a *secondary sequence number* should be equal after a successful write operation
-    secondary_sequence_number: u32,
+    pub secondary_sequence_number: u32,
 
     // FILETIME (UTC)
-    last_written_timestamp: u64,
+    pub last_written_timestamp: u64,
 
     // Major version of a hive writer
-    major_version: u32,
+    pub major_version: u32,
 
     // Minor version of a hive writer
-    minor_version: u32,
+    pub minor_version: u32,
 
     // 0 means *primary file*
-    file_type: u32,
+    pub file_type: u32,
 
     // 1 means *direct memory load*
-    file_format: u32,
+    pub file_format: u32,
 
     // Offset of a root cell in bytes, relative from the start of the hive bins data
-    root_cell_offset: u32,
+    pub root_cell_offset: u32,
 
     // Size of the hive bins data in bytes
     pub hive_bins_data_size: u32,
 
     // Logical sector size of the underlying disk in bytes divided by 512
-    clustering_factor: u32,
+    pub clustering_factor: u32,
 
     // UTF-16LE string (contains a partial file path to the primary file, or a file name of the primary file), used for debugging purposes
-    file_name: [u16; 32],
+    pub file_name: [u16; 32],
 
     //
-    reserved1: [u8; 396],
+    pub reserved1: [u8; 396],
 
     // XOR-32 checksum of the previous 508 bytes
-    checksum: u32,
+    pub checksum: u32,
 
     //
-    reserved2: [u8; 3576],
+    pub reserved2: [u8; 3576],
 
     // This field has no meaning on a disk
-    boot_type: u32,
+    pub boot_type: u32,
 
     // This field has no meaning on a disk
-    boot_recover: u32,
+    pub boot_recover: u32,
 }
 
 impl fmt::Display for BaseBlock {
@@ -176,25 +283,25 @@ impl fmt::Display for BaseBlock {
 }
 
 // Hive bin header
-#[derive(Debug, Decode)]
+#[derive(Debug, Clone, Decode, Encode)]
 pub struct HiveBinHeader {
     // ASCII string
-    signature: [u8; 4],
+    pub signature: [u8; 4],
 
     // Offset of a current hive bin in bytes, relative from the start of the hive bins data
-    offset: u32,
+    pub offset: u32,
 
     // Size of a current hive bin in bytes
     pub size: u32,
 
     //
-    reserved: u64,
+    pub reserved: u64,
 
     // FILETIME (UTC), defined for the first hive bin only (see below)
-    timestamp: u64,
+    pub timestamp: u64,
 
     // This field has no meaning on a disk (see below)
-    spare: u32,
+    pub spare: u32,
 }
 
 impl fmt::Display for HiveBinHeader {
@@ -209,14 +316,12 @@ impl fmt::Display for HiveBinHeader {
     }
 }
 
-impl TryFrom<&mut BufReader<File>> for HiveBinHeader {
-    type Error = DecodeError;
+impl TryFrom<&[u8]> for HiveBinHeader {
+    type Error = anyhow::Error;
 
-    fn try_from(reader: &mut BufReader<File>) -> Result<Self, Self::Error> {
-        let config = bincode::config::standard()
-            .with_little_endian()
-            .with_fixed_int_encoding();
-        bincode::decode_from_reader(reader, config)
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let (header, _) = bincode::decode_from_slice(bytes, bincode_config())?;
+        Ok(header)
     }
 }
 
@@ -228,68 +333,154 @@ impl TryFrom<&mut BufReader<File>> for HiveBinHeader {
 #[derive(Debug)]
 pub struct HiveBin {
     pub header: HiveBinHeader,
-    pub cells_data: Cursor<Vec<u8>>,
-    // pub cells: Vec<u8>,
+    pub cells_data: HiveBuffer,
+
     // this will keep current cell size when reading cells
     current_cells_size: u32,
+
+    // invalid cells found so far, skipped up to the next plausible cell
+    pub issues: Vec<String>,
 }
 
 impl Iterator for HiveBin {
     type Item = Cell;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let cells_size = self.header.size.saturating_sub(32);
+
         // not at the end
-        if self.current_cells_size < self.header.size - 32 {
-            let cell = Cell::try_from(&mut self.cells_data).ok()?;
+        while self.current_cells_size < cells_size {
+            // cells start right after the 32 bytes hbin header
+            let offset = self.header.offset + 32 + self.current_cells_size;
+
+            let size = self.cell_size_at(self.current_cells_size);
+            if !plausible_cell_size(size, cells_size - self.current_cells_size) {
+                // resynchronize on the next plausible cell, cells being 8 bytes aligned
+                let next = (self.current_cells_size + 8..cells_size)
+                    .step_by(8)
+                    .find(|p| self.resync_point(*p, cells_size))
+                    .unwrap_or(cells_size);
+                self.issues.push(format!(
+                    "invalid cell size {size} at 0x{offset:X}, 0x{:X} bytes skipped",
+                    next - self.current_cells_size
+                ));
+                self.current_cells_size = next;
+                continue;
+            }
+
+            let mut cell = Cell::read(&self.cells_data, self.current_cells_size as usize).ok()?;
+            cell.offset = offset;
 
             // need to take absolute value because cell size is negative for allocated cells
             self.current_cells_size += cell.size.unsigned_abs();
 
-            Some(cell)
-        } else {
-            None
+            return Some(cell);
         }
+
+        None
     }
 }
 
+// a cell is at least 8 bytes (size and 2 bytes signature, rounded up), 8 bytes aligned and within its
+// hbin
+fn plausible_cell_size(size: i32, remaining: u32) -> bool {
+    let abs_size = size.unsigned_abs();
+    abs_size >= 8 && abs_size.is_multiple_of(8) && abs_size <= remaining
+}
+
 impl fmt::Display for HiveBin {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "header: {} ", self.header)?;
-        let len = self.cells_data.get_ref().len();
+        let len = self.cells_data.len();
         write!(
             f,
             "data: {:X?}, len = {} last bytes: {:X?}",
-            &self.cells_data.get_ref()[0..16],
+            &self.cells_data[0..16],
             len,
-            &self.cells_data.get_ref()[len - 16..len]
+            &self.cells_data[len - 16..len]
         )
     }
 }
 
-impl TryFrom<&mut BufReader<File>> for HiveBin {
-    type Error = anyhow::Error;
-
-    fn try_from(reader: &mut BufReader<File>) -> Result<Self, Self::Error> {
-        // let pos = reader.seek(SeekFrom::Current(0))?;
-        // println!("pos index try_from before={:x?}", pos);
-        let header = HiveBinHeader::try_from(&mut *reader)?;
-        let mut data = vec![0u8; header.size as usize - size_of::<HiveBinHeader>()];
+impl HiveBin {
+    // an allocated cell with a known signature, followed by another plausible cell or ending the hbin:
+    // unlikely to be found in the middle of a cell
+    fn resync_point(&self, position: u32, cells_size: u32) -> bool {
+        let size = self.cell_size_at(position);
+        if !plausible_cell_size(size, cells_size - position) {
+            return false;
+        }
 
-        reader.read_exact(&mut data)?;
+        let signature = self
+            .cells_data
+            .get(position as usize + 4..position as usize + 6);
+        let known = matches!(
+            signature,
+            Some(b"nk" | b"vk" | b"sk" | b"lf" | b"lh" | b"li" | b"ri" | b"db")
+        );
+        let next = position + size.unsigned_abs();
+        size < 0
+            && known
+            && (next == cells_size
+                || plausible_cell_size(self.cell_size_at(next), cells_size - next))
+    }
 
-        Ok(Self {
-            header,
-            cells_data: Cursor::new(data),
-            current_cells_size: 0,
-        })
+    // size field of the cell at a position of the cells data, 0 past the end
+    fn cell_size_at(&self, position: u32) -> i32 {
+        match self
+            .cells_data
+            .get(position as usize..position as usize + 4)
+        {
+            Some(b) => i32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            None => 0,
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct Cell {
+    // offset relative from the start of the hive bins data
+    pub offset: u32,
     pub size: i32,
     pub r#type: CellType,
-    pub data: Vec<u8>,
+
+    // after the size field
+    content: HiveBuffer,
+}
+
+impl Cell {
+    // cell at a position of the cells data of a hbin
+    fn read(cells_data: &HiveBuffer, position: usize) -> anyhow::Result<Self> {
+        let remaining = cells_data.len().saturating_sub(position);
+        let Some(b) = cells_data.get(position..position + 4) else {
+            anyhow::bail!("cell past the end of the hbin");
+        };
+        let cell_size = i32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+        anyhow::ensure!(
+            plausible_cell_size(cell_size, remaining.min(u32::MAX as usize) as u32),
+            "invalid cell size {cell_size}"
+        );
+
+        let content = cells_data
+            .slice(position + 4..position + cell_size.unsigned_abs() as usize)
+            .ok_or_else(|| anyhow::anyhow!("cell past the end of the hbin"))?;
+        Ok(Self {
+            offset: 0,
+            size: cell_size,
+            r#type: CellType::from([content[0], content[1]]),
+            content,
+        })
+    }
+
+    // cell data including the 2 bytes read as the cell type
+    pub fn content(&self) -> &[u8] {
+        &self.content
+    }
+
+    // cell data after the 2 bytes read as the cell type
+    pub fn data(&self) -> &[u8] {
+        &self.content[2..]
+    }
 }
 
 impl fmt::Display for Cell {
@@ -297,36 +488,18 @@ impl fmt::Display for Cell {
         write!(
             f,
             "size: {} type: {:X?} data: {:X?}",
-            self.size, self.r#type, &self.data
+            self.size,
+            self.r#type,
+            self.data()
         )?;
         if self.r#type == CellType::NamedKey {
-            let s = String::from_utf8_lossy(&self.data);
+            let s = String::from_utf8_lossy(self.data());
             write!(f, "found s = {s}")?;
         }
         write!(f, "")
     }
 }
 
-impl TryFrom<&mut Cursor<Vec<u8>>> for Cell {
-    type Error = anyhow::Error;
-
-    fn try_from(c: &mut Cursor<Vec<u8>>) -> Result<Self, Self::Error> {
-        let mut buf = [0u8; 4];
-        let _ = c.read_exact(&mut buf);
-        let cell_size = i32::from_le_bytes(buf);
-        let cell_type = CellType::try_from(&mut *c)?;
-
-        let mut cell_data = vec![0u8; cell_size.unsigned_abs() as usize - 6];
-        let _ = c.read_exact(&mut cell_data);
-
-        Ok(Self {
-            size: cell_size,
-            r#type: cell_type,
-            data: cell_data,
-        })
-    }
-}
-
 // each cell can only be this enum
 #[derive(Debug, PartialEq)]
 pub enum CellType {
@@ -341,22 +514,52 @@ pub enum CellType {
     Unknown([u8; 2]),
 }
 
-impl TryFrom<&mut Cursor<Vec<u8>>> for CellType {
-    type Error = anyhow::Error;
+impl CellType {
+    pub fn signature(&self) -> [u8; 2] {
+        match self {
+            CellType::LeafIndex => *b"li",
+            CellType::LeafFast => *b"lf",
+            CellType::LeafHash => *b"lh",
+            CellType::RootIndex => *b"ri",
+            CellType::NamedKey => *b"nk",
+            CellType::ValueKey => *b"vk",
+            CellType::SecurityKey => *b"sk",
+            CellType::DataBlock => *b"db",
+            CellType::Unknown(key) => *key,
+        }
+    }
+}
 
-    fn try_from(c: &mut Cursor<Vec<u8>>) -> Result<Self, Self::Error> {
-        let mut key = [0u8; 2];
-        c.read_exact(&mut key)?;
+impl From<[u8; 2]> for CellType {
+    fn from(key: [u8; 2]) -> Self {
         match &key {
-            b"li" => Ok(CellType::LeafIndex),
-            b"lf" => Ok(CellType::LeafFast),
-            b"lh" => Ok(CellType::LeafHash),
-            b"ri" => Ok(CellType::RootIndex),
-            b"nk" => Ok(CellType::NamedKey),
-            b"vk" => Ok(CellType::ValueKey),
-            b"sk" => Ok(CellType::SecurityKey),
-            b"db" => Ok(CellType::DataBlock),
-            _ => Ok(CellType::Unknown(key)),
+            b"li" => CellType::LeafIndex,
+            b"lf" => CellType::LeafFast,
+            b"lh" => CellType::LeafHash,
+            b"ri" => CellType::RootIndex,
+            b"nk" => CellType::NamedKey,
+            b"vk" => CellType::ValueKey,
+            b"sk" => CellType::SecurityKey,
+            b"db" => CellType::DataBlock,
+            _ => CellType::Unknown(key),
+        }
+    }
+}
+
+impl fmt::Display for CellType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CellType::LeafIndex => write!(f, "li"),
+            CellType::LeafFast => write!(f, "lf"),
+            CellType::LeafHash => write!(f, "lh"),
+            CellType::RootIndex => write!(f, "ri"),
+            CellType::NamedKey => write!(f, "nk"),
+            CellType::ValueKey => write!(f, "vk"),
+            CellType::SecurityKey => write!(f, "sk"),
+            CellType::DataBlock => write!(f, "db"),
+
+            // value lists and data cells have no signature
+            CellType::Unknown(_) => write!(f, "data"),
         }
     }
 }
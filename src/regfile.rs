@@ -0,0 +1,242 @@
+// Parser of .reg files as written by regedit (version 5.00 or REGEDIT4)
+//
+// Windows Registry Editor Version 5.00
+//
+// [HKEY_LOCAL_MACHINE\SOFTWARE\Key]      <- create key
+// "Name"="string"                        <- set value
+// @=dword:00000001                       <- set default value
+// "Data"=hex(2):25,00,00,00              <- any type, as hex bytes (lines can end with '\')
+// "Old"=-                                <- delete value
+//
+// [-HKEY_LOCAL_MACHINE\SOFTWARE\Old]     <- delete key
+//
+use std::{fmt, path::Path};
+
+use crate::{tree::TreeValue, value::RegType};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegOperation {
+    AddKey(String),
+    DeleteKey(String),
+    SetValue(String, TreeValue),
+    DeleteValue(String, String),
+}
+
+impl RegOperation {
+    // full key path, as written in the file
+    pub fn path(&self) -> &str {
+        match self {
+            RegOperation::AddKey(path)
+            | RegOperation::DeleteKey(path)
+            | RegOperation::SetValue(path, _)
+            | RegOperation::DeleteValue(path, _) => path,
+        }
+    }
+}
+
+impl fmt::Display for RegOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegOperation::AddKey(path) => write!(f, "add key {path}"),
+            RegOperation::DeleteKey(path) => write!(f, "delete key {path}"),
+            RegOperation::SetValue(path, value) => {
+                write!(f, "set value {path}\\{} ({})", value.name, value.reg_type)
+            }
+            RegOperation::DeleteValue(path, name) => write!(f, "delete value {path}\\{name}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RegFile {
+    pub operations: Vec<RegOperation>,
+}
+
+impl RegFile {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::parse(&decode_text(&bytes))
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut lines = logical_lines(text).into_iter();
+
+        // the first non empty line is the header
+        let header = lines.find(|(_, l)| !l.is_empty()).unwrap_or_default().1;
+        let unicode = match header.as_str() {
+            "Windows Registry Editor Version 5.00" => true,
+            "REGEDIT4" => false,
+            _ => anyhow::bail!("not a .reg file (header is '{header}')"),
+        };
+
+        let mut operations = Vec::new();
+        let mut current: Option<String> = None;
+
+        for (number, line) in lines {
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            let context = |e: anyhow::Error| anyhow::anyhow!("line {number}: {e}");
+
+            if let Some(key) = line.strip_prefix('[') {
+                let key = key
+                    .strip_suffix(']')
+                    .ok_or_else(|| context(anyhow::anyhow!("missing ']'")))?;
+                match key.strip_prefix('-') {
+                    Some(key) => {
+                        operations.push(RegOperation::DeleteKey(key.to_string()));
+                        current = None;
+                    }
+                    None => {
+                        operations.push(RegOperation::AddKey(key.to_string()));
+                        current = Some(key.to_string());
+                    }
+                }
+                continue;
+            }
+
+            let key = current
+                .clone()
+                .ok_or_else(|| context(anyhow::anyhow!("value outside of a key")))?;
+            let (name, data) = parse_assignment(&line).map_err(context)?;
+
+            if data == "-" {
+                operations.push(RegOperation::DeleteValue(key, name));
+            } else {
+                let (reg_type, data) = parse_data(data, unicode).map_err(context)?;
+                operations.push(RegOperation::SetValue(
+                    key,
+                    TreeValue {
+                        name,
+                        reg_type,
+                        data,
+                    },
+                ));
+            }
+        }
+
+        Ok(Self { operations })
+    }
+}
+
+// regedit writes UTF-16LE with a BOM, other tools UTF-8
+fn decode_text(bytes: &[u8]) -> String {
+    if let Some(utf16) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        let utf8 = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+        String::from_utf8_lossy(utf8).into_owned()
+    }
+}
+
+// hex data lines ending with '\' continue on the next line, returns line numbers
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut pending: Option<(usize, String)> = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        let (number, mut joined) = match pending.take() {
+            Some((number, previous)) => (number, previous + line.trim_start()),
+            None => (i + 1, line.trim().to_string()),
+        };
+
+        if joined.ends_with('\\') && joined.contains("=hex") {
+            joined.pop();
+            pending = Some((number, joined));
+        } else {
+            lines.push((number, joined));
+        }
+    }
+
+    if let Some(line) = pending {
+        lines.push(line);
+    }
+    lines
+}
+
+// "name"=data or @=data
+fn parse_assignment(line: &str) -> anyhow::Result<(String, &str)> {
+    if let Some(data) = line.strip_prefix("@=") {
+        return Ok((String::new(), data));
+    }
+
+    let (name, rest) = parse_quoted(line)?;
+    let data = rest
+        .strip_prefix('=')
+        .ok_or_else(|| anyhow::anyhow!("missing '=' after value name"))?;
+    Ok((name, data))
+}
+
+// quoted string with \\ and \" escapes, returns the string and what follows it
+fn parse_quoted(s: &str) -> anyhow::Result<(String, &str)> {
+    let s = s
+        .strip_prefix('"')
+        .ok_or_else(|| anyhow::anyhow!("expected '\"'"))?;
+
+    let mut result = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, escaped)) => result.push(escaped),
+                None => break,
+            },
+            '"' => return Ok((result, &s[i + 1..])),
+            c => result.push(c),
+        }
+    }
+
+    anyhow::bail!("unterminated string")
+}
+
+fn parse_data(data: &str, unicode: bool) -> anyhow::Result<(RegType, Vec<u8>)> {
+    if data.starts_with('"') {
+        let (s, rest) = parse_quoted(data)?;
+        anyhow::ensure!(rest.trim().is_empty(), "unexpected data after string");
+        return Ok((RegType::Sz, string_data(&s)));
+    }
+
+    if let Some(dword) = data.strip_prefix("dword:") {
+        let d = u32::from_str_radix(dword.trim(), 16)?;
+        return Ok((RegType::Dword, d.to_le_bytes().to_vec()));
+    }
+
+    let (reg_type, bytes) = if let Some(bytes) = data.strip_prefix("hex:") {
+        (RegType::Binary, bytes)
+    } else if let Some(rest) = data.strip_prefix("hex(") {
+        let (t, bytes) = rest
+            .split_once("):")
+            .ok_or_else(|| anyhow::anyhow!("invalid hex type"))?;
+        (RegType::from(u32::from_str_radix(t, 16)?), bytes)
+    } else {
+        anyhow::bail!("unknown data format '{data}'");
+    };
+
+    let bytes = bytes
+        .split(',')
+        .map(str::trim)
+        .filter(|b| !b.is_empty())
+        .map(|b| u8::from_str_radix(b, 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    // REGEDIT4 strings are ANSI
+    if !unicode && matches!(reg_type, RegType::ExpandSz | RegType::MultiSz) {
+        return Ok((reg_type, bytes.iter().flat_map(|b| [*b, 0]).collect()));
+    }
+
+    Ok((reg_type, bytes))
+}
+
+// REG_SZ data: null terminated UTF-16LE
+fn string_data(s: &str) -> Vec<u8> {
+    s.encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(|u| u.to_le_bytes())
+        .collect()
+}
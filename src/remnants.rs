@@ -0,0 +1,141 @@
+// Content of the reserved regions of the base block
+//
+// as of Windows 8, reserved1 holds the GUIDs of the KTM resource manager, log and transaction manager,
+// then the last reorganization timestamp as of Windows 10. reserved2 ends with the GUIDs used to thaw
+// a frozen hive. All other bytes are expected to be zero: non-zero content there is left over by a
+// previous writer or has been stashed on purpose.
+//
+// 0x70  RmId        0x94  TmId           0xA8  last reorganized    0xB0..0x1FC  reserved
+// 0x80  LogId       0xA4  "rmtm"
+// 0x90  flags
+// 0x200..0xFC8  reserved      0xFC8  ThawTmId      0xFD8  ThawRmId      0xFE8  ThawLogId
+//
+use std::fmt;
+
+use crate::{
+    reg::{BaseBlock, bincode_config},
+    utils::{filetime_to_string, guid_to_string},
+    value::hex_string,
+};
+
+// runs of non-zero bytes separated by less zeros than this are reported as a single remnant
+const MIN_ZERO_GAP: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    Guid,
+    Flags,
+    Signature,
+    Timestamp,
+    Reserved,
+}
+
+// offset, length, name and kind of the fields of the reserved regions
+const FIELDS: &[(usize, usize, &str, FieldKind)] = &[
+    (0x70, 16, "RmId", FieldKind::Guid),
+    (0x80, 16, "LogId", FieldKind::Guid),
+    (0x90, 4, "flags", FieldKind::Flags),
+    (0x94, 16, "TmId", FieldKind::Guid),
+    (0xA4, 4, "GUID signature", FieldKind::Signature),
+    (0xA8, 8, "last reorganized", FieldKind::Timestamp),
+    (0xB0, 0x1FC - 0xB0, "reserved1", FieldKind::Reserved),
+    (0x200, 0xFC8 - 0x200, "reserved2", FieldKind::Reserved),
+    (0xFC8, 16, "ThawTmId", FieldKind::Guid),
+    (0xFD8, 16, "ThawRmId", FieldKind::Guid),
+    (0xFE8, 16, "ThawLogId", FieldKind::Guid),
+];
+
+#[derive(Debug, Clone)]
+pub struct Remnant {
+    // offset in the file
+    pub offset: usize,
+    pub field: &'static str,
+    pub kind: FieldKind,
+    pub data: Vec<u8>,
+}
+
+impl fmt::Display for Remnant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:03X}\t{}\t", self.offset, self.field)?;
+        match self.kind {
+            FieldKind::Guid => write!(f, "{}", guid_to_string(&self.data)),
+            FieldKind::Flags => {
+                let flags = u32::from_le_bytes(self.data[..4].try_into().unwrap());
+                write!(f, "0x{flags:08X}")?;
+                if flags & 1 != 0 {
+                    write!(f, " KTM_LOCKED")?;
+                }
+                if flags & 2 != 0 {
+                    write!(f, " DEFRAGMENTED")?;
+                }
+                Ok(())
+            }
+            FieldKind::Signature => write!(f, "{}", String::from_utf8_lossy(&self.data)),
+            FieldKind::Timestamp => {
+                let ft = u64::from_le_bytes(self.data[..8].try_into().unwrap());
+                write!(f, "{}", filetime_to_string(ft))
+            }
+            FieldKind::Reserved => {
+                let printable: String = self
+                    .data
+                    .iter()
+                    .filter(|b| **b != 0)
+                    .map(|b| {
+                        if b.is_ascii_graphic() || *b == b' ' {
+                            *b as char
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                write!(f, "{}\t{printable}", hex_string(&self.data))
+            }
+        }
+    }
+}
+
+// non-zero content of the reserved regions
+pub fn base_block_remnants(base_block: &BaseBlock) -> anyhow::Result<Vec<Remnant>> {
+    let bytes = bincode::encode_to_vec(base_block, bincode_config())?;
+    let mut remnants = Vec::new();
+
+    for (offset, len, field, kind) in FIELDS {
+        let data = &bytes[*offset..*offset + *len];
+        if *kind != FieldKind::Reserved {
+            if data.iter().any(|b| *b != 0) {
+                remnants.push(Remnant {
+                    offset: *offset,
+                    field,
+                    kind: *kind,
+                    data: data.to_vec(),
+                });
+            }
+            continue;
+        }
+
+        for (start, end) in non_zero_runs(data) {
+            remnants.push(Remnant {
+                offset: offset + start,
+                field,
+                kind: *kind,
+                data: data[start..end].to_vec(),
+            });
+        }
+    }
+
+    Ok(remnants)
+}
+
+// ranges of non-zero bytes, merged when separated by less than MIN_ZERO_GAP zeros
+fn non_zero_runs(data: &[u8]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+
+    for (i, _) in data.iter().enumerate().filter(|(_, b)| **b != 0) {
+        match runs.last_mut() {
+            Some((_, end)) if i - *end < MIN_ZERO_GAP => *end = i + 1,
+            _ => runs.push((i, i + 1)),
+        }
+    }
+
+    runs
+}
@@ -0,0 +1,674 @@
+// Repair of truncated hives, e.g. copied while being written or carved out of an image
+//
+// the hive bins data is cut at the end of the hive bins present in the file: a hive bin cut by the
+// end of the file keeps its complete pages, its size being reduced, and the cell cut by its new end
+// is replaced by a free cell. References to the cells lost are then pruned, starting from the root
+// key: subkeys and values are removed from their lists (shrunk in their cells) along with their
+// counts, lost class names are dropped, a lost security descriptor is replaced by the one of the root
+// key, and the key security list is relinked with reference counts of the keys left. A subkeys list
+// lost while its keys are left is rebuilt from the keys whose parent is the key (lists are often moved
+// to the end of the hive as they grow), in a free cell or a hive bin added at the end. Everything
+// else is kept as is.
+//
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    ops::Range,
+    path::Path,
+};
+
+use bincode::Encode;
+
+use crate::{
+    buffer::default_options,
+    hive::{BASE_BLOCK_SIZE, MAX_DEPTH, join_path, name_sort_key},
+    key::{KeyNode, NO_OFFSET},
+    list::{LEAF_MAX_ELEMENTS, SubkeysList, key_values_list, name_hash, name_hint},
+    reg::{BaseBlock, HiveBinHeader, base_block_checksum, bincode_config},
+    security::KeySecurityHeader,
+    value::{BIG_DATA_SEGMENT_SIZE, BigData, DATA_IS_RESIDENT, KeyValue},
+};
+
+const PAGE_SIZE: u32 = 4096;
+const HIVE_BIN_HEADER_SIZE: u32 = 32;
+
+// something lost with the missing part of the hive
+#[derive(Debug, Clone)]
+pub struct Loss {
+    // key owning the record
+    pub path: String,
+    pub offset: u32,
+    pub description: String,
+}
+
+impl fmt::Display for Loss {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t0x{:X}\t{}",
+            self.path, self.offset, self.description
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Repair {
+    // size of the hive bins data in the base block, in the file and once repaired
+    pub declared_size: u32,
+    pub available_size: u64,
+    pub repaired_size: u32,
+
+    pub losses: Vec<Loss>,
+
+    // whole repaired file
+    pub hive: Vec<u8>,
+}
+
+pub fn repair(path: &Path) -> anyhow::Result<Repair> {
+    default_options().check_size(std::fs::metadata(path)?.len(), "hive")?;
+    repair_bytes(std::fs::read(path)?)
+}
+
+pub fn repair_bytes(mut data: Vec<u8>) -> anyhow::Result<Repair> {
+    anyhow::ensure!(
+        data.len() as u64 >= BASE_BLOCK_SIZE,
+        "base block truncated, only salvage can read this hive"
+    );
+    let (mut base_block, _): (BaseBlock, usize) =
+        bincode::decode_from_slice(&data, bincode_config())?;
+    anyhow::ensure!(&base_block.signature == b"regf", "not a registry hive");
+
+    let declared_size = base_block.hive_bins_data_size;
+    let available_size = (data.len() as u64).saturating_sub(BASE_BLOCK_SIZE);
+    anyhow::ensure!(
+        available_size < declared_size as u64,
+        "hive isn't truncated, it has 0x{available_size:X} bytes of hive bins data"
+    );
+
+    let mut losses = Vec::new();
+    let (end, cut_cell) = cut_bins(&mut data, available_size as u32, &mut losses)?;
+    data.truncate((BASE_BLOCK_SIZE + end as u64) as usize);
+
+    let mut pruner = Pruner {
+        data,
+        end,
+        losses,
+        minor_version: base_block.minor_version,
+        visited: HashSet::new(),
+        securities: BTreeMap::new(),
+        root_security: NO_OFFSET,
+        children: HashMap::new(),
+        free_cells: Vec::new(),
+        cut_cell,
+    };
+    pruner.prune(base_block.root_cell_offset)?;
+
+    base_block.hive_bins_data_size = pruner.end;
+    let mut block = bincode::encode_to_vec(&base_block, bincode_config())?;
+    let checksum = base_block_checksum(&block);
+    block[508..512].copy_from_slice(&checksum.to_le_bytes());
+    pruner.data[..block.len()].copy_from_slice(&block);
+
+    Ok(Repair {
+        declared_size,
+        available_size,
+        repaired_size: pruner.end,
+        losses: pruner.losses,
+        hive: pruner.data,
+    })
+}
+
+// new size of the hive bins data: the hive bins present, the last one reduced to its complete pages.
+// Also returns the offset of the free cell replacing the cell cut, if any
+fn cut_bins(
+    data: &mut [u8],
+    available: u32,
+    losses: &mut Vec<Loss>,
+) -> anyhow::Result<(u32, Option<u32>)> {
+    let bins = BASE_BLOCK_SIZE as usize;
+    let mut position = 0;
+    let mut cut_cell = None;
+    while position + HIVE_BIN_HEADER_SIZE <= available {
+        let header = bins + position as usize;
+        let size = u32_at(data, header + 8);
+        if &data[header..header + 4] != b"hbin"
+            || u32_at(data, header + 4) != position
+            || size == 0
+            || !size.is_multiple_of(PAGE_SIZE)
+        {
+            losses.push(Loss {
+                path: String::new(),
+                offset: position,
+                description: "invalid hive bin, hive bins data cut there".to_string(),
+            });
+            break;
+        }
+        if position + size <= available {
+            position += size;
+            continue;
+        }
+
+        // pages of the last hive bin present in the file
+        let pages = (available - position) / PAGE_SIZE * PAGE_SIZE;
+        if pages == 0 {
+            break;
+        }
+        data[header + 8..header + 12].copy_from_slice(&pages.to_le_bytes());
+
+        let bin_end = position + pages;
+        let mut cell = position + HIVE_BIN_HEADER_SIZE;
+        while cell < bin_end {
+            let size = u32_at(data, bins + cell as usize) as i32;
+            let abs_size = size.unsigned_abs();
+            if abs_size >= 8 && abs_size.is_multiple_of(8) && abs_size <= bin_end - cell {
+                cell += abs_size;
+                continue;
+            }
+
+            if size < 0 {
+                losses.push(Loss {
+                    path: String::new(),
+                    offset: cell,
+                    description: format!("allocated cell of {abs_size} bytes cut"),
+                });
+            }
+            let free = bin_end - cell;
+            data[bins + cell as usize..bins + cell as usize + 4]
+                .copy_from_slice(&(free as i32).to_le_bytes());
+            cut_cell = Some(cell);
+            break;
+        }
+        position = bin_end;
+        break;
+    }
+
+    anyhow::ensure!(position > 0, "no complete hive bin left");
+    Ok((position, cut_cell))
+}
+
+struct Pruner {
+    data: Vec<u8>,
+
+    // size of the hive bins data
+    end: u32,
+
+    losses: Vec<Loss>,
+    minor_version: u32,
+
+    // keys already pruned
+    visited: HashSet<u32>,
+
+    // key securities left and the number of keys using them
+    securities: BTreeMap<u32, u32>,
+    root_security: u32,
+
+    // allocated key nodes by parent offset, and free cells (offset and size)
+    children: HashMap<u32, Vec<u32>>,
+    free_cells: Vec<(u32, u32)>,
+
+    // not reused, references to the cell cut being lost
+    cut_cell: Option<u32>,
+}
+
+impl Pruner {
+    fn prune(&mut self, root: u32) -> anyhow::Result<()> {
+        let node = self
+            .record(root, b"nk")
+            .and_then(|cell| KeyNode::try_from(cell).ok())
+            .ok_or_else(|| anyhow::anyhow!("root key lost, only salvage can read this hive"))?;
+        let security = node.header.key_security_offset;
+        anyhow::ensure!(
+            self.record(security, b"sk").is_some(),
+            "security descriptor of the root key lost"
+        );
+        self.root_security = security;
+
+        self.scan_cells();
+        self.prune_key(root, "\\", 0)?;
+        self.relink_securities()
+    }
+
+    // cells of the hive bins left, up to the first invalid one of each bin
+    fn scan_cells(&mut self) {
+        let bins = BASE_BLOCK_SIZE as usize;
+        let mut position = 0;
+        while position < self.end {
+            let bin_size = u32_at(&self.data, bins + position as usize + 8);
+            if bin_size == 0 {
+                break;
+            }
+            let bin_end = position + bin_size;
+            let mut cell = position + HIVE_BIN_HEADER_SIZE;
+            while cell < bin_end {
+                let size = u32_at(&self.data, bins + cell as usize) as i32;
+                let abs_size = size.unsigned_abs();
+                if abs_size < 8 || !abs_size.is_multiple_of(8) || abs_size > bin_end - cell {
+                    break;
+                }
+                if size > 0 && self.cut_cell != Some(cell) {
+                    self.free_cells.push((cell, abs_size));
+                } else if let Some(node) = self
+                    .record(cell, b"nk")
+                    .and_then(|cell| KeyNode::try_from(cell).ok())
+                    && !node.is_root()
+                {
+                    self.children
+                        .entry(node.header.parent)
+                        .or_default()
+                        .push(cell);
+                }
+                cell += abs_size;
+            }
+            position = bin_end;
+        }
+    }
+
+    // content of the allocated cell at offset, if entirely present
+    fn cell(&self, offset: u32) -> Option<Range<usize>> {
+        if offset == NO_OFFSET || !offset.is_multiple_of(8) || offset as u64 + 4 > self.end as u64 {
+            return None;
+        }
+        let start = (BASE_BLOCK_SIZE + offset as u64) as usize;
+        let size = u32_at(&self.data, start) as i32;
+        let abs_size = size.unsigned_abs();
+        (size < 0 && abs_size >= 8 && offset as u64 + abs_size as u64 <= self.end as u64)
+            .then_some(start + 4..start + abs_size as usize)
+    }
+
+    fn record(&self, offset: u32, signature: &[u8; 2]) -> Option<&[u8]> {
+        let cell = &self.data[self.cell(offset)?];
+        cell.starts_with(signature).then_some(cell)
+    }
+
+    fn free(&mut self, offset: u32) {
+        if let Some(range) = self.cell(offset) {
+            let start = range.start - 4;
+            let size = (range.end - start) as i32;
+            self.data[start..start + 4].copy_from_slice(&size.to_le_bytes());
+            self.free_cells.push((offset, size as u32));
+        }
+    }
+
+    // first free cell large enough, split when larger, or a new hive bin
+    fn alloc(&mut self, content: &[u8]) -> anyhow::Result<u32> {
+        let size = (content.len() as u32 + 4).next_multiple_of(8);
+        let index = match self.free_cells.iter().position(|(_, s)| *s >= size) {
+            Some(index) => index,
+            None => {
+                self.add_hive_bin(size)?;
+                self.free_cells.len() - 1
+            }
+        };
+        let (offset, free_size) = self.free_cells[index];
+
+        let size = if free_size - size >= 8 {
+            self.free_cells[index] = (offset + size, free_size - size);
+            let rest = BASE_BLOCK_SIZE as usize + (offset + size) as usize;
+            self.data[rest..rest + 4].copy_from_slice(&((free_size - size) as i32).to_le_bytes());
+            size
+        } else {
+            self.free_cells.remove(index);
+            free_size
+        };
+
+        let start = BASE_BLOCK_SIZE as usize + offset as usize;
+        self.data[start..start + 4].copy_from_slice(&(-(size as i32)).to_le_bytes());
+        self.data[start + 4..start + 4 + content.len()].copy_from_slice(content);
+        Ok(offset)
+    }
+
+    // appended after the last hive bin, with a single free cell
+    fn add_hive_bin(&mut self, cell_size: u32) -> anyhow::Result<()> {
+        let offset = self.end;
+        let size = (HIVE_BIN_HEADER_SIZE + cell_size).next_multiple_of(PAGE_SIZE);
+        let header = HiveBinHeader {
+            signature: *b"hbin",
+            offset,
+            size,
+            reserved: 0,
+            timestamp: 0,
+            spare: 0,
+        };
+        let mut bin = bincode::encode_to_vec(&header, bincode_config())?;
+        bin.extend_from_slice(&((size - HIVE_BIN_HEADER_SIZE) as i32).to_le_bytes());
+        bin.resize(size as usize, 0);
+
+        self.data.extend_from_slice(&bin);
+        self.end += size;
+        self.free_cells
+            .push((offset + HIVE_BIN_HEADER_SIZE, size - HIVE_BIN_HEADER_SIZE));
+        Ok(())
+    }
+
+    // cell content written from its start, the cell being large enough
+    fn write<T: Encode>(&mut self, offset: u32, record: &T) -> anyhow::Result<()> {
+        let bytes = bincode::encode_to_vec(record, bincode_config())?;
+        self.write_bytes(offset, &bytes)
+    }
+
+    fn write_bytes(&mut self, offset: u32, bytes: &[u8]) -> anyhow::Result<()> {
+        let range = self
+            .cell(offset)
+            .ok_or_else(|| anyhow::anyhow!("cell at 0x{offset:X} lost"))?;
+        anyhow::ensure!(bytes.len() <= range.len(), "cell at 0x{offset:X} too small");
+        self.data[range.start..range.start + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn lost(&mut self, path: &str, offset: u32, description: String) {
+        self.losses.push(Loss {
+            path: path.to_string(),
+            offset,
+            description,
+        });
+    }
+
+    fn prune_key(&mut self, offset: u32, path: &str, depth: usize) -> anyhow::Result<()> {
+        if depth > MAX_DEPTH || !self.visited.insert(offset) {
+            return Ok(());
+        }
+        let cell = self.record(offset, b"nk").unwrap_or_default();
+        let mut header = KeyNode::try_from(cell)?.header;
+
+        let class_name_lost = header.class_name_offset != NO_OFFSET
+            && self
+                .cell(header.class_name_offset)
+                .is_none_or(|range| range.len() < header.class_name_length as usize);
+        if class_name_lost {
+            self.lost(path, header.class_name_offset, "class name".to_string());
+            header.class_name_offset = NO_OFFSET;
+            header.class_name_length = 0;
+        }
+
+        if self.record(header.key_security_offset, b"sk").is_none() {
+            self.lost(
+                path,
+                header.key_security_offset,
+                "security descriptor, the one of the root key is used".to_string(),
+            );
+            header.key_security_offset = self.root_security;
+        }
+        *self
+            .securities
+            .entry(header.key_security_offset)
+            .or_default() += 1;
+
+        let count = self.prune_values(
+            path,
+            header.key_values_list_offset,
+            header.number_of_key_values,
+        )?;
+        if count == 0 && header.key_values_list_offset != NO_OFFSET {
+            self.free(header.key_values_list_offset);
+            header.key_values_list_offset = NO_OFFSET;
+        }
+        header.number_of_key_values = count;
+
+        let mut list_lost = false;
+        let mut subkeys = if header.number_of_subkeys > 0 {
+            self.prune_subkeys(path, header.subkeys_list_offset, 0, &mut list_lost)?
+        } else {
+            Vec::new()
+        };
+        if list_lost {
+            let found = self.children_of(offset);
+            if found.len() > subkeys.len() {
+                self.free_list(header.subkeys_list_offset);
+                let list = self.write_list(&found)?;
+                self.lost(
+                    path,
+                    list,
+                    format!("subkeys list rebuilt with the {} keys left", found.len()),
+                );
+                header.subkeys_list_offset = list;
+                subkeys = found;
+            }
+        }
+        if subkeys.is_empty() && header.subkeys_list_offset != NO_OFFSET {
+            self.free(header.subkeys_list_offset);
+            header.subkeys_list_offset = NO_OFFSET;
+        }
+        header.number_of_subkeys = subkeys.len() as u32;
+
+        self.write(offset, &header)?;
+
+        for (subkey, name) in subkeys {
+            self.prune_key(subkey, &join_path(path, &name), depth + 1)?;
+        }
+        Ok(())
+    }
+
+    // number of values left in the list
+    fn prune_values(&mut self, path: &str, list: u32, count: u32) -> anyhow::Result<u32> {
+        if count == 0 {
+            return Ok(0);
+        }
+        let Some(range) = self.cell(list) else {
+            self.lost(path, list, format!("values list of {count} values"));
+            return Ok(0);
+        };
+
+        // the end of a list cut by a cell size is lost
+        let present = (count as usize).min(range.len() / 4);
+        let offsets = key_values_list(&self.data[range], present)?;
+        if present < count as usize {
+            self.lost(
+                path,
+                list,
+                format!("{} values of the values list", count as usize - present),
+            );
+        }
+
+        let mut kept = Vec::with_capacity(offsets.len());
+        for offset in offsets {
+            let value = self
+                .record(offset, b"vk")
+                .and_then(|cell| KeyValue::try_from(cell).ok());
+            match value {
+                Some(value) if self.data_present(&value) => kept.push(offset),
+                Some(value) => self.lost(
+                    path,
+                    offset,
+                    format!("data of the value '{}'", value.display_name()),
+                ),
+                None => self.lost(path, offset, "value".to_string()),
+            }
+        }
+
+        if kept.len() != count as usize {
+            let bytes: Vec<u8> = kept.iter().flat_map(|o| o.to_le_bytes()).collect();
+            self.write_bytes(list, &bytes)?;
+        }
+        Ok(kept.len() as u32)
+    }
+
+    fn data_present(&self, value: &KeyValue) -> bool {
+        let size = (value.header.data_size & !DATA_IS_RESIDENT) as usize;
+        if value.is_resident() || size == 0 {
+            return true;
+        }
+
+        let Some(range) = self.cell(value.header.data_offset) else {
+            return false;
+        };
+        let cell = &self.data[range];
+        if size <= BIG_DATA_SEGMENT_SIZE || !cell.starts_with(b"db") {
+            return cell.len() >= size;
+        }
+
+        let Ok((db, _)) = bincode::decode_from_slice::<BigData, _>(cell, bincode_config()) else {
+            return false;
+        };
+        let Some(segments) = self.cell(db.segments_list_offset) else {
+            return false;
+        };
+        key_values_list(&self.data[segments], db.number_of_segments as usize)
+            .is_ok_and(|segments| segments.iter().all(|s| self.cell(*s).is_some()))
+    }
+
+    // key nodes left in the list and their names, the list being shrunk. Index root elements are
+    // lists themselves, emptied ones being freed
+    fn prune_subkeys(
+        &mut self,
+        path: &str,
+        list: u32,
+        depth: usize,
+        list_lost: &mut bool,
+    ) -> anyhow::Result<Vec<(u32, String)>> {
+        let parsed = self
+            .cell(list)
+            .and_then(|range| SubkeysList::try_from(&self.data[range]).ok());
+        let Some(parsed) = parsed else {
+            self.lost(path, list, "subkeys list".to_string());
+            *list_lost = true;
+            return Ok(Vec::new());
+        };
+
+        let count = parsed.offsets().len();
+        let mut subkeys = Vec::new();
+        let pruned = match parsed {
+            SubkeysList::IndexRoot(leaves) if depth == 0 => {
+                let mut kept = Vec::new();
+                for leaf in leaves {
+                    let leaf_subkeys = self.prune_subkeys(path, leaf, depth + 1, list_lost)?;
+                    if leaf_subkeys.is_empty() {
+                        self.free(leaf);
+                    } else {
+                        kept.push(leaf);
+                        subkeys.extend(leaf_subkeys);
+                    }
+                }
+                SubkeysList::IndexRoot(kept)
+            }
+            SubkeysList::IndexRoot(_) => {
+                self.lost(path, list, "nested index root".to_string());
+                *list_lost = true;
+                return Ok(Vec::new());
+            }
+            SubkeysList::IndexLeaf(offsets) => {
+                SubkeysList::IndexLeaf(self.keep_keys(path, offsets, |o| *o, &mut subkeys))
+            }
+            SubkeysList::FastLeaf(elements) => {
+                SubkeysList::FastLeaf(self.keep_keys(path, elements, |e| e.0, &mut subkeys))
+            }
+            SubkeysList::HashLeaf(elements) => {
+                SubkeysList::HashLeaf(self.keep_keys(path, elements, |e| e.0, &mut subkeys))
+            }
+        };
+
+        if pruned.offsets().len() != count {
+            self.write_bytes(list, &pruned.to_bytes())?;
+        }
+        Ok(subkeys)
+    }
+
+    // key nodes left whose parent is the key, in name order
+    fn children_of(&self, parent: u32) -> Vec<(u32, String)> {
+        let mut found: Vec<(u32, String)> = self
+            .children
+            .get(&parent)
+            .into_iter()
+            .flatten()
+            .filter_map(|offset| {
+                let node = KeyNode::try_from(self.record(*offset, b"nk")?).ok()?;
+                Some((*offset, node.name))
+            })
+            .collect();
+        found.sort_by_key(|(_, name)| name_sort_key(name));
+        found
+    }
+
+    fn free_list(&mut self, list: u32) {
+        let parsed = self
+            .cell(list)
+            .and_then(|range| SubkeysList::try_from(&self.data[range]).ok());
+        if let Some(SubkeysList::IndexRoot(leaves)) = parsed {
+            for leaf in leaves {
+                self.free(leaf);
+            }
+        }
+        self.free(list);
+    }
+
+    // hash leaves are used as of version 1.5, fast leaves before
+    fn write_list(&mut self, subkeys: &[(u32, String)]) -> anyhow::Result<u32> {
+        let mut leaves = Vec::new();
+        for chunk in subkeys.chunks(LEAF_MAX_ELEMENTS) {
+            let leaf = if self.minor_version >= 5 {
+                SubkeysList::HashLeaf(chunk.iter().map(|(o, n)| (*o, name_hash(n))).collect())
+            } else {
+                SubkeysList::FastLeaf(chunk.iter().map(|(o, n)| (*o, name_hint(n))).collect())
+            };
+            leaves.push(self.alloc(&leaf.to_bytes())?);
+        }
+        match leaves.as_slice() {
+            [leaf] => Ok(*leaf),
+            _ => self.alloc(&SubkeysList::IndexRoot(leaves).to_bytes()),
+        }
+    }
+
+    // elements whose key node is present
+    fn keep_keys<T>(
+        &mut self,
+        path: &str,
+        elements: Vec<T>,
+        offset: fn(&T) -> u32,
+        subkeys: &mut Vec<(u32, String)>,
+    ) -> Vec<T> {
+        let mut kept = Vec::with_capacity(elements.len());
+        for element in elements {
+            let key = offset(&element);
+            match self
+                .record(key, b"nk")
+                .and_then(|cell| KeyNode::try_from(cell).ok())
+            {
+                Some(node) => {
+                    subkeys.push((key, node.name));
+                    kept.push(element);
+                }
+                None => self.lost(path, key, "subkey and its subtree".to_string()),
+            }
+        }
+        kept
+    }
+
+    // the key securities used by the keys left form the list, in offset order. The others of the
+    // former list are freed
+    fn relink_securities(&mut self) -> anyhow::Result<()> {
+        let mut former = HashSet::new();
+        let mut offset = self.root_security;
+        while self.record(offset, b"sk").is_some() && former.insert(offset) {
+            offset = self.security_header(offset)?.flink;
+        }
+        for offset in former {
+            if !self.securities.contains_key(&offset) {
+                self.free(offset);
+            }
+        }
+
+        let offsets: Vec<u32> = self.securities.keys().copied().collect();
+        for (i, offset) in offsets.iter().enumerate() {
+            let mut header = self.security_header(*offset)?;
+            header.flink = offsets[(i + 1) % offsets.len()];
+            header.blink = offsets[(i + offsets.len() - 1) % offsets.len()];
+            header.reference_count = self.securities[offset];
+            self.write(*offset, &header)?;
+        }
+        Ok(())
+    }
+
+    fn security_header(&self, offset: u32) -> anyhow::Result<KeySecurityHeader> {
+        let cell = self
+            .record(offset, b"sk")
+            .ok_or_else(|| anyhow::anyhow!("key security at 0x{offset:X} lost"))?;
+        let (header, _) = bincode::decode_from_slice(cell, bincode_config())?;
+        Ok(header)
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        None => 0,
+    }
+}
@@ -0,0 +1,197 @@
+// Incremental re-parse of a hive file being written to, e.g. while watched
+//
+// the content last parsed is kept along with the cells each key and its values were read from. When
+// the file is read again, nothing is parsed if its sequence numbers and content are unchanged.
+// Otherwise the hbins are compared with the previous content page by page (hbins are made of 4 KB
+// pages), and only the keys read from modified pages have their values parsed again: the others are
+// taken from the previous snapshot.
+//
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    buffer::HiveBuffer,
+    diff::{Change, KeySnapshot, Snapshot, diff},
+    hive::{BASE_BLOCK_SIZE, RegistryHive},
+    record::{Record, key_records},
+};
+
+// hbins are sized in pages
+const PAGE_SIZE: usize = 4096;
+
+// a key as last parsed
+struct ParsedKey {
+    path: String,
+
+    // cells the key and its values were read from
+    cells: Vec<u32>,
+}
+
+// what is kept from a parse for the next one
+struct Parse {
+    snapshot: Snapshot,
+
+    // by key node offset
+    keys: HashMap<u32, ParsedKey>,
+}
+
+pub struct IncrementalSnapshot {
+    path: PathBuf,
+    key: String,
+
+    // content and sequence numbers of the file last parsed
+    data: HiveBuffer,
+    sequence_numbers: (u32, u32),
+
+    parse: Parse,
+}
+
+impl IncrementalSnapshot {
+    // snapshot of the subtree under key of a hive file
+    pub fn load(path: &Path, key: &str) -> anyhow::Result<Self> {
+        let mut hive = RegistryHive::read(path)?;
+        let parse = parse(&mut hive, path, key, None)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            key: key.to_string(),
+            data: hive.buffer().clone(),
+            sequence_numbers: sequence_numbers(&hive),
+            parse,
+        })
+    }
+
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.parse.snapshot
+    }
+
+    // changes since the file was last parsed, the previous snapshot being kept if it can't be parsed
+    pub fn update(&mut self) -> anyhow::Result<Vec<Change>> {
+        let mut hive = RegistryHive::read(&self.path)?;
+        if sequence_numbers(&hive) == self.sequence_numbers && hive.buffer()[..] == self.data[..] {
+            return Ok(Vec::new());
+        }
+
+        let modified = modified_pages(hive.buffer(), &self.data);
+        let parse = parse(
+            &mut hive,
+            &self.path,
+            &self.key,
+            Some((&self.parse, &modified)),
+        )?;
+
+        let changes = diff(&self.parse.snapshot, &parse.snapshot);
+        self.data = hive.buffer().clone();
+        self.sequence_numbers = sequence_numbers(&hive);
+        self.parse = parse;
+        Ok(changes)
+    }
+}
+
+fn sequence_numbers(hive: &RegistryHive) -> (u32, u32) {
+    (
+        hive.base_block.primary_sequence_number,
+        hive.base_block.secondary_sequence_number,
+    )
+}
+
+// pages of the hive bins data whose bytes differ from the previous content
+fn modified_pages(current: &[u8], previous: &[u8]) -> Vec<bool> {
+    let current = current.get(BASE_BLOCK_SIZE as usize..).unwrap_or_default();
+    let previous = previous.get(BASE_BLOCK_SIZE as usize..).unwrap_or_default();
+    current
+        .chunks(PAGE_SIZE)
+        .enumerate()
+        .map(|(i, page)| previous.get(i * PAGE_SIZE..i * PAGE_SIZE + page.len()) != Some(page))
+        .collect()
+}
+
+// whether the cell at an offset, size field included, lies in unmodified pages
+fn is_unchanged(hive: &RegistryHive, modified: &[bool], offset: u32) -> bool {
+    let start = BASE_BLOCK_SIZE as usize + offset as usize;
+    let Some(size) = hive.buffer().get(start..start + 4) else {
+        return false;
+    };
+    let size = i32::from_le_bytes(size.try_into().unwrap()).unsigned_abs() as usize;
+
+    let first = offset as usize / PAGE_SIZE;
+    let last = (offset as usize + size.max(4) - 1) / PAGE_SIZE;
+    modified
+        .get(first..=last)
+        .is_some_and(|pages| pages.iter().all(|m| !m))
+}
+
+// keys whose cells all lie in unmodified pages are taken from the previous parse
+fn parse(
+    hive: &mut RegistryHive,
+    path: &Path,
+    key: &str,
+    previous: Option<(&Parse, &[bool])>,
+) -> anyhow::Result<Parse> {
+    let (key_path, start) = hive
+        .open_key_path(key)?
+        .ok_or_else(|| anyhow::anyhow!("key '{key}' not found in {}", path.display()))?;
+
+    let mut snapshot = Snapshot::new();
+    let mut keys = HashMap::new();
+    hive.walk(&start, &key_path, &mut |hive, path, key| {
+        let reusable = match previous {
+            Some((parse, modified)) => parse.keys.get(&key.offset).filter(|k| {
+                k.cells
+                    .iter()
+                    .all(|offset| is_unchanged(hive, modified, *offset))
+            }),
+            None => None,
+        };
+        let reused = reusable.and_then(|k| {
+            let values = &previous?.0.snapshot.get(&k.path)?.values;
+            Some((k, values))
+        });
+
+        let (cells, values) = match reused {
+            Some((k, values)) if k.path == path => (k.cells.clone(), Arc::clone(values)),
+
+            // moved along with its parent
+            Some((k, values)) => {
+                let values = values
+                    .iter()
+                    .map(|(name, r)| {
+                        let record = Record {
+                            path: path.to_string(),
+                            ..r.clone()
+                        };
+                        (name.clone(), record)
+                    })
+                    .collect();
+                (k.cells.clone(), Arc::new(values))
+            }
+            None => {
+                let values = key_records(hive, path, key)?
+                    .into_iter()
+                    .map(|r| (r.value_name.clone(), r))
+                    .collect();
+                (hive.value_cells(key)?, Arc::new(values))
+            }
+        };
+
+        snapshot.insert(
+            path.to_string(),
+            KeySnapshot {
+                timestamp: key.node.header.last_written_timestamp,
+                values,
+            },
+        );
+        keys.insert(
+            key.offset,
+            ParsedKey {
+                path: path.to_string(),
+                cells,
+            },
+        );
+        Ok(())
+    })?;
+
+    Ok(Parse { snapshot, keys })
+}
@@ -0,0 +1,224 @@
+// Standalone HTML report of a hive: collapsible keys tree, searchable values table, anomaly findings
+// and the findings of the artifact plugins
+//
+// styles and scripts are inlined so the page can be attached to a case or opened offline. Keys are
+// nested <details> elements, written as the tree is walked.
+//
+use std::io::Write;
+
+use crate::{
+    findings::{Finding, Severity, sort_findings},
+    hive::RegistryHive,
+    plugins::{HiveType, RegPlugin},
+    record::key_records,
+    utils::{escape_name, filetime_to_string},
+};
+
+// data longer than this is cut in the values table, the full data is in the other exports
+const MAX_DATA_CHARS: usize = 256;
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+h1 { font-size: 1.4em; }
+h2 { font-size: 1.2em; border-bottom: 1px solid #ccc; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border: 1px solid #ddd; padding: 2px 6px; text-align: left; vertical-align: top; }
+th { background: #f0f0f0; }
+td, summary, .leaf { font-family: monospace; word-break: break-all; }
+details { margin-left: 1.2em; }
+.leaf { margin-left: 2.4em; }
+.meta { color: #777; }
+.HIGH { color: #b00; font-weight: bold; }
+.MEDIUM { color: #c60; }
+.LOW { color: #880; }
+.INFO { color: #777; }
+#search { width: 40em; margin-bottom: 0.5em; }";
+
+// rows not containing the searched text are hidden
+const SCRIPT: &str = "\
+document.getElementById('search').addEventListener('input', function () {
+  var q = this.value.toLowerCase();
+  document.querySelectorAll('#values tbody tr').forEach(function (row) {
+    row.hidden = q !== '' && row.textContent.toLowerCase().indexOf(q) < 0;
+  });
+});";
+
+// hive_name is the path of the hive file shown in the title. Plugins failing are listed with their
+// error.
+pub fn write_html_report<W: Write>(
+    out: &mut W,
+    hive: &mut RegistryHive,
+    hive_name: &str,
+    anomalies: &[Finding],
+    plugins: &[(&dyn RegPlugin, anyhow::Result<Vec<Finding>>)],
+) -> anyhow::Result<()> {
+    let title = html_escape(hive_name);
+    let hive_type = HiveType::detect(hive)?;
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html><head><meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>readregf report: {title}</title>")?;
+    writeln!(out, "<style>\n{STYLE}\n</style></head><body>")?;
+    writeln!(out, "<h1>{title}</h1>")?;
+    writeln!(
+        out,
+        "<p>{hive_type} hive, last written {}, report by readregf {}</p>",
+        filetime_to_string(hive.base_block.last_written_timestamp),
+        env!("CARGO_PKG_VERSION")
+    )?;
+
+    let mut anomalies = anomalies.to_vec();
+    sort_findings(&mut anomalies);
+    writeln!(out, "<h2>Anomalies ({})</h2>", anomalies.len())?;
+    write_findings(out, &anomalies)?;
+
+    writeln!(out, "<h2>Plugins ({})</h2>", plugins.len())?;
+    for (plugin, result) in plugins {
+        let name = html_escape(plugin.name());
+        let description = html_escape(plugin.description());
+        match result {
+            Ok(findings) => {
+                let mut findings = findings.clone();
+                sort_findings(&mut findings);
+                writeln!(
+                    out,
+                    "<h3>{name} ({})</h3><p class=\"meta\">{description}</p>",
+                    findings.len()
+                )?;
+                write_findings(out, &findings)?;
+            }
+            Err(e) => writeln!(
+                out,
+                "<h3>{name}</h3><p class=\"meta\">{description}</p><p class=\"HIGH\">failed: {}</p>",
+                html_escape(&format!("{e:#}"))
+            )?,
+        }
+    }
+
+    writeln!(out, "<h2>Keys</h2>")?;
+    write_tree(out, hive)?;
+
+    writeln!(out, "<h2>Values</h2>")?;
+    writeln!(
+        out,
+        "<input id=\"search\" type=\"search\" placeholder=\"search paths, names, types and data\">"
+    )?;
+    writeln!(out, "<table id=\"values\"><thead>")?;
+    writeln!(
+        out,
+        "<tr><th>path</th><th>name</th><th>type</th><th>size</th><th>data</th></tr>"
+    )?;
+    writeln!(out, "</thead><tbody>")?;
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |hive, path, key| {
+        for record in key_records(hive, path, key)? {
+            let mut data = record.data().to_string();
+            if let Some((cut, _)) = data.char_indices().nth(MAX_DATA_CHARS) {
+                data.truncate(cut);
+                data.push_str("...");
+            }
+            writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                html_escape(&escape_name(path)),
+                html_escape(&escape_name(record.display_name())),
+                record.reg_type,
+                record.data.len(),
+                html_escape(&escape_name(&data))
+            )?;
+        }
+        Ok(())
+    })?;
+    writeln!(out, "</tbody></table>")?;
+
+    writeln!(out, "<script>\n{SCRIPT}\n</script>")?;
+    writeln!(out, "</body></html>")?;
+    Ok(())
+}
+
+fn write_findings<W: Write>(out: &mut W, findings: &[Finding]) -> anyhow::Result<()> {
+    if findings.is_empty() {
+        writeln!(out, "<p class=\"meta\">no findings</p>")?;
+        return Ok(());
+    }
+
+    let counts: Vec<String> = [
+        Severity::High,
+        Severity::Medium,
+        Severity::Low,
+        Severity::Info,
+    ]
+    .iter()
+    .map(|s| {
+        format!(
+            "{s} {}",
+            findings.iter().filter(|f| f.severity == *s).count()
+        )
+    })
+    .collect();
+    writeln!(out, "<p class=\"meta\">{}</p>", counts.join(", "))?;
+
+    writeln!(
+        out,
+        "<table><tr><th>severity</th><th>category</th><th>path</th><th>description</th></tr>"
+    )?;
+    for finding in findings {
+        writeln!(
+            out,
+            "<tr><td class=\"{0}\">{0}</td><td>{1}</td><td>{2}</td><td>{3}</td></tr>",
+            finding.severity,
+            html_escape(&finding.category),
+            html_escape(&escape_name(&finding.path)),
+            html_escape(&finding.description)
+        )?;
+    }
+    writeln!(out, "</table>")?;
+    Ok(())
+}
+
+// keys with subkeys are <details> elements left open until the walk gets back to their parent, the
+// others are single lines
+fn write_tree<W: Write>(out: &mut W, hive: &mut RegistryHive) -> anyhow::Result<()> {
+    // offsets of the keys whose element is open
+    let mut open: Vec<u32> = Vec::new();
+
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |_, _, key| {
+        while open
+            .last()
+            .is_some_and(|offset| *offset != key.node.header.parent)
+        {
+            writeln!(out, "</details>")?;
+            open.pop();
+        }
+
+        let values = key.node.header.number_of_key_values;
+        let label = format!(
+            "{} <span class=\"meta\">{values} value{}, {}</span>",
+            html_escape(&escape_name(key.name())),
+            if values == 1 { "" } else { "s" },
+            filetime_to_string(key.node.header.last_written_timestamp)
+        );
+        if key.node.header.number_of_subkeys > 0 {
+            let state = if open.is_empty() { " open" } else { "" };
+            writeln!(out, "<details{state}><summary>{label}</summary>")?;
+            open.push(key.offset);
+        } else {
+            writeln!(out, "<div class=\"leaf\">{label}</div>")?;
+        }
+        Ok(())
+    })?;
+
+    for _ in open {
+        writeln!(out, "</details>")?;
+    }
+    Ok(())
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
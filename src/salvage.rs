@@ -0,0 +1,284 @@
+// Partial parsing of damaged hives, such as hives extracted from memory with paged out hive bins
+//
+// the hive bins are checked first: pages not covered by a valid hive bin are bad regions (zeroed when
+// they were paged out), as is the rest of a hive bin after an invalid cell. The keys tree is then
+// walked from the root, errors on lists, keys and values being recorded instead of stopping the walk.
+// Allocated key cells of the good hive bins not reached from the root are the roots of unreachable
+// subtrees, their parent or a list leading to them being lost: they're walked too, their paths
+// starting with "?" when their parent isn't known, as the paths of carved keys.
+//
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    path::Path,
+};
+
+use crate::{
+    hive::{BASE_BLOCK_SIZE, Key, MAX_DEPTH, RegistryHive, join_path},
+    key::NO_OFFSET,
+    value::{KeyValue, RegData},
+};
+
+const PAGE_SIZE: u32 = 4096;
+const HIVE_BIN_HEADER_SIZE: u32 = 32;
+
+#[derive(Debug, Clone)]
+pub struct BadRegion {
+    // relative to the start of the hive bins data
+    pub offset: u32,
+    pub size: u32,
+    pub reason: String,
+}
+
+impl fmt::Display for BadRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:08X}-0x{:08X}\t{}",
+            self.offset,
+            self.offset + self.size,
+            self.reason
+        )
+    }
+}
+
+// a record which couldn't be read
+#[derive(Debug, Clone)]
+pub struct Damage {
+    // key owning the record
+    pub path: String,
+    pub offset: u32,
+    pub error: String,
+}
+
+impl fmt::Display for Damage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\t0x{:X}\t{}", self.path, self.offset, self.error)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SalvagedKey {
+    pub path: String,
+    pub key: Key,
+
+    // false for keys of unreachable subtrees
+    pub reachable: bool,
+
+    // None when the data couldn't be read
+    pub values: Vec<(KeyValue, Option<RegData>)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Salvage {
+    pub bad_regions: Vec<BadRegion>,
+    pub keys: Vec<SalvagedKey>,
+    pub damages: Vec<Damage>,
+}
+
+pub fn salvage(path: &Path) -> anyhow::Result<Salvage> {
+    let mut hive = RegistryHive::open(path)?;
+    let bytes = hive.buffer().clone();
+    let bins = bytes.get(BASE_BLOCK_SIZE as usize..).unwrap_or_default();
+
+    let mut salvage = Salvage::default();
+    let key_cells = check_bins(bins, hive.base_block.hive_bins_data_size, &mut salvage);
+
+    let mut walker = Walker {
+        salvage,
+        paths: HashMap::new(),
+    };
+    match hive.root() {
+        Ok(root) => walker.walk(&mut hive, &root, "\\", true, 0),
+        Err(e) => walker.damage("\\", hive.base_block.root_cell_offset, e),
+    }
+
+    // unreachable subtrees, from their topmost key whose parent is known or lost
+    for offset in &key_cells {
+        if walker.paths.contains_key(offset) {
+            continue;
+        }
+        let Ok(mut top) = hive.key(*offset) else {
+            continue;
+        };
+        for _ in 0..MAX_DEPTH {
+            let parent = top.node.header.parent;
+            if top.node.is_root()
+                || !key_cells.contains(&parent)
+                || walker.paths.contains_key(&parent)
+            {
+                break;
+            }
+            match hive.key(parent) {
+                Ok(key) => top = key,
+                Err(_) => break,
+            }
+        }
+        if walker.paths.contains_key(&top.offset) {
+            continue;
+        }
+
+        let path = match walker.paths.get(&top.node.header.parent) {
+            Some(parent) if !top.node.is_root() => join_path(parent, top.name()),
+            _ => format!("?\\{}", top.name()),
+        };
+        walker.walk(&mut hive, &top, &path, false, 0);
+    }
+
+    Ok(walker.salvage)
+}
+
+// records the bad regions of the hive bins data, returns the offsets of the allocated key cells in
+// order
+fn check_bins(bins: &[u8], bins_size: u32, salvage: &mut Salvage) -> BTreeSet<u32> {
+    let mut key_cells = BTreeSet::new();
+    let available = bins.len().min(bins_size as usize) as u32;
+
+    let mut bad = |offset: u32, size: u32, reason: &str| match salvage.bad_regions.last_mut() {
+        Some(last) if last.offset + last.size == offset && last.reason == reason => {
+            last.size += size
+        }
+        _ => salvage.bad_regions.push(BadRegion {
+            offset,
+            size,
+            reason: reason.to_string(),
+        }),
+    };
+
+    let mut position = 0;
+    while position < bins_size {
+        if position >= available {
+            bad(position, bins_size - position, "missing from the file");
+            break;
+        }
+
+        let page = &bins[position as usize..(position + PAGE_SIZE).min(available) as usize];
+        let bin_size = u32_at(page, 8);
+        let valid = page.starts_with(b"hbin")
+            && u32_at(page, 4) == position
+            && bin_size != 0
+            && bin_size.is_multiple_of(PAGE_SIZE)
+            && bin_size <= available - position;
+        if !valid {
+            let reason = if is_zeroed(page) {
+                "zeroed"
+            } else {
+                "no hive bin header"
+            };
+            bad(position, page.len() as u32, reason);
+            position += PAGE_SIZE;
+            continue;
+        }
+
+        // cells of the bin, up to the first invalid one
+        let end = position + bin_size;
+        let mut cell = position + HIVE_BIN_HEADER_SIZE;
+        while cell < end {
+            let size = u32_at(bins, cell as usize) as i32;
+            let abs_size = size.unsigned_abs();
+            if abs_size < 8 || !abs_size.is_multiple_of(8) || abs_size > end - cell {
+                let reason = if is_zeroed(&bins[cell as usize..end as usize]) {
+                    "zeroed"
+                } else {
+                    "invalid cell"
+                };
+                bad(cell, end - cell, reason);
+                break;
+            }
+            if size < 0 && bins[cell as usize + 4..].starts_with(b"nk") {
+                key_cells.insert(cell);
+            }
+            cell += abs_size;
+        }
+        position = end;
+    }
+
+    key_cells
+}
+
+fn is_zeroed(bytes: &[u8]) -> bool {
+    bytes.iter().all(|b| *b == 0)
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    match bytes.get(offset..offset + 4) {
+        Some(b) => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        None => 0,
+    }
+}
+
+struct Walker {
+    salvage: Salvage,
+
+    // keys already walked
+    paths: HashMap<u32, String>,
+}
+
+impl Walker {
+    fn damage(&mut self, path: &str, offset: u32, error: anyhow::Error) {
+        self.salvage.damages.push(Damage {
+            path: path.to_string(),
+            offset,
+            error: format!("{error:#}"),
+        });
+    }
+
+    fn walk(
+        &mut self,
+        hive: &mut RegistryHive,
+        key: &Key,
+        path: &str,
+        reachable: bool,
+        depth: usize,
+    ) {
+        if depth > MAX_DEPTH || self.paths.contains_key(&key.offset) {
+            return;
+        }
+        self.paths.insert(key.offset, path.to_string());
+
+        let mut values = Vec::new();
+        match hive.value_offsets(key) {
+            Ok(offsets) => {
+                for offset in offsets {
+                    match hive.value(offset) {
+                        Ok(value) => {
+                            let data = match hive.value_data(&value) {
+                                Ok(data) => Some(data),
+                                Err(e) => {
+                                    self.damage(path, value.header.data_offset, e);
+                                    None
+                                }
+                            };
+                            values.push((value, data));
+                        }
+                        Err(e) => self.damage(path, offset, e),
+                    }
+                }
+            }
+            Err(e) => self.damage(path, key.node.header.key_values_list_offset, e),
+        }
+        self.salvage.keys.push(SalvagedKey {
+            path: path.to_string(),
+            key: key.clone(),
+            reachable,
+            values,
+        });
+
+        let offsets = match hive.subkey_offsets(key) {
+            Ok(offsets) => offsets,
+            Err(e) => {
+                self.damage(path, key.node.header.subkeys_list_offset, e);
+                return;
+            }
+        };
+        for offset in offsets.into_iter().filter(|o| *o != NO_OFFSET) {
+            match hive.key(offset) {
+                Ok(subkey) => {
+                    let subpath = join_path(path, subkey.name());
+                    self.walk(hive, &subkey, &subpath, reachable, depth + 1);
+                }
+                Err(e) => self.damage(path, offset, e),
+            }
+        }
+    }
+}
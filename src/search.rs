@@ -0,0 +1,83 @@
+// Search of key names, value names and string data with a wildcard pattern
+use std::fmt;
+
+use crate::{
+    filter::ValueFilter,
+    hive::{Key, RegistryHive},
+    record::{Record, key_records},
+    utils::glob_match,
+    value::RegData,
+};
+
+#[derive(Debug, Clone)]
+pub enum SearchMatch {
+    Key(String),
+    Value(Record),
+}
+
+impl fmt::Display for SearchMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchMatch::Key(path) => write!(f, "{path}"),
+            SearchMatch::Value(record) => write!(
+                f,
+                "{}\t{}\t{}\t{}",
+                record.path,
+                record.display_name(),
+                record.reg_type,
+                record.data()
+            ),
+        }
+    }
+}
+
+// key names match only when no value filter is set, values must match the pattern on their
+// name or string data and pass the filter
+pub fn search<F>(
+    hive: &mut RegistryHive,
+    key: &Key,
+    path: &str,
+    pattern: &str,
+    filter: &ValueFilter,
+    f: &mut F,
+) -> anyhow::Result<()>
+where
+    F: FnMut(&SearchMatch) -> anyhow::Result<()>,
+{
+    // values are read and matched in parallel
+    hive.par_walk(
+        key,
+        path,
+        |hive, path, key| {
+            // the key matches even when its values can't be read
+            let key_match = filter.is_empty() && glob_match(pattern, key.name());
+            let records = key_records(hive, path, key).map(|records| {
+                records
+                    .into_iter()
+                    .filter(|r| {
+                        filter.matches(r)
+                            && (glob_match(pattern, r.display_name()) || data_matches(pattern, r))
+                    })
+                    .collect::<Vec<Record>>()
+            });
+            Ok((key_match, records))
+        },
+        &mut |path, _, (key_match, records)| {
+            if key_match {
+                f(&SearchMatch::Key(path.to_string()))?;
+            }
+            for record in records? {
+                f(&SearchMatch::Value(record))?;
+            }
+            Ok(())
+        },
+    )
+}
+
+fn data_matches(pattern: &str, record: &Record) -> bool {
+    match record.data() {
+        RegData::Sz(s) | RegData::ExpandSz(s) | RegData::Link(s) => glob_match(pattern, &s),
+        RegData::MultiSz(v) => v.iter().any(|s| glob_match(pattern, s)),
+        _ => false,
+    }
+}
@@ -0,0 +1,648 @@
+// Key security (sk) record
+// see: https://github.com/msuhanov/regf/blob/master/Windows%20registry%20file%20format%20specification.md#key-security
+//
+// all key security items form a doubly linked list, a security descriptor is shared between keys
+//
+// +------+----------+-------+-------+-----------+-----------------+------------------------+
+// | "sk" | reserved | flink | blink | ref count | descriptor size | security descriptor... |
+// +------+----------+-------+-------+-----------+-----------------+------------------------+
+//
+use std::{fmt, str::FromStr};
+
+use bincode::{Decode, Encode};
+
+use crate::reg::bincode_config;
+
+// fixed part of a key security, the security descriptor follows
+#[derive(Debug, Clone, Decode, Encode)]
+pub struct KeySecurityHeader {
+    // ASCII string
+    pub signature: [u8; 2],
+
+    //
+    pub reserved: u16,
+
+    // In bytes, relative from the start of the hive bins data
+    pub flink: u32,
+
+    // In bytes, relative from the start of the hive bins data
+    pub blink: u32,
+
+    // Number of key nodes pointing to this item
+    pub reference_count: u32,
+
+    // In bytes
+    pub security_descriptor_size: u32,
+}
+
+// size of the fixed part
+pub const KEY_SECURITY_HEADER_SIZE: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct KeySecurity {
+    pub header: KeySecurityHeader,
+
+    // self-relative security descriptor
+    pub descriptor: Vec<u8>,
+}
+
+// bytes start at the signature
+impl TryFrom<&[u8]> for KeySecurity {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let (header, _): (KeySecurityHeader, usize) =
+            bincode::decode_from_slice(data, bincode_config())?;
+        anyhow::ensure!(&header.signature == b"sk", "not a key security");
+
+        let size = header.security_descriptor_size as usize;
+        let descriptor = data
+            .get(KEY_SECURITY_HEADER_SIZE..KEY_SECURITY_HEADER_SIZE + size)
+            .ok_or_else(|| anyhow::anyhow!("security descriptor overflows cell"))?
+            .to_vec();
+
+        Ok(Self { header, descriptor })
+    }
+}
+
+// Self-relative security descriptor
+// see: https://learn.microsoft.com/en-us/windows/win32/api/winnt/ns-winnt-security_descriptor_relative
+#[derive(Debug, Clone, Decode, Encode)]
+pub struct SecurityDescriptorHeader {
+    pub revision: u8,
+    pub sbz1: u8,
+
+    // SE_xxx flags
+    pub control: u16,
+
+    // offsets are relative to the start of the security descriptor, 0 means not present
+    pub owner_offset: u32,
+    pub group_offset: u32,
+    pub sacl_offset: u32,
+    pub dacl_offset: u32,
+}
+
+// security descriptor control flags
+pub const SE_DACL_PRESENT: u16 = 0x0004;
+pub const SE_SACL_PRESENT: u16 = 0x0010;
+pub const SE_DACL_AUTO_INHERIT_REQ: u16 = 0x0100;
+pub const SE_DACL_AUTO_INHERITED: u16 = 0x0400;
+pub const SE_DACL_PROTECTED: u16 = 0x1000;
+pub const SE_SELF_RELATIVE: u16 = 0x8000;
+
+// ACE types
+pub const ACCESS_ALLOWED_ACE_TYPE: u8 = 0x00;
+pub const ACCESS_DENIED_ACE_TYPE: u8 = 0x01;
+pub const SYSTEM_AUDIT_ACE_TYPE: u8 = 0x02;
+pub const ACCESS_ALLOWED_OBJECT_ACE_TYPE: u8 = 0x05;
+pub const ACCESS_DENIED_OBJECT_ACE_TYPE: u8 = 0x06;
+pub const SYSTEM_AUDIT_OBJECT_ACE_TYPE: u8 = 0x07;
+pub const SYSTEM_MANDATORY_LABEL_ACE_TYPE: u8 = 0x11;
+
+// ACE flags
+pub const OBJECT_INHERIT_ACE: u8 = 0x01;
+pub const CONTAINER_INHERIT_ACE: u8 = 0x02;
+pub const NO_PROPAGATE_INHERIT_ACE: u8 = 0x04;
+pub const INHERIT_ONLY_ACE: u8 = 0x08;
+pub const INHERITED_ACE: u8 = 0x10;
+pub const SUCCESSFUL_ACCESS_ACE_FLAG: u8 = 0x40;
+pub const FAILED_ACCESS_ACE_FLAG: u8 = 0x80;
+
+// registry access rights
+pub const KEY_QUERY_VALUE: u32 = 0x0001;
+pub const KEY_SET_VALUE: u32 = 0x0002;
+pub const KEY_CREATE_SUB_KEY: u32 = 0x0004;
+pub const KEY_ENUMERATE_SUB_KEYS: u32 = 0x0008;
+pub const KEY_NOTIFY: u32 = 0x0010;
+pub const KEY_CREATE_LINK: u32 = 0x0020;
+pub const DELETE: u32 = 0x0001_0000;
+pub const READ_CONTROL: u32 = 0x0002_0000;
+pub const WRITE_DAC: u32 = 0x0004_0000;
+pub const WRITE_OWNER: u32 = 0x0008_0000;
+pub const GENERIC_ALL: u32 = 0x1000_0000;
+pub const GENERIC_EXECUTE: u32 = 0x2000_0000;
+pub const GENERIC_WRITE: u32 = 0x4000_0000;
+pub const GENERIC_READ: u32 = 0x8000_0000;
+pub const KEY_READ: u32 = 0x0002_0019;
+pub const KEY_WRITE: u32 = 0x0002_0006;
+pub const KEY_ALL_ACCESS: u32 = 0x000F_003F;
+
+// any of these rights allows to modify the key, its values or its security
+pub const KEY_WRITE_RIGHTS: u32 = KEY_SET_VALUE
+    | KEY_CREATE_SUB_KEY
+    | KEY_CREATE_LINK
+    | DELETE
+    | WRITE_DAC
+    | WRITE_OWNER
+    | GENERIC_ALL
+    | GENERIC_WRITE;
+
+// well-known SIDs: string form, SDDL alias, account name
+const WELL_KNOWN_SIDS: &[(&str, &str, &str)] = &[
+    ("S-1-1-0", "WD", "Everyone"),
+    ("S-1-3-0", "CO", "CREATOR OWNER"),
+    ("S-1-3-1", "CG", "CREATOR GROUP"),
+    ("S-1-5-2", "NU", "NETWORK"),
+    ("S-1-5-4", "IU", "INTERACTIVE"),
+    ("S-1-5-6", "SU", "SERVICE"),
+    ("S-1-5-7", "AN", "ANONYMOUS LOGON"),
+    ("S-1-5-9", "ED", "ENTERPRISE DOMAIN CONTROLLERS"),
+    ("S-1-5-10", "PS", "SELF"),
+    ("S-1-5-11", "AU", "Authenticated Users"),
+    ("S-1-5-12", "RC", "RESTRICTED"),
+    ("S-1-5-18", "SY", "SYSTEM"),
+    ("S-1-5-19", "LS", "LOCAL SERVICE"),
+    ("S-1-5-20", "NS", "NETWORK SERVICE"),
+    ("S-1-5-32-544", "BA", "Administrators"),
+    ("S-1-5-32-545", "BU", "Users"),
+    ("S-1-5-32-546", "BG", "Guests"),
+    ("S-1-5-32-547", "PU", "Power Users"),
+    ("S-1-5-32-551", "BO", "Backup Operators"),
+    ("S-1-5-32-555", "RD", "Remote Desktop Users"),
+    ("S-1-15-2-1", "AC", "ALL APPLICATION PACKAGES"),
+    ("S-1-16-4096", "LW", "Low Mandatory Level"),
+    ("S-1-16-8192", "ME", "Medium Mandatory Level"),
+    ("S-1-16-12288", "HI", "High Mandatory Level"),
+    ("S-1-16-16384", "SI", "System Mandatory Level"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Sid {
+    pub revision: u8,
+    pub authority: u64,
+    pub sub_authorities: Vec<u32>,
+}
+
+impl Sid {
+    // parse a binary SID, returns the SID and its size
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<(Self, usize)> {
+        anyhow::ensure!(data.len() >= 8, "SID too small");
+        let count = data[1] as usize;
+        let size = 8 + 4 * count;
+        anyhow::ensure!(data.len() >= size, "SID overflows security descriptor");
+
+        // identifier authority is big endian
+        let authority = data[2..8]
+            .iter()
+            .fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        let sub_authorities = data[8..size]
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+
+        Ok((
+            Self {
+                revision: data[0],
+                authority,
+                sub_authorities,
+            },
+            size,
+        ))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.revision, self.sub_authorities.len() as u8];
+        bytes.extend_from_slice(&self.authority.to_be_bytes()[2..]);
+        for sub in &self.sub_authorities {
+            bytes.extend_from_slice(&sub.to_le_bytes());
+        }
+        bytes
+    }
+
+    // SDDL alias like "BA" when the SID is well-known
+    pub fn alias(&self) -> Option<&'static str> {
+        let s = self.to_string();
+        WELL_KNOWN_SIDS
+            .iter()
+            .find(|(sid, _, _)| *sid == s)
+            .map(|(_, alias, _)| *alias)
+    }
+
+    // account name like "Administrators" when the SID is well-known
+    pub fn account_name(&self) -> Option<&'static str> {
+        let s = self.to_string();
+        WELL_KNOWN_SIDS
+            .iter()
+            .find(|(sid, _, _)| *sid == s)
+            .map(|(_, _, name)| *name)
+    }
+
+    // SDDL representation: alias if well-known, string form otherwise
+    pub fn to_sddl(&self) -> String {
+        self.alias()
+            .map(String::from)
+            .unwrap_or_else(|| self.to_string())
+    }
+}
+
+impl fmt::Display for Sid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "S-{}-{}", self.revision, self.authority)?;
+        for sub in &self.sub_authorities {
+            write!(f, "-{sub}")?;
+        }
+        Ok(())
+    }
+}
+
+// accepts "S-1-5-32-544", an SDDL alias ("BA") or an account name ("Administrators")
+impl FromStr for Sid {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let string_sid = WELL_KNOWN_SIDS
+            .iter()
+            .find(|(_, alias, name)| alias.eq_ignore_ascii_case(s) || name.eq_ignore_ascii_case(s))
+            .map(|(sid, _, _)| *sid)
+            .unwrap_or(s);
+
+        let parts: Vec<&str> = string_sid.split('-').collect();
+        anyhow::ensure!(
+            parts.len() >= 3 && parts[0].eq_ignore_ascii_case("S"),
+            "invalid SID '{s}'"
+        );
+
+        Ok(Self {
+            revision: parts[1].parse()?,
+            authority: parts[2].parse()?,
+            sub_authorities: parts[3..]
+                .iter()
+                .map(|p| p.parse())
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Ace {
+    pub ace_type: u8,
+    pub flags: u8,
+    pub mask: u32,
+
+    // None for ACE types which are not decoded
+    pub sid: Option<Sid>,
+}
+
+impl Ace {
+    // inherit-only ACEs don't apply to the key itself
+    pub fn applies_to_key(&self) -> bool {
+        self.flags & INHERIT_ONLY_ACE == 0
+    }
+
+    pub fn to_sddl(&self) -> String {
+        let ace_type = match self.ace_type {
+            ACCESS_ALLOWED_ACE_TYPE => "A".to_string(),
+            ACCESS_DENIED_ACE_TYPE => "D".to_string(),
+            SYSTEM_AUDIT_ACE_TYPE => "AU".to_string(),
+            ACCESS_ALLOWED_OBJECT_ACE_TYPE => "OA".to_string(),
+            ACCESS_DENIED_OBJECT_ACE_TYPE => "OD".to_string(),
+            SYSTEM_AUDIT_OBJECT_ACE_TYPE => "OU".to_string(),
+            SYSTEM_MANDATORY_LABEL_ACE_TYPE => "ML".to_string(),
+            t => format!("0x{t:X}"),
+        };
+
+        let mut flags = String::new();
+        for (flag, s) in [
+            (OBJECT_INHERIT_ACE, "OI"),
+            (CONTAINER_INHERIT_ACE, "CI"),
+            (NO_PROPAGATE_INHERIT_ACE, "NP"),
+            (INHERIT_ONLY_ACE, "IO"),
+            (INHERITED_ACE, "ID"),
+            (SUCCESSFUL_ACCESS_ACE_FLAG, "SA"),
+            (FAILED_ACCESS_ACE_FLAG, "FA"),
+        ] {
+            if self.flags & flag != 0 {
+                flags.push_str(s);
+            }
+        }
+
+        let sid = self.sid.as_ref().map(|s| s.to_sddl()).unwrap_or_default();
+        format!("({ace_type};{flags};{};;;{sid})", rights_to_sddl(self.mask))
+    }
+
+    // human readable form, e.g. "Allow Everyone KEY_READ [CI]"
+    pub fn expanded(&self) -> String {
+        let ace_type = match self.ace_type {
+            ACCESS_ALLOWED_ACE_TYPE | ACCESS_ALLOWED_OBJECT_ACE_TYPE => "Allow",
+            ACCESS_DENIED_ACE_TYPE | ACCESS_DENIED_OBJECT_ACE_TYPE => "Deny",
+            SYSTEM_AUDIT_ACE_TYPE | SYSTEM_AUDIT_OBJECT_ACE_TYPE => "Audit",
+            SYSTEM_MANDATORY_LABEL_ACE_TYPE => "Label",
+            _ => "Unknown",
+        };
+        let sid = match &self.sid {
+            Some(sid) => match sid.account_name() {
+                Some(name) => format!("{name} ({sid})"),
+                None => sid.to_string(),
+            },
+            None => "?".to_string(),
+        };
+
+        let mut flags = Vec::new();
+        for (flag, s) in [
+            (OBJECT_INHERIT_ACE, "OI"),
+            (CONTAINER_INHERIT_ACE, "CI"),
+            (NO_PROPAGATE_INHERIT_ACE, "NP"),
+            (INHERIT_ONLY_ACE, "IO"),
+            (INHERITED_ACE, "ID"),
+        ] {
+            if self.flags & flag != 0 {
+                flags.push(s);
+            }
+        }
+
+        format!(
+            "{ace_type} {sid} {} [{}]",
+            rights_to_names(self.mask),
+            flags.join(",")
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Acl {
+    pub revision: u8,
+    pub aces: Vec<Ace>,
+}
+
+impl Acl {
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(data.len() >= 8, "ACL too small");
+        let count = u16::from_le_bytes([data[4], data[5]]) as usize;
+
+        let mut aces = Vec::with_capacity(count);
+        let mut pos = 8;
+        for _ in 0..count {
+            let header = data
+                .get(pos..pos + 4)
+                .ok_or_else(|| anyhow::anyhow!("ACE overflows ACL"))?;
+            let ace_type = header[0];
+            let flags = header[1];
+            let size = u16::from_le_bytes([header[2], header[3]]) as usize;
+            anyhow::ensure!(size >= 8, "invalid ACE size {size}");
+            let body = data
+                .get(pos + 4..pos + size)
+                .ok_or_else(|| anyhow::anyhow!("ACE overflows ACL"))?;
+
+            let mask = u32::from_le_bytes(body[..4].try_into().unwrap());
+            let sid = match ace_type {
+                ACCESS_ALLOWED_ACE_TYPE
+                | ACCESS_DENIED_ACE_TYPE
+                | SYSTEM_AUDIT_ACE_TYPE
+                | SYSTEM_MANDATORY_LABEL_ACE_TYPE => Sid::from_bytes(&body[4..]).ok().map(|s| s.0),
+
+                // object ACEs have optional GUIDs before the SID
+                ACCESS_ALLOWED_OBJECT_ACE_TYPE
+                | ACCESS_DENIED_OBJECT_ACE_TYPE
+                | SYSTEM_AUDIT_OBJECT_ACE_TYPE => {
+                    let object_flags = body
+                        .get(4..8)
+                        .map(|f| u32::from_le_bytes(f.try_into().unwrap()))
+                        .unwrap_or_default();
+                    let mut sid_pos = 8;
+                    if object_flags & 1 != 0 {
+                        sid_pos += 16;
+                    }
+                    if object_flags & 2 != 0 {
+                        sid_pos += 16;
+                    }
+                    body.get(sid_pos..)
+                        .and_then(|b| Sid::from_bytes(b).ok())
+                        .map(|s| s.0)
+                }
+                _ => None,
+            };
+
+            aces.push(Ace {
+                ace_type,
+                flags,
+                mask,
+                sid,
+            });
+            pos += size;
+        }
+
+        Ok(Self {
+            revision: data[0],
+            aces,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SecurityDescriptor {
+    pub header: SecurityDescriptorHeader,
+    pub owner: Option<Sid>,
+    pub group: Option<Sid>,
+    pub dacl: Option<Acl>,
+    pub sacl: Option<Acl>,
+}
+
+impl TryFrom<&[u8]> for SecurityDescriptor {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let (header, _): (SecurityDescriptorHeader, usize) =
+            bincode::decode_from_slice(data, bincode_config())?;
+        anyhow::ensure!(header.revision == 1, "unknown security descriptor revision");
+
+        let at = |offset: u32| -> anyhow::Result<&[u8]> {
+            data.get(offset as usize..)
+                .ok_or_else(|| anyhow::anyhow!("offset 0x{offset:X} overflows security descriptor"))
+        };
+
+        let owner = match header.owner_offset {
+            0 => None,
+            o => Some(Sid::from_bytes(at(o)?)?.0),
+        };
+        let group = match header.group_offset {
+            0 => None,
+            o => Some(Sid::from_bytes(at(o)?)?.0),
+        };
+        let dacl = match header.dacl_offset {
+            0 => None,
+            _ if header.control & SE_DACL_PRESENT == 0 => None,
+            o => Some(Acl::from_bytes(at(o)?)?),
+        };
+        let sacl = match header.sacl_offset {
+            0 => None,
+            _ if header.control & SE_SACL_PRESENT == 0 => None,
+            o => Some(Acl::from_bytes(at(o)?)?),
+        };
+
+        Ok(Self {
+            header,
+            owner,
+            group,
+            dacl,
+            sacl,
+        })
+    }
+}
+
+impl SecurityDescriptor {
+    pub fn to_sddl(&self) -> String {
+        let mut sddl = String::new();
+
+        if let Some(owner) = &self.owner {
+            sddl.push_str(&format!("O:{}", owner.to_sddl()));
+        }
+        if let Some(group) = &self.group {
+            sddl.push_str(&format!("G:{}", group.to_sddl()));
+        }
+        if let Some(dacl) = &self.dacl {
+            sddl.push_str("D:");
+            sddl.push_str(&self.acl_flags());
+            for ace in &dacl.aces {
+                sddl.push_str(&ace.to_sddl());
+            }
+        }
+        if let Some(sacl) = &self.sacl {
+            sddl.push_str("S:");
+            for ace in &sacl.aces {
+                sddl.push_str(&ace.to_sddl());
+            }
+        }
+
+        sddl
+    }
+
+    fn acl_flags(&self) -> String {
+        let mut flags = String::new();
+        if self.header.control & SE_DACL_PROTECTED != 0 {
+            flags.push('P');
+        }
+        if self.header.control & SE_DACL_AUTO_INHERIT_REQ != 0 {
+            flags.push_str("AR");
+        }
+        if self.header.control & SE_DACL_AUTO_INHERITED != 0 {
+            flags.push_str("AI");
+        }
+        flags
+    }
+
+    // rights effectively granted to a SID on the key itself, considering deny ACEs in order
+    pub fn granted_rights(&self, sid: &Sid) -> u32 {
+        let Some(dacl) = &self.dacl else {
+            // no DACL: everyone has full access
+            return KEY_ALL_ACCESS | GENERIC_ALL;
+        };
+
+        let mut granted = 0u32;
+        let mut denied = 0u32;
+        for ace in dacl.aces.iter().filter(|a| a.applies_to_key()) {
+            if ace.sid.as_ref() != Some(sid) {
+                continue;
+            }
+            match ace.ace_type {
+                ACCESS_ALLOWED_ACE_TYPE => granted |= ace.mask & !denied,
+                ACCESS_DENIED_ACE_TYPE => denied |= ace.mask & !granted,
+                _ => (),
+            }
+        }
+
+        granted
+    }
+
+    pub fn writable_by(&self, sid: &Sid) -> bool {
+        self.granted_rights(sid) & KEY_WRITE_RIGHTS != 0
+    }
+}
+
+// self-relative descriptor of new hives: owned by Administrators, full control for SYSTEM and
+// Administrators and read access for Everyone, inherited by subkeys
+// (O:BAG:SYD:P(A;CI;KA;;;SY)(A;CI;KA;;;BA)(A;CI;KR;;;WD))
+pub fn default_descriptor() -> Vec<u8> {
+    let sid = |s: &str| {
+        s.parse::<Sid>()
+            .map(|sid| sid.to_bytes())
+            .unwrap_or_default()
+    };
+
+    let mut aces = Vec::new();
+    for (mask, trustee) in [
+        (KEY_ALL_ACCESS, "SY"),
+        (KEY_ALL_ACCESS, "BA"),
+        (KEY_READ, "WD"),
+    ] {
+        let trustee = sid(trustee);
+        aces.push(ACCESS_ALLOWED_ACE_TYPE);
+        aces.push(CONTAINER_INHERIT_ACE);
+        aces.extend_from_slice(&(8 + trustee.len() as u16).to_le_bytes());
+        aces.extend_from_slice(&mask.to_le_bytes());
+        aces.extend_from_slice(&trustee);
+    }
+
+    let mut dacl = vec![2, 0];
+    dacl.extend_from_slice(&(8 + aces.len() as u16).to_le_bytes());
+    dacl.extend_from_slice(&3u16.to_le_bytes());
+    dacl.extend_from_slice(&[0, 0]);
+    dacl.extend_from_slice(&aces);
+
+    let owner = sid("BA");
+    let group = sid("SY");
+    let dacl_offset = 20u32;
+    let owner_offset = dacl_offset + dacl.len() as u32;
+    let group_offset = owner_offset + owner.len() as u32;
+
+    let mut descriptor = vec![1, 0];
+    descriptor
+        .extend_from_slice(&(SE_SELF_RELATIVE | SE_DACL_PRESENT | SE_DACL_PROTECTED).to_le_bytes());
+    for offset in [owner_offset, group_offset, 0, dacl_offset] {
+        descriptor.extend_from_slice(&offset.to_le_bytes());
+    }
+    descriptor.extend_from_slice(&dacl);
+    descriptor.extend_from_slice(&owner);
+    descriptor.extend_from_slice(&group);
+    descriptor
+}
+
+// SDDL rights string: a well-known combination or an hex mask
+pub fn rights_to_sddl(mask: u32) -> String {
+    match mask {
+        KEY_ALL_ACCESS => "KA".to_string(),
+        KEY_READ => "KR".to_string(),
+        KEY_WRITE => "KW".to_string(),
+        GENERIC_ALL => "GA".to_string(),
+        GENERIC_READ => "GR".to_string(),
+        GENERIC_WRITE => "GW".to_string(),
+        GENERIC_EXECUTE => "GX".to_string(),
+        _ => format!("0x{mask:X}"),
+    }
+}
+
+// rights names separated by "|"
+pub fn rights_to_names(mask: u32) -> String {
+    match mask {
+        KEY_ALL_ACCESS => return "KEY_ALL_ACCESS".to_string(),
+        KEY_READ => return "KEY_READ".to_string(),
+        KEY_WRITE => return "KEY_WRITE".to_string(),
+        _ => (),
+    }
+
+    let names: Vec<&str> = [
+        (KEY_QUERY_VALUE, "KEY_QUERY_VALUE"),
+        (KEY_SET_VALUE, "KEY_SET_VALUE"),
+        (KEY_CREATE_SUB_KEY, "KEY_CREATE_SUB_KEY"),
+        (KEY_ENUMERATE_SUB_KEYS, "KEY_ENUMERATE_SUB_KEYS"),
+        (KEY_NOTIFY, "KEY_NOTIFY"),
+        (KEY_CREATE_LINK, "KEY_CREATE_LINK"),
+        (DELETE, "DELETE"),
+        (READ_CONTROL, "READ_CONTROL"),
+        (WRITE_DAC, "WRITE_DAC"),
+        (WRITE_OWNER, "WRITE_OWNER"),
+        (GENERIC_ALL, "GENERIC_ALL"),
+        (GENERIC_EXECUTE, "GENERIC_EXECUTE"),
+        (GENERIC_WRITE, "GENERIC_WRITE"),
+        (GENERIC_READ, "GENERIC_READ"),
+    ]
+    .iter()
+    .filter(|(right, _)| mask & right != 0)
+    .map(|(_, name)| *name)
+    .collect();
+
+    if names.is_empty() {
+        format!("0x{mask:X}")
+    } else {
+        names.join("|")
+    }
+}
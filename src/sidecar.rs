@@ -0,0 +1,170 @@
+// Index of the key paths of a hive, kept in a file next to the hive
+//
+// opening a key by path reads the node of each component and searches its subkey list. The first run
+// against a big hive walks the tree once and saves the offset of each key next to the hive
+// (SYSTEM.keyidx): later runs load it instead, after checking it was written for the same content.
+// The check reads the base block only, hashing the hive would cost more than the walk saved: the file
+// size, the sequence numbers and last written time bumped by every write, the hive bins data size and
+// the checksum of the base block.
+//
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use bincode::{Decode, Encode};
+
+use crate::{
+    hive::{RegistryHive, name_sort_key},
+    reg::bincode_config,
+};
+
+const SIDECAR_SIGNATURE: [u8; 4] = *b"rkix";
+const SIDECAR_VERSION: u32 = 3;
+
+// what the index was built from
+#[derive(Debug, PartialEq, Encode, Decode)]
+struct Fingerprint {
+    size: u64,
+    primary_sequence_number: u32,
+    secondary_sequence_number: u32,
+    last_written_timestamp: u64,
+    hive_bins_data_size: u32,
+    checksum: u32,
+}
+
+impl Fingerprint {
+    fn of(hive: &RegistryHive) -> Self {
+        Self {
+            size: hive.buffer().len() as u64,
+            primary_sequence_number: hive.base_block.primary_sequence_number,
+            secondary_sequence_number: hive.base_block.secondary_sequence_number,
+            last_written_timestamp: hive.base_block.last_written_timestamp,
+            hive_bins_data_size: hive.base_block.hive_bins_data_size,
+            checksum: hive.base_block.checksum,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct KeyLocation {
+    // with the case of the names in the hive
+    pub path: String,
+
+    // key node
+    pub offset: u32,
+}
+
+#[derive(Encode, Decode)]
+struct Sidecar {
+    signature: [u8; 4],
+    version: u32,
+    fingerprint: Fingerprint,
+    keys: Vec<KeyLocation>,
+}
+
+#[derive(Debug, Default)]
+pub struct KeyPathIndex {
+    // by uppercase path, without the leading backslash
+    keys: HashMap<Vec<u16>, KeyLocation>,
+}
+
+impl KeyPathIndex {
+    // all the keys reachable from the root
+    pub fn build(hive: &mut RegistryHive) -> anyhow::Result<Self> {
+        let mut locations = Vec::new();
+        let root = hive.root()?;
+        hive.walk(&root, "\\", &mut |_, path, key| {
+            locations.push(KeyLocation {
+                path: path.to_string(),
+                offset: key.offset,
+            });
+            Ok(())
+        })?;
+        Ok(Self::from_locations(locations))
+    }
+
+    fn from_locations(locations: Vec<KeyLocation>) -> Self {
+        let mut keys = HashMap::with_capacity(locations.len());
+        for location in locations {
+            // the first of keys with the same name wins, as when searching subkey lists
+            keys.entry(path_key(&location.path)).or_insert(location);
+        }
+        Self { keys }
+    }
+
+    // None when there's no index for this content of the hive
+    pub fn load(path: &Path, hive: &RegistryHive) -> anyhow::Result<Option<Self>> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let Ok((sidecar, _)) = bincode::decode_from_slice::<Sidecar, _>(&bytes, bincode_config())
+        else {
+            return Ok(None);
+        };
+        if sidecar.signature != SIDECAR_SIGNATURE
+            || sidecar.version != SIDECAR_VERSION
+            || sidecar.fingerprint != Fingerprint::of(hive)
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(Self::from_locations(sidecar.keys)))
+    }
+
+    pub fn save(&self, path: &Path, hive: &RegistryHive) -> anyhow::Result<()> {
+        let mut keys: Vec<_> = self.keys.values().cloned().collect();
+        keys.sort_by(|a, b| a.path.cmp(&b.path));
+
+        let sidecar = Sidecar {
+            signature: SIDECAR_SIGNATURE,
+            version: SIDECAR_VERSION,
+            fingerprint: Fingerprint::of(hive),
+            keys,
+        };
+        std::fs::write(path, bincode::encode_to_vec(&sidecar, bincode_config())?)?;
+        Ok(())
+    }
+
+    // the index next to the hive file, built and saved if missing or out of date. Failing to save
+    // it only means building it again next time
+    pub fn load_or_build(hive_path: &Path, hive: &mut RegistryHive) -> anyhow::Result<Self> {
+        let path = sidecar_path(hive_path);
+        if let Some(index) = Self::load(&path, hive)? {
+            return Ok(index);
+        }
+
+        let index = Self::build(hive)?;
+        if let Err(e) = index.save(&path, hive) {
+            eprintln!("warning: can't write key index {}: {e}", path.display());
+        }
+        Ok(index)
+    }
+
+    // key names are compared case-insensitively
+    pub fn get(&self, path: &str) -> Option<&KeyLocation> {
+        self.keys.get(&path_key(path))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+pub fn sidecar_path(hive_path: &Path) -> PathBuf {
+    let mut name = hive_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".keyidx");
+    hive_path.with_file_name(name)
+}
+
+fn path_key(path: &str) -> Vec<u16> {
+    let path: Vec<_> = path.split('\\').filter(|n| !n.is_empty()).collect();
+    name_sort_key(&path.join("\\"))
+}
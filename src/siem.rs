@@ -0,0 +1,104 @@
+// Findings written as CEF or LEEF events, the line formats read by SIEM log collectors
+//
+// CEF (ArcSight Common Event Format):
+//   CEF:0|readregf|readregf|VERSION|CATEGORY|DESCRIPTION|SEVERITY|filePath=HIVE cs1Label=...
+// LEEF (QRadar Log Event Extended Format), attributes separated by tabs:
+//   LEEF:1.0|readregf|readregf|VERSION|CATEGORY|cat=CATEGORY	sev=SEVERITY	resource=...
+// The category of a finding is the event identifier, its severity is mapped to the 0-10 scale of
+// both formats, the key or value path it's about is a custom string (CEF) or the resource (LEEF).
+//
+use std::{fmt, str::FromStr};
+
+use crate::findings::{Finding, Severity};
+
+const VENDOR: &str = "readregf";
+const PRODUCT: &str = "readregf";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SiemFormat {
+    Cef,
+    Leef,
+}
+
+impl FromStr for SiemFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cef" => Ok(SiemFormat::Cef),
+            "leef" => Ok(SiemFormat::Leef),
+            _ => anyhow::bail!("unknown SIEM format '{s}' (cef or leef)"),
+        }
+    }
+}
+
+impl fmt::Display for SiemFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SiemFormat::Cef => write!(f, "cef"),
+            SiemFormat::Leef => write!(f, "leef"),
+        }
+    }
+}
+
+fn severity_number(severity: Severity) -> u8 {
+    match severity {
+        Severity::Info => 1,
+        Severity::Low => 3,
+        Severity::Medium => 6,
+        Severity::High => 9,
+    }
+}
+
+// a single line, hive is the path of the hive file the finding was made in
+pub fn siem_event(format: SiemFormat, finding: &Finding, hive: &str) -> String {
+    let version = env!("CARGO_PKG_VERSION");
+    let severity = severity_number(finding.severity);
+    match format {
+        SiemFormat::Cef => format!(
+            "CEF:0|{VENDOR}|{PRODUCT}|{version}|{}|{}|{severity}|filePath={} cs1Label=registryPath \
+             cs1={} cs2Label=severity cs2={} msg={}",
+            cef_header(&finding.category),
+            cef_header(&finding.description),
+            cef_extension(hive),
+            cef_extension(&finding.path),
+            finding.severity,
+            cef_extension(&finding.description)
+        ),
+        SiemFormat::Leef => format!(
+            "LEEF:1.0|{VENDOR}|{PRODUCT}|{version}|{}|cat={}\tsev={severity}\tfilePath={}\t\
+             resource={}\tmsg={}",
+            leef_header(&finding.category),
+            leef_attribute(&finding.category),
+            leef_attribute(hive),
+            leef_attribute(&finding.path),
+            leef_attribute(&finding.description)
+        ),
+    }
+}
+
+// line breaks aren't allowed anywhere, pipes and backslashes are escaped in headers
+fn cef_header(s: &str) -> String {
+    single_line(s).replace('\\', "\\\\").replace('|', "\\|")
+}
+
+// equal signs and backslashes are escaped in extension values, line breaks written as \n
+fn cef_extension(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace("\r\n", "\\n")
+        .replace(['\r', '\n'], "\\n")
+}
+
+fn leef_header(s: &str) -> String {
+    single_line(s).replace('|', "\\|")
+}
+
+// tabs separate the attributes
+fn leef_attribute(s: &str) -> String {
+    single_line(s).replace('\t', " ")
+}
+
+fn single_line(s: &str) -> String {
+    s.replace("\r\n", " ").replace(['\r', '\n'], " ")
+}
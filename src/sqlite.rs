@@ -0,0 +1,232 @@
+// Keys, values, security descriptors and deleted records written to a SQLite database
+//
+// tables are normalized: values reference their key, keys their parent and their security
+// descriptor, each sk cell being written once whatever the number of keys sharing it. Paths are
+// compared case-insensitively (COLLATE NOCASE) as in the registry, and are indexed together with
+// timestamps, written as "YYYY-MM-DD HH:MM:SS" so that SQLite date functions apply. The value
+// filter only applies to values: all the keys of the subtree are written. Deleted records are the
+// ones recovered under the subtree.
+//
+use std::{collections::HashMap, path::Path};
+
+use rusqlite::{Connection, params};
+
+use crate::{
+    deleted::{deleted_keys, deleted_values},
+    filter::ValueFilter,
+    hive::{Key, RegistryHive},
+    key::NO_OFFSET,
+    record::Record,
+    security::SecurityDescriptor,
+    utils::filetime_to_string,
+    value::utf16_string,
+};
+
+const SCHEMA: &str = r#"
+CREATE TABLE security_descriptors (
+    id INTEGER PRIMARY KEY,
+    offset INTEGER NOT NULL,
+    reference_count INTEGER NOT NULL,
+    sddl TEXT,
+    descriptor BLOB NOT NULL
+);
+CREATE TABLE keys (
+    id INTEGER PRIMARY KEY,
+    parent_id INTEGER REFERENCES keys(id),
+    path TEXT NOT NULL COLLATE NOCASE,
+    name TEXT NOT NULL COLLATE NOCASE,
+    last_written TEXT NOT NULL,
+    flags INTEGER NOT NULL,
+    class_name TEXT,
+    security_id INTEGER REFERENCES security_descriptors(id),
+    offset INTEGER NOT NULL
+);
+CREATE TABLE "values" (
+    id INTEGER PRIMARY KEY,
+    key_id INTEGER NOT NULL REFERENCES keys(id),
+    name TEXT NOT NULL COLLATE NOCASE,
+    type TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    data TEXT NOT NULL,
+    raw BLOB NOT NULL,
+    offset INTEGER NOT NULL
+);
+CREATE TABLE deleted_records (
+    id INTEGER PRIMARY KEY,
+    kind TEXT NOT NULL,
+    offset INTEGER NOT NULL,
+    path TEXT COLLATE NOCASE,
+    name TEXT COLLATE NOCASE,
+    last_written TEXT,
+    type TEXT,
+    data TEXT
+);
+CREATE INDEX keys_path ON keys(path);
+CREATE INDEX keys_last_written ON keys(last_written);
+CREATE INDEX keys_parent ON keys(parent_id);
+CREATE INDEX values_key ON "values"(key_id);
+CREATE INDEX values_name ON "values"(name);
+CREATE INDEX deleted_records_path ON deleted_records(path);
+CREATE INDEX deleted_records_last_written ON deleted_records(last_written);
+"#;
+
+// rows written to each table
+#[derive(Debug, Default)]
+pub struct SqliteReport {
+    pub keys: usize,
+    pub values: usize,
+    pub descriptors: usize,
+    pub deleted: usize,
+}
+
+// an existing database file is replaced
+pub fn export_sqlite(
+    hive: &mut RegistryHive,
+    key: &Key,
+    path: &str,
+    filter: &ValueFilter,
+    db: &Path,
+) -> anyhow::Result<SqliteReport> {
+    if db.exists() {
+        std::fs::remove_file(db)?;
+    }
+    let mut connection = Connection::open(db)?;
+    connection.execute_batch(SCHEMA)?;
+
+    // a single transaction, rows are inserted much faster
+    let transaction = connection.transaction()?;
+    let mut report = SqliteReport::default();
+    {
+        let mut insert_descriptor = transaction.prepare(
+            "INSERT INTO security_descriptors (offset, reference_count, sddl, descriptor)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let mut insert_key = transaction.prepare(
+            "INSERT INTO keys (parent_id, path, name, last_written, flags, class_name,
+             security_id, offset) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?;
+        let mut insert_value = transaction.prepare(
+            "INSERT INTO \"values\" (key_id, name, type, size, data, raw, offset)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+
+        // row ids by cell offset
+        let mut descriptors: HashMap<u32, i64> = HashMap::new();
+        let mut keys: HashMap<u32, i64> = HashMap::new();
+
+        hive.walk(key, path, &mut |hive, path, key| {
+            let h = &key.node.header;
+
+            let security_id = match descriptors.get(&h.key_security_offset) {
+                Some(id) => Some(*id),
+                None if h.key_security_offset == NO_OFFSET => None,
+                None => {
+                    let security = hive.security(key)?;
+                    let sddl = SecurityDescriptor::try_from(&security.descriptor[..])
+                        .ok()
+                        .map(|sd| sd.to_sddl());
+                    insert_descriptor.execute(params![
+                        h.key_security_offset,
+                        security.header.reference_count,
+                        sddl,
+                        security.descriptor,
+                    ])?;
+                    let id = transaction.last_insert_rowid();
+                    descriptors.insert(h.key_security_offset, id);
+                    report.descriptors += 1;
+                    Some(id)
+                }
+            };
+
+            let class_name = if h.class_name_offset != NO_OFFSET && h.class_name_length > 0 {
+                let (_, data) = hive.cell(h.class_name_offset)?;
+                Some(utf16_string(
+                    data.get(..h.class_name_length as usize).unwrap_or(data),
+                ))
+            } else {
+                None
+            };
+
+            insert_key.execute(params![
+                keys.get(&h.parent),
+                path,
+                key.name(),
+                filetime_to_string(h.last_written_timestamp),
+                h.flags,
+                class_name,
+                security_id,
+                key.offset,
+            ])?;
+            let key_id = transaction.last_insert_rowid();
+            keys.insert(key.offset, key_id);
+            report.keys += 1;
+
+            for offset in hive.value_offsets(key)? {
+                let value = hive.value(offset)?;
+                let record = Record {
+                    path: path.to_string(),
+                    timestamp: h.last_written_timestamp,
+                    value_name: value.name.clone(),
+                    reg_type: value.reg_type(),
+                    data: hive.value_bytes(&value)?.into_owned(),
+                };
+                if !filter.matches(&record) {
+                    continue;
+                }
+                insert_value.execute(params![
+                    key_id,
+                    record.value_name,
+                    record.reg_type.to_string(),
+                    record.data.len(),
+                    record.data().to_string(),
+                    record.data,
+                    offset,
+                ])?;
+                report.values += 1;
+            }
+            Ok(())
+        })?;
+
+        // records of the subtree, all of them (orphans included) for the whole hive
+        let prefix = format!("{}\\", path.to_lowercase());
+        let in_subtree = |record_path: &str| {
+            let record_path = format!("{}\\", record_path.to_lowercase());
+            path == "\\" || record_path.starts_with(&prefix)
+        };
+        let mut insert_deleted = transaction.prepare(
+            "INSERT INTO deleted_records (kind, offset, path, name, last_written, type, data)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        let deleted = deleted_keys(hive)?;
+        for key in deleted.iter().filter(|k| in_subtree(&k.path)) {
+            insert_deleted.execute(params![
+                "key",
+                key.offset,
+                key.path,
+                key.node.name,
+                filetime_to_string(key.node.header.last_written_timestamp),
+                None::<String>,
+                None::<String>,
+            ])?;
+            report.deleted += 1;
+        }
+        for value in deleted_values(hive, &deleted)? {
+            if !in_subtree(value.path.as_deref().unwrap_or("?")) {
+                continue;
+            }
+            insert_deleted.execute(params![
+                "value",
+                value.offset,
+                value.path,
+                value.value.name,
+                None::<String>,
+                value.value.reg_type().to_string(),
+                value.data.map(|d| d.to_string()),
+            ])?;
+            report.deleted += 1;
+        }
+    }
+    transaction.commit()?;
+
+    Ok(report)
+}
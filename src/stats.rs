@@ -0,0 +1,210 @@
+// Hive composition metrics: cells per type, allocated vs free space, keys tree shape
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::Path,
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    hive::RegistryHive,
+    reg::{HiveBin, RegistryFile},
+};
+
+// upper bounds of free cell sizes buckets
+const FREE_BUCKETS: &[u32] = &[16, 64, 256, 1024, 4096, u32::MAX];
+
+#[derive(Debug, Default)]
+pub struct CellStats {
+    pub count: usize,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct HiveStats {
+    pub hbins: usize,
+    pub hbins_bytes: u64,
+
+    // allocated cells per type ("nk", "vk", ...)
+    pub cell_types: BTreeMap<String, CellStats>,
+    pub allocated: CellStats,
+    pub free: CellStats,
+    pub largest_free_cell: u32,
+
+    // number of free cells per size bucket, see FREE_BUCKETS
+    pub free_distribution: Vec<usize>,
+
+    pub keys: usize,
+    pub values: usize,
+    pub deepest_key: (usize, String),
+
+    // path, value name, data size
+    pub largest_value: (String, String, u32),
+
+    // number of keys referencing each sk record
+    pub security_refs: HashMap<u32, usize>,
+}
+
+impl HiveStats {
+    // 0 when all free space is contiguous, close to 1 when it's split into many small cells
+    pub fn fragmentation(&self) -> f64 {
+        if self.free.bytes == 0 {
+            0.0
+        } else {
+            1.0 - self.largest_free_cell as f64 / self.free.bytes as f64
+        }
+    }
+}
+
+// first pass: cells of all hbins, hbins being counted in parallel
+pub fn cell_stats(path: &Path, stats: &mut HiveStats) -> anyhow::Result<()> {
+    let mut regf = RegistryFile::try_from(path)?;
+    regf.read_header()?;
+    stats.free_distribution = vec![0; FREE_BUCKETS.len()];
+
+    let hbins: Vec<HiveBin> = regf.by_ref().collect();
+    let counts: Vec<HiveStats> = hbins.into_par_iter().map(hbin_stats).collect();
+
+    for count in counts {
+        stats.hbins += count.hbins;
+        stats.hbins_bytes += count.hbins_bytes;
+        for (cell_type, cs) in count.cell_types {
+            let entry = stats.cell_types.entry(cell_type).or_default();
+            entry.count += cs.count;
+            entry.bytes += cs.bytes;
+        }
+        stats.allocated.count += count.allocated.count;
+        stats.allocated.bytes += count.allocated.bytes;
+        stats.free.count += count.free.count;
+        stats.free.bytes += count.free.bytes;
+        stats.largest_free_cell = stats.largest_free_cell.max(count.largest_free_cell);
+        for (total, n) in stats
+            .free_distribution
+            .iter_mut()
+            .zip(count.free_distribution)
+        {
+            *total += n;
+        }
+    }
+
+    Ok(())
+}
+
+// cells of a single hbin
+fn hbin_stats(mut hbin: HiveBin) -> HiveStats {
+    let mut stats = HiveStats {
+        hbins: 1,
+        hbins_bytes: hbin.header.size as u64,
+        free_distribution: vec![0; FREE_BUCKETS.len()],
+        ..Default::default()
+    };
+
+    for cell in &mut hbin {
+        let size = cell.size.unsigned_abs();
+
+        // a free cell has a positive size
+        if cell.size > 0 {
+            stats.free.count += 1;
+            stats.free.bytes += size as u64;
+            stats.largest_free_cell = stats.largest_free_cell.max(size);
+            let bucket = FREE_BUCKETS.iter().position(|b| size <= *b).unwrap();
+            stats.free_distribution[bucket] += 1;
+        } else {
+            stats.allocated.count += 1;
+            stats.allocated.bytes += size as u64;
+            let entry = stats.cell_types.entry(cell.r#type.to_string()).or_default();
+            entry.count += 1;
+            entry.bytes += size as u64;
+        }
+    }
+
+    stats
+}
+
+// second pass: keys tree
+pub fn tree_stats(hive: &mut RegistryHive, stats: &mut HiveStats) -> anyhow::Result<()> {
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |hive, path, key| {
+        stats.keys += 1;
+
+        let depth = path.matches('\\').count() - usize::from(path == "\\");
+        if depth > stats.deepest_key.0 || stats.deepest_key.1.is_empty() {
+            stats.deepest_key = (depth, path.to_string());
+        }
+
+        *stats
+            .security_refs
+            .entry(key.node.header.key_security_offset)
+            .or_default() += 1;
+
+        for value in hive.values(key)? {
+            stats.values += 1;
+            if value.data_size() > stats.largest_value.2 {
+                stats.largest_value = (
+                    path.to_string(),
+                    value.display_name().to_string(),
+                    value.data_size(),
+                );
+            }
+        }
+        Ok(())
+    })
+}
+
+impl fmt::Display for HiveStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "hbins: {} ({} bytes)", self.hbins, self.hbins_bytes)?;
+        writeln!(
+            f,
+            "allocated cells: {} ({} bytes)",
+            self.allocated.count, self.allocated.bytes
+        )?;
+        for (cell_type, cs) in &self.cell_types {
+            writeln!(f, "    {cell_type}: {} ({} bytes)", cs.count, cs.bytes)?;
+        }
+
+        writeln!(
+            f,
+            "free cells: {} ({} bytes)",
+            self.free.count, self.free.bytes
+        )?;
+        writeln!(f, "    largest free cell: {} bytes", self.largest_free_cell)?;
+        writeln!(f, "    fragmentation: {:.2}", self.fragmentation())?;
+        let mut previous = 0;
+        for (bucket, count) in FREE_BUCKETS.iter().zip(&self.free_distribution) {
+            if *bucket == u32::MAX {
+                writeln!(f, "    > {previous} bytes: {count}")?;
+            } else {
+                writeln!(f, "    {}-{bucket} bytes: {count}", previous + 1)?;
+            }
+            previous = *bucket;
+        }
+
+        writeln!(f, "keys: {}", self.keys)?;
+        writeln!(f, "values: {}", self.values)?;
+        writeln!(
+            f,
+            "deepest key: {} (depth {})",
+            self.deepest_key.1, self.deepest_key.0
+        )?;
+        writeln!(
+            f,
+            "largest value: {} {} ({} bytes)",
+            self.largest_value.0, self.largest_value.1, self.largest_value.2
+        )?;
+
+        // sk sharing
+        let sk_count = self.security_refs.len();
+        let most_shared = self.security_refs.values().max().copied().unwrap_or(0);
+        writeln!(f, "security descriptors: {sk_count}")?;
+        if sk_count > 0 {
+            writeln!(
+                f,
+                "    keys per descriptor: {:.2} average, {most_shared} max",
+                self.keys as f64 / sk_count as f64
+            )?;
+        }
+        Ok(())
+    }
+}
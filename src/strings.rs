@@ -0,0 +1,91 @@
+// Extraction of ASCII and UTF-16LE strings from cells, like the strings(1) utility
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Ascii,
+    Utf16,
+    Both,
+}
+
+impl FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ascii" => Ok(Encoding::Ascii),
+            "utf16" | "utf-16" => Ok(Encoding::Utf16),
+            "both" => Ok(Encoding::Both),
+            _ => anyhow::bail!("unknown encoding '{s}' (ascii, utf16 or both)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FoundString {
+    // position in the scanned data
+    pub position: usize,
+    pub utf16: bool,
+    pub value: String,
+}
+
+impl fmt::Display for FoundString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let enc = if self.utf16 { "utf16" } else { "ascii" };
+        write!(f, "{enc} {}", self.value)
+    }
+}
+
+fn is_printable(b: u8) -> bool {
+    (0x20..0x7F).contains(&b) || b == b'\t'
+}
+
+// strings of at least min_len characters, sorted by position
+pub fn extract_strings(data: &[u8], min_len: usize, encoding: Encoding) -> Vec<FoundString> {
+    let mut found = Vec::new();
+
+    if encoding != Encoding::Utf16 {
+        let mut start = 0;
+        for (i, b) in data.iter().chain(std::iter::once(&0)).enumerate() {
+            if !is_printable(*b) {
+                if i - start >= min_len {
+                    found.push(FoundString {
+                        position: start,
+                        utf16: false,
+                        value: String::from_utf8_lossy(&data[start..i]).to_string(),
+                    });
+                }
+                start = i + 1;
+            }
+        }
+    }
+
+    // printable characters followed by a null byte, at both alignments
+    if encoding != Encoding::Ascii {
+        for alignment in 0..2 {
+            let mut start = alignment;
+            let mut current = String::new();
+
+            // the last iteration reads past the end to flush the current string
+            for i in (alignment..=data.len()).step_by(2) {
+                match data.get(i..i + 2) {
+                    Some([c, 0]) if is_printable(*c) => current.push(*c as char),
+                    _ => {
+                        if current.len() >= min_len {
+                            found.push(FoundString {
+                                position: start,
+                                utf16: true,
+                                value: current.clone(),
+                            });
+                        }
+                        current.clear();
+                        start = i + 2;
+                    }
+                }
+            }
+        }
+    }
+
+    found.sort_by_key(|s| s.position);
+    found
+}
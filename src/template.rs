@@ -0,0 +1,134 @@
+// User defined output format for records, e.g. "{path}\t{value_name}\t{data}"
+//
+// fields are enclosed in braces ("{{" and "}}" for literal braces), and \t, \n and \\ escapes are
+// accepted because shells don't expand them inside quotes.
+use std::str::FromStr;
+
+use crate::{record::Record, utils::filetime_to_string, value::hex_string};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Field {
+    Path,
+    KeyName,
+    Timestamp,
+    ValueName,
+    Type,
+    Size,
+    Data,
+    Hex,
+}
+
+const FIELDS: [(&str, Field); 8] = [
+    ("path", Field::Path),
+    ("key_name", Field::KeyName),
+    ("timestamp", Field::Timestamp),
+    ("value_name", Field::ValueName),
+    ("type", Field::Type),
+    ("size", Field::Size),
+    ("data", Field::Data),
+    ("hex", Field::Hex),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn render(&self, record: &Record) -> String {
+        let mut s = String::new();
+
+        for part in &self.parts {
+            match part {
+                Part::Literal(l) => s.push_str(l),
+                Part::Field(field) => match field {
+                    Field::Path => s.push_str(&record.path),
+                    Field::KeyName => s.push_str(record.key_name()),
+                    Field::Timestamp => s.push_str(&filetime_to_string(record.timestamp)),
+                    Field::ValueName => s.push_str(record.display_name()),
+                    Field::Type => s.push_str(&record.reg_type.to_string()),
+                    Field::Size => s.push_str(&record.data.len().to_string()),
+                    Field::Data => s.push_str(&record.data().to_string()),
+                    Field::Hex => s.push_str(&hex_string(&record.data)),
+                },
+            }
+        }
+
+        s
+    }
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    anyhow::ensure!(closed, "unterminated template field '{{{name}'");
+
+                    let field = FIELDS
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map(|(_, f)| *f)
+                        .ok_or_else(|| {
+                            let names: Vec<&str> = FIELDS.iter().map(|(n, _)| *n).collect();
+                            anyhow::anyhow!(
+                                "unknown template field '{{{name}}}' (available: {})",
+                                names.join(", ")
+                            )
+                        })?;
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                _ => literal.push(c),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Template { parts })
+    }
+}
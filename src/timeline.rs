@@ -0,0 +1,43 @@
+// Timeline of all keys sorted by their last written timestamp
+use std::fmt;
+
+use crate::{hive::RegistryHive, utils::filetime_to_string};
+
+#[derive(Debug, Clone)]
+pub struct TimelineEntry {
+    // FILETIME (UTC)
+    pub timestamp: u64,
+    pub path: String,
+}
+
+impl TimelineEntry {
+    // "YYYY-MM-DD" part of the timestamp, used for grouping
+    pub fn day(&self) -> String {
+        filetime_to_string(self.timestamp)[..10].to_string()
+    }
+}
+
+impl fmt::Display for TimelineEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", filetime_to_string(self.timestamp), self.path)
+    }
+}
+
+// all keys of the hive, oldest first
+pub fn timeline(hive: &mut RegistryHive) -> anyhow::Result<Vec<TimelineEntry>> {
+    let mut entries = Vec::new();
+
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |_, path, key| {
+        entries.push(TimelineEntry {
+            timestamp: key.node.header.last_written_timestamp,
+            path: path.to_string(),
+        });
+        Ok(())
+    })?;
+
+    // stable sort: keys with the same timestamp stay in tree order
+    entries.sort_by_key(|e| e.timestamp);
+
+    Ok(entries)
+}
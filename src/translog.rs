@@ -0,0 +1,485 @@
+// Transaction log files (.LOG1, .LOG2)
+// see: https://github.com/msuhanov/regf/blob/master/Windows%20registry%20file%20format%20specification.md#format-of-transaction-log-files
+//
+// as of Windows 8.1, a log file is a partial copy of the base block (first sector) followed by log
+// entries, each one holding the hive bins data pages modified by a write operation:
+//
+// +------------+----------+----------+-----+
+// | Base block | HvLE     | HvLE     | ... |
+// | (sector)   | entry    | entry    |     |
+// +------------+----------+----------+-----+
+//
+// +--------+------+-------+-----------------+-----------+-------------+--------+--------+------------+-------+
+// | "HvLE" | size | flags | sequence number | bins size | pages count | hash-1 | hash-2 | page refs  | pages |
+// +--------+------+-------+-----------------+-----------+-------------+--------+--------+------------+-------+
+//
+// before Windows 8.1, a log file holds a single set of dirty pages: the base block is followed by a
+// dirty vector, a bitmap where each bit is set for a dirty 512 bytes page of the hive bins data, then
+// by the dirty pages themselves, starting at the next sector:
+//
+// +------------+--------+---------------+-------+-------+-----+
+// | Base block | "DIRT" | bitmap        | page  | page  | ... |
+// | (sector)   |        |               | (512) | (512) |     |
+// +------------+--------+---------------+-------+-------+-----+
+//
+use std::{
+    fmt,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    buffer::default_options,
+    diff::{Change, diff, snapshot},
+    hive::RegistryHive,
+    reg::{BaseBlock, base_block_checksum, bincode_config},
+};
+
+// the base block copy and log entries are aligned on sectors
+const SECTOR_SIZE: usize = 512;
+
+// fixed part of a log entry, dirty page references follow
+const LOG_ENTRY_HEADER_SIZE: usize = 40;
+
+// seed of the Marvin32 hashes of log entries
+const MARVIN32_SEED: u64 = 0x82EF_4D88_7A4E_55C5;
+
+// file type of a log file in the new format
+const LOG_FILE_TYPE: u32 = 6;
+
+// unit of the dirty pages written by Windows
+const PAGE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub struct DirtyPage {
+    // offset relative to the start of the hive bins data
+    pub offset: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    // offset in the log file
+    pub file_offset: usize,
+    pub size: u32,
+
+    // partial copy of the base block flags
+    pub flags: u32,
+
+    // primary and secondary sequence numbers of the hive once this entry is applied
+    pub sequence_number: u32,
+    pub hive_bins_data_size: u32,
+
+    pub hash1: u64,
+    pub hash2: u64,
+
+    // both hashes match the entry content, an entry with invalid hashes must not be applied
+    pub hashes_valid: bool,
+    pub pages: Vec<DirtyPage>,
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:X}\tsequence {}\t{} pages\thive bins data size 0x{:X}\t{}",
+            self.file_offset,
+            self.sequence_number,
+            self.pages.len(),
+            self.hive_bins_data_size,
+            if self.hashes_valid {
+                "valid"
+            } else {
+                "invalid hashes"
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    // dirty vector, before Windows 8.1
+    Legacy,
+
+    // HvLE log entries
+    New,
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Legacy => write!(f, "legacy"),
+            LogFormat::New => write!(f, "new"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TransactionLog {
+    pub format: LogFormat,
+
+    // only the first sector is kept in the log, the remaining bytes are zeroed
+    pub base_block: BaseBlock,
+
+    // a legacy log has a single entry, whose hashes are replaced by the base block checksum
+    pub entries: Vec<LogEntry>,
+}
+
+impl TransactionLog {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        Self::parse(&bytes)
+    }
+
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let first_sector = bytes
+            .get(..SECTOR_SIZE)
+            .ok_or_else(|| anyhow::anyhow!("log file is too small"))?;
+
+        let mut block = first_sector.to_vec();
+        block.resize(4096, 0);
+        let (base_block, _): (BaseBlock, usize) =
+            bincode::decode_from_slice(&block, bincode_config())?;
+        anyhow::ensure!(&base_block.signature == b"regf", "not a transaction log");
+
+        let sector = SECTOR_SIZE * base_block.clustering_factor.max(1) as usize;
+        if bytes.get(sector..sector + 4) == Some(b"DIRT") {
+            let checksum_valid = base_block.checksum == base_block_checksum(&block);
+            let entries = parse_dirty_vector(bytes, sector, &base_block, checksum_valid)
+                .into_iter()
+                .collect();
+            return Ok(Self {
+                format: LogFormat::Legacy,
+                base_block,
+                entries,
+            });
+        }
+
+        // entries follow each other until one doesn't look like an entry (stale data)
+        let mut entries = Vec::new();
+        let mut pos = SECTOR_SIZE;
+        while let Some(entry) = parse_entry(bytes, pos) {
+            pos += entry.size as usize;
+            entries.push(entry);
+        }
+
+        Ok(Self {
+            format: LogFormat::New,
+            base_block,
+            entries,
+        })
+    }
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(bytes[pos..pos + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap())
+}
+
+fn parse_entry(bytes: &[u8], file_offset: usize) -> Option<LogEntry> {
+    let header = bytes.get(file_offset..file_offset + LOG_ENTRY_HEADER_SIZE)?;
+    if !header.starts_with(b"HvLE") {
+        return None;
+    }
+
+    let size = u32_at(header, 4);
+    if (size as usize) < LOG_ENTRY_HEADER_SIZE || !(size as usize).is_multiple_of(SECTOR_SIZE) {
+        return None;
+    }
+    let entry = bytes.get(file_offset..file_offset + size as usize)?;
+
+    let count = u32_at(header, 20) as usize;
+    let refs = entry.get(LOG_ENTRY_HEADER_SIZE..LOG_ENTRY_HEADER_SIZE + count.checked_mul(8)?)?;
+
+    // pages are stored in the order of their references, right after them
+    let mut pages = Vec::with_capacity(count);
+    let mut data_pos = LOG_ENTRY_HEADER_SIZE + refs.len();
+    for r in refs.chunks_exact(8) {
+        let page_size = u32_at(r, 4) as usize;
+        let data = entry.get(data_pos..data_pos + page_size)?;
+        pages.push(DirtyPage {
+            offset: u32_at(r, 0),
+            data: data.to_vec(),
+        });
+        data_pos += page_size;
+    }
+
+    let hash1 = u64_at(header, 24);
+    let hash2 = u64_at(header, 32);
+    let hashes_valid = hash1 == marvin32(MARVIN32_SEED, &entry[LOG_ENTRY_HEADER_SIZE..])
+        && hash2 == marvin32(MARVIN32_SEED, &entry[..32]);
+
+    Some(LogEntry {
+        file_offset,
+        size,
+        flags: u32_at(header, 8),
+        sequence_number: u32_at(header, 12),
+        hive_bins_data_size: u32_at(header, 16),
+        hash1,
+        hash2,
+        hashes_valid,
+        pages,
+    })
+}
+
+#[derive(Debug)]
+pub struct Replay {
+    // content of the recovered hive file
+    pub hive: Vec<u8>,
+
+    // entries applied in order, along with the index of their log
+    pub applied: Vec<(usize, LogEntry)>,
+}
+
+// a write operation on the primary file didn't complete
+pub fn is_dirty(base_block: &BaseBlock) -> bool {
+    base_block.primary_sequence_number != base_block.secondary_sequence_number
+}
+
+// entries of the logs which a replay applies to a primary hive with this base block, with the index
+// of their log, without reading the primary
+pub fn applicable_entries<'a>(
+    base_block: &BaseBlock,
+    logs: &'a [TransactionLog],
+) -> Vec<(usize, &'a LogEntry)> {
+    let mut entries: Vec<(usize, &LogEntry)> = logs
+        .iter()
+        .enumerate()
+        .flat_map(|(i, log)| log.entries.iter().map(move |e| (i, e)))
+        .filter(|(_, e)| {
+            e.hashes_valid
+                && e.sequence_number >= base_block.secondary_sequence_number
+                && e.hive_bins_data_size.is_multiple_of(4096)
+        })
+        .collect();
+    entries.sort_by_key(|(_, e)| e.sequence_number);
+    entries.dedup_by_key(|(_, e)| e.sequence_number);
+
+    // until a sequence number is missing
+    if let Some(end) = entries
+        .windows(2)
+        .position(|w| w[1].1.sequence_number != w[0].1.sequence_number + 1)
+    {
+        entries.truncate(end + 1);
+    }
+    entries
+}
+
+// apply the entries of the logs (LOG1 and LOG2 in any order) to a primary hive file content: entries
+// with valid hashes are applied by sequence number, starting at the secondary sequence number of the
+// primary, and until a sequence number is missing
+pub fn replay(primary: &[u8], logs: &[TransactionLog]) -> anyhow::Result<Replay> {
+    let block = primary
+        .get(..4096)
+        .ok_or_else(|| anyhow::anyhow!("hive is too small"))?;
+    let (mut base_block, _): (BaseBlock, usize) =
+        bincode::decode_from_slice(block, bincode_config())?;
+
+    let mut hive = primary.to_vec();
+    let mut applied: Vec<(usize, LogEntry)> = Vec::new();
+    for (log, entry) in applicable_entries(&base_block, logs) {
+        let size = 4096 + entry.hive_bins_data_size as u64;
+        default_options().check_size(
+            size,
+            &format!("hive of log entry {}", entry.sequence_number),
+        )?;
+        hive.resize(size as usize, 0);
+        for page in &entry.pages {
+            let start = 4096 + page.offset as usize;
+            let target = hive
+                .get_mut(start..start + page.data.len())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("dirty page at 0x{:X} out of hive bins data", page.offset)
+                })?;
+            target.copy_from_slice(&page.data);
+        }
+        applied.push((log, entry.clone()));
+    }
+
+    // the recovered base block is consistent again
+    if let Some((_, last)) = applied.last() {
+        base_block.primary_sequence_number = last.sequence_number;
+        base_block.secondary_sequence_number = last.sequence_number;
+        base_block.hive_bins_data_size = last.hive_bins_data_size;
+
+        let mut bytes = bincode::encode_to_vec(&base_block, bincode_config())?;
+        let checksum = base_block_checksum(&bytes);
+        bytes[508..512].copy_from_slice(&checksum.to_le_bytes());
+        hive[..4096].copy_from_slice(&bytes);
+    }
+
+    Ok(Replay { hive, applied })
+}
+
+// keys and values changed by a replay, i.e. the most recent writes to the hive
+pub fn replay_changes(primary: &[u8], replay: &Replay) -> anyhow::Result<Vec<Change>> {
+    let mut snapshots = Vec::new();
+    for bytes in [primary, replay.hive.as_slice()] {
+        let mut hive = RegistryHive::new(Cursor::new(bytes))?;
+        let root = hive.root()?;
+        snapshots.push(snapshot(&mut hive, &root, "\\")?);
+    }
+
+    Ok(diff(&snapshots[0], &snapshots[1]))
+}
+
+// log file in the new format holding a single entry with the pages of the hive bins data changed
+// from before to after, as written by Windows before writing them to the primary file: the base block
+// copied is the one of the primary before the write, the entry has its secondary sequence number
+pub fn write_log(base_block: &BaseBlock, before: &[u8], after: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut log_block = base_block.clone();
+    log_block.file_type = LOG_FILE_TYPE;
+    let mut bytes = bincode::encode_to_vec(&log_block, bincode_config())?;
+    let checksum = base_block_checksum(&bytes);
+    bytes[508..512].copy_from_slice(&checksum.to_le_bytes());
+    bytes.truncate(SECTOR_SIZE);
+
+    // contiguous dirty pages are merged
+    let mut pages: Vec<DirtyPage> = Vec::new();
+    for (i, page) in after.chunks(PAGE_SIZE).enumerate() {
+        let offset = i * PAGE_SIZE;
+        if before.get(offset..offset + page.len()) == Some(page) {
+            continue;
+        }
+        match pages.last_mut() {
+            Some(last) if last.offset as usize + last.data.len() == offset => {
+                last.data.extend_from_slice(page)
+            }
+            _ => pages.push(DirtyPage {
+                offset: offset as u32,
+                data: page.to_vec(),
+            }),
+        }
+    }
+
+    let mut entry = b"HvLE".to_vec();
+    let size = (LOG_ENTRY_HEADER_SIZE
+        + 8 * pages.len()
+        + pages.iter().map(|p| p.data.len()).sum::<usize>())
+    .next_multiple_of(SECTOR_SIZE);
+    for field in [
+        size as u32,
+        0,
+        base_block.secondary_sequence_number,
+        after.len() as u32,
+        pages.len() as u32,
+    ] {
+        entry.extend_from_slice(&field.to_le_bytes());
+    }
+    entry.resize(LOG_ENTRY_HEADER_SIZE, 0);
+    for page in &pages {
+        entry.extend_from_slice(&page.offset.to_le_bytes());
+        entry.extend_from_slice(&(page.data.len() as u32).to_le_bytes());
+    }
+    for page in &pages {
+        entry.extend_from_slice(&page.data);
+    }
+    entry.resize(size, 0);
+
+    let hash1 = marvin32(MARVIN32_SEED, &entry[LOG_ENTRY_HEADER_SIZE..]);
+    entry[24..32].copy_from_slice(&hash1.to_le_bytes());
+    let hash2 = marvin32(MARVIN32_SEED, &entry[..32]);
+    entry[32..40].copy_from_slice(&hash2.to_le_bytes());
+
+    bytes.extend_from_slice(&entry);
+    Ok(bytes)
+}
+
+// existing log files next to a hive file: SYSTEM.LOG, SYSTEM.LOG1, SYSTEM.LOG2
+pub fn find_logs(hive: &Path) -> Vec<PathBuf> {
+    let Some(name) = hive.file_name() else {
+        return Vec::new();
+    };
+
+    let mut logs = Vec::new();
+    for ext in ["LOG", "LOG1", "LOG2"] {
+        for ext in [ext.to_string(), ext.to_lowercase()] {
+            let mut log_name = name.to_os_string();
+            log_name.push(format!(".{ext}"));
+            let log = hive.with_file_name(log_name);
+            if log.is_file() && !logs.contains(&log) {
+                logs.push(log);
+            }
+        }
+    }
+
+    logs
+}
+
+// dirty pages of a legacy log, contiguous dirty pages are merged
+fn parse_dirty_vector(
+    bytes: &[u8],
+    file_offset: usize,
+    base_block: &BaseBlock,
+    checksum_valid: bool,
+) -> Option<LogEntry> {
+    let page_count = base_block.hive_bins_data_size as usize / SECTOR_SIZE;
+    let bitmap = bytes.get(file_offset + 4..file_offset + 4 + page_count.div_ceil(8))?;
+
+    let sector = SECTOR_SIZE * base_block.clustering_factor.max(1) as usize;
+    let mut data_pos = (file_offset + 4 + bitmap.len()).next_multiple_of(sector);
+
+    let mut pages: Vec<DirtyPage> = Vec::new();
+    for i in (0..page_count).filter(|i| bitmap[i / 8] & (1 << (i % 8)) != 0) {
+        let data = bytes.get(data_pos..data_pos + SECTOR_SIZE)?;
+        data_pos += SECTOR_SIZE;
+
+        let offset = (i * SECTOR_SIZE) as u32;
+        match pages.last_mut() {
+            Some(page) if page.offset as usize + page.data.len() == offset as usize => {
+                page.data.extend_from_slice(data)
+            }
+            _ => pages.push(DirtyPage {
+                offset,
+                data: data.to_vec(),
+            }),
+        }
+    }
+
+    Some(LogEntry {
+        file_offset,
+        size: (data_pos - file_offset) as u32,
+        flags: 0,
+        sequence_number: base_block.primary_sequence_number,
+        hive_bins_data_size: base_block.hive_bins_data_size,
+        hash1: 0,
+        hash2: 0,
+        hashes_valid: checksum_valid,
+        pages,
+    })
+}
+
+// Marvin32 hash, as implemented by .NET for randomized string hashing
+fn marvin32(seed: u64, data: &[u8]) -> u64 {
+    let mut lo = seed as u32;
+    let mut hi = (seed >> 32) as u32;
+
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        lo = lo.wrapping_add(u32::from_le_bytes(chunk.try_into().unwrap()));
+        mix(&mut lo, &mut hi);
+    }
+
+    // remaining bytes are padded with 0x80
+    let mut last = [0u8; 4];
+    let rest = chunks.remainder();
+    last[..rest.len()].copy_from_slice(rest);
+    last[rest.len()] = 0x80;
+    lo = lo.wrapping_add(u32::from_le_bytes(last));
+
+    mix(&mut lo, &mut hi);
+    mix(&mut lo, &mut hi);
+
+    lo as u64 | ((hi as u64) << 32)
+}
+
+fn mix(lo: &mut u32, hi: &mut u32) {
+    *hi ^= *lo;
+    *lo = lo.rotate_left(20).wrapping_add(*hi);
+    *hi = hi.rotate_left(9) ^ *lo;
+    *lo = lo.rotate_left(27).wrapping_add(*hi);
+    *hi = hi.rotate_left(19);
+}
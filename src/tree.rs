@@ -0,0 +1,201 @@
+// In-memory keys tree, independent of cell offsets: the model used to write new hives
+
+use crate::{
+    hive::{Key, MAX_DEPTH, RegistryHive, join_path, name_sort_key, names_equal},
+    key::{KEY_COMP_NAME, NO_OFFSET},
+    value::RegType,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeValue {
+    // empty for the default value
+    pub name: String,
+    pub reg_type: RegType,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeKey {
+    pub name: String,
+
+    // key node flags, the name compression flag is set when writing
+    pub flags: u16,
+
+    // FILETIME (UTC)
+    pub timestamp: u64,
+
+    // UTF-16LE class name, empty if not set
+    pub class_name: Vec<u8>,
+
+    // self-relative security descriptor, the parent one is used when empty
+    pub security: Vec<u8>,
+
+    pub values: Vec<TreeValue>,
+    pub subkeys: Vec<TreeKey>,
+}
+
+impl TreeKey {
+    // key without values nor subkeys, using the parent security descriptor
+    pub fn new(name: &str, timestamp: u64) -> Self {
+        Self {
+            name: name.to_string(),
+            flags: 0,
+            timestamp,
+            class_name: Vec::new(),
+            security: Vec::new(),
+            values: Vec::new(),
+            subkeys: Vec::new(),
+        }
+    }
+
+    // copy of the subtree starting at key
+    pub fn load(hive: &mut RegistryHive, key: &Key) -> anyhow::Result<Self> {
+        Self::load_inner(hive, key, 0)
+    }
+
+    fn load_inner(hive: &mut RegistryHive, key: &Key, depth: usize) -> anyhow::Result<Self> {
+        anyhow::ensure!(depth <= MAX_DEPTH, "keys tree too deep at '{}'", key.name());
+        let mut tree = Self::load_node(hive, key)?;
+
+        for value in hive.values(key)? {
+            tree.values.push(TreeValue {
+                name: value.name.clone(),
+                reg_type: value.reg_type(),
+                data: hive.value_bytes(&value)?.into_owned(),
+            });
+        }
+        for subkey in hive.subkeys(key)? {
+            tree.subkeys
+                .push(Self::load_inner(hive, &subkey, depth + 1)?);
+        }
+        Ok(tree)
+    }
+
+    // the key alone, without values nor subkeys
+    pub fn load_node(hive: &mut RegistryHive, key: &Key) -> anyhow::Result<Self> {
+        let h = &key.node.header;
+
+        let class_name = if h.class_name_offset != NO_OFFSET && h.class_name_length > 0 {
+            let (_, data) = hive.cell(h.class_name_offset)?;
+            data.get(..h.class_name_length as usize)
+                .unwrap_or(data)
+                .to_vec()
+        } else {
+            Vec::new()
+        };
+
+        let security = if h.key_security_offset != NO_OFFSET {
+            hive.security(key)?.descriptor
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            name: key.name().to_string(),
+            flags: h.flags & !KEY_COMP_NAME,
+            timestamp: h.last_written_timestamp,
+            class_name,
+            security,
+            values: Vec::new(),
+            subkeys: Vec::new(),
+        })
+    }
+
+    // names are case insensitive
+    pub fn subkey(&self, name: &str) -> Option<&TreeKey> {
+        self.subkeys.iter().find(|k| names_equal(&k.name, name))
+    }
+
+    pub fn subkey_mut(&mut self, name: &str) -> Option<&mut TreeKey> {
+        self.subkeys.iter_mut().find(|k| names_equal(&k.name, name))
+    }
+
+    pub fn value(&self, name: &str) -> Option<&TreeValue> {
+        self.values.iter().find(|v| names_equal(&v.name, name))
+    }
+
+    // path is relative to this key, case insensitive
+    pub fn find(&self, path: &str) -> Option<&TreeKey> {
+        path.split('\\')
+            .filter(|n| !n.is_empty())
+            .try_fold(self, |key, name| key.subkey(name))
+    }
+
+    pub fn find_mut(&mut self, path: &str) -> Option<&mut TreeKey> {
+        path.split('\\')
+            .filter(|n| !n.is_empty())
+            .try_fold(self, |key, name| key.subkey_mut(name))
+    }
+
+    // key at path, missing keys are created with the timestamp
+    pub fn create(&mut self, path: &str, timestamp: u64) -> &mut TreeKey {
+        let mut key = self;
+        for name in path.split('\\').filter(|n| !n.is_empty()) {
+            let index = match key.subkeys.iter().position(|k| names_equal(&k.name, name)) {
+                Some(index) => index,
+                None => {
+                    key.subkeys.push(TreeKey::new(name, timestamp));
+                    key.subkeys.len() - 1
+                }
+            };
+            key = &mut key.subkeys[index];
+        }
+        key
+    }
+
+    // remove the key at path with all its subkeys, returns false if it doesn't exist
+    pub fn remove(&mut self, path: &str) -> bool {
+        let (parent, name) = match path.trim_end_matches('\\').rsplit_once('\\') {
+            Some((parent, name)) => (parent, name),
+            None => ("", path),
+        };
+
+        match self.find_mut(parent) {
+            Some(parent) => {
+                let count = parent.subkeys.len();
+                parent.subkeys.retain(|k| !names_equal(&k.name, name));
+                parent.subkeys.len() != count
+            }
+            None => false,
+        }
+    }
+
+    // path of the first key whose attributes or values differ, subkeys being compared in name order.
+    // An empty security descriptor stands for the one inherited from the parent
+    pub fn first_difference(&self, other: &TreeKey, path: &str) -> Option<String> {
+        if self.name != other.name
+            || self.flags != other.flags
+            || self.timestamp != other.timestamp
+            || self.class_name != other.class_name
+            || (!self.security.is_empty() && self.security != other.security)
+            || self.values != other.values
+            || self.subkeys.len() != other.subkeys.len()
+        {
+            return Some(path.to_string());
+        }
+
+        sorted_subkeys(self)
+            .into_iter()
+            .zip(sorted_subkeys(other))
+            .find_map(|(a, b)| a.first_difference(b, &join_path(path, &a.name)))
+    }
+
+    // every key of the subtree gets the same last written time
+    pub fn set_timestamps(&mut self, timestamp: u64) {
+        self.timestamp = timestamp;
+        for subkey in &mut self.subkeys {
+            subkey.set_timestamps(timestamp);
+        }
+    }
+
+    // number of keys of the subtree, this key included
+    pub fn count(&self) -> usize {
+        1 + self.subkeys.iter().map(TreeKey::count).sum::<usize>()
+    }
+}
+
+fn sorted_subkeys(key: &TreeKey) -> Vec<&TreeKey> {
+    let mut subkeys: Vec<&TreeKey> = key.subkeys.iter().collect();
+    subkeys.sort_by_key(|k| name_sort_key(&k.name));
+    subkeys
+}
@@ -0,0 +1,189 @@
+// helpers shared by all modules
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// number of seconds between 1601-01-01 and 1970-01-01
+const FILETIME_UNIX_EPOCH: u64 = 11_644_473_600;
+
+// convert a FILETIME (100-nanosecond intervals since 1601-01-01 UTC) to seconds since the unix epoch
+pub fn filetime_to_unix(ft: u64) -> i64 {
+    (ft / 10_000_000) as i64 - FILETIME_UNIX_EPOCH as i64
+}
+
+// current time as a FILETIME
+pub fn now_filetime() -> u64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (since_epoch.as_secs() + FILETIME_UNIX_EPOCH) * 10_000_000
+        + since_epoch.subsec_nanos() as u64 / 100
+}
+
+// format a FILETIME as "YYYY-MM-DD HH:MM:SS"
+pub fn filetime_to_string(ft: u64) -> String {
+    let secs = filetime_to_unix(ft);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let rem = secs.rem_euclid(86400);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+// format a FILETIME as ISO-8601 in UTC, down to its 100 nanoseconds: "YYYY-MM-DDTHH:MM:SS.FFFFFFFZ"
+pub fn filetime_to_iso8601(ft: u64) -> String {
+    format!(
+        "{}.{:07}Z",
+        filetime_to_string(ft).replace(' ', "T"),
+        ft % 10_000_000
+    )
+}
+
+// days since the unix epoch to (year, month, day)
+// see: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+// (year, month, day) to days since the unix epoch
+// see: http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+// convert seconds since the unix epoch to a FILETIME
+pub fn unix_to_filetime(secs: i64) -> u64 {
+    ((secs + FILETIME_UNIX_EPOCH as i64).max(0) as u64) * 10_000_000
+}
+
+// format a 16 bytes GUID (first 3 groups little endian) as "{XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX}"
+pub fn guid_to_string(bytes: &[u8]) -> String {
+    let b = bytes;
+    format!(
+        "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+        u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        u16::from_le_bytes([b[4], b[5]]),
+        u16::from_le_bytes([b[6], b[7]]),
+        b[8],
+        b[9],
+        b[10],
+        b[11],
+        b[12],
+        b[13],
+        b[14],
+        b[15]
+    )
+}
+
+// hex dump of data, 16 bytes per line prefixed by their offset from start
+pub fn hex_dump(data: &[u8], start: usize) -> String {
+    let mut dump = String::new();
+    for (i, line) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{b:02X}")).collect();
+        let ascii: String = line
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() {
+                    *b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        dump.push_str(&format!(
+            "{:08X}  {:<47}  {ascii}\n",
+            start + i * 16,
+            hex.join(" ")
+        ));
+    }
+    dump
+}
+
+// case insensitive wildcard match: '*' matches any sequence, '?' any single character
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let p: Vec<char> = pattern.to_uppercase().chars().collect();
+    let n: Vec<char> = name.to_uppercase().chars().collect();
+
+    // classic backtracking on the last '*'
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+// characters regedit doesn't display: control characters, characters which failed to decode and
+// invisible formatting characters (zero width, bidirectional overrides, BOM)
+pub fn is_hidden_char(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{FFFD}' | '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}' | '\u{FEFF}'
+        )
+}
+
+// name with hidden characters made visible as <U+XXXX>
+pub fn escape_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if is_hidden_char(c) {
+            escaped.push_str(&format!("<U+{:04X}>", c as u32));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+// Shannon entropy in bits per byte: 0 for constant data, close to 8 for compressed or encrypted data
+pub fn shannon_entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+
+    let mut counts = [0usize; 256];
+    for b in data {
+        counts[*b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|c| **c != 0)
+        .map(|c| {
+            let p = *c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
@@ -0,0 +1,396 @@
+// Key value (vk) record and value data
+// see: https://github.com/msuhanov/regf/blob/master/Windows%20registry%20file%20format%20specification.md#key-value
+//
+// +------+----------+-----------+-------------+-----------+-------+-------+------------+
+// | "vk" | name len | data size | data offset | data type | flags | spare | name ...   |
+// +------+----------+-----------+-------------+-----------+-------+-------+------------+
+//
+use std::{fmt, ops::Deref, str::FromStr, sync::Arc};
+
+use bincode::{Decode, Encode};
+
+use crate::{key::decode_name, reg::bincode_config};
+
+// value flags
+pub const VALUE_COMP_NAME: u16 = 0x0001;
+pub const VALUE_TOMBSTONE: u16 = 0x0002;
+
+// when the most significant bit of the data size is set, data is stored in the data offset field
+pub const DATA_IS_RESIDENT: u32 = 0x8000_0000;
+
+// type of the device properties (DEVPROP_TYPE_FILETIME), e.g. USB devices install dates
+pub const REG_DEVPROP_FILETIME: u32 = 0xFFFF_0010;
+
+// above this size, data is split into segments using a big data (db) record
+pub const BIG_DATA_SEGMENT_SIZE: usize = 16344;
+
+// fixed part of a key value, the value name follows
+#[derive(Debug, Clone, Decode, Encode)]
+pub struct KeyValueHeader {
+    // ASCII string
+    pub signature: [u8; 2],
+
+    // In bytes, can be 0 (name isn't set)
+    pub name_length: u16,
+
+    // In bytes, can be 0 (value isn't set), the most significant bit has a special meaning
+    pub data_size: u32,
+
+    // In bytes, relative from the start of the hive bins data (or data itself)
+    pub data_offset: u32,
+
+    // Type of data
+    pub data_type: u32,
+
+    // Bit mask
+    pub flags: u16,
+
+    // Not used
+    pub spare: u16,
+}
+
+// size of the fixed part
+pub const KEY_VALUE_HEADER_SIZE: usize = 20;
+
+#[derive(Debug, Clone)]
+pub struct KeyValue {
+    pub header: KeyValueHeader,
+    pub name: String,
+}
+
+impl KeyValue {
+    // the default value of a key has no name
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() {
+            "(default)"
+        } else {
+            &self.name
+        }
+    }
+
+    pub fn reg_type(&self) -> RegType {
+        RegType::from(self.header.data_type)
+    }
+
+    // actual size of the data, without the resident flag
+    pub fn data_size(&self) -> u32 {
+        self.header.data_size & !DATA_IS_RESIDENT
+    }
+
+    // data is stored in the data offset field
+    pub fn is_resident(&self) -> bool {
+        self.header.data_size & DATA_IS_RESIDENT != 0
+    }
+
+    // when data is resident, at most 4 bytes are stored in the data offset field
+    pub fn resident_data(&self) -> ValueBytes<'static> {
+        let size = (self.data_size() as usize).min(4);
+        ValueBytes::Inline(self.header.data_offset.to_le_bytes(), size)
+    }
+}
+
+// bytes start at the signature
+impl TryFrom<&[u8]> for KeyValue {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        let (header, _): (KeyValueHeader, usize) =
+            bincode::decode_from_slice(data, bincode_config())?;
+        anyhow::ensure!(&header.signature == b"vk", "not a key value");
+
+        let name_length = header.name_length as usize;
+        let name_bytes = data
+            .get(KEY_VALUE_HEADER_SIZE..KEY_VALUE_HEADER_SIZE + name_length)
+            .ok_or_else(|| anyhow::anyhow!("value name overflows cell"))?;
+        let name = decode_name(name_bytes, header.flags & VALUE_COMP_NAME != 0);
+
+        Ok(Self { header, name })
+    }
+}
+
+// Big data (db) record, used when data is larger than 16344 bytes (hive version 1.4 and above)
+#[derive(Debug, Clone, Decode, Encode)]
+pub struct BigData {
+    // ASCII string
+    pub signature: [u8; 2],
+
+    // Number of data segments
+    pub number_of_segments: u16,
+
+    // In bytes, relative from the start of the hive bins data
+    pub segments_list_offset: u32,
+}
+
+// value data types
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegType {
+    None,
+    Sz,
+    ExpandSz,
+    Binary,
+    Dword,
+    DwordBigEndian,
+    Link,
+    MultiSz,
+    ResourceList,
+    FullResourceDescriptor,
+    ResourceRequirementsList,
+    Qword,
+    Unknown(u32),
+}
+
+impl From<u32> for RegType {
+    fn from(t: u32) -> Self {
+        match t {
+            0 => RegType::None,
+            1 => RegType::Sz,
+            2 => RegType::ExpandSz,
+            3 => RegType::Binary,
+            4 => RegType::Dword,
+            5 => RegType::DwordBigEndian,
+            6 => RegType::Link,
+            7 => RegType::MultiSz,
+            8 => RegType::ResourceList,
+            9 => RegType::FullResourceDescriptor,
+            10 => RegType::ResourceRequirementsList,
+            11 => RegType::Qword,
+            _ => RegType::Unknown(t),
+        }
+    }
+}
+
+impl From<RegType> for u32 {
+    fn from(t: RegType) -> Self {
+        match t {
+            RegType::None => 0,
+            RegType::Sz => 1,
+            RegType::ExpandSz => 2,
+            RegType::Binary => 3,
+            RegType::Dword => 4,
+            RegType::DwordBigEndian => 5,
+            RegType::Link => 6,
+            RegType::MultiSz => 7,
+            RegType::ResourceList => 8,
+            RegType::FullResourceDescriptor => 9,
+            RegType::ResourceRequirementsList => 10,
+            RegType::Qword => 11,
+            RegType::Unknown(t) => t,
+        }
+    }
+}
+
+// "REG_SZ", "sz" or the type number
+impl FromStr for RegType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_uppercase();
+        let name = upper.strip_prefix("REG_").unwrap_or(&upper);
+
+        if let Some(t) = (0..=11)
+            .map(RegType::from)
+            .find(|t| t.to_string()[4..] == *name)
+        {
+            return Ok(t);
+        }
+
+        let number = match name.strip_prefix("0X") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => name.parse(),
+        };
+        number
+            .map(RegType::from)
+            .map_err(|_| anyhow::anyhow!("unknown value type '{s}'"))
+    }
+}
+
+impl fmt::Display for RegType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegType::None => write!(f, "REG_NONE"),
+            RegType::Sz => write!(f, "REG_SZ"),
+            RegType::ExpandSz => write!(f, "REG_EXPAND_SZ"),
+            RegType::Binary => write!(f, "REG_BINARY"),
+            RegType::Dword => write!(f, "REG_DWORD"),
+            RegType::DwordBigEndian => write!(f, "REG_DWORD_BIG_ENDIAN"),
+            RegType::Link => write!(f, "REG_LINK"),
+            RegType::MultiSz => write!(f, "REG_MULTI_SZ"),
+            RegType::ResourceList => write!(f, "REG_RESOURCE_LIST"),
+            RegType::FullResourceDescriptor => write!(f, "REG_FULL_RESOURCE_DESCRIPTOR"),
+            RegType::ResourceRequirementsList => write!(f, "REG_RESOURCE_REQUIREMENTS_LIST"),
+            RegType::Qword => write!(f, "REG_QWORD"),
+            RegType::Unknown(t) => write!(f, "0x{t:X}"),
+        }
+    }
+}
+
+// value data interpreted according to its type
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegData {
+    None(Vec<u8>),
+    Sz(String),
+    ExpandSz(String),
+    Binary(Vec<u8>),
+    Dword(u32),
+    DwordBigEndian(u32),
+    Link(String),
+    MultiSz(Vec<String>),
+    Qword(u64),
+
+    // resource lists and unknown types are kept as is
+    Other(RegType, Vec<u8>),
+}
+
+impl RegData {
+    pub fn new(reg_type: RegType, data: &[u8]) -> Self {
+        match reg_type {
+            RegType::None => RegData::None(data.to_vec()),
+            RegType::Sz => RegData::Sz(utf16_string(data)),
+            RegType::ExpandSz => RegData::ExpandSz(utf16_string(data)),
+            RegType::Link => RegData::Link(utf16_string(data)),
+            RegType::Binary => RegData::Binary(data.to_vec()),
+            RegType::Dword if data.len() >= 4 => {
+                RegData::Dword(u32::from_le_bytes(data[..4].try_into().unwrap()))
+            }
+            RegType::DwordBigEndian if data.len() >= 4 => {
+                RegData::DwordBigEndian(u32::from_be_bytes(data[..4].try_into().unwrap()))
+            }
+            RegType::Qword if data.len() >= 8 => {
+                RegData::Qword(u64::from_le_bytes(data[..8].try_into().unwrap()))
+            }
+            RegType::MultiSz => RegData::MultiSz(
+                utf16_string(data)
+                    .split('\0')
+                    .filter(|s| !s.is_empty())
+                    .map(String::from)
+                    .collect(),
+            ),
+            _ => RegData::Other(reg_type, data.to_vec()),
+        }
+    }
+
+    pub fn reg_type(&self) -> RegType {
+        match self {
+            RegData::None(_) => RegType::None,
+            RegData::Sz(_) => RegType::Sz,
+            RegData::ExpandSz(_) => RegType::ExpandSz,
+            RegData::Binary(_) => RegType::Binary,
+            RegData::Dword(_) => RegType::Dword,
+            RegData::DwordBigEndian(_) => RegType::DwordBigEndian,
+            RegData::Link(_) => RegType::Link,
+            RegData::MultiSz(_) => RegType::MultiSz,
+            RegData::Qword(_) => RegType::Qword,
+            RegData::Other(t, _) => *t,
+        }
+    }
+
+    // raw data as stored in a hive: strings are null terminated UTF-16LE, except links which aren't
+    // terminated, and multi strings end with an empty string
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let utf16 =
+            |s: &str| -> Vec<u8> { s.encode_utf16().flat_map(|u| u.to_le_bytes()).collect() };
+        match self {
+            RegData::Sz(s) | RegData::ExpandSz(s) => utf16(&format!("{s}\0")),
+            RegData::Link(s) => utf16(s),
+            RegData::MultiSz(v) => {
+                let mut s: String = v.iter().map(|s| format!("{s}\0")).collect();
+                s.push('\0');
+                utf16(&s)
+            }
+            RegData::Dword(d) => d.to_le_bytes().to_vec(),
+            RegData::DwordBigEndian(d) => d.to_be_bytes().to_vec(),
+            RegData::Qword(q) => q.to_le_bytes().to_vec(),
+            RegData::None(b) | RegData::Binary(b) | RegData::Other(_, b) => b.clone(),
+        }
+    }
+}
+
+impl fmt::Display for RegData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegData::Sz(s) | RegData::ExpandSz(s) | RegData::Link(s) => write!(f, "{s}"),
+            RegData::Dword(d) | RegData::DwordBigEndian(d) => write!(f, "0x{d:08X} ({d})"),
+            RegData::Qword(q) => write!(f, "0x{q:016X} ({q})"),
+            RegData::MultiSz(v) => write!(f, "{}", v.join(", ")),
+            RegData::None(b) | RegData::Binary(b) | RegData::Other(_, b) => {
+                write!(f, "{}", hex_string(b))
+            }
+        }
+    }
+}
+
+// raw data of a value: a view into the hive for data in a cell, the bytes of resident data (most
+// values: DWORDs, short strings) held inline without any allocation, and the reassembled data of big
+// data values, shared with the big data cache
+#[derive(Debug, Clone)]
+pub enum ValueBytes<'a> {
+    Borrowed(&'a [u8]),
+    Shared(Arc<[u8]>),
+
+    // bytes and length
+    Inline([u8; 4], usize),
+}
+
+impl ValueBytes<'_> {
+    pub fn into_owned(self) -> Vec<u8> {
+        match self {
+            ValueBytes::Borrowed(b) => b.to_vec(),
+            ValueBytes::Shared(a) => a.to_vec(),
+            ValueBytes::Inline(bytes, len) => bytes[..len].to_vec(),
+        }
+    }
+}
+
+impl Deref for ValueBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ValueBytes::Borrowed(b) => b,
+            ValueBytes::Shared(a) => a,
+            ValueBytes::Inline(bytes, len) => &bytes[..*len],
+        }
+    }
+}
+
+impl AsRef<[u8]> for ValueBytes<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+// strings are UTF-16LE, usually null terminated
+pub fn utf16_string(data: &[u8]) -> String {
+    let units = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]));
+    let mut s: String = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    s.truncate(s.trim_end_matches('\0').len());
+    s
+}
+
+pub fn hex_string(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02X}")).collect()
+}
+
+// standard alphabet, padded
+pub fn base64_string(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let mut bytes = [0u8; 3];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
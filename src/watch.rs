@@ -0,0 +1,52 @@
+// Re-parse a hive whenever it changes on disk and report the differences
+use std::{path::Path, sync::mpsc, thread, time::Duration};
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::{diff::Change, reparse::IncrementalSnapshot};
+
+// writers usually trigger a burst of events: wait for it to end before parsing
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+// f is called with the changes of the subtree under key after each update of the file, or with the
+// error if the updated file can't be parsed (e.g. still being copied). Only the keys read from
+// modified hbins are parsed again.
+pub fn watch<F>(path: &Path, key: &str, mut f: F) -> anyhow::Result<()>
+where
+    F: FnMut(anyhow::Result<Vec<Change>>) -> anyhow::Result<()>,
+{
+    let mut snapshot = IncrementalSnapshot::load(path, key)?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+
+    // hives copied over are often replaced instead of rewritten: watch the directory
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    for event in &rx {
+        let event = event?;
+        if event.kind.is_access()
+            || !event
+                .paths
+                .iter()
+                .any(|p| p.file_name() == path.file_name())
+        {
+            continue;
+        }
+
+        thread::sleep(SETTLE_DELAY);
+        while rx.try_recv().is_ok() {}
+
+        match snapshot.update() {
+            Ok(changes) if changes.is_empty() => (),
+            Ok(changes) => f(Ok(changes))?,
+            Err(e) => f(Err(e))?,
+        }
+    }
+
+    Ok(())
+}
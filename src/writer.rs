@@ -0,0 +1,505 @@
+// Writer of a new hive file from an in-memory keys tree
+//
+// cells are laid out depth-first (key node, security, class name, values, subkeys, subkeys list)
+// into hive bins of 4096 bytes, a cell never crosses a bin boundary. Subkeys are written in lists
+// sorted by uppercase name: lh lists as of version 1.5, lf lists before.
+//
+use std::{collections::HashMap, io::Write};
+
+use crate::{
+    buffer::HiveBuffer,
+    diff::{Change, diff, snapshot},
+    hive::{Key, RegistryHive, name_sort_key},
+    key::{
+        KEY_COMP_NAME, KEY_HIVE_ENTRY, KEY_HIVE_EXIT, KEY_NO_DELETE, KEY_NODE_HEADER_SIZE,
+        KEY_SYM_LINK, KeyNodeHeader, NO_OFFSET,
+    },
+    list::{LEAF_MAX_ELEMENTS, SubkeysList, name_hash, name_hint},
+    reg::{BaseBlock, HiveBinHeader, base_block_checksum, bincode_config},
+    security::{KEY_SECURITY_HEADER_SIZE, KeySecurityHeader, default_descriptor},
+    tree::{TreeKey, TreeValue},
+    utils::now_filetime,
+    value::{BIG_DATA_SEGMENT_SIZE, DATA_IS_RESIDENT, KeyValueHeader, VALUE_COMP_NAME},
+};
+
+// hive bins are multiple of this size
+const HIVE_BIN_SIZE: usize = 4096;
+const HIVE_BIN_HEADER_SIZE: usize = 32;
+
+#[derive(Debug, Clone)]
+pub struct HiveWriter {
+    // 3, 4 or 5 (big data records are used as of 4)
+    pub minor_version: u32,
+
+    // stored in the base block for debugging purposes
+    pub file_name: String,
+
+    // FILETIME of the base block and first hive bin
+    pub timestamp: u64,
+}
+
+impl HiveWriter {
+    pub fn new(minor_version: u32, file_name: &str) -> Self {
+        Self {
+            minor_version,
+            file_name: file_name.to_string(),
+            timestamp: now_filetime(),
+        }
+    }
+
+    // write a complete hive: base block and hive bins
+    pub fn write<W: Write>(&self, root: &TreeKey, out: &mut W) -> anyhow::Result<()> {
+        let mut cells = CellWriter::new(self.timestamp, self.minor_version);
+        // every key needs a security cell
+        let security = if root.security.is_empty() {
+            default_descriptor()
+        } else {
+            root.security.clone()
+        };
+        let root_offset = cells.write_key(root, NO_OFFSET, &security)?;
+        cells.write_security_list()?;
+        let bins = cells.finish();
+
+        out.write_all(&self.base_block(root_offset, bins.len() as u32)?)?;
+        out.write_all(&bins)?;
+
+        Ok(())
+    }
+
+    // the keys and the base block get the same timestamp, e.g. 0, and the file name is left empty:
+    // hives with the same keys and values are then written with the same bytes, whatever their last
+    // written times and the file they're written to, to be deduplicated or compared by hash
+    pub fn normalize(&mut self, root: &mut TreeKey, timestamp: u64) {
+        self.timestamp = timestamp;
+        self.file_name.clear();
+        root.set_timestamps(timestamp);
+    }
+
+    // hive made of a root key only, as saved from a newly created key
+    pub fn write_empty<W: Write>(&self, root_name: &str, out: &mut W) -> anyhow::Result<()> {
+        let mut root = TreeKey::new(root_name, self.timestamp);
+        root.flags = KEY_HIVE_ENTRY | KEY_NO_DELETE;
+        self.write(&root, out)
+    }
+
+    pub fn base_block(
+        &self,
+        root_offset: u32,
+        hive_bins_data_size: u32,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut file_name = [0u16; 32];
+        let units: Vec<u16> = self.file_name.encode_utf16().collect();
+        // keep the end of long paths, the last unit stays null
+        let tail = &units[units.len().saturating_sub(31)..];
+        file_name[..tail.len()].copy_from_slice(tail);
+
+        let base_block = BaseBlock {
+            signature: *b"regf",
+            primary_sequence_number: 1,
+            secondary_sequence_number: 1,
+            last_written_timestamp: self.timestamp,
+            major_version: 1,
+            minor_version: self.minor_version,
+            file_type: 0,
+            file_format: 1,
+            root_cell_offset: root_offset,
+            hive_bins_data_size,
+            clustering_factor: 1,
+            file_name,
+            reserved1: [0; 396],
+            checksum: 0,
+            reserved2: [0; 3576],
+            boot_type: 0,
+            boot_recover: 0,
+        };
+
+        let mut bytes = bincode::encode_to_vec(&base_block, bincode_config())?;
+        let checksum = base_block_checksum(&bytes);
+        bytes[508..512].copy_from_slice(&checksum.to_le_bytes());
+
+        Ok(bytes)
+    }
+}
+
+// defragmented copy of a hive: the keys tree reachable from the root is written again, then read back
+// and compared with the original: keys and values with a diff, then the key attributes the diff
+// doesn't cover (flags, class names, security descriptors). With a timestamp, the copy is normalized
+// and the last written times of keys aren't compared
+pub fn compact(
+    hive: &mut RegistryHive,
+    file_name: &str,
+    timestamp: Option<u64>,
+) -> anyhow::Result<Vec<u8>> {
+    convert(hive, hive.base_block.minor_version, file_name, timestamp)
+}
+
+// same as compact, the copy being written in another minor version of the format: lf lists and big
+// data in a single cell for 1.3, big data records as of 1.4, lh lists as of 1.5
+pub fn convert(
+    hive: &mut RegistryHive,
+    minor_version: u32,
+    file_name: &str,
+    timestamp: Option<u64>,
+) -> anyhow::Result<Vec<u8>> {
+    let root = hive.root()?;
+    let mut tree = TreeKey::load(hive, &root)?;
+
+    let mut writer = HiveWriter::new(minor_version, file_name);
+    if let Some(timestamp) = timestamp {
+        writer.normalize(&mut tree, timestamp);
+    }
+    let mut bytes = Vec::new();
+    writer.write(&tree, &mut bytes)?;
+
+    let mut compacted = RegistryHive::from_buffer(HiveBuffer::from(bytes.clone()))?;
+    let compacted_root = compacted.root()?;
+    let changes: Vec<Change> = diff(
+        &snapshot(hive, &root, "\\")?,
+        &snapshot(&mut compacted, &compacted_root, "\\")?,
+    )
+    .into_iter()
+    .filter(|change| timestamp.is_none() || !matches!(change, Change::KeyTouched(..)))
+    .collect();
+    if let Some(change) = changes.first() {
+        anyhow::bail!(
+            "written hive differs from the original ({} changes): {change}",
+            changes.len()
+        );
+    }
+    if let Some(path) =
+        tree.first_difference(&TreeKey::load(&mut compacted, &compacted_root)?, "\\")
+    {
+        anyhow::bail!("written hive differs from the original at {path}");
+    }
+
+    Ok(bytes)
+}
+
+// standalone hive made of the subtree under a key, e.g. ControlSet001\Services: the key becomes the
+// root, flagged as such, and keeps its security descriptor and those of its subkeys
+pub fn extract(
+    hive: &mut RegistryHive,
+    key: &Key,
+    root_name: &str,
+    file_name: &str,
+    timestamp: Option<u64>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut tree = TreeKey::load(hive, key)?;
+    tree.name = root_name.to_string();
+    tree.flags = (tree.flags & !(KEY_HIVE_EXIT | KEY_SYM_LINK)) | KEY_HIVE_ENTRY | KEY_NO_DELETE;
+
+    let mut writer = HiveWriter::new(hive.base_block.minor_version, file_name);
+    if let Some(timestamp) = timestamp {
+        writer.normalize(&mut tree, timestamp);
+    }
+    let mut bytes = Vec::new();
+    writer.write(&tree, &mut bytes)?;
+    Ok(bytes)
+}
+
+// key and value names are stored as Latin-1 when possible
+pub fn encode_name(name: &str) -> (Vec<u8>, bool) {
+    if name.chars().all(|c| (c as u32) < 0x100) {
+        (name.chars().map(|c| c as u8).collect(), true)
+    } else {
+        (
+            name.encode_utf16().flat_map(|u| u.to_le_bytes()).collect(),
+            false,
+        )
+    }
+}
+
+// size of a name as a UTF-16LE string, as stored in the largest name fields
+pub fn utf16_size(name: &str) -> u32 {
+    name.encode_utf16().count() as u32 * 2
+}
+
+// hive bins being built, with the security cells shared by keys
+struct CellWriter {
+    data: Vec<u8>,
+    bin_end: usize,
+    timestamp: u64,
+    minor_version: u32,
+
+    // index of each descriptor cell, in allocation order
+    security: HashMap<Vec<u8>, usize>,
+    security_cells: Vec<SecurityCell>,
+}
+
+struct SecurityCell {
+    offset: u32,
+    reference_count: u32,
+    descriptor_size: u32,
+}
+
+impl CellWriter {
+    fn new(timestamp: u64, minor_version: u32) -> Self {
+        let mut writer = Self {
+            data: Vec::new(),
+            bin_end: 0,
+            timestamp,
+            minor_version,
+            security: HashMap::new(),
+            security_cells: Vec::new(),
+        };
+        writer.open_bin(0);
+        writer
+    }
+
+    fn open_bin(&mut self, cell_size: usize) {
+        let size = (HIVE_BIN_HEADER_SIZE + cell_size).div_ceil(HIVE_BIN_SIZE) * HIVE_BIN_SIZE;
+        let header = HiveBinHeader {
+            signature: *b"hbin",
+            offset: self.data.len() as u32,
+            size: size as u32,
+            reserved: 0,
+            // only meaningful for the first bin
+            timestamp: if self.data.is_empty() {
+                self.timestamp
+            } else {
+                0
+            },
+            spare: 0,
+        };
+
+        let header = bincode::encode_to_vec(&header, bincode_config()).unwrap_or_default();
+        self.bin_end = self.data.len() + size;
+        self.data.extend_from_slice(&header);
+    }
+
+    // remaining space of the current bin is a free cell
+    fn close_bin(&mut self) {
+        let free = self.bin_end - self.data.len();
+        if free > 0 {
+            self.data.extend_from_slice(&(free as i32).to_le_bytes());
+            self.data.resize(self.bin_end, 0);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.close_bin();
+        self.data
+    }
+
+    // allocate a cell for content, returns its offset
+    fn alloc(&mut self, content: &[u8]) -> u32 {
+        let size = (content.len() + 4).div_ceil(8) * 8;
+        if self.data.len() + size > self.bin_end {
+            self.close_bin();
+            self.open_bin(size);
+        }
+
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(&(-(size as i32)).to_le_bytes());
+        self.data.extend_from_slice(content);
+        self.data.resize(offset as usize + size, 0);
+        offset
+    }
+
+    // overwrite the content of an allocated cell
+    fn patch(&mut self, offset: u32, content: &[u8]) {
+        let start = offset as usize + 4;
+        self.data[start..start + content.len()].copy_from_slice(content);
+    }
+
+    fn write_key(&mut self, key: &TreeKey, parent: u32, inherited: &[u8]) -> anyhow::Result<u32> {
+        let (name, compressed) = encode_name(&key.name);
+        let offset = self.alloc(&vec![0; KEY_NODE_HEADER_SIZE + name.len()]);
+
+        let descriptor = if key.security.is_empty() {
+            inherited
+        } else {
+            &key.security
+        };
+        let security_offset = self.security_cell(descriptor);
+
+        let class_name_offset = if key.class_name.is_empty() {
+            NO_OFFSET
+        } else {
+            self.alloc(&key.class_name)
+        };
+
+        let mut value_offsets = Vec::new();
+        for value in &key.values {
+            value_offsets.push(self.write_value(value)?);
+        }
+        let values_list_offset = if value_offsets.is_empty() {
+            NO_OFFSET
+        } else {
+            self.alloc(&offsets_bytes(&value_offsets))
+        };
+
+        // lists are sorted by uppercase name
+        let mut subkeys: Vec<&TreeKey> = key.subkeys.iter().collect();
+        subkeys.sort_by_key(|k| name_sort_key(&k.name));
+
+        let mut elements = Vec::new();
+        for subkey in &subkeys {
+            let subkey_offset = self.write_key(subkey, offset, descriptor)?;
+            elements.push((subkey_offset, subkey.name.as_str()));
+        }
+        let subkeys_list_offset = self.write_subkeys_list(&elements);
+
+        let header = KeyNodeHeader {
+            signature: *b"nk",
+            flags: if compressed {
+                key.flags | KEY_COMP_NAME
+            } else {
+                key.flags & !KEY_COMP_NAME
+            },
+            last_written_timestamp: key.timestamp,
+            access_bits: 0,
+            parent,
+            number_of_subkeys: subkeys.len() as u32,
+            number_of_volatile_subkeys: 0,
+            subkeys_list_offset,
+            volatile_subkeys_list_offset: NO_OFFSET,
+            number_of_key_values: value_offsets.len() as u32,
+            key_values_list_offset: values_list_offset,
+            key_security_offset: security_offset,
+            class_name_offset,
+            largest_subkey_name_length: subkeys
+                .iter()
+                .map(|k| utf16_size(&k.name))
+                .max()
+                .unwrap_or(0),
+            largest_subkey_class_name_length: subkeys
+                .iter()
+                .map(|k| k.class_name.len() as u32)
+                .max()
+                .unwrap_or(0),
+            largest_value_name_length: key
+                .values
+                .iter()
+                .map(|v| utf16_size(&v.name))
+                .max()
+                .unwrap_or(0),
+            largest_value_data_size: key
+                .values
+                .iter()
+                .map(|v| v.data.len() as u32)
+                .max()
+                .unwrap_or(0),
+            work_var: 0,
+            key_name_length: name.len() as u16,
+            class_name_length: key.class_name.len() as u16,
+        };
+
+        let mut content = bincode::encode_to_vec(&header, bincode_config())?;
+        content.extend_from_slice(&name);
+        self.patch(offset, &content);
+
+        Ok(offset)
+    }
+
+    fn write_value(&mut self, value: &TreeValue) -> anyhow::Result<u32> {
+        let (name, compressed) = encode_name(&value.name);
+        let size = value.data.len();
+
+        let (data_size, data_offset) = if size <= 4 {
+            let mut resident = [0u8; 4];
+            resident[..size].copy_from_slice(&value.data);
+            (size as u32 | DATA_IS_RESIDENT, u32::from_le_bytes(resident))
+        } else if size > BIG_DATA_SEGMENT_SIZE && self.minor_version > 3 {
+            let segments: Vec<u32> = value
+                .data
+                .chunks(BIG_DATA_SEGMENT_SIZE)
+                .map(|chunk| self.alloc(chunk))
+                .collect();
+            let list_offset = self.alloc(&offsets_bytes(&segments));
+
+            let mut db = b"db".to_vec();
+            db.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+            db.extend_from_slice(&list_offset.to_le_bytes());
+            (size as u32, self.alloc(&db))
+        } else {
+            (size as u32, self.alloc(&value.data))
+        };
+
+        let header = KeyValueHeader {
+            signature: *b"vk",
+            name_length: name.len() as u16,
+            data_size,
+            data_offset,
+            data_type: value.reg_type.into(),
+            flags: if compressed { VALUE_COMP_NAME } else { 0 },
+            spare: 0,
+        };
+
+        let mut content = bincode::encode_to_vec(&header, bincode_config())?;
+        content.extend_from_slice(&name);
+        Ok(self.alloc(&content))
+    }
+
+    // lh or lf leaves, under an index root when there are too many subkeys for a single leaf
+    fn write_subkeys_list(&mut self, elements: &[(u32, &str)]) -> u32 {
+        if elements.is_empty() {
+            return NO_OFFSET;
+        }
+
+        let leaves: Vec<u32> = elements
+            .chunks(LEAF_MAX_ELEMENTS)
+            .map(|chunk| {
+                let leaf = if self.minor_version >= 5 {
+                    SubkeysList::HashLeaf(chunk.iter().map(|(o, n)| (*o, name_hash(n))).collect())
+                } else {
+                    SubkeysList::FastLeaf(chunk.iter().map(|(o, n)| (*o, name_hint(n))).collect())
+                };
+                self.alloc(&leaf.to_bytes())
+            })
+            .collect();
+
+        if leaves.len() == 1 {
+            return leaves[0];
+        }
+        self.alloc(&SubkeysList::IndexRoot(leaves).to_bytes())
+    }
+
+    // security cells are shared by all keys having the same descriptor
+    fn security_cell(&mut self, descriptor: &[u8]) -> u32 {
+        if let Some(&index) = self.security.get(descriptor) {
+            self.security_cells[index].reference_count += 1;
+            return self.security_cells[index].offset;
+        }
+
+        // links are set once all cells are allocated
+        let mut content = vec![0; KEY_SECURITY_HEADER_SIZE];
+        content.extend_from_slice(descriptor);
+        let offset = self.alloc(&content);
+
+        self.security
+            .insert(descriptor.to_vec(), self.security_cells.len());
+        self.security_cells.push(SecurityCell {
+            offset,
+            reference_count: 1,
+            descriptor_size: descriptor.len() as u32,
+        });
+        offset
+    }
+
+    // security cells form a circular doubly linked list
+    fn write_security_list(&mut self) -> anyhow::Result<()> {
+        let count = self.security_cells.len();
+
+        for (index, cell) in self.security_cells.iter().enumerate() {
+            let header = KeySecurityHeader {
+                signature: *b"sk",
+                reserved: 0,
+                flink: self.security_cells[(index + 1) % count].offset,
+                blink: self.security_cells[(index + count - 1) % count].offset,
+                reference_count: cell.reference_count,
+                security_descriptor_size: cell.descriptor_size,
+            };
+            let content = bincode::encode_to_vec(&header, bincode_config())?;
+
+            let start = cell.offset as usize + 4;
+            self.data[start..start + content.len()].copy_from_slice(&content);
+        }
+
+        Ok(())
+    }
+}
+
+pub fn offsets_bytes(offsets: &[u32]) -> Vec<u8> {
+    offsets.iter().flat_map(|o| o.to_le_bytes()).collect()
+}
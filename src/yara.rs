@@ -0,0 +1,566 @@
+// YARA rules run on value data, free cells and slack space
+//
+// no YARA engine is available as a dependency, rules are evaluated by this module which supports a
+// subset of the language:
+//
+// - text strings with the nocase, ascii and wide modifiers and the \" \\ \t \n \r \xHH escapes
+// - hex strings with ?? and nibble (?A, A?) wildcards, but not jumps nor alternatives
+// - conditions made of $string, any/all/N of them, any/all/N of ($a, $b*), and, or, not, true,
+//   false and parentheses
+//
+// meta sections are skipped, modules (import) and other condition constructs are rejected. A
+// condition is evaluated on each scanned region: the data of a value, a free cell or the slack space
+// after the data of an allocated cell.
+//
+use std::{collections::HashMap, fmt, path::Path};
+
+use crate::{
+    hive::{RegistryHive, join_path},
+    reachability::reachable_cells,
+};
+
+#[derive(Debug, Clone)]
+pub struct YaraRule {
+    pub name: String,
+    pub tags: Vec<String>,
+    strings: Vec<(String, Vec<Pattern>)>,
+    condition: Condition,
+}
+
+// bytes with their mask, one pattern per encoding of a text string
+#[derive(Debug, Clone)]
+struct Pattern {
+    bytes: Vec<(u8, u8)>,
+    nocase: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Bool(bool),
+    String(String),
+    // minimum number of strings of the set to match, None for all of them
+    Of(Option<usize>, Vec<String>),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Debug, Clone)]
+pub struct YaraMatch {
+    pub rule: String,
+
+    // "value", "free" or "slack"
+    pub region: &'static str,
+
+    // value path, or path of the key owning the cell ("-" for free cells)
+    pub path: String,
+
+    // matched strings with the offset of their first match: in the data for values, in the file for
+    // cells
+    pub strings: Vec<(String, usize)>,
+}
+
+impl fmt::Display for YaraMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let strings: Vec<_> = self
+            .strings
+            .iter()
+            .map(|(id, offset)| format!("{id}@0x{offset:X}"))
+            .collect();
+        write!(
+            f,
+            "{}\t{}\t{}\t{}",
+            self.rule,
+            self.region,
+            self.path,
+            strings.join(",")
+        )
+    }
+}
+
+impl Pattern {
+    fn matches_at(&self, data: &[u8], at: usize) -> bool {
+        self.bytes.iter().enumerate().all(|(i, (value, mask))| {
+            let b = data[at + i];
+            if self.nocase {
+                b.eq_ignore_ascii_case(value)
+            } else {
+                b & mask == *value
+            }
+        })
+    }
+
+    fn find(&self, data: &[u8]) -> Option<usize> {
+        if self.bytes.is_empty() || self.bytes.len() > data.len() {
+            return None;
+        }
+        (0..=data.len() - self.bytes.len()).find(|at| self.matches_at(data, *at))
+    }
+}
+
+impl Condition {
+    fn eval(&self, matched: &HashMap<&str, usize>, ids: &[&str]) -> bool {
+        match self {
+            Condition::Bool(b) => *b,
+            Condition::String(id) => matched.contains_key(id.as_str()),
+            Condition::Of(count, set) => {
+                let members: Vec<&&str> = ids
+                    .iter()
+                    .filter(|id| set.iter().any(|s| set_member(s, id)))
+                    .collect();
+                let found = members
+                    .iter()
+                    .filter(|id| matched.contains_key(**id))
+                    .count();
+                match count {
+                    Some(n) => found >= *n,
+                    None => found == members.len(),
+                }
+            }
+            Condition::Not(c) => !c.eval(matched, ids),
+            Condition::And(a, b) => a.eval(matched, ids) && b.eval(matched, ids),
+            Condition::Or(a, b) => a.eval(matched, ids) || b.eval(matched, ids),
+        }
+    }
+}
+
+// "$a*" sets match the strings whose identifier starts with "$a"
+fn set_member(set: &str, id: &str) -> bool {
+    match set.strip_suffix('*') {
+        Some(prefix) => id.starts_with(prefix),
+        None => set == id,
+    }
+}
+
+impl YaraRule {
+    // matched strings with the offset of their first match, None if the condition isn't met
+    pub fn scan(&self, data: &[u8]) -> Option<Vec<(String, usize)>> {
+        let mut matched = HashMap::new();
+        for (id, patterns) in &self.strings {
+            if let Some(offset) = patterns.iter().filter_map(|p| p.find(data)).min() {
+                matched.insert(id.as_str(), offset);
+            }
+        }
+
+        let ids: Vec<&str> = self.strings.iter().map(|(id, _)| id.as_str()).collect();
+        if !self.condition.eval(&matched, &ids) {
+            return None;
+        }
+
+        let mut strings: Vec<_> = matched
+            .into_iter()
+            .map(|(id, offset)| (id.to_string(), offset))
+            .collect();
+        strings.sort_by_key(|(_, offset)| *offset);
+        Some(strings)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    // string identifier, with its trailing '*' if any
+    Var(String),
+    Text(Vec<u8>),
+    Hex(Vec<(u8, u8)>),
+    Number(usize),
+    Punct(char),
+}
+
+fn tokenize(source: &str) -> anyhow::Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if source_at(&chars, i, "//") {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if source_at(&chars, i, "/*") {
+            i += 2;
+            while i < chars.len() && !source_at(&chars, i, "*/") {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '"' {
+            let mut text = Vec::new();
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' {
+                    i += 1;
+                    match chars.get(i) {
+                        Some('n') => text.push(b'\n'),
+                        Some('t') => text.push(b'\t'),
+                        Some('r') => text.push(b'\r'),
+                        Some('x') => {
+                            let hex: String = chars.iter().skip(i + 1).take(2).collect();
+                            text.push(u8::from_str_radix(&hex, 16).map_err(|_| {
+                                anyhow::anyhow!("invalid escape sequence '\\x{hex}'")
+                            })?);
+                            i += 2;
+                        }
+                        Some(c) => {
+                            let mut buf = [0; 4];
+                            text.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                        }
+                        None => anyhow::bail!("unterminated string"),
+                    }
+                } else {
+                    let mut buf = [0; 4];
+                    text.extend_from_slice(chars[i].encode_utf8(&mut buf).as_bytes());
+                }
+                i += 1;
+            }
+            anyhow::ensure!(i < chars.len(), "unterminated string");
+            tokens.push(Token::Text(text));
+            i += 1;
+        } else if c == '{' && tokens.last() == Some(&Token::Punct('=')) {
+            let end = chars[i..]
+                .iter()
+                .position(|c| *c == '}')
+                .ok_or_else(|| anyhow::anyhow!("unterminated hex string"))?;
+            let hex: String = chars[i + 1..i + end].iter().collect();
+            tokens.push(Token::Hex(parse_hex(&hex)?));
+            i += end + 1;
+        } else if c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            if i < chars.len() && chars[i] == '*' {
+                i += 1;
+            }
+            tokens.push(Token::Var(chars[start..i].iter().collect()));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(number.parse()?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if "{}()=:,".contains(c) {
+            tokens.push(Token::Punct(c));
+            i += 1;
+        } else {
+            anyhow::bail!("unsupported character '{c}' in rules");
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn source_at(chars: &[char], i: usize, s: &str) -> bool {
+    s.chars()
+        .enumerate()
+        .all(|(j, c)| chars.get(i + j) == Some(&c))
+}
+
+// "4D 5A ?? 9?" to (value, mask) pairs
+fn parse_hex(hex: &str) -> anyhow::Result<Vec<(u8, u8)>> {
+    let digits: Vec<char> = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    anyhow::ensure!(
+        digits.iter().all(|c| c.is_ascii_hexdigit() || *c == '?'),
+        "unsupported hex string '{{{hex}}}': jumps and alternatives aren't supported"
+    );
+    anyhow::ensure!(
+        !digits.is_empty() && digits.len().is_multiple_of(2),
+        "invalid hex string '{{{hex}}}'"
+    );
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| {
+            let nibble = |c: char| c.to_digit(16).map_or((0, 0), |d| (d as u8, 0xF));
+            let (high, high_mask) = nibble(pair[0]);
+            let (low, low_mask) = nibble(pair[1]);
+            (high << 4 | low, high_mask << 4 | low_mask)
+        })
+        .collect())
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> anyhow::Result<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("unexpected end of rules"))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn is_ident(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(i)) if i == name)
+    }
+
+    fn expect(&mut self, expected: Token) -> anyhow::Result<()> {
+        let token = self.next()?;
+        anyhow::ensure!(token == expected, "expected {expected:?}, found {token:?}");
+        Ok(())
+    }
+
+    fn ident(&mut self) -> anyhow::Result<String> {
+        match self.next()? {
+            Token::Ident(i) => Ok(i),
+            token => anyhow::bail!("expected an identifier, found {token:?}"),
+        }
+    }
+
+    fn rule(&mut self) -> anyhow::Result<YaraRule> {
+        while self.is_ident("private") || self.is_ident("global") {
+            self.next()?;
+        }
+        match self.ident()?.as_str() {
+            "rule" => (),
+            "import" | "include" => {
+                anyhow::bail!("modules and includes aren't supported")
+            }
+            other => anyhow::bail!("expected 'rule', found '{other}'"),
+        }
+
+        let name = self.ident()?;
+        let mut tags = Vec::new();
+        if self.peek() == Some(&Token::Punct(':')) {
+            self.next()?;
+            while let Some(Token::Ident(_)) = self.peek() {
+                tags.push(self.ident()?);
+            }
+        }
+        self.expect(Token::Punct('{'))?;
+
+        if self.is_ident("meta") {
+            self.next()?;
+            self.expect(Token::Punct(':'))?;
+            while !self.is_ident("strings") && !self.is_ident("condition") {
+                self.ident()?;
+                self.expect(Token::Punct('='))?;
+                self.next()?;
+            }
+        }
+
+        let mut strings = Vec::new();
+        if self.is_ident("strings") {
+            self.next()?;
+            self.expect(Token::Punct(':'))?;
+            while let Some(Token::Var(id)) = self.peek().cloned() {
+                self.next()?;
+                self.expect(Token::Punct('='))?;
+                let patterns = match self.next()? {
+                    Token::Text(text) => self.text_patterns(&text)?,
+                    Token::Hex(bytes) => vec![Pattern {
+                        bytes,
+                        nocase: false,
+                    }],
+                    token => anyhow::bail!("expected a string for {id}, found {token:?}"),
+                };
+                strings.push((id, patterns));
+            }
+        }
+
+        anyhow::ensure!(
+            self.ident()? == "condition",
+            "rule '{name}' has no condition"
+        );
+        self.expect(Token::Punct(':'))?;
+        let condition = self.or()?;
+        self.expect(Token::Punct('}'))?;
+
+        Ok(YaraRule {
+            name,
+            tags,
+            strings,
+            condition,
+        })
+    }
+
+    // modifiers following a text string
+    fn text_patterns(&mut self, text: &[u8]) -> anyhow::Result<Vec<Pattern>> {
+        let (mut ascii, mut wide, mut nocase) = (false, false, false);
+        while let Some(Token::Ident(modifier)) = self.peek() {
+            match modifier.as_str() {
+                "ascii" => ascii = true,
+                "wide" => wide = true,
+                "nocase" => nocase = true,
+                "fullword" | "private" | "xor" | "base64" | "base64wide" => {
+                    anyhow::bail!("unsupported string modifier '{modifier}'")
+                }
+                _ => break,
+            }
+            self.next()?;
+        }
+
+        let mut patterns = Vec::new();
+        if ascii || !wide {
+            patterns.push(Pattern {
+                bytes: text.iter().map(|b| (*b, 0xFF)).collect(),
+                nocase,
+            });
+        }
+        if wide {
+            patterns.push(Pattern {
+                bytes: text.iter().flat_map(|b| [(*b, 0xFF), (0, 0xFF)]).collect(),
+                nocase,
+            });
+        }
+        Ok(patterns)
+    }
+
+    fn or(&mut self) -> anyhow::Result<Condition> {
+        let mut condition = self.and()?;
+        while self.is_ident("or") {
+            self.next()?;
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Condition> {
+        let mut condition = self.not()?;
+        while self.is_ident("and") {
+            self.next()?;
+            condition = Condition::And(Box::new(condition), Box::new(self.not()?));
+        }
+        Ok(condition)
+    }
+
+    fn not(&mut self) -> anyhow::Result<Condition> {
+        if self.is_ident("not") {
+            self.next()?;
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Condition> {
+        match self.next()? {
+            Token::Punct('(') => {
+                let condition = self.or()?;
+                self.expect(Token::Punct(')'))?;
+                Ok(condition)
+            }
+            Token::Var(id) => Ok(Condition::String(id)),
+            Token::Ident(i) if i == "true" => Ok(Condition::Bool(true)),
+            Token::Ident(i) if i == "false" => Ok(Condition::Bool(false)),
+            Token::Ident(i) if i == "any" => self.of(Some(1)),
+            Token::Ident(i) if i == "all" => self.of(None),
+            Token::Number(n) => self.of(Some(n)),
+            token => anyhow::bail!("unsupported condition at {token:?}"),
+        }
+    }
+
+    fn of(&mut self, count: Option<usize>) -> anyhow::Result<Condition> {
+        anyhow::ensure!(self.ident()? == "of", "expected 'of'");
+        if self.is_ident("them") {
+            self.next()?;
+            return Ok(Condition::Of(count, vec!["$*".to_string()]));
+        }
+
+        self.expect(Token::Punct('('))?;
+        let mut set = Vec::new();
+        loop {
+            match self.next()? {
+                Token::Var(id) => set.push(id),
+                token => anyhow::bail!("expected a string identifier, found {token:?}"),
+            }
+            match self.next()? {
+                Token::Punct(',') => continue,
+                Token::Punct(')') => break,
+                token => anyhow::bail!("expected ',' or ')', found {token:?}"),
+            }
+        }
+        Ok(Condition::Of(count, set))
+    }
+}
+
+pub fn parse_rules(source: &str) -> anyhow::Result<Vec<YaraRule>> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+
+    let mut rules = Vec::new();
+    while parser.peek().is_some() {
+        rules.push(parser.rule()?);
+    }
+    Ok(rules)
+}
+
+// data of all values, then free cells and slack space of the hive file
+pub fn yara_scan(path: &Path, rules: &[YaraRule]) -> anyhow::Result<Vec<YaraMatch>> {
+    let mut matches = Vec::new();
+    let mut hive = RegistryHive::open(path)?;
+    scan_values(&mut hive, rules, &mut matches)?;
+
+    let owners = reachable_cells(&mut hive)?;
+    let index = hive.index()?;
+    for cell in index.cells() {
+        let content = index.content(cell);
+        let (region, path, start) = match owners.get(&cell.offset) {
+            _ if cell.is_free() => ("free", "-", 0),
+            Some(owner) if owner.used < content.len() => ("slack", owner.path.as_str(), owner.used),
+            _ => continue,
+        };
+
+        // file offset: base block, cell offset, cell size field
+        let file_offset = 4096 + cell.offset as usize + 4 + start;
+        for rule in rules {
+            if let Some(strings) = rule.scan(&content[start..]) {
+                matches.push(YaraMatch {
+                    rule: rule.name.clone(),
+                    region,
+                    path: path.to_string(),
+                    strings: strings
+                        .into_iter()
+                        .map(|(id, offset)| (id, file_offset + offset))
+                        .collect(),
+                });
+            }
+        }
+    }
+
+    Ok(matches)
+}
+
+fn scan_values(
+    hive: &mut RegistryHive,
+    rules: &[YaraRule],
+    matches: &mut Vec<YaraMatch>,
+) -> anyhow::Result<()> {
+    let root = hive.root()?;
+    hive.walk(&root, "\\", &mut |hive, path, key| {
+        for value in hive.values(key)? {
+            let data = hive.value_bytes(&value)?;
+            for rule in rules {
+                if let Some(strings) = rule.scan(&data) {
+                    matches.push(YaraMatch {
+                        rule: rule.name.clone(),
+                        region: "value",
+                        path: join_path(path, value.display_name()),
+                        strings,
+                    });
+                }
+            }
+        }
+        Ok(())
+    })
+}
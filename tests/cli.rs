@@ -0,0 +1,89 @@
+// Runs of the readregf binary on hives written by its create command
+use std::{
+    fs::File,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+fn readregf(args: &[&str], stdin: Option<&PathBuf>) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_readregf"));
+    command.args(args);
+    if let Some(path) = stdin {
+        command.stdin(Stdio::from(File::open(path).unwrap()));
+    }
+    command.output().unwrap()
+}
+
+// a new hive with only a root key, in a directory of its own
+fn new_hive(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("readregf-{test}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let hive = dir.join("HIVE");
+    let output = readregf(&["create", hive.to_str().unwrap()], None);
+    assert!(output.status.success());
+    hive
+}
+
+#[test]
+fn patch_stdin_without_out() {
+    let hive = new_hive("patch");
+    let output = readregf(
+        &["patch", "-", "--offset", "0x20", "--bytes", "00"],
+        Some(&hive),
+    );
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("use --out"));
+}
+
+#[test]
+fn stdout_closed() {
+    let hive = new_hive("pipe");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_readregf"))
+        .args(["ls", hive.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // as head does once it has read enough
+    drop(child.stdout.take());
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn not_a_hive() {
+    let hive = new_hive("signature");
+    let text = hive.with_file_name("text");
+    std::fs::write(&text, "not a hive").unwrap();
+    let output = readregf(&["ls", text.to_str().unwrap()], None);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a registry hive"));
+}
+
+#[test]
+fn ioc_failing_hive() {
+    let hive = new_hive("ioc");
+    let text = hive.with_file_name("text");
+    std::fs::write(&text, "not a hive").unwrap();
+    let iocs = hive.with_file_name("iocs");
+    std::fs::write(&iocs, "key:*\n").unwrap();
+    let output = readregf(
+        &[
+            "ioc",
+            "--iocs",
+            iocs.to_str().unwrap(),
+            text.to_str().unwrap(),
+            hive.to_str().unwrap(),
+        ],
+        None,
+    );
+
+    // the readable hive is still matched
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("key:*"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("not a registry hive"));
+}